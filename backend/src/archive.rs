@@ -0,0 +1,480 @@
+//! Archives in and out of the working tree.
+//!
+//! Export (`/api/fs/archive`): directories are streamed out as zip or tar.gz,
+//! produced on a blocking worker and pushed through a bounded channel straight
+//! into the response body, so nothing is buffered in full.
+//!
+//! Import (`/api/fs/extract`): uploaded zips are spooled to a temp file and
+//! unpacked with entry-name validation and entry-count, size and compression
+//! ratio limits. Revision uploads (`POST /api/revisions`, `/from-url`) go
+//! through the same [`inspect`] and [`extract_zip`].
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Multipart, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Seek, Write},
+    path::{Component, Path as StdPath, PathBuf},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use walkdir::WalkDir;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{audit, auth, is_sidecar, quota, read_path, write_path, AppState};
+
+const CHUNK: usize = 64 * 1024;
+
+// ===== streaming plumbing ====================================================
+
+/// `Write` half of a response body: bytes are batched into `CHUNK`-sized frames
+/// and handed to the async side, blocking when the client reads slowly.
+pub(crate) struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn send_buf(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK)));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+    }
+
+    /// Polls whether the client has gone away, without having to write first.
+    pub(crate) fn disconnected(&self) -> impl Fn() -> bool + Send + Sync + 'static {
+        let tx = self.tx.clone();
+        move || tx.is_closed()
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK {
+            self.send_buf()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buf()
+    }
+}
+
+/// Runs `produce` on a blocking thread and returns a body that yields whatever
+/// it writes. An error aborts the body mid-stream so the client sees a failed
+/// download rather than a silently truncated file.
+pub(crate) fn stream_body<F>(produce: F) -> Body
+where
+    F: FnOnce(&mut ChannelWriter) -> io::Result<()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let mut w = ChannelWriter { tx: tx.clone(), buf: Vec::with_capacity(CHUNK) };
+        if let Err(e) = produce(&mut w).and_then(|_| w.flush()) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                tracing::error!("archive stream error: {e}");
+            }
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+// ===== writers ===============================================================
+
+/// Writes every file and directory under `root` into a streaming (seek-free) zip,
+/// after the in-memory `extra` entries (name, bytes). Symlinks are never
+/// followed into the archive.
+pub(crate) fn write_zip<W: Write>(root: &StdPath, out: W, extra: &[(&str, &[u8])]) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(out);
+    let opts = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, bytes) in extra {
+        zip.start_file(*name, opts).map_err(io::Error::other)?;
+        zip.write_all(bytes)?;
+    }
+
+    for entry in WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter().filter_entry(|e| !sidecar(root, e)) {
+        let entry = entry.map_err(io::Error::other)?;
+        let name = entry_name(root, entry.path());
+        if entry.file_type().is_dir() {
+            zip.add_directory(name, opts).map_err(io::Error::other)?;
+        } else if entry.file_type().is_file() {
+            let len = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            zip.start_file(name, opts.large_file(len >= u32::MAX as u64))
+                .map_err(io::Error::other)?;
+            io::copy(&mut fs::File::open(entry.path())?, &mut zip)?;
+        }
+    }
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+pub(crate) fn write_tar_gz<W: Write>(root: &StdPath, out: W) -> io::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    tar.follow_symlinks(false);
+    for entry in WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter().filter_entry(|e| !sidecar(root, e)) {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().is_dir() || entry.file_type().is_file() {
+            tar.append_path_with_name(entry.path(), entry_name(root, entry.path()))?;
+        }
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Archiving the whole storage root leaves out the server's own files.
+fn sidecar(root: &StdPath, e: &walkdir::DirEntry) -> bool {
+    e.depth() == 1 && is_sidecar(root, e.file_name())
+}
+
+fn entry_name(root: &StdPath, p: &StdPath) -> String {
+    p.strip_prefix(root).unwrap_or(p).to_string_lossy().replace('\\', "/")
+}
+
+// ===== GET /api/fs/archive ===================================================
+
+#[derive(Deserialize)]
+pub(crate) struct ArchiveParams {
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    format: ArchiveFormat,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+pub(crate) enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+}
+
+pub(crate) async fn fs_archive(State(st): State<AppState>, Query(q): Query<ArchiveParams>) -> impl IntoResponse {
+    let root = match read_path(&st.config, &q.path) {
+        Ok(p) if p.is_dir() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+
+    let base = root
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "decisions".into());
+    let (ext, mime) = match q.format {
+        ArchiveFormat::Zip => ("zip", "application/zip"),
+        ArchiveFormat::TarGz => ("tar.gz", "application/gzip"),
+    };
+
+    let format = q.format;
+    let body = stream_body(move |w| match format {
+        ArchiveFormat::Zip => write_zip(&root, w, &[]),
+        ArchiveFormat::TarGz => write_tar_gz(&root, w),
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(mime));
+    if let Ok(v) = HeaderValue::from_str(&format!("attachment; filename=\"{}.{ext}\"", base.replace('"', ""))) {
+        headers.insert(header::CONTENT_DISPOSITION, v);
+    }
+    (headers, body).into_response()
+}
+
+// ===== safe extraction =======================================================
+
+pub(crate) struct ExtractLimits {
+    pub max_entries: usize,
+    pub max_total_bytes: u64,
+    /// inflated ÷ compressed size of any one entry; entries up to
+    /// `RATIO_EXEMPT_BYTES` are exempt, a small file of spaces legitimately
+    /// compresses a thousandfold
+    pub max_ratio: u64,
+}
+
+pub(crate) const UPLOAD_LIMITS: ExtractLimits = ExtractLimits {
+    max_entries: 10_000,
+    max_total_bytes: 256 * 1024 * 1024,
+    max_ratio: 100,
+};
+
+const RATIO_EXEMPT_BYTES: u64 = 1024 * 1024;
+
+impl ExtractLimits {
+    /// Most an entry stored in `compressed` bytes may inflate to.
+    fn entry_cap(&self, compressed: u64) -> u64 {
+        compressed.saturating_mul(self.max_ratio).max(RATIO_EXEMPT_BYTES)
+    }
+}
+
+#[derive(Serialize, Default)]
+pub(crate) struct ExtractReport {
+    pub files: usize,
+    pub directories: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug)]
+pub(crate) enum ExtractError {
+    /// the archive itself is malformed or violates a limit → 400
+    Rejected(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for ExtractError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ExtractError {
+    fn from(e: zip::result::ZipError) -> Self {
+        match e {
+            zip::result::ZipError::Io(e) => Self::Io(e),
+            e => Self::Rejected(e.to_string()),
+        }
+    }
+}
+
+impl From<ExtractError> for Response {
+    fn from(e: ExtractError) -> Self {
+        e.into_response()
+    }
+}
+
+impl IntoResponse for ExtractError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Rejected(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            Self::Io(e) => {
+                tracing::error!("extract error: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Whether writing `rel` under `dest` would pass through a symlink already
+/// there (an extract into an existing folder).
+fn through_symlink(dest: &StdPath, rel: &StdPath) -> bool {
+    let mut cur = dest.to_path_buf();
+    rel.parent().into_iter().flat_map(StdPath::components).any(|c| {
+        cur.push(c);
+        fs::symlink_metadata(&cur).is_ok_and(|m| m.file_type().is_symlink())
+    })
+}
+
+/// Unpacks `archive` into `dest`. Entry names must be plain relative paths
+/// (no `..`, no absolute/drive prefixes), symlink entries are refused, and
+/// nothing is written through a symlink already in `dest`; sizes and ratios
+/// are enforced on the bytes actually inflated, not the declared ones.
+pub(crate) fn extract_zip<R: Read + Seek>(
+    archive: R,
+    dest: &StdPath,
+    limits: &ExtractLimits,
+) -> Result<ExtractReport, ExtractError> {
+    let mut archive = ZipArchive::new(archive)?;
+    if archive.len() > limits.max_entries {
+        return Err(ExtractError::Rejected(format!(
+            "archive has {} entries, limit is {}",
+            archive.len(),
+            limits.max_entries
+        )));
+    }
+
+    let mut report = ExtractReport::default();
+    for i in 0..archive.len() {
+        let mut f = archive.by_index(i)?;
+        let rel = sanitize_entry(f.name())
+            .ok_or_else(|| ExtractError::Rejected(format!("unsafe entry name: {}", f.name())))?;
+        if f.is_symlink() {
+            return Err(ExtractError::Rejected(format!("symlink entries are not allowed: {}", f.name())));
+        }
+        if through_symlink(dest, &rel) {
+            return Err(ExtractError::Rejected(format!("{} would be written through a symlink", f.name())));
+        }
+
+        let out_path = dest.join(&rel);
+        if f.is_dir() {
+            fs::create_dir_all(&out_path)?;
+            report.directories += 1;
+            continue;
+        }
+        if let Some(p) = out_path.parent() {
+            fs::create_dir_all(p)?;
+        }
+
+        let budget = limits.max_total_bytes - report.bytes;
+        let cap = limits.entry_cap(f.compressed_size());
+        // a fresh inode: the old file may be hardlinked into a snapshot
+        match fs::remove_file(&out_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut w = fs::File::create(&out_path)?;
+        let written = io::copy(&mut (&mut f).take(budget.min(cap) + 1), &mut w)?;
+        if written > budget.min(cap) {
+            drop(w);
+            let _ = fs::remove_file(&out_path);
+            return Err(ExtractError::Rejected(if written > budget {
+                format!("archive expands beyond {} bytes", limits.max_total_bytes)
+            } else {
+                format!("{} is compressed more than {}:1", f.name(), limits.max_ratio)
+            }));
+        }
+        report.bytes += written;
+        report.files += 1;
+    }
+    Ok(report)
+}
+
+/// Checks `archive` against `limits` from its central directory alone and
+/// returns its declared `(bytes, files)`, for quota pre-checks: catches bad
+/// names, symlinks and bombs before anything is written. Declared sizes can
+/// lie, so `extract_zip` still enforces the actual ones.
+pub(crate) fn inspect<R: Read + Seek>(archive: R, limits: &ExtractLimits) -> Result<(u64, u64), ExtractError> {
+    let mut archive = ZipArchive::new(archive)?;
+    if archive.len() > limits.max_entries {
+        return Err(ExtractError::Rejected(format!(
+            "archive has {} entries, limit is {}",
+            archive.len(),
+            limits.max_entries
+        )));
+    }
+    let (mut bytes, mut files) = (0u64, 0u64);
+    for i in 0..archive.len() {
+        let f = archive.by_index_raw(i)?;
+        if sanitize_entry(f.name()).is_none() {
+            return Err(ExtractError::Rejected(format!("unsafe entry name: {}", f.name())));
+        }
+        if f.is_symlink() {
+            return Err(ExtractError::Rejected(format!("symlink entries are not allowed: {}", f.name())));
+        }
+        if f.is_dir() {
+            continue;
+        }
+        if f.size() > limits.entry_cap(f.compressed_size()) {
+            return Err(ExtractError::Rejected(format!("{} is compressed more than {}:1", f.name(), limits.max_ratio)));
+        }
+        bytes = bytes.saturating_add(f.size());
+        files += 1;
+    }
+    if bytes > limits.max_total_bytes {
+        return Err(ExtractError::Rejected(format!("archive expands beyond {} bytes", limits.max_total_bytes)));
+    }
+    Ok((bytes, files))
+}
+
+pub(crate) fn sanitize_entry(name: &str) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for comp in StdPath::new(&name.replace('\\', "/")).components() {
+        match comp {
+            Component::Normal(c) => out.push(c),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!out.as_os_str().is_empty()).then_some(out)
+}
+
+// ===== POST /api/fs/extract ==================================================
+
+/// multipart fields: `path` (target folder, created if missing; the working
+/// copy by default), `file` (the zip) and `owner` (the lock owner, required
+/// when something in the target folder is locked)
+pub(crate) async fn fs_extract(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, mut multipart: Multipart) -> Response {
+    let mut target: Option<String> = None;
+    let mut owner: Option<String> = None;
+    let mut upload: Option<tempfile::NamedTempFile> = None;
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(f)) => f,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+        };
+        match field.name() {
+            Some("path") => match field.text().await {
+                Ok(t) => target = Some(t),
+                Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+            },
+            Some("owner") => match field.text().await {
+                Ok(t) => owner = Some(t),
+                Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+            },
+            Some("file") => {
+                let tmp = match tempfile::NamedTempFile::new() {
+                    Ok(t) => t,
+                    Err(e) => return ExtractError::Io(e).into_response(),
+                };
+                let mut out = match tmp.reopen() {
+                    Ok(f) => tokio::fs::File::from_std(f),
+                    Err(e) => return ExtractError::Io(e).into_response(),
+                };
+                loop {
+                    match field.chunk().await {
+                        Ok(Some(chunk)) => {
+                            if let Err(e) = out.write_all(&chunk).await {
+                                return ExtractError::Io(e).into_response();
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+                    }
+                }
+                if let Err(e) = out.flush().await {
+                    return ExtractError::Io(e).into_response();
+                }
+                upload = Some(tmp);
+            }
+            _ => {}
+        }
+    }
+
+    let Some(upload) = upload else {
+        return (StatusCode::BAD_REQUEST, "missing file field").into_response();
+    };
+    let target = target.unwrap_or_else(|| "0".to_string());
+    if let Err(resp) = st.locks.check_tree(&target, auth::acting(&ident, owner).as_deref()) {
+        return resp;
+    }
+    let dest = match write_path(&target) {
+        Ok(p) if p.is_file() => return (StatusCode::CONFLICT, "target is a file").into_response(),
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<(ExtractReport, Option<String>), Response> {
+        let (bytes, files) = inspect(upload.reopen().map_err(ExtractError::Io)?, &UPLOAD_LIMITS)?;
+        let _g = state.rev_lock.lock();
+        quota::check(&state.config, bytes as i64, files as i64)?;
+        fs::create_dir_all(&dest).map_err(ExtractError::Io)?;
+        let report = extract_zip(upload.reopen().map_err(ExtractError::Io)?, &dest, &UPLOAD_LIMITS)?;
+        Ok((report, audit::hash_file(upload.path())))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((report, zip_hash))) => {
+            st.index.touch(&target);
+            st.engines.invalidate(&target);
+            audit::record(audit::Event::new("extract").path(&target).hashes(None, zip_hash)).await;
+            (StatusCode::CREATED, Json(report)).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
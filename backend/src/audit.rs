@@ -0,0 +1,338 @@
+//! The audit log: every change made through the API, who made it and when,
+//! in `./decisions/.audit.log`; `GET /api/audit` to read it and
+//! `GET /api/audit/verify` to check nobody else has.
+//!
+//! Each line is one JSON entry. Besides what happened (`action`, `path`,
+//! `to` for renames and workflow transitions, `rev`) and the SHA-256 of a
+//! file before and after (`oldHash`, `newHash`; for a revision, of its path
+//! map), an entry holds the hash of the entry before it (`prev`) and its own
+//! (`hash`, over `prev` and the rest of the entry). Editing, dropping or
+//! reordering lines breaks the chain from there on, which verification
+//! reports. The file is only ever appended to, under a lock every instance
+//! takes (see [`storelock`]).
+//!
+//! Actions: `save`, `patch`, `mkdir`, `duplicate`, `rename`, `delete`,
+//! `extract`, `replace`, `import-table`, `new-from-template`,
+//! `history-restore`, `restore-file`, `snapshot`, `revision` (uploaded,
+//! imported from a URL or committed from staging), `restore`, `checkout`,
+//! `revision-delete`, `prune`, `gc` (revisions the retention policy
+//! removed), `transition` (to the state in `to`), `approve`, `tag` and
+//! `tag-delete`, `alias-set` and `alias-delete`, `read-only-on`,
+//! `read-only-off`, `secret-set` and `secret-delete` (tags, aliases and
+//! secrets by name as `path`), and `share` (a share link made). The user is
+//! the authenticated caller, or the author a request claims while the API is
+//! open; automatic snapshots and GC have none.
+//!
+//! [`storelock`]: crate::storelock
+
+use axum::{
+    extract::{Json, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{auth, objects, storelock::StorageLock};
+
+const AUDIT_FILE: &str = "./decisions/.audit.log";
+
+/// `prev` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Listed at once unless `limit` says otherwise.
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Enough of the file's end to hold its last entry.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+static LOG: StorageLock = StorageLock::new("audit");
+
+/// Something that changed, before it's chained.
+#[derive(Default)]
+pub(crate) struct Event {
+    action: &'static str,
+    user: Option<String>,
+    path: Option<String>,
+    to: Option<String>,
+    rev: Option<u64>,
+    old_hash: Option<String>,
+    new_hash: Option<String>,
+}
+
+impl Event {
+    pub(crate) fn new(action: &'static str) -> Self {
+        Self { action, ..Self::default() }
+    }
+
+    pub(crate) fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub(crate) fn to(mut self, to: &str) -> Self {
+        self.to = Some(to.to_string());
+        self
+    }
+
+    /// A revision made or touched, with its path map's hash as `newHash`.
+    pub(crate) fn rev(mut self, rev: u64) -> Self {
+        self.rev = Some(rev);
+        self.new_hash = self.new_hash.or_else(|| tree_hash(rev));
+        self
+    }
+
+    /// A revision about to go, with its path map's hash as `oldHash`.
+    pub(crate) fn removed(mut self, rev: u64) -> Self {
+        self.rev = Some(rev);
+        self.old_hash = tree_hash(rev);
+        self
+    }
+
+    pub(crate) fn hashes(mut self, old: Option<String>, new: Option<String>) -> Self {
+        self.old_hash = old;
+        self.new_hash = new;
+        self
+    }
+
+    /// Who, unless the request is authenticated; then it's always the caller.
+    pub(crate) fn by(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+}
+
+/// What each line holds, less its `hash`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Body {
+    seq: u64,
+    /// unix milliseconds
+    at: u64,
+    action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    old_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    new_hash: Option<String>,
+    prev: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Entry {
+    #[serde(flatten)]
+    body: Body,
+    hash: String,
+}
+
+fn chain_hash(body: &Body) -> String {
+    let json = serde_json::to_vec(body).expect("serialize audit entry");
+    format!("{:x}", Sha256::digest(&json))
+}
+
+pub(crate) fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// SHA-256 of a file as it is now; `None` for a folder or nothing.
+pub(crate) fn hash_file(full: &std::path::Path) -> Option<String> {
+    full.is_file().then(|| objects::hash_file(full).ok()).flatten()
+}
+
+fn tree_hash(rev: u64) -> Option<String> {
+    let map = objects::file_map(rev)?;
+    Some(format!("{:x}", Sha256::digest(serde_json::to_vec(&map).ok()?)))
+}
+
+/// The last entry's `seq` and `hash`, read from the end of the file.
+fn last(file: &mut fs::File) -> io::Result<Option<(u64, String)>> {
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = String::new();
+    file.read_to_string(&mut tail)?;
+    let Some(line) = tail.lines().rev().find(|l| !l.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let entry: Entry = serde_json::from_str(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Some((entry.body.seq, entry.hash)))
+}
+
+/// Chains `ev` onto the log. Blocking; [`record`] from async code.
+pub(crate) fn append(ev: Event) {
+    let _g = LOG.lock();
+    let res = (|| -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(AUDIT_FILE)?;
+        let (seq, prev) = match last(&mut file)? {
+            Some((seq, hash)) => (seq + 1, hash),
+            None => (1, GENESIS.to_string()),
+        };
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let body = Body {
+            seq,
+            at,
+            action: ev.action.to_string(),
+            user: ev.user,
+            path: ev.path,
+            to: ev.to,
+            rev: ev.rev,
+            old_hash: ev.old_hash,
+            new_hash: ev.new_hash,
+            prev,
+        };
+        let hash = chain_hash(&body);
+        let mut line = serde_json::to_vec(&Entry { body, hash }).expect("serialize audit entry");
+        line.push(b'\n');
+        file.write_all(&line)?;
+        file.sync_data()
+    })();
+    if let Err(e) = res {
+        tracing::error!("could not append to the audit log: {e}");
+    }
+}
+
+/// [`append`], with the request's caller as the user when there is one.
+pub(crate) async fn record(mut ev: Event) {
+    if let Some(ident) = auth::caller() {
+        ev.user = Some(ident.name);
+    }
+    let _ = tokio::task::spawn_blocking(move || append(ev)).await;
+}
+
+fn read_all() -> io::Result<Vec<String>> {
+    match fs::File::open(AUDIT_FILE) {
+        Ok(f) => BufReader::new(f).lines().filter(|l| l.as_ref().map_or(true, |l| !l.trim().is_empty())).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn internal(e: io::Error) -> Response {
+    tracing::error!("could not read the audit log: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+// ===== handlers ===============================================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditQuery {
+    /// entries for this path or anything under it (either side of a rename)
+    path: Option<String>,
+    action: Option<String>,
+    user: Option<String>,
+    /// unix seconds, inclusive
+    since: Option<u64>,
+    /// unix seconds, exclusive
+    until: Option<u64>,
+    /// entries older than this `seq`, for the next page
+    before: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditResp {
+    /// newest first
+    entries: Vec<Entry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_before: Option<u64>,
+}
+
+fn under(path: Option<&str>, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.is_some_and(|p| p == prefix || p.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/')))
+}
+
+impl AuditQuery {
+    fn matches(&self, b: &Body) -> bool {
+        self.path.as_deref().is_none_or(|p| under(b.path.as_deref(), p) || under(b.to.as_deref(), p))
+            && self.action.as_deref().is_none_or(|a| b.action == a)
+            && self.user.as_deref().is_none_or(|u| b.user.as_deref() == Some(u))
+            && self.since.is_none_or(|s| b.at >= s * 1000)
+            && self.until.is_none_or(|u| b.at < u * 1000)
+            && self.before.is_none_or(|s| b.seq < s)
+    }
+}
+
+pub(crate) async fn list(Query(q): Query<AuditQuery>) -> Response {
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let res = tokio::task::spawn_blocking(move || -> io::Result<AuditResp> {
+        let mut entries: Vec<Entry> = read_all()?
+            .iter()
+            .rev()
+            .filter_map(|l| serde_json::from_str::<Entry>(l).ok())
+            .filter(|e| q.matches(&e.body))
+            .take(limit + 1)
+            .collect();
+        let next_before = (entries.len() > limit).then(|| {
+            entries.pop();
+            entries.last().expect("limit is at least 1").body.seq
+        });
+        Ok(AuditResp { entries, next_before })
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(e)) => internal(e),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyResp {
+    ok: bool,
+    /// entries checked, up to the first broken one
+    checked: usize,
+    /// the line (from 1) where the chain breaks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broken_at: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Walks the whole chain: every entry must parse, hash to its `hash`, point
+/// at the one before and follow its `seq`.
+pub(crate) async fn verify() -> Response {
+    let res = tokio::task::spawn_blocking(|| -> io::Result<VerifyResp> {
+        let lines = read_all()?;
+        let (mut prev, mut seq) = (GENESIS.to_string(), 0);
+        for (i, line) in lines.iter().enumerate() {
+            let problem = match serde_json::from_str::<Entry>(line) {
+                Err(e) => Some(format!("unreadable entry: {e}")),
+                Ok(e) if e.body.prev != prev => Some("doesn't follow the entry before".to_string()),
+                Ok(e) if e.body.seq != seq + 1 => Some(format!("seq {} after {seq}", e.body.seq)),
+                Ok(e) if chain_hash(&e.body) != e.hash => Some("altered since it was written".to_string()),
+                Ok(e) => {
+                    (prev, seq) = (e.hash, e.body.seq);
+                    None
+                }
+            };
+            if let Some(reason) = problem {
+                tracing::warn!("audit log broken at line {}: {reason}", i + 1);
+                return Ok(VerifyResp { ok: false, checked: i, broken_at: Some(i + 1), reason: Some(reason) });
+            }
+        }
+        Ok(VerifyResp { ok: true, checked: lines.len(), broken_at: None, reason: None })
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(e)) => internal(e),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
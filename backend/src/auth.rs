@@ -0,0 +1,266 @@
+//! Authentication for `/api`: API keys, tokens from an OIDC identity
+//! provider (see [`oidc`]), and session cookies for the frontend (see
+//! [`session`]).
+//!
+//! Keys come from `API_KEYS` (comma-separated) and from the file named by
+//! `API_KEYS_FILE` (one per line, `#` starts a comment), both written as
+//! `name=key`, or `name:role=key` to give the key a [`Role`]. The key may be
+//! given as `sha256:<hex>` of the key instead, so the file needn't hold the
+//! secret itself. Names show up in the logs and don't have to be secret; both
+//! names and keys must be unique.
+//!
+//! With no keys, no `OIDC_ISSUER` and no `USERS_FILE` the API stays open, as
+//! before. Otherwise every `/api` request except `/api/health`, signing in
+//! and out, and share links needs `Authorization: Bearer <key or token>` or
+//! a session cookie, and is answered 401 without one; the static frontend
+//! is never guarded. The caller's [`Identity`] goes into the request
+//! extensions, and handlers record it (see [`acting`]) as the author of
+//! revisions they make and on workflow changes. What it may then call depends
+//! on its role (see [`roles`]), and what it may change on the folder ACLs
+//! (see [`acl`]).
+
+use axum::{
+    extract::{Extension, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs, future::Future};
+
+use crate::{config::Config, AppState};
+
+pub(crate) mod acl;
+pub(crate) mod oidc;
+pub(crate) mod roles;
+pub(crate) mod session;
+pub(crate) mod users;
+
+pub(crate) use roles::Role;
+
+/// Routes anyone may call, keys or not.
+const OPEN: &[&str] = &["/api/health", "/api/auth/login", "/api/auth/logout"];
+/// Where share links lead; the token in the path is the credential (see
+/// [`share`](crate::share)).
+const SHARED: &str = "/api/shared/";
+
+type KeyHash = [u8; 32];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Via {
+    ApiKey,
+    Oidc,
+    /// a `USERS_FILE` user, through the session login
+    Password,
+}
+
+impl Via {
+    fn name(self) -> &'static str {
+        match self {
+            Via::ApiKey => "api-key",
+            Via::Oidc => "oidc",
+            Via::Password => "password",
+        }
+    }
+}
+
+/// Who is calling: the key's name, or the signed-in user.
+#[derive(Clone, Debug)]
+pub(crate) struct Identity {
+    pub name: String,
+    pub via: Via,
+    pub role: Role,
+}
+
+tokio::task_local! {
+    /// The caller of the request being handled, for code that has no
+    /// extractor to ask, like [`acl`] checks deep inside handlers.
+    static CALLER: Identity;
+}
+
+/// The authenticated caller, inside a request's handler (not in blocking
+/// tasks it spawns); `None` while the API is open.
+pub(crate) fn caller() -> Option<Identity> {
+    CALLER.try_with(Identity::clone).ok()
+}
+
+async fn scope<F: Future>(ident: Identity, fut: F) -> F::Output {
+    CALLER.scope(ident, fut).await
+}
+
+/// What to record as having done something: whoever is authenticated, not
+/// what the request claims, or the claim while the API is open.
+pub(crate) fn acting(ident: &Option<Extension<Identity>>, claimed: Option<String>) -> Option<String> {
+    match ident {
+        Some(Extension(id)) => Some(id.name.clone()),
+        None => claimed,
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct KeyStore {
+    /// sha256 of the key → its name and role; looking the caller up by
+    /// digest keeps the comparison from leaking how much of a key was right
+    keys: HashMap<KeyHash, (String, Role)>,
+}
+
+impl KeyStore {
+    pub(crate) fn load(cfg: &Config) -> Result<Self, String> {
+        let mut store = KeyStore::default();
+        for entry in &cfg.api_keys {
+            store.add(entry).map_err(|e| format!("API_KEYS: {e}"))?;
+        }
+        if let Some(path) = &cfg.api_keys_file {
+            let text = fs::read_to_string(path).map_err(|e| format!("API_KEYS_FILE {}: {e}", path.display()))?;
+            for (n, line) in text.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                if !line.is_empty() {
+                    store.add(line).map_err(|e| format!("API_KEYS_FILE {} line {}: {e}", path.display(), n + 1))?;
+                }
+            }
+        }
+        Ok(store)
+    }
+
+    fn add(&mut self, entry: &str) -> Result<(), String> {
+        let (name, key) = entry.split_once('=').ok_or("expected name=key")?;
+        let (name, key) = (name.trim(), key.trim());
+        let (name, role) = match name.split_once(':') {
+            Some((name, role)) => (name.trim(), role.parse::<Role>().map_err(|e| format!("{}: {e}", name.trim()))?),
+            None => (name, Role::Admin),
+        };
+        if name.is_empty() || key.is_empty() {
+            return Err("expected name=key".into());
+        }
+        let hash = match key.strip_prefix("sha256:") {
+            Some(hex) => parse_hex(hex).ok_or_else(|| format!("{name}: sha256 wants 64 hex digits"))?,
+            None => Sha256::digest(key.as_bytes()).into(),
+        };
+        if self.keys.values().any(|(n, _)| n == name) {
+            return Err(format!("{name} is given twice"));
+        }
+        if let Some((other, _)) = self.keys.insert(hash, (name.to_string(), role)) {
+            return Err(format!("{name} has the same key as {other}"));
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, key: &str) -> Option<&(String, Role)> {
+        let hash: KeyHash = Sha256::digest(key.as_bytes()).into();
+        self.keys.get(&hash)
+    }
+}
+
+pub(crate) struct Auth {
+    keys: KeyStore,
+    oidc: Option<oidc::Oidc>,
+    users: users::Users,
+    sessions: session::Sessions,
+}
+
+impl Auth {
+    pub(crate) fn load(cfg: &Config) -> Result<Self, String> {
+        let auth = Auth {
+            keys: KeyStore::load(cfg)?,
+            oidc: oidc::Oidc::from_config(cfg)?,
+            users: users::Users::load(cfg)?,
+            sessions: session::Sessions::from_config(cfg),
+        };
+        let (keys, users) = (auth.keys.keys.len(), auth.users.len());
+        match &auth.oidc {
+            Some(o) => tracing::info!("auth on: {keys} API keys, {users} users, OIDC tokens from {}", o.issuer()),
+            None if auth.enabled() => tracing::info!("auth on: {keys} API keys, {users} users"),
+            None => tracing::warn!("no API keys, users or OIDC_ISSUER configured, /api is open to anyone"),
+        }
+        if auth.enabled() && cfg.session_secret.is_none() {
+            tracing::warn!("no SESSION_SECRET set, sessions end when the server restarts");
+        }
+        if !auth.enabled() && acl::present() {
+            tracing::warn!("{} is ignored while /api is open", acl::ACL_FILE);
+        }
+        Ok(auth)
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        !self.keys.keys.is_empty() || self.oidc.is_some() || self.users.len() > 0
+    }
+
+    /// Who `token` belongs to. Keys are tried first; anything else shaped
+    /// like a JWT goes to the identity provider.
+    async fn identify(&self, token: &str) -> Result<Identity, Response> {
+        if let Some((name, role)) = self.keys.lookup(token) {
+            return Ok(Identity { name: name.clone(), via: Via::ApiKey, role: *role });
+        }
+        match &self.oidc {
+            Some(o) if token.split('.').count() == 3 => {
+                let (name, role) = o.verify(token).await?;
+                Ok(Identity { name, via: Via::Oidc, role })
+            }
+            _ => Err(unauthorized("unknown API key")),
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<KeyHash> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0; 32];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+fn bearer_header(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim()).filter(|t| !t.is_empty())
+}
+
+fn unauthorized(msg: &'static str) -> Response {
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], msg).into_response()
+}
+
+// ===== middleware =============================================================
+
+pub(crate) async fn require_auth(State(st): State<AppState>, mut req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    if !st.auth.enabled() || !path.starts_with("/api/") || OPEN.contains(&path.as_str()) || path.starts_with(SHARED) {
+        return next.run(req).await;
+    }
+    let method = req.method().clone();
+    let ident = match (bearer_header(req.headers()), session::cookie(req.headers(), session::SESSION_COOKIE)) {
+        (Some(token), _) => match st.auth.identify(token).await {
+            Ok(i) => i,
+            Err(resp) => {
+                let why = resp.status().as_u16();
+                tracing::warn!(%method, path, status = why, "rejected request with bad credentials");
+                return resp;
+            }
+        },
+        (None, Some(cookie)) => {
+            let Some(claims) = st.auth.sessions.verify(cookie) else {
+                tracing::warn!(%method, path, "rejected request with an expired or forged session");
+                return unauthorized("session expired, sign in again");
+            };
+            if !claims.allows(&method, req.headers()) {
+                tracing::warn!(%method, path, "rejected session request without its CSRF token");
+                return (StatusCode::FORBIDDEN, "missing or wrong X-XSRF-TOKEN").into_response();
+            }
+            let Some(ident) = claims.identity() else {
+                return unauthorized("session expired, sign in again");
+            };
+            ident
+        }
+        (None, None) => {
+            tracing::warn!(%method, path, "rejected request without credentials");
+            return unauthorized("missing API key or token");
+        }
+    };
+    let (name, via, role) = (ident.name.clone(), ident.via.name(), ident.role.name());
+    req.extensions_mut().insert(ident.clone());
+    let resp = scope(ident, next.run(req)).await;
+    tracing::info!(caller = name, via, role, %method, path, status = resp.status().as_u16(), "api request");
+    resp
+}
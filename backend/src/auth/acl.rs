@@ -0,0 +1,124 @@
+//! Per-folder write access: `.acl.json` at the storage root names who may
+//! change what's under some folders.
+//!
+//! ```json
+//! {
+//!   "pricing/":    ["alice", "pricing-bot", "role:admin"],
+//!   "pricing/eu/": ["eu-pricing"]
+//! }
+//! ```
+//!
+//! Folders are relative to the root of the working copy (and of staging
+//! areas). Someone is allowed by a list that names them (the key's name or
+//! the signed-in user) or a role they have at least (`role:editor` lets admins
+//! in too). The longest folder holding a path decides who may write it; a
+//! folder with no entry above it is open to every editor. Changing a folder
+//! also needs the say of every listed folder inside it, so deleting, renaming
+//! or extracting over `pricing/`'s parent, and restoring or checking out over
+//! the whole working copy, are refused to those the folders don't allow.
+//! Reads aren't restricted.
+//!
+//! The file is outside the working copy, so the API can't change it; edits
+//! on disk apply from the next request. One that doesn't parse refuses every
+//! write until it's fixed. Like roles, nothing is checked while the API is
+//! open.
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path as StdPath},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use super::{Identity, Role};
+use crate::STORAGE_ROOT;
+
+pub(crate) const ACL_FILE: &str = ".acl.json";
+
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct Acl(BTreeMap<String, Vec<String>>);
+
+/// The parsed file, by the mtime it was read at; `None` when there's no file.
+type Loaded = Option<(SystemTime, Result<Vec<(String, Vec<String>)>, String>)>;
+
+static LOADED: Mutex<Loaded> = Mutex::new(None);
+
+fn normalize(path: &str) -> String {
+    let parts: Vec<_> = StdPath::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+/// The entries as folder → who, re-read whenever the file changed.
+fn entries() -> Result<Vec<(String, Vec<String>)>, String> {
+    let file = StdPath::new(STORAGE_ROOT).join(ACL_FILE);
+    let Ok(mtime) = fs::metadata(&file).and_then(|m| m.modified()) else {
+        return Ok(Vec::new());
+    };
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    match &*loaded {
+        Some((at, parsed)) if *at == mtime => parsed.clone(),
+        _ => {
+            let parsed = fs::read(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| serde_json::from_slice::<Acl>(&bytes).map_err(|e| e.to_string()))
+                .map(|acl| acl.0.into_iter().map(|(dir, who)| (normalize(&dir), who)).collect::<Vec<_>>())
+                .map_err(|e| format!("{ACL_FILE}: {e}"));
+            match &parsed {
+                Ok(list) => tracing::info!("{ACL_FILE}: {} protected folders", list.len()),
+                Err(e) => tracing::error!("{e}; refusing every write until it's fixed"),
+            }
+            *loaded = Some((mtime, parsed.clone()));
+            parsed
+        }
+    }
+}
+
+/// Whether the storage root has an ACL file, for the startup log.
+pub(crate) fn present() -> bool {
+    StdPath::new(STORAGE_ROOT).join(ACL_FILE).exists()
+}
+
+fn allows(who: &[String], ident: &Identity) -> bool {
+    who.iter().any(|p| match p.strip_prefix("role:") {
+        Some(role) => role.parse::<Role>().is_ok_and(|r| ident.role >= r),
+        None => *p == ident.name,
+    })
+}
+
+fn inside(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path == dir || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether the caller may change `rel`, a path inside the working copy or a
+/// staging area ("" for all of it); 403 when they may not. Always fine outside
+/// an authenticated request.
+pub(crate) fn check(rel: &str) -> Result<(), StatusCode> {
+    match super::caller() {
+        Some(ident) => allowed(&ident, rel),
+        None => Ok(()),
+    }
+}
+
+fn allowed(ident: &Identity, rel: &str) -> Result<(), StatusCode> {
+    let entries = entries().map_err(|_| StatusCode::FORBIDDEN)?;
+    let rel = normalize(rel);
+    let governing = entries.iter().filter(|(dir, _)| inside(&rel, dir)).max_by_key(|(dir, _)| dir.len());
+    let below = entries.iter().filter(|(dir, _)| dir != &rel && inside(dir, &rel));
+    for (dir, who) in governing.into_iter().chain(below) {
+        if !allows(who, ident) {
+            tracing::warn!(caller = ident.name, path = rel, "{ACL_FILE} keeps {} out of {dir}/", ident.name);
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Sign-in through an OIDC identity provider: bearer tokens that are JWTs
+//! signed by `OIDC_ISSUER`.
+//!
+//! The provider's signing keys come from `OIDC_JWKS_URL`, or else from the
+//! `jwks_uri` of its discovery document
+//! (`<issuer>/.well-known/openid-configuration`). They're fetched on the first
+//! token and cached for an hour; a token signed with a key the cache doesn't
+//! know fetches them again early (at most once a minute), so rotating keys
+//! at the provider needs no restart.
+//!
+//! A token must carry a matching `iss`, an unexpired `exp` (a minute of clock
+//! skew allowed), `aud` naming `OIDC_AUDIENCE` when that's set, and be signed
+//! with an asymmetric algorithm; shared-secret (`HS*`) tokens are refused. The
+//! user is the `OIDC_USER_CLAIM` claim, by default the first of
+//! `preferred_username`, `email` and `sub` the token has. Its role is the
+//! highest of the roles its `OIDC_ROLE_CLAIM` claim names, or
+//! `OIDC_DEFAULT_ROLE` when it names none.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::Role;
+use crate::config::Config;
+
+/// How long fetched keys are trusted before they're fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+/// Least time between two fetches for a key the cache doesn't have.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Allowed clock skew on `exp` and `nbf`, in seconds.
+const LEEWAY: u64 = 60;
+/// Claims tried for the user's name, in order, without `OIDC_USER_CLAIM`.
+const USER_CLAIMS: &[&str] = &["preferred_username", "email", "sub"];
+
+#[derive(Default)]
+struct Cache {
+    keys: Option<JwkSet>,
+    fetched: Option<Instant>,
+    /// from the discovery document, once it's been read
+    jwks_url: Option<String>,
+}
+
+pub(crate) struct Oidc {
+    issuer: String,
+    audience: Option<String>,
+    user_claim: Option<String>,
+    role_claim: String,
+    default_role: Role,
+    client: reqwest::Client,
+    /// also makes concurrent requests wait for one fetch rather than each
+    /// starting their own
+    cache: Mutex<Cache>,
+}
+
+fn invalid(msg: String) -> Response {
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")], msg).into_response()
+}
+
+const UNAVAILABLE: &str = "can't check the token: the identity provider's keys are unavailable";
+
+fn unavailable(msg: String) -> Response {
+    tracing::error!("OIDC: {msg}");
+    (StatusCode::SERVICE_UNAVAILABLE, UNAVAILABLE).into_response()
+}
+
+impl Oidc {
+    pub(crate) fn from_config(cfg: &Config) -> Result<Option<Self>, String> {
+        let Some(issuer) = cfg.oidc_issuer.clone() else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build().map_err(|e| format!("OIDC client: {e}"))?;
+        Ok(Some(Self {
+            issuer,
+            audience: cfg.oidc_audience.clone(),
+            user_claim: cfg.oidc_user_claim.clone(),
+            role_claim: cfg.oidc_role_claim.clone(),
+            default_role: cfg.oidc_default_role,
+            client,
+            cache: Mutex::new(Cache { jwks_url: cfg.oidc_jwks_url.clone(), ..Cache::default() }),
+        }))
+    }
+
+    pub(crate) fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    async fn get_json(&self, url: &str) -> Result<Value, String> {
+        let resp = self.client.get(url).send().await.map_err(|e| format!("{url}: {e}"))?;
+        if !resp.status().is_success() {
+            return Err(format!("{url} answered {}", resp.status()));
+        }
+        let bytes = resp.bytes().await.map_err(|e| format!("{url}: {e}"))?;
+        serde_json::from_slice(&bytes).map_err(|e| format!("{url}: {e}"))
+    }
+
+    async fn refresh(&self, cache: &mut Cache) -> Result<(), String> {
+        // a failed fetch still counts, so a provider that's down isn't asked
+        // again on every request
+        cache.fetched = Some(Instant::now());
+        let url = match &cache.jwks_url {
+            Some(u) => u.clone(),
+            None => {
+                let discovery = format!("{}/.well-known/openid-configuration", self.issuer.trim_end_matches('/'));
+                let doc = self.get_json(&discovery).await?;
+                let url = doc["jwks_uri"].as_str().ok_or_else(|| format!("{discovery} has no jwks_uri"))?.to_string();
+                cache.jwks_url = Some(url.clone());
+                url
+            }
+        };
+        let keys: JwkSet = serde_json::from_value(self.get_json(&url).await?).map_err(|e| format!("{url}: {e}"))?;
+        tracing::info!("OIDC: fetched {} signing keys from {url}", keys.keys.len());
+        cache.keys = Some(keys);
+        Ok(())
+    }
+
+    /// The key `kid` names; a token without `kid` may use the only key there is.
+    async fn key(&self, kid: Option<&str>) -> Result<DecodingKey, Response> {
+        let mut cache = self.cache.lock().await;
+        // without any keys yet, retry like for an unknown key
+        let due = match cache.fetched {
+            None => true,
+            Some(t) => t.elapsed() >= if cache.keys.is_some() { JWKS_TTL } else { JWKS_MIN_REFRESH },
+        };
+        if due {
+            if let Err(e) = self.refresh(&mut cache).await {
+                match cache.keys {
+                    Some(_) => tracing::warn!("OIDC: keeping the cached keys: {e}"),
+                    None => return Err(unavailable(e)),
+                }
+            }
+        }
+        if cache.keys.is_none() {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, UNAVAILABLE).into_response());
+        }
+        let find = |set: &JwkSet| match kid {
+            Some(kid) => set.find(kid).cloned(),
+            None if set.keys.len() == 1 => set.keys.first().cloned(),
+            None => None,
+        };
+        let mut jwk = cache.keys.as_ref().and_then(find);
+        if jwk.is_none() && cache.fetched.is_none_or(|t| t.elapsed() >= JWKS_MIN_REFRESH) {
+            if let Err(e) = self.refresh(&mut cache).await {
+                tracing::warn!("OIDC: {e}");
+            }
+            jwk = cache.keys.as_ref().and_then(find);
+        }
+        let jwk = jwk.ok_or_else(|| invalid(format!("unknown signing key {}", kid.unwrap_or("(no kid)"))))?;
+        DecodingKey::from_jwk(&jwk).map_err(|e| invalid(format!("unusable signing key: {e}")))
+    }
+
+    /// Checks `token` and returns the user it's for, and their role.
+    pub(crate) async fn verify(&self, token: &str) -> Result<(String, Role), Response> {
+        let head = jsonwebtoken::decode_header(token).map_err(|e| invalid(format!("malformed token: {e}")))?;
+        if matches!(head.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(invalid(format!("{:?} tokens aren't accepted", head.alg)));
+        }
+        let key = self.key(head.kid.as_deref()).await?;
+        let mut validation = Validation::new(head.alg);
+        validation.leeway = LEEWAY;
+        validation.set_issuer(&[&self.issuer]);
+        validation.required_spec_claims.insert("iss".into());
+        match &self.audience {
+            Some(aud) => {
+                validation.set_audience(&[aud]);
+                validation.required_spec_claims.insert("aud".into());
+            }
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| invalid(format!("invalid token: {e}")))?
+            .claims;
+        let name = |k: &str| claims.get(k).and_then(Value::as_str).map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+        let user = match &self.user_claim {
+            Some(claim) => name(claim).ok_or_else(|| invalid(format!("token has no {claim} claim")))?,
+            None => USER_CLAIMS.iter().find_map(|c| name(c)).ok_or_else(|| invalid("token names no user".into()))?,
+        };
+        Ok((user, self.role(&claims)))
+    }
+
+    fn role(&self, claims: &Map<String, Value>) -> Role {
+        let mut parts = self.role_claim.split('.');
+        let first = parts.next().and_then(|k| claims.get(k));
+        let roles = parts.fold(first, |v, k| v.and_then(|v| v.get(k)));
+        let named = match roles {
+            Some(Value::String(s)) => s.split([' ', ',']).filter_map(|r| r.parse().ok()).max(),
+            Some(Value::Array(a)) => a.iter().filter_map(Value::as_str).filter_map(|r| r.parse().ok()).max(),
+            _ => None,
+        };
+        named.unwrap_or(self.default_role)
+    }
+}
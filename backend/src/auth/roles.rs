@@ -0,0 +1,128 @@
+//! Roles: what an authenticated caller may do.
+//!
+//! Viewers may read (any `GET`) and run things that change nothing:
+//! simulations, evaluation, searches, validation and lint, test runs. Editors
+//! may also change files, snapshots, revisions, branches and the like. Only
+//! admins may prune or delete revisions, reach `/api/admin/*`, read the audit
+//! log, rebuild the search index, clear the simulation cache, change eval
+//! aliases or set secrets.
+//!
+//! A key's role is written into its entry as `name:role=key`; a key without
+//! one is an admin, as every key was before roles. A token's role comes from
+//! its `OIDC_ROLE_CLAIM` claim (see [`oidc`](super::oidc)). Nothing is
+//! checked while the API is open.
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{fmt, str::FromStr};
+
+use super::Identity;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Role {
+    #[default]
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("unknown role {other:?} (viewer, editor or admin)")),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// `POST`s that only read, so viewers may make them.
+const VIEWER_POSTS: &[&str] = &[
+    "/api/simulate",
+    "/api/simulate/batch",
+    "/api/simulate/compare",
+    "/api/simulate/csv",
+    "/api/simulate/stream",
+    "/api/simulations/:id/replay",
+    "/api/eval/:alias",
+    "/api/eval/:alias/bulk",
+    "/api/search/jdm",
+    "/api/searches/:id/run",
+    "/api/validate",
+    "/api/lint",
+    "/api/expression/eval",
+    "/api/analyze/table",
+    "/api/tests/run",
+    "/api/revisions/:id/verify",
+    "/api/auth/login",
+    "/api/auth/logout",
+];
+
+/// Admin-only routes besides everything under `/api/admin/`.
+const ADMIN_ROUTES: &[(Method, &str)] = &[
+    (Method::POST, "/api/revisions/prune"),
+    (Method::DELETE, "/api/revisions/:id"),
+    (Method::POST, "/api/fs/index/rebuild"),
+    (Method::DELETE, "/api/simulate/cache"),
+    (Method::PUT, "/api/aliases/:name"),
+    (Method::DELETE, "/api/aliases/:name"),
+    (Method::GET, "/api/audit"),
+    (Method::GET, "/api/audit/verify"),
+    (Method::POST, "/api/secrets"),
+    (Method::DELETE, "/api/secrets/:name"),
+];
+
+/// The least role that may call `method` on the route `route` (as it's
+/// written in the router, e.g. `/api/revisions/:id`).
+pub(crate) fn required(method: &Method, route: &str) -> Role {
+    // axum answers HEAD with the GET handler
+    let method = if method == Method::HEAD { &Method::GET } else { method };
+    if route.starts_with("/api/admin/") || ADMIN_ROUTES.iter().any(|(m, r)| m == method && *r == route) {
+        return Role::Admin;
+    }
+    if method == Method::GET || (method == Method::POST && VIEWER_POSTS.contains(&route)) {
+        return Role::Viewer;
+    }
+    Role::Editor
+}
+
+// ===== middleware =============================================================
+
+/// Answers 403 to a caller whose role is below what the route needs. Sits
+/// inside [`require_auth`](super::require_auth), which puts the caller there.
+pub(crate) async fn require_role(req: Request, next: Next) -> Response {
+    let Some(ident) = req.extensions().get::<Identity>() else {
+        return next.run(req).await;
+    };
+    // unmatched paths are 404 anyway; judge them by the raw path
+    let route = req.extensions().get::<MatchedPath>().map_or(req.uri().path(), MatchedPath::as_str);
+    let needed = required(req.method(), route);
+    if ident.role < needed {
+        tracing::warn!(caller = ident.name, role = %ident.role, method = %req.method(), route, "rejected request needing the {needed} role");
+        return (StatusCode::FORBIDDEN, format!("this needs the {needed} role; {} is a {}", ident.name, ident.role)).into_response();
+    }
+    next.run(req).await
+}
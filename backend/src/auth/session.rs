@@ -0,0 +1,269 @@
+//! Session login for the bundled frontend: cookies instead of bearer tokens
+//! the browser would have to keep.
+//!
+//! `POST /api/auth/login` takes `{username, password}` of a user in
+//! `USERS_FILE` (see [`users`](super::users)), or `{token}` holding an API
+//! key or an OIDC token, and answers with the caller (as `/api/auth/me`
+//! does) and two cookies. `editor_session` is the signed session itself:
+//! http-only, so scripts can't read it, and good for `SESSION_TTL_SECS`
+//! (default eight hours). `XSRF-TOKEN` is readable, and every request the
+//! session makes other than `GET` and `HEAD` must echo it back in
+//! `X-XSRF-TOKEN`, which a page on another site can't do; axios does this on
+//! its own for same-origin requests. `POST /api/auth/logout` ends the session,
+//! `GET /api/auth/me` says who's signed in.
+//!
+//! Sessions are signed with `SESSION_SECRET`, so any instance given the same
+//! secret accepts them; without one a random secret is made at startup and
+//! restarting signs everyone out. Logging out only revokes the session on the
+//! instance that got the request; on others it lasts until it expires.
+//! Cookies are `Secure` unless `SESSION_COOKIE_INSECURE` is set, for trying
+//! things out over plain HTTP.
+
+use axum::{
+    extract::{Extension, Json, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{hmac, rand::SecureRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{Identity, Via};
+use crate::{config::Config, AppState};
+
+pub(crate) const SESSION_COOKIE: &str = "editor_session";
+const CSRF_COOKIE: &str = "XSRF-TOKEN";
+const CSRF_HEADER: &str = "x-xsrf-token";
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    ring::rand::SystemRandom::new().fill(&mut bytes).expect("system randomness");
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// What the session cookie carries, signed.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Claims {
+    sub: String,
+    role: String,
+    via: String,
+    /// unix seconds
+    exp: i64,
+    /// the session's id, for logging out
+    sid: String,
+    /// what `X-XSRF-TOKEN` must say
+    csrf: String,
+}
+
+impl Claims {
+    pub(crate) fn identity(&self) -> Option<Identity> {
+        let via = match self.via.as_str() {
+            "api-key" => Via::ApiKey,
+            "oidc" => Via::Oidc,
+            "password" => Via::Password,
+            _ => return None,
+        };
+        Some(Identity { name: self.sub.clone(), via, role: self.role.parse().ok()? })
+    }
+
+    /// Whether a request with `method` and `headers` may act on this
+    /// session: reads always, anything else with the CSRF token.
+    pub(crate) fn allows(&self, method: &Method, headers: &HeaderMap) -> bool {
+        if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+            return true;
+        }
+        let sent = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok()).unwrap_or_default();
+        ring::constant_time::verify_slices_are_equal(sent.as_bytes(), self.csrf.as_bytes()).is_ok()
+    }
+}
+
+pub(crate) struct Sessions {
+    key: hmac::Key,
+    ttl: Duration,
+    secure: bool,
+    /// sid → when it would have expired, for sessions logged out early
+    revoked: Mutex<HashMap<String, i64>>,
+}
+
+impl Sessions {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        let secret = match &cfg.session_secret {
+            Some(s) => s.clone(),
+            None => random_hex(32),
+        };
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            ttl: cfg.session_ttl,
+            secure: !cfg.session_cookie_insecure,
+            revoked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn sign(&self, claims: &Claims) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("serialize session"));
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+        format!("{payload}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    /// The claims of a session cookie that's genuine, unexpired and not
+    /// logged out.
+    pub(crate) fn verify(&self, cookie: &str) -> Option<Claims> {
+        let (payload, tag) = cookie.split_once('.')?;
+        hmac::verify(&self.key, payload.as_bytes(), &URL_SAFE_NO_PAD.decode(tag).ok()?).ok()?;
+        let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (claims.exp > now_secs() && !self.revoked.lock().unwrap().contains_key(&claims.sid)).then_some(claims)
+    }
+
+    fn revoke(&self, claims: &Claims) {
+        let now = now_secs();
+        let mut revoked = self.revoked.lock().unwrap();
+        revoked.retain(|_, exp| *exp > now);
+        revoked.insert(claims.sid.clone(), claims.exp);
+    }
+
+    fn start(&self, ident: &Identity) -> Claims {
+        Claims {
+            sub: ident.name.clone(),
+            role: ident.role.name().to_string(),
+            via: ident.via.name().to_string(),
+            exp: now_secs() + self.ttl.as_secs() as i64,
+            sid: random_hex(16),
+            csrf: random_hex(16),
+        }
+    }
+
+    fn cookies(&self, session: Option<&str>, csrf: Option<&str>) -> AppendHeaders<[(header::HeaderName, HeaderValue); 2]> {
+        let secure = if self.secure { "; Secure" } else { "" };
+        // no value clears the cookie
+        let max_age = if session.is_some() { self.ttl.as_secs() } else { 0 };
+        let cookie = |v: String| (header::SET_COOKIE, HeaderValue::from_str(&v).expect("cookie header"));
+        AppendHeaders([
+            cookie(format!("{SESSION_COOKIE}={}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}", session.unwrap_or_default())),
+            cookie(format!("{CSRF_COOKIE}={}; Path=/; Max-Age={max_age}; SameSite=Lax{secure}", csrf.unwrap_or_default())),
+        ])
+    }
+}
+
+/// The value of the cookie `name` in `headers`.
+pub(crate) fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| c.trim().strip_prefix(name)?.strip_prefix('='))
+        .filter(|v| !v.is_empty())
+}
+
+// ===== POST /api/auth/login, POST /api/auth/logout, GET /api/auth/me =========
+
+#[derive(Deserialize)]
+pub(crate) struct LoginReq {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Me {
+    auth_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    via: Option<&'static str>,
+    /// unix seconds; only for sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+    /// only for sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csrf_token: Option<String>,
+}
+
+impl Me {
+    fn of(ident: &Identity, session: Option<&Claims>) -> Self {
+        Me {
+            auth_required: true,
+            user: Some(ident.name.clone()),
+            role: Some(ident.role.name()),
+            via: Some(ident.via.name()),
+            expires_at: session.map(|c| c.exp),
+            csrf_token: session.map(|c| c.csrf.clone()),
+        }
+    }
+}
+
+fn wrong_login() -> Response {
+    (StatusCode::UNAUTHORIZED, "wrong user name or password").into_response()
+}
+
+pub(crate) async fn login(State(st): State<AppState>, Json(req): Json<LoginReq>) -> Response {
+    if !st.auth.enabled() {
+        return (StatusCode::CONFLICT, "the API is open; there's nothing to sign in to").into_response();
+    }
+    let ident = match (req.username, req.password, req.token) {
+        (Some(user), Some(password), None) => {
+            let auth = st.auth.clone();
+            let name = user.trim().to_string();
+            let checked = {
+                let name = name.clone();
+                tokio::task::spawn_blocking(move || auth.users.check(&name, &password)).await
+            };
+            match checked {
+                Ok(Some(role)) => Identity { name, via: Via::Password, role },
+                Ok(None) => {
+                    tracing::warn!(user = name, "failed sign-in");
+                    return wrong_login();
+                }
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        (None, None, Some(token)) => match st.auth.identify(token.trim()).await {
+            Ok(ident) => ident,
+            Err(resp) => {
+                tracing::warn!(status = resp.status().as_u16(), "failed sign-in with a token");
+                return resp;
+            }
+        },
+        _ => return (StatusCode::BAD_REQUEST, "expected {username, password} or {token}").into_response(),
+    };
+    let claims = st.auth.sessions.start(&ident);
+    tracing::info!(caller = ident.name, via = ident.via.name(), role = ident.role.name(), "signed in");
+    let cookies = st.auth.sessions.cookies(Some(&st.auth.sessions.sign(&claims)), Some(&claims.csrf));
+    (cookies, Json(Me::of(&ident, Some(&claims)))).into_response()
+}
+
+pub(crate) async fn logout(State(st): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(claims) = cookie(&headers, SESSION_COOKIE).and_then(|c| st.auth.sessions.verify(c)) {
+        st.auth.sessions.revoke(&claims);
+        tracing::info!(caller = claims.sub, "signed out");
+    }
+    (StatusCode::NO_CONTENT, st.auth.sessions.cookies(None, None)).into_response()
+}
+
+pub(crate) async fn me(State(st): State<AppState>, ident: Option<Extension<Identity>>, headers: HeaderMap) -> Response {
+    let Some(Extension(ident)) = ident else {
+        return Json(Me { auth_required: false, user: None, role: None, via: None, expires_at: None, csrf_token: None }).into_response();
+    };
+    // a bearer token wins over the cookie in `require_auth`; only report the
+    // session when it's what this request used
+    let session = match (super::bearer_header(&headers), cookie(&headers, SESSION_COOKIE)) {
+        (None, Some(c)) => st.auth.sessions.verify(c),
+        _ => None,
+    };
+    Json(Me::of(&ident, session.as_ref())).into_response()
+}
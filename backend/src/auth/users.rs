@@ -0,0 +1,124 @@
+//! Users who sign in with a name and a password, for the session login (see
+//! [`session`](super::session)).
+//!
+//! They're listed in the file `USERS_FILE` names, one per line (`#` starts a
+//! comment), as `name=hash` or `name:role=hash`, where the hash is what
+//! `editor hash-password` prints for the password it reads on stdin:
+//! `pbkdf2-sha256:<iterations>:<salt>:<digest>`, salt and digest in hex. A
+//! user without a role is a viewer. The file is read at startup.
+
+use ring::{digest, pbkdf2, rand::SecureRandom};
+use std::{collections::HashMap, fs, num::NonZeroU32};
+
+use super::Role;
+use crate::config::Config;
+
+const ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+const SCHEME: &str = "pbkdf2-sha256";
+/// What new hashes are made with.
+const ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+struct Hash {
+    iterations: NonZeroU32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+}
+
+impl Hash {
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(':');
+        if parts.next()? != SCHEME {
+            return None;
+        }
+        let iterations = parts.next()?.parse().ok()?;
+        let salt = from_hex(parts.next()?)?;
+        let digest = from_hex(parts.next()?)?;
+        (parts.next().is_none() && digest.len() == digest::SHA256_OUTPUT_LEN).then_some(Self { iterations, salt, digest })
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        pbkdf2::verify(ALG, self.iterations, &self.salt, password.as_bytes(), &self.digest).is_ok()
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The `USERS_FILE` line's hash for `password`, with a fresh salt.
+pub(crate) fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; SALT_LEN];
+    ring::rand::SystemRandom::new().fill(&mut salt).expect("system randomness");
+    let mut out = [0u8; digest::SHA256_OUTPUT_LEN];
+    let iterations = NonZeroU32::new(ITERATIONS).unwrap();
+    pbkdf2::derive(ALG, iterations, &salt, password.as_bytes(), &mut out);
+    format!("{SCHEME}:{ITERATIONS}:{}:{}", to_hex(&salt), to_hex(&out))
+}
+
+#[derive(Default)]
+pub(crate) struct Users {
+    users: HashMap<String, (Hash, Role)>,
+    /// checked against for unknown names, so they take as long as wrong
+    /// passwords
+    decoy: Option<Hash>,
+}
+
+impl Users {
+    pub(crate) fn load(cfg: &Config) -> Result<Self, String> {
+        let mut users = Users::default();
+        let Some(path) = &cfg.users_file else {
+            return Ok(users);
+        };
+        let text = fs::read_to_string(path).map_err(|e| format!("USERS_FILE {}: {e}", path.display()))?;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if !line.is_empty() {
+                users.add(line).map_err(|e| format!("USERS_FILE {} line {}: {e}", path.display(), n + 1))?;
+            }
+        }
+        users.decoy = Hash::parse(&hash_password(""));
+        Ok(users)
+    }
+
+    fn add(&mut self, entry: &str) -> Result<(), String> {
+        let (name, hash) = entry.split_once('=').ok_or("expected name=hash")?;
+        let (name, hash) = (name.trim(), hash.trim());
+        let (name, role) = match name.split_once(':') {
+            Some((name, role)) => (name.trim(), role.parse::<Role>().map_err(|e| format!("{}: {e}", name.trim()))?),
+            None => (name, Role::Viewer),
+        };
+        if name.is_empty() {
+            return Err("expected name=hash".into());
+        }
+        let hash = Hash::parse(hash).ok_or_else(|| format!("{name}: expected a hash from `editor hash-password`"))?;
+        if self.users.insert(name.to_string(), (hash, role)).is_some() {
+            return Err(format!("{name} is given twice"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    /// `name`'s role, when `password` is theirs. Slow on purpose.
+    pub(crate) fn check(&self, name: &str, password: &str) -> Option<Role> {
+        match self.users.get(name) {
+            Some((hash, role)) => hash.verify(password).then_some(*role),
+            None => {
+                if let Some(decoy) = &self.decoy {
+                    decoy.verify(password);
+                }
+                None
+            }
+        }
+    }
+}
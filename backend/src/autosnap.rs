@@ -0,0 +1,271 @@
+//! Scheduled automatic snapshots (`AUTO_SNAPSHOT_*`, see
+//! [`Config`](crate::config::Config)).
+//!
+//! A background task snapshots the working copy every
+//! `AUTO_SNAPSHOT_INTERVAL_SECS`, or whenever `AUTO_SNAPSHOT_CRON` matches,
+//! but only if it changed since the current branch's head: an idle workspace
+//! doesn't pile up identical revisions. Automatic snapshots carry
+//! `"auto": true` in their manifest, which retention can collect separately
+//! (`RETENTION_KEEP_AUTO`). `/api/admin/auto-snapshot` reports the schedule
+//! and the last run.
+
+use axum::{
+    extract::{Json, State},
+    response::IntoResponse,
+};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{audit, branches, events::ServerEvent, freeze, revisions, AppState};
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+// ===== cron ==================================================================
+
+/// A five-field cron expression (`minute hour day-of-month month
+/// day-of-week`), evaluated in UTC. Fields take `*`, numbers, `a-b` ranges,
+/// `/n` steps and comma lists; day-of-week runs 0–7 with both 0 and 7 Sunday.
+/// As in classic cron, when both day fields are restricted either may match.
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Bitset of the values `field` allows within `lo..=hi`.
+fn cron_field(field: &str, lo: u32, hi: u32) -> Option<u64> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>().ok().filter(|&s| s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (lo, hi),
+            r => match r.split_once('-') {
+                Some((a, b)) => (a.parse().ok()?, b.parse().ok()?),
+                // `5/15` means from 5 to the end
+                None if step > 1 => (r.parse().ok()?, hi),
+                None => {
+                    let v = r.parse().ok()?;
+                    (v, v)
+                }
+            },
+        };
+        if start < lo || end > hi || start > end {
+            return None;
+        }
+        for v in (start..=end).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Some(set)
+}
+
+impl Cron {
+    fn parse(expr: &str) -> Option<Self> {
+        let f: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = f[..] else { return None };
+        let mut weekdays = cron_field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Some(Self {
+            minutes: cron_field(minute, 0, 59)?,
+            hours: cron_field(hour, 0, 23)?,
+            days: cron_field(day, 1, 31)?,
+            months: cron_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    fn matches(&self, t: i64) -> bool {
+        let (days, secs) = (t.div_euclid(86_400), t.rem_euclid(86_400));
+        let (_, month, day) = civil_from_days(days);
+        let weekday = (days + 4).rem_euclid(7); // 1970-01-01 was a Thursday
+        let bit = |set: u64, v: i64| set & (1 << v) != 0;
+        let day_ok = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => bit(self.days, day),
+            (true, false) => bit(self.weekdays, weekday),
+            (false, false) => bit(self.days, day) || bit(self.weekdays, weekday),
+        };
+        bit(self.minutes, secs / 60 % 60) && bit(self.hours, secs / 3600) && bit(self.months, month) && day_ok
+    }
+
+    /// The first matching minute after `t`, looking up to about four years
+    /// ahead (enough for `0 0 29 2 *`).
+    fn next_after(&self, t: i64) -> Option<i64> {
+        let start = t.div_euclid(60) * 60 + 60;
+        (0..4 * 366 * 24 * 60).map(|m| start + m * 60).find(|&t| self.matches(t))
+    }
+}
+
+/// (year, month 1–12, day 1–31) of a day count since the epoch; Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+// ===== scheduler =============================================================
+
+enum Schedule {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    fn from_config(st: &AppState) -> Option<Self> {
+        if let Some(expr) = &st.config.auto_snapshot_cron {
+            match Cron::parse(expr) {
+                Some(c) => return Some(Self::Cron(c)),
+                None => tracing::warn!("ignoring invalid AUTO_SNAPSHOT_CRON={expr:?}"),
+            }
+        }
+        st.config.auto_snapshot_interval.map(Self::Every)
+    }
+
+    fn next_after(&self, t: i64) -> Option<i64> {
+        match self {
+            Self::Every(d) => Some(t + d.as_secs() as i64),
+            Self::Cron(c) => c.next_after(t),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LastRun {
+    /// unix seconds
+    at: i64,
+    /// the revision made, absent when nothing had changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct AutoState {
+    enabled: bool,
+    last_run: Option<LastRun>,
+    next_run_at: Option<i64>,
+}
+
+/// Shared view of the auto-snapshot task, for the admin endpoint.
+#[derive(Clone, Default)]
+pub(crate) struct AutoSnap(Arc<Mutex<AutoState>>);
+
+/// Whether the working copy differs from the head of its branch. Blocking.
+fn changed(st: &AppState) -> std::io::Result<bool> {
+    let follow = st.config.follow_symlinks;
+    let current = revisions::rev_hashes(0, follow)?;
+    match branches::heads().get(&branches::current()).copied() {
+        Some(head) if head > 0 => Ok(current != revisions::rev_hashes(head, follow)?),
+        _ => Ok(!current.is_empty()),
+    }
+}
+
+/// One scheduled pass. Blocking.
+fn run_once(st: &AppState) -> LastRun {
+    let at = now_secs();
+    let res = changed(st).and_then(|changed| {
+        if !changed {
+            return Ok(None);
+        }
+        let meta = revisions::RevMeta { message: Some("automatic snapshot".to_string()), auto: true, ..Default::default() };
+        revisions::snapshot_working_copy(&st.config, &st.rev_lock, meta).map(|m| Some(m.id))
+    });
+    match res {
+        Ok(snapshot) => {
+            if let Some(id) = snapshot {
+                tracing::info!("automatic snapshot: revision {id}");
+                audit::append(audit::Event::new("snapshot").rev(id));
+            }
+            LastRun { at, snapshot, error: None }
+        }
+        Err(e) => {
+            tracing::error!("automatic snapshot failed: {e}");
+            LastRun { at, snapshot: None, error: Some(e.to_string()) }
+        }
+    }
+}
+
+/// Starts the scheduler if `AUTO_SNAPSHOT_INTERVAL_SECS` or
+/// `AUTO_SNAPSHOT_CRON` is set.
+pub(crate) fn spawn(st: AppState) {
+    let Some(schedule) = Schedule::from_config(&st) else { return };
+    st.autosnap.0.lock().unwrap().enabled = true;
+    match &schedule {
+        Schedule::Every(d) => tracing::info!("automatic snapshots every {}s", d.as_secs()),
+        Schedule::Cron(_) => tracing::info!("automatic snapshots on {:?} (UTC)", st.config.auto_snapshot_cron.as_deref().unwrap_or("")),
+    }
+    tokio::spawn(async move {
+        loop {
+            let now = now_secs();
+            let Some(next) = schedule.next_after(now) else {
+                tracing::warn!("AUTO_SNAPSHOT_CRON never matches; automatic snapshots stopped");
+                return;
+            };
+            st.autosnap.0.lock().unwrap().next_run_at = Some(next);
+            tokio::time::sleep(Duration::from_secs((next - now).max(0) as u64)).await;
+            if freeze::frozen(&st.config).is_some() {
+                tracing::info!("automatic snapshot skipped: read-only mode");
+                continue;
+            }
+            let state = st.clone();
+            let Ok(run) = tokio::task::spawn_blocking(move || run_once(&state)).await else { continue };
+            if let Some(id) = run.snapshot {
+                st.index.touch(&id.to_string());
+                st.events.publish(ServerEvent::SnapshotCreated { id });
+            }
+            st.autosnap.0.lock().unwrap().last_run = Some(run);
+        }
+    });
+}
+
+// ===== GET /api/admin/auto-snapshot ==========================================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoStatus {
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cron: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_run_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_run: Option<LastRun>,
+}
+
+pub(crate) async fn status(State(st): State<AppState>) -> impl IntoResponse {
+    let auto = st.autosnap.0.lock().unwrap();
+    Json(AutoStatus {
+        enabled: auto.enabled,
+        interval_secs: st.config.auto_snapshot_interval.map(|d| d.as_secs()),
+        cron: st.config.auto_snapshot_cron.clone(),
+        next_run_at: auto.next_run_at,
+        last_run: auto.last_run.clone(),
+    })
+}
@@ -0,0 +1,293 @@
+//! Named lines of revisions (`draft`, `production`), each with its own head.
+//!
+//! Revision numbers stay global, and `HEAD` is still the newest revision on
+//! any branch. A branch is a name for the revision it's at; every new revision
+//! lands on one (the working copy's, unless the request names another) and
+//! moves it forward. Heads, and which branch the working copy is on, live in
+//! `./decisions/.revisions/branches.json`. Without that file there is only
+//! `main`, whose head is `HEAD`: storage from before branches works unchanged.
+//!
+//! Branch names share a namespace with tags, so `rev=draft` resolves like a tag.
+
+use axum::{
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path as StdPath};
+
+use crate::{
+    audit, auth,
+    events::ServerEvent,
+    gitmirror,
+    read_head,
+    revisions::{self, Manifest, RevMeta, RevRef},
+    storelock::StorageLock,
+    write_atomic, AppState, WriteOpts,
+};
+
+const BRANCHES_FILE: &str = "./decisions/.revisions/branches.json";
+pub(crate) const DEFAULT_BRANCH: &str = "main";
+
+static STORE: StorageLock = StorageLock::new("branches");
+
+#[derive(Serialize, Deserialize)]
+struct Branches {
+    /// the branch the working copy is on
+    current: String,
+    /// branch → head revision
+    heads: BTreeMap<String, u64>,
+}
+
+fn load() -> Branches {
+    fs::read(BRANCHES_FILE)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .unwrap_or_else(|| Branches {
+            current: DEFAULT_BRANCH.to_string(),
+            heads: BTreeMap::from([(DEFAULT_BRANCH.to_string(), read_head())]),
+        })
+}
+
+/// Caller holds `STORE`.
+fn save(b: &Branches) -> io::Result<()> {
+    let bytes = serde_json::to_vec_pretty(b).expect("serialize branches");
+    write_atomic(StdPath::new(BRANCHES_FILE), &bytes, WriteOpts::default())
+}
+
+fn internal(e: io::Error) -> Response {
+    tracing::error!("branches write error: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// branch → head revision
+pub(crate) fn heads() -> BTreeMap<String, u64> {
+    load().heads
+}
+
+/// The branch the working copy is on.
+pub(crate) fn current() -> String {
+    load().current
+}
+
+/// 404 unless `name` is a branch.
+pub(crate) fn check(name: &str) -> Result<(), Response> {
+    if !heads().contains_key(name) {
+        return Err((StatusCode::NOT_FOUND, format!("no such branch: {name}")).into_response());
+    }
+    Ok(())
+}
+
+/// Where a new revision went.
+pub(crate) struct Placed {
+    pub branch: String,
+    /// the branch's head before it, 0 for its first revision
+    pub parent: u64,
+}
+
+/// Moves `branch` (the working copy's if `None`) to the just-made revision
+/// `rev`. A branch deleted in the meantime falls back to the current one.
+pub(crate) fn advance(branch: Option<&str>, rev: u64) -> io::Result<Placed> {
+    let _g = STORE.lock();
+    let mut all = load();
+    let name = branch.filter(|b| all.heads.contains_key(*b)).unwrap_or(&all.current).to_string();
+    // HEAD has already moved on to `rev` itself; an implicit main was one behind
+    let parent = match all.heads.get(&name) {
+        Some(&h) if h == rev => rev.saturating_sub(1),
+        Some(&h) => h,
+        None => 0,
+    };
+    all.heads.insert(name.clone(), rev);
+    save(&all)?;
+    Ok(Placed { branch: name, parent })
+}
+
+// ===== GET/POST /api/branches ================================================
+
+#[derive(Serialize)]
+struct BranchInfo {
+    name: String,
+    head: u64,
+    current: bool,
+}
+
+#[derive(Serialize)]
+struct ListResp {
+    current: String,
+    branches: Vec<BranchInfo>,
+}
+
+pub(crate) async fn list() -> impl IntoResponse {
+    let all = load();
+    let branches = all
+        .heads
+        .into_iter()
+        .map(|(name, head)| BranchInfo { current: name == all.current, name, head })
+        .collect();
+    Json(ListResp { current: all.current, branches })
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreateReq {
+    name: String,
+    /// revision to start from; the current branch's head by default
+    #[serde(default)]
+    from: Option<RevRef>,
+}
+
+/// 201 with the new branch; 409 if the name is taken by a branch or a tag.
+pub(crate) async fn create(State(st): State<AppState>, Json(body): Json<CreateReq>) -> Response {
+    let name = body.name.trim().to_string();
+    if !revisions::valid_tag(&name) {
+        return (StatusCode::BAD_REQUEST, "branch name must be [A-Za-z0-9._-]+ and not a number").into_response();
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<BranchInfo, Response> {
+        let _rev = st.rev_lock.lock();
+        let _g = STORE.lock();
+        let mut all = load();
+        if all.heads.contains_key(&name) || revisions::tags().contains_key(&name) {
+            return Err((StatusCode::CONFLICT, format!("{name:?} is already a branch or tag")).into_response());
+        }
+        let head = match &body.from {
+            Some(r) => r.resolve()?,
+            None => all.heads.get(&all.current).copied().unwrap_or(0),
+        };
+        if head == 0 {
+            return Err((StatusCode::BAD_REQUEST, "a branch starts from a revision, not the working copy").into_response());
+        }
+        all.heads.insert(name.clone(), head);
+        save(&all).map_err(internal)?;
+        gitmirror::update_mirror(&st.config);
+        Ok(BranchInfo { name, head, current: false })
+    })
+    .await;
+    match res {
+        Ok(Ok(info)) => (StatusCode::CREATED, Json(info)).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// `DELETE /api/branches/:name`: forgets the name; its revisions stay. The
+/// default branch and the working copy's can't be deleted.
+pub(crate) async fn delete(State(st): State<AppState>, Path(name): Path<String>) -> Response {
+    let res = tokio::task::spawn_blocking(move || {
+        let _g = STORE.lock();
+        let mut all = load();
+        if name == DEFAULT_BRANCH || name == all.current {
+            return Err((StatusCode::CONFLICT, "can't delete the default or the current branch").into_response());
+        }
+        if all.heads.remove(&name).is_none() {
+            return Err((StatusCode::NOT_FOUND, "no such branch").into_response());
+        }
+        save(&all).map_err(internal)?;
+        gitmirror::update_mirror(&st.config);
+        Ok(())
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/branches/:name/checkout =====================================
+
+#[derive(Deserialize, Default)]
+pub(crate) struct CheckoutReq {
+    /// lock owner; leases held by anyone else under `0/` block the switch
+    #[serde(default)]
+    owner: Option<String>,
+    /// recorded on the safety snapshot, if one is taken
+    #[serde(default)]
+    author: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckoutResp {
+    branch: String,
+    head: u64,
+    /// unsaved work, snapshotted onto the branch being left
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_snapshot: Option<Manifest>,
+}
+
+/// Switches the working copy to branch `name`: it becomes a copy of that
+/// branch's head, and later snapshots land on it. Changes not yet snapshotted
+/// are first saved onto the branch being left.
+pub(crate) async fn checkout(
+    State(st): State<AppState>,
+    Path(name): Path<String>,
+    ident: Option<Extension<auth::Identity>>,
+    body: Option<Json<CheckoutReq>>,
+) -> Response {
+    let mut body = body.map(|Json(b)| b).unwrap_or_default();
+    body.author = auth::acting(&ident, body.author);
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check_tree("0", body.owner.as_deref()) {
+        return resp;
+    }
+    if let Err(e) = auth::acl::check("") {
+        return e.into_response();
+    }
+    let author = body.author.clone();
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<CheckoutResp, Response> {
+        // held through the swap and the `current` update, so no write lands
+        // between the snapshot and the switch
+        let _rev = state.rev_lock.lock();
+        let all = load();
+        let Some(&head) = all.heads.get(&name) else {
+            return Err((StatusCode::NOT_FOUND, format!("no such branch: {name}")).into_response());
+        };
+        if head == 0 {
+            return Err((StatusCode::CONFLICT, format!("branch {name} has no revisions yet")).into_response());
+        }
+        let internal = |e: io::Error| {
+            tracing::error!("checkout of branch {name} failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        };
+        let follow = state.config.follow_symlinks;
+        let left = all.heads.get(&all.current).copied().unwrap_or(0);
+        let dirty = left == 0 || revisions::rev_hashes(0, follow).map_err(internal)? != revisions::rev_hashes(left, follow).map_err(internal)?;
+        let safety = if dirty && !revisions::rev_files(0, follow).is_empty() {
+            let meta = RevMeta {
+                message: Some(format!("before switching to branch {name}")),
+                author: body.author,
+                branch: Some(all.current.clone()),
+                ..Default::default()
+            };
+            Some(revisions::snapshot_locked(&state.config, meta).map_err(internal)?)
+        } else {
+            None
+        };
+        revisions::replace_working_copy(&state.config, head).map_err(internal)?;
+        let _g = STORE.lock();
+        let mut all = load();
+        all.current = name.clone();
+        save(&all).map_err(internal)?;
+        gitmirror::update_mirror(&state.config);
+        Ok(CheckoutResp { branch: name, head, safety_snapshot: safety })
+    })
+    .await;
+
+    match res {
+        Ok(Ok(resp)) => {
+            if let Some(safety) = &resp.safety_snapshot {
+                st.index.touch(&safety.id.to_string());
+                st.events.publish(ServerEvent::SnapshotCreated { id: safety.id });
+                audit::record(audit::Event::new("snapshot").rev(safety.id).by(author.clone())).await;
+            }
+            audit::record(audit::Event::new("checkout").rev(resp.head).by(author)).await;
+            st.index.touch("0");
+            st.engines.invalidate("0");
+            st.events.publish(ServerEvent::BranchCheckedOut { name: resp.branch.clone(), head: resp.head });
+            Json(resp).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,254 @@
+//! Runtime configuration, read once from the environment at startup.
+
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::auth::Role;
+
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    /// `QUOTA_MAX_BYTES`: total bytes allowed under the storage root
+    pub quota_max_bytes: Option<u64>,
+    /// `QUOTA_MAX_FILES`: total number of files allowed under the storage root
+    pub quota_max_files: Option<u64>,
+    /// `FOLLOW_SYMLINKS`: let reads, copies and searches follow symlinks that
+    /// resolve inside the storage root (writes through symlinks are always refused)
+    pub follow_symlinks: bool,
+    /// `SEARCH_THREADS`: worker threads per content search; defaults to half
+    /// the cores (at most 8) so a search leaves room for other requests
+    pub search_threads: usize,
+    /// `SEARCH_TIMEOUT_MS`: wall-clock budget of one search, after which it
+    /// answers with what it found so far (default 10 s)
+    pub search_timeout: Duration,
+    /// `RETENTION_KEEP_LAST`: automatic GC keeps this many of the newest revisions
+    pub retention_keep_last: Option<usize>,
+    /// `RETENTION_DAILY_DAYS`: automatic GC also keeps the newest revision of
+    /// each of the last this-many days (UTC)
+    pub retention_daily_days: Option<u64>,
+    /// `RETENTION_INTERVAL_SECS`: time between GC runs (default 1 h); GC only
+    /// runs when one of the two policies above is set
+    pub retention_interval: Duration,
+    /// `RETENTION_KEEP_AUTO`: keep only this many of the newest automatic
+    /// snapshots, whatever the policies above would keep
+    pub retention_keep_auto: Option<usize>,
+    /// `READ_ONLY_REVISIONS`: also drop write permission on every directory of
+    /// a revision once it's made, so nothing outside the API adds, removes or
+    /// renames files in it either
+    pub read_only_revisions: bool,
+    /// `SNAPSHOT_HARDLINKS`: snapshot by hardlinking working-copy files rather
+    /// than cloning or copying them; only safe when nothing outside the API
+    /// rewrites those files in place
+    pub snapshot_hardlinks: bool,
+    /// `AUTO_SNAPSHOT_INTERVAL_SECS`: snapshot the working copy this often,
+    /// when it changed
+    pub auto_snapshot_interval: Option<Duration>,
+    /// `AUTO_SNAPSHOT_CRON`: or on a five-field cron schedule (UTC); wins over
+    /// the interval
+    pub auto_snapshot_cron: Option<String>,
+    /// `FILE_HISTORY`: keep the previous content of a file on every save or
+    /// patch, under `.history/`
+    pub file_history: bool,
+    /// `FILE_HISTORY_KEEP`: versions kept per file (default 50)
+    pub file_history_keep: usize,
+    /// `GIT_MIRROR_DIR`: keep a bare git repository of every revision there
+    pub git_mirror_dir: Option<PathBuf>,
+    /// `IMPORT_ALLOWED_HOSTS`: comma-separated hosts `/api/revisions/from-url`
+    /// may download from; `.example.com` allows its subdomains. Empty: off
+    pub import_allowed_hosts: Vec<String>,
+    /// `IMPORT_MAX_BYTES`: cap on a downloaded archive (default 64 MiB)
+    pub import_max_bytes: u64,
+    /// `REQUIRED_APPROVALS`: approvals a revision in review needs before it
+    /// can be published (default 1)
+    pub required_approvals: usize,
+    /// `STAGING_TTL_SECS`: staging areas nobody has filled for this long are
+    /// dropped (default a day)
+    pub staging_ttl: Duration,
+    /// `SIMULATE_MAX_DEPTH`: deepest nesting of decision nodes a simulation
+    /// may ask for, and the depth it gets by default (default 50)
+    pub simulate_max_depth: u8,
+    /// `SIMULATE_MAX_TIMEOUT_MS`: longest `timeoutMs` a simulation may ask for
+    /// (default 60 s)
+    pub simulate_max_timeout: Duration,
+    /// `SIMULATE_TIMEOUT_MS`: how long a simulation that doesn't say runs
+    /// before it's given up on (default 10 s, never above the max)
+    pub simulate_timeout: Duration,
+    /// `VALIDATE_ON_SAVE_DIRS`: comma-separated working-copy folders (e.g.
+    /// `decisions`) whose `*.json` files must pass decision validation to be
+    /// saved or patched, whether or not the save asks for it
+    pub validate_dirs: Vec<String>,
+    /// `EXPRESSION_TIMEOUT_MS`: how long `/api/expression/eval` waits for an
+    /// expression (default 1 s)
+    pub expression_timeout: Duration,
+    /// `SIMULATE_CACHE_ENTRIES`: simulation results kept for identical
+    /// requests (default 1000; 0 turns the cache off)
+    pub simulate_cache_entries: usize,
+    /// `SIMULATION_HISTORY_KEEP`: simulations logged for
+    /// `/api/simulations` and replay (default 100; 0 turns the log off)
+    pub simulation_history_keep: usize,
+    /// `EVAL_RATE_LIMIT`: requests per minute each `/api/eval` alias takes
+    /// unless it sets its own (default 600; 0 is unlimited)
+    pub eval_rate_limit: u32,
+    /// `RATE_LIMIT_CHEAP`: requests per minute each client may make to
+    /// routes that aren't expensive or eval (default 1200; 0 is unlimited)
+    pub rate_limit_cheap: u32,
+    /// `RATE_LIMIT_EXPENSIVE`: … to searches, simulations, snapshots and the
+    /// like (default 120; see [`ratelimit`](crate::ratelimit))
+    pub rate_limit_expensive: u32,
+    /// `RATE_LIMIT_EVAL`: … to `/api/eval`, on top of each alias's own
+    /// budget (default 600)
+    pub rate_limit_eval: u32,
+    /// `RATE_LIMIT_TRUST_PROXY`: tell clients without a key apart by the
+    /// first `X-Forwarded-For` address rather than the peer's; only behind a
+    /// proxy that sets it
+    pub rate_limit_trust_proxy: bool,
+    /// `READ_ONLY`: start with editing frozen, and keep it so; see
+    /// [`freeze`](crate::freeze)
+    pub read_only: bool,
+    /// `READ_ONLY_REASON`: what the refused requests are told while
+    /// `READ_ONLY` is set
+    pub read_only_reason: Option<String>,
+    /// `API_KEYS`: comma-separated `name=key` API keys; with none here or in
+    /// the file below, `/api` needs no key
+    pub api_keys: Vec<String>,
+    /// `API_KEYS_FILE`: more keys, one `name=key` per line
+    pub api_keys_file: Option<PathBuf>,
+    /// `USERS_FILE`: users who may sign in with a password, one
+    /// `name:role=hash` per line (see [`users`](crate::auth::users))
+    pub users_file: Option<PathBuf>,
+    /// `SESSION_SECRET`: signs session cookies; random per start when unset
+    pub session_secret: Option<String>,
+    /// `SESSION_TTL_SECS`: how long a session lasts (default 28800, eight hours)
+    pub session_ttl: Duration,
+    /// `SESSION_COOKIE_INSECURE`: leave `Secure` off session cookies, so they
+    /// work over plain HTTP
+    pub session_cookie_insecure: bool,
+    /// `SHARE_SECRET`: signs share links; random per start when unset
+    pub share_secret: Option<String>,
+    /// `SHARE_MAX_TTL_SECS`: the longest a share link may last (default
+    /// 2592000, 30 days)
+    pub share_max_ttl: Duration,
+    /// `SECRETS_KEY`: 64 hex digits, the AES-256 key secrets are encrypted
+    /// with; without it there are none (see [`secrets`](crate::secrets))
+    pub secrets_key: Option<String>,
+    /// `OIDC_ISSUER`: accept JWTs this identity provider signed (its `iss`,
+    /// e.g. `https://login.example.com/realms/main`)
+    pub oidc_issuer: Option<String>,
+    /// `OIDC_AUDIENCE`: the `aud` tokens must be for; not checked when unset
+    pub oidc_audience: Option<String>,
+    /// `OIDC_JWKS_URL`: where the provider's signing keys are; found through
+    /// its discovery document when unset
+    pub oidc_jwks_url: Option<String>,
+    /// `OIDC_USER_CLAIM`: the claim naming the user (default the first of
+    /// `preferred_username`, `email` and `sub`)
+    pub oidc_user_claim: Option<String>,
+    /// `OIDC_ROLE_CLAIM`: the claim holding the user's roles, a string or a
+    /// list; dots reach into objects, e.g. `realm_access.roles` (default `roles`)
+    pub oidc_role_claim: String,
+    /// `OIDC_DEFAULT_ROLE`: the role of a user whose claim names none of
+    /// `viewer`, `editor` and `admin` (default viewer)
+    pub oidc_default_role: Role,
+    /// `CUSTOM_NODE_HTTP_HOSTS`: hosts `http` custom nodes may call, written
+    /// like `IMPORT_ALLOWED_HOSTS` (`http-node` feature)
+    #[cfg(feature = "http-node")]
+    pub custom_node_http_hosts: Vec<String>,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            quota_max_bytes: parse("QUOTA_MAX_BYTES"),
+            quota_max_files: parse("QUOTA_MAX_FILES"),
+            follow_symlinks: flag("FOLLOW_SYMLINKS"),
+            search_threads: parse::<usize>("SEARCH_THREADS").filter(|&n| n > 0).unwrap_or_else(default_search_threads),
+            search_timeout: Duration::from_millis(parse("SEARCH_TIMEOUT_MS").unwrap_or(10_000)),
+            retention_keep_last: parse("RETENTION_KEEP_LAST"),
+            retention_daily_days: parse("RETENTION_DAILY_DAYS"),
+            retention_interval: Duration::from_secs(parse::<u64>("RETENTION_INTERVAL_SECS").filter(|&s| s > 0).unwrap_or(3600)),
+            retention_keep_auto: parse("RETENTION_KEEP_AUTO"),
+            read_only_revisions: flag("READ_ONLY_REVISIONS"),
+            snapshot_hardlinks: flag("SNAPSHOT_HARDLINKS"),
+            auto_snapshot_interval: parse::<u64>("AUTO_SNAPSHOT_INTERVAL_SECS").filter(|&s| s > 0).map(Duration::from_secs),
+            auto_snapshot_cron: env::var("AUTO_SNAPSHOT_CRON").ok().filter(|s| !s.trim().is_empty()),
+            file_history: flag("FILE_HISTORY"),
+            file_history_keep: parse::<usize>("FILE_HISTORY_KEEP").filter(|&n| n > 0).unwrap_or(50),
+            git_mirror_dir: env::var_os("GIT_MIRROR_DIR").filter(|s| !s.is_empty()).map(PathBuf::from),
+            import_allowed_hosts: env::var("IMPORT_ALLOWED_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            import_max_bytes: parse("IMPORT_MAX_BYTES").unwrap_or(64 * 1024 * 1024),
+            required_approvals: parse("REQUIRED_APPROVALS").unwrap_or(1),
+            staging_ttl: Duration::from_secs(parse::<u64>("STAGING_TTL_SECS").filter(|&s| s > 0).unwrap_or(86_400)),
+            simulate_max_depth: parse::<u8>("SIMULATE_MAX_DEPTH").filter(|&d| d > 0).unwrap_or(50),
+            simulate_max_timeout: Duration::from_millis(parse::<u64>("SIMULATE_MAX_TIMEOUT_MS").filter(|&ms| ms > 0).unwrap_or(60_000)),
+            simulate_timeout: Duration::from_millis(parse::<u64>("SIMULATE_TIMEOUT_MS").filter(|&ms| ms > 0).unwrap_or(10_000)),
+            expression_timeout: Duration::from_millis(parse::<u64>("EXPRESSION_TIMEOUT_MS").filter(|&ms| ms > 0).unwrap_or(1_000)),
+            simulate_cache_entries: parse::<usize>("SIMULATE_CACHE_ENTRIES").unwrap_or(1_000),
+            simulation_history_keep: parse::<usize>("SIMULATION_HISTORY_KEEP").unwrap_or(100),
+            eval_rate_limit: parse::<u32>("EVAL_RATE_LIMIT").unwrap_or(600),
+            rate_limit_cheap: parse::<u32>("RATE_LIMIT_CHEAP").unwrap_or(1_200),
+            rate_limit_expensive: parse::<u32>("RATE_LIMIT_EXPENSIVE").unwrap_or(120),
+            rate_limit_eval: parse::<u32>("RATE_LIMIT_EVAL").unwrap_or(600),
+            rate_limit_trust_proxy: flag("RATE_LIMIT_TRUST_PROXY"),
+            read_only: flag("READ_ONLY"),
+            read_only_reason: text("READ_ONLY_REASON"),
+            api_keys: env::var("API_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect(),
+            api_keys_file: env::var_os("API_KEYS_FILE").filter(|s| !s.is_empty()).map(PathBuf::from),
+            users_file: env::var_os("USERS_FILE").filter(|s| !s.is_empty()).map(PathBuf::from),
+            session_secret: text("SESSION_SECRET"),
+            session_ttl: Duration::from_secs(parse::<u64>("SESSION_TTL_SECS").filter(|&s| s > 0).unwrap_or(28_800)),
+            session_cookie_insecure: flag("SESSION_COOKIE_INSECURE"),
+            share_secret: text("SHARE_SECRET"),
+            share_max_ttl: Duration::from_secs(parse::<u64>("SHARE_MAX_TTL_SECS").filter(|&s| s > 0).unwrap_or(30 * 24 * 3600)),
+            secrets_key: text("SECRETS_KEY"),
+            oidc_issuer: text("OIDC_ISSUER"),
+            oidc_audience: text("OIDC_AUDIENCE"),
+            oidc_jwks_url: text("OIDC_JWKS_URL"),
+            oidc_user_claim: text("OIDC_USER_CLAIM"),
+            oidc_role_claim: text("OIDC_ROLE_CLAIM").unwrap_or_else(|| "roles".into()),
+            oidc_default_role: parse("OIDC_DEFAULT_ROLE").unwrap_or(Role::Viewer),
+            #[cfg(feature = "http-node")]
+            custom_node_http_hosts: env::var("CUSTOM_NODE_HTTP_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            validate_dirs: env::var("VALIDATE_ON_SAVE_DIRS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|d| d.trim().trim_matches('/').to_string())
+                .filter(|d| !d.is_empty())
+                .collect(),
+        }
+    }
+}
+
+fn default_search_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get() / 2).clamp(1, 8)
+}
+
+fn parse<T: FromStr>(key: &str) -> Option<T> {
+    let raw = env::var(key).ok()?;
+    match raw.trim().parse() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            tracing::warn!("ignoring invalid {key}={raw:?}");
+            None
+        }
+    }
+}
+
+fn text(key: &str) -> Option<String> {
+    env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn flag(key: &str) -> bool {
+    env::var(key).is_ok_and(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+}
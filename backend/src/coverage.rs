@@ -0,0 +1,264 @@
+//! Rule coverage of the last test run: `GET /api/tests/coverage`.
+//!
+//! `POST /api/tests/run` evaluates with the trace on and, once the run is
+//! over, reads off it which decision-table rules matched and which switch
+//! branches were taken, in the decision a case names and in every decision
+//! it reached through decision nodes. The tally is measured against the
+//! decisions as they were when the run read them, and kept in
+//! `./decisions/.coverage.json` until the next run replaces it, so it covers
+//! what that run's scope covered and nothing more.
+//!
+//! A rule counts once it matched one case; a table or switch the evaluation
+//! never got to counts as never hit, every rule of it.
+
+use axum::{
+    extract::Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use crate::{config::Config, decisions, jdm, quota, write_atomic, WriteOpts, STORAGE_ROOT};
+
+const COVERAGE_FILE: &str = "./decisions/.coverage.json";
+
+/// One evaluation to tally: the revision, the decision's key in it, and the
+/// engine's trace of it.
+pub(crate) struct Evaluated {
+    pub(crate) rev: u64,
+    pub(crate) key: String,
+    pub(crate) trace: Value,
+}
+
+#[derive(Default)]
+struct NodeHits {
+    /// table rules, by position
+    rows: BTreeSet<usize>,
+    /// switch statements, by id
+    statements: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NeverHit {
+    node_id: String,
+    node_name: String,
+    /// `rule` of a decision table or `branch` of a switch
+    kind: String,
+    /// its position in the node
+    index: usize,
+    /// the rule's `_id` or the statement's `id`, when it has one
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    /// the rule's `_description` or the statement's condition
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileCoverage {
+    filepath: String,
+    rev: u64,
+    rules: usize,
+    rules_hit: usize,
+    branches: usize,
+    branches_hit: usize,
+    /// of rules and branches together; absent when the file has neither
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<f64>,
+    never_hit: Vec<NeverHit>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Report {
+    /// unix seconds
+    ran_at: i64,
+    /// cases that evaluated; the ones that errored added nothing
+    cases: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<f64>,
+    /// by path, then revision
+    files: Vec<FileCoverage>,
+}
+
+fn percent(hit: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| (hit as f64 * 1000.0 / total as f64).round() / 10.0)
+}
+
+/// The rows a table's trace data names: one object when the first match
+/// wins, a list of them when every match is collected, and a list of either
+/// per item in loop mode.
+fn rows(trace_data: &Value, out: &mut BTreeSet<usize>) {
+    match trace_data {
+        Value::Array(items) => items.iter().for_each(|i| rows(i, out)),
+        Value::Object(m) => {
+            if let Some(i) = m.get("index").and_then(Value::as_u64) {
+                out.insert(i as usize);
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Tally {
+    /// decisions as read, `None` for one that couldn't be
+    docs: HashMap<(u64, String), Option<Value>>,
+    hits: BTreeMap<(u64, String), HashMap<String, NodeHits>>,
+}
+
+impl Tally {
+    fn doc(&mut self, rev: u64, key: &str) -> Option<&Value> {
+        self.docs
+            .entry((rev, key.to_string()))
+            .or_insert_with(|| {
+                fs::read(format!("{STORAGE_ROOT}/{rev}/{key}"))
+                    .ok()
+                    .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+                    .filter(|d| d["nodes"].is_array())
+            })
+            .as_ref()
+    }
+
+    /// Adds what `trace` (node id → trace entry) shows of `key`, and of the
+    /// decisions its decision nodes called.
+    fn add(&mut self, rev: u64, key: &str, trace: &Value) {
+        let Some(doc) = self.doc(rev, key).cloned() else { return };
+        self.hits.entry((rev, key.to_string())).or_default();
+        for node in jdm::nodes(&doc) {
+            let id = node["id"].as_str().unwrap_or_default();
+            let Some(trace_data) = trace.get(id).map(|t| &t["traceData"]) else { continue };
+            match jdm::node_type(node) {
+                "decisionTableNode" => {
+                    let file = self.hits.get_mut(&(rev, key.to_string())).expect("added above");
+                    rows(trace_data, &mut file.entry(id.to_string()).or_default().rows);
+                }
+                "switchNode" => {
+                    let taken = trace_data["statements"].as_array().into_iter().flatten();
+                    let file = self.hits.get_mut(&(rev, key.to_string())).expect("added above");
+                    let node_hits = file.entry(id.to_string()).or_default();
+                    node_hits.statements.extend(taken.filter_map(|s| s["id"].as_str()).map(str::to_string));
+                }
+                "decisionNode" => {
+                    let Some(called) = node["content"]["key"].as_str().and_then(decisions::normalize) else { continue };
+                    // the called decision's own trace; one per item in loop mode
+                    match trace_data {
+                        Value::Array(items) => items.iter().for_each(|t| self.add(rev, &called, t)),
+                        t @ Value::Object(_) => self.add(rev, &called, t),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn file(&self, rev: u64, key: &str, hits: &HashMap<String, NodeHits>) -> FileCoverage {
+        let mut cov = FileCoverage {
+            filepath: key.to_string(),
+            rev,
+            rules: 0,
+            rules_hit: 0,
+            branches: 0,
+            branches_hit: 0,
+            percent: None,
+            never_hit: Vec::new(),
+        };
+        let doc = self.docs.get(&(rev, key.to_string())).and_then(Option::as_ref).expect("tallied files were read");
+        let none = NodeHits::default();
+        let str_of = |v: &Value| v.as_str().unwrap_or_default().to_string();
+        for node in jdm::nodes(doc) {
+            let id = node["id"].as_str().unwrap_or_default();
+            let node_hits = hits.get(id).unwrap_or(&none);
+            let never = |kind: &str, index, id: &Value, description: &Value| NeverHit {
+                node_id: str_of(&node["id"]),
+                node_name: str_of(&node["name"]),
+                kind: kind.to_string(),
+                index,
+                id: str_of(id),
+                description: str_of(description),
+            };
+            match jdm::node_type(node) {
+                "decisionTableNode" => {
+                    for (i, rule) in node["content"]["rules"].as_array().into_iter().flatten().enumerate() {
+                        cov.rules += 1;
+                        if node_hits.rows.contains(&i) {
+                            cov.rules_hit += 1;
+                        } else {
+                            cov.never_hit.push(never("rule", i, &rule["_id"], &rule["_description"]));
+                        }
+                    }
+                }
+                "switchNode" => {
+                    for (i, s) in node["content"]["statements"].as_array().into_iter().flatten().enumerate() {
+                        cov.branches += 1;
+                        if s["id"].as_str().is_some_and(|sid| node_hits.statements.contains(sid)) {
+                            cov.branches_hit += 1;
+                        } else {
+                            cov.never_hit.push(never("branch", i, &s["id"], &s["condition"]));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        cov.percent = percent(cov.rules_hit + cov.branches_hit, cov.rules + cov.branches);
+        cov
+    }
+}
+
+/// Blocking. Tallies `evaluated` into a report.
+pub(crate) fn tally(evaluated: &[Evaluated], ran_at: i64) -> Report {
+    let mut t = Tally { docs: HashMap::new(), hits: BTreeMap::new() };
+    for e in evaluated {
+        t.add(e.rev, &e.key, &e.trace);
+    }
+    let mut files: Vec<FileCoverage> = t.hits.iter().map(|((rev, key), hits)| t.file(*rev, key, hits)).collect();
+    files.sort_by(|a, b| (&a.filepath, a.rev).cmp(&(&b.filepath, b.rev)));
+    let (hit, total) = files
+        .iter()
+        .fold((0, 0), |(h, n), f| (h + f.rules_hit + f.branches_hit, n + f.rules + f.branches));
+    Report { ran_at, cases: evaluated.len(), percent: percent(hit, total), files }
+}
+
+/// Blocking. Replaces the stored report; a failure is logged, not returned,
+/// since the run it came from succeeded.
+pub(crate) fn store(cfg: &Config, report: &Report) {
+    let bytes = serde_json::to_vec_pretty(report).expect("serialize coverage");
+    let target = Path::new(COVERAGE_FILE);
+    if quota::check_write(cfg, target, bytes.len() as u64).is_err() {
+        tracing::warn!("coverage not stored: over quota");
+        return;
+    }
+    if let Err(e) = write_atomic(target, &bytes, WriteOpts::default()) {
+        tracing::error!("coverage store error: {e}");
+    }
+}
+
+// ===== GET /api/tests/coverage ===============================================
+
+pub(crate) async fn report() -> Response {
+    match tokio::fs::read(COVERAGE_FILE).await {
+        Ok(raw) => match serde_json::from_slice::<Report>(&raw) {
+            Ok(report) => Json(report).into_response(),
+            Err(e) => {
+                tracing::error!("coverage read error: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            (StatusCode::NOT_FOUND, "no test run yet").into_response()
+        }
+        Err(e) => {
+            tracing::error!("coverage read error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
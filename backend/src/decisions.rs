@@ -0,0 +1,234 @@
+//! `GET /api/decisions/graph`: which decisions call which. The context a
+//! decision expects is [`schema`]'s, a picture of one [`export`]'s, what's
+//! off about its tables [`analyze`]'s, and how complex it is [`metrics`]'.
+//!
+//! A decision node names another decision by its key, a path from the root of
+//! the revision being evaluated (not from the calling file). This walks the
+//! working copy, or the revision `rev` names, reads every decision graph in it
+//! and reports one edge per decision node: from the file holding the node to
+//! the file its key names. Keys naming nothing (or a file that isn't a
+//! decision, or a place outside the revision) are reported as missing, and
+//! files that end up calling themselves, directly or around a loop, as cycles;
+//! the engine would only stop those at its depth limit.
+
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Component, Path as StdPath, PathBuf},
+};
+
+use crate::{jdm, revisions::RevRef, search, AppState, STORAGE_ROOT};
+
+pub(crate) mod analyze;
+pub(crate) mod export;
+pub(crate) mod metrics;
+pub(crate) mod schema;
+
+#[derive(Deserialize)]
+pub(crate) struct GraphParams {
+    /// revision number or tag; the working copy when absent
+    rev: Option<RevRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DecisionRef {
+    /// the calling file
+    from: String,
+    /// its key, made relative to the revision
+    to: String,
+    node_id: String,
+    node_name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Missing {
+    #[serde(flatten)]
+    reference: DecisionRef,
+    reason: &'static str, // "notFound", "notADecision", "outsideRevision"
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphResp {
+    rev: u64,
+    /// every decision file, with what it calls and what calls it
+    decisions: BTreeMap<String, Links>,
+    edges: Vec<DecisionRef>,
+    missing: Vec<Missing>,
+    /// each a set of files that call each other, sorted; a file calling
+    /// itself is a cycle of one
+    cycles: Vec<Vec<String>>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Links {
+    calls: BTreeSet<String>,
+    called_by: BTreeSet<String>,
+}
+
+/// `key` as the loader would resolve it under the revision root, or `None`
+/// when it leaves the revision.
+pub(crate) fn normalize(key: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for c in StdPath::new(key.trim().trim_start_matches('/')).components() {
+        match c {
+            Component::Normal(s) => parts.push(s.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// The revision number and the key inside it of a path `read_path` gave.
+pub(crate) fn split_rev(full: &StdPath) -> Option<(u64, String)> {
+    let rel = full.strip_prefix(STORAGE_ROOT).ok()?.to_string_lossy().replace('\\', "/");
+    let (rev, key) = rel.split_once('/')?;
+    Some((rev.parse().ok()?, key.to_string()))
+}
+
+/// Strongly connected components of more than one file, plus files that call
+/// themselves (Tarjan's algorithm).
+fn cycles(calls: &BTreeMap<String, Links>) -> Vec<Vec<String>> {
+    struct Walk<'a> {
+        calls: &'a BTreeMap<String, Links>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        out: Vec<Vec<String>>,
+    }
+
+    impl<'a> Walk<'a> {
+        fn visit(&mut self, v: &'a str) {
+            let i = self.index.len();
+            self.index.insert(v, i);
+            self.low.insert(v, i);
+            self.stack.push(v);
+            self.on_stack.insert(v);
+            for w in self.calls.get(v).into_iter().flat_map(|l| l.calls.iter()) {
+                let w = w.as_str();
+                if !self.calls.contains_key(w) {
+                    continue;
+                }
+                if !self.index.contains_key(w) {
+                    self.visit(w);
+                    let low = self.low[v].min(self.low[w]);
+                    self.low.insert(v, low);
+                } else if self.on_stack.contains(w) {
+                    let low = self.low[v].min(self.index[w]);
+                    self.low.insert(v, low);
+                }
+            }
+            if self.low[v] == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack.remove(w);
+                    component.push(w.to_string());
+                    if w == v {
+                        break;
+                    }
+                }
+                let looped = component.len() > 1 || self.calls[v].calls.contains(v);
+                if looped {
+                    component.sort();
+                    self.out.push(component);
+                }
+            }
+        }
+    }
+
+    let mut walk = Walk {
+        calls,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        out: Vec::new(),
+    };
+    for v in calls.keys() {
+        if !walk.index.contains_key(v.as_str()) {
+            walk.visit(v);
+        }
+    }
+    walk.out.sort();
+    walk.out
+}
+
+/// Blocking.
+fn build(rev: u64, follow: bool) -> GraphResp {
+    let root = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+    let mut docs: BTreeMap<String, Value> = BTreeMap::new();
+    let mut other_files = BTreeSet::new();
+    for entry in search::files(&root, follow) {
+        let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+        let is_json = entry.path().extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let doc = (is_json && entry.metadata().map_or(0, |m| m.len()) <= search::MAX_FILE_BYTES)
+            .then(|| std::fs::read(entry.path()).ok())
+            .flatten()
+            .and_then(|b| serde_json::from_slice::<Value>(&b).ok())
+            .filter(|d| d["nodes"].is_array());
+        match doc {
+            Some(d) => {
+                docs.insert(rel, d);
+            }
+            None => {
+                other_files.insert(rel);
+            }
+        }
+    }
+
+    let mut decisions: BTreeMap<String, Links> = docs.keys().map(|k| (k.clone(), Links::default())).collect();
+    let (mut edges, mut missing) = (Vec::new(), Vec::new());
+    for (from, doc) in &docs {
+        for node in jdm::nodes(doc).filter(|n| jdm::node_type(n) == "decisionNode") {
+            let key = node["content"]["key"].as_str().unwrap_or_default();
+            let target = normalize(key);
+            let reference = DecisionRef {
+                from: from.clone(),
+                to: target.clone().unwrap_or_else(|| key.to_string()),
+                node_id: node["id"].as_str().unwrap_or_default().to_string(),
+                node_name: node["name"].as_str().unwrap_or_default().to_string(),
+            };
+            let reason = match &target {
+                None => "outsideRevision",
+                Some(t) if docs.contains_key(t) => {
+                    decisions.get_mut(from).expect("every decision has links").calls.insert(t.clone());
+                    decisions.get_mut(t).expect("every decision has links").called_by.insert(from.clone());
+                    edges.push(reference);
+                    continue;
+                }
+                Some(t) if other_files.contains(t) => "notADecision",
+                Some(_) => "notFound",
+            };
+            missing.push(Missing { reference, reason });
+        }
+    }
+
+    let cycles = cycles(&decisions);
+    GraphResp { rev, decisions, edges, missing, cycles }
+}
+
+pub(crate) async fn graph(State(st): State<AppState>, Query(q): Query<GraphParams>) -> Response {
+    let rev = match q.rev.as_ref().map(RevRef::resolve).transpose() {
+        Ok(rev) => rev.unwrap_or(0),
+        Err(resp) => return resp,
+    };
+    let follow = st.config.follow_symlinks;
+    match tokio::task::spawn_blocking(move || build(rev, follow)).await {
+        Ok(resp) => Json(resp).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,628 @@
+//! `POST /api/analyze/table`: what's off about the rules of a decision table,
+//! found by reading its cells rather than running it.
+//!
+//! Every input cell is read as the engine reads it, a unary test against the
+//! column's field, and turned into the set of values it takes: numbers
+//! (`> 5`, `[18..65)`, `-3`), strings (`"gold", "silver"`), booleans, and
+//! `!=`, `not in`, `and`, `or` and commas over those. A blank cell takes
+//! anything. Each column's values are then cut into the few pieces the
+//! cells can tell apart (for numbers, the bounds the cells name and what's
+//! between them; for strings, the strings named and every other string),
+//! and a rule is a box of pieces, one set per column. From those:
+//!
+//! - `overlap`: two rules match some context alike. Under `collect` that's
+//!   every such pair; under `first` only pairs where neither rule matches
+//!   everything the other does, as a specific rule before a broader one is
+//!   the usual way to write a first-hit table;
+//! - `unreachable`: under `first`, a rule whose every context is matched by
+//!   earlier rules, one of them or several together, so it never fires;
+//! - `gap`: contexts no rule matches, up to [`MAX_GAPS`] of them.
+//!
+//! Findings name rules by their index in the table's `rules` (0-based, as in
+//! a [`lint`](crate::lint) location), and overlaps and gaps name the region
+//! they're about per input column, written as a cell would be, so the UI can
+//! fill in a rule that closes a gap.
+//!
+//! A cell that isn't a comparison with a literal (`$ > limit`, `len($) > 3`,
+//! any cell of a column without a field) can't be read this way; its rule is
+//! reported as `unanalyzed` and left out, so claims about it aren't made and
+//! gaps it might fill are still reported. Null and missing fields aren't
+//! part of any column's values.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use bumpalo::Bump;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fs};
+use zen_expression::{
+    lexer::{ArithmeticOperator, ComparisonOperator, Lexer, LogicalOperator, Operator},
+    parser::{BuiltInFunction, Node, Parser},
+};
+
+use crate::{jdm, read_path, AppState};
+
+/// Rules past this many aren't analyzed.
+const MAX_RULES: usize = 2000;
+
+/// Findings reported per table.
+const MAX_FINDINGS: usize = 500;
+
+/// Gaps reported per table; there's one finding each.
+const MAX_GAPS: usize = 20;
+
+/// Pieces the uncovered region may be cut into before gap analysis gives up.
+const MAX_PIECES: usize = 20_000;
+
+/// One end of a range of numbers: the bound and whether it's included;
+/// `None` is unbounded.
+type End = Option<(Decimal, bool)>;
+
+/// What a cell takes, as read from its unary test.
+enum Pred {
+    Num(End, End),
+    Str(String),
+    Bool(bool),
+    Not(Box<Pred>),
+    And(Box<Pred>, Box<Pred>),
+    Or(Box<Pred>, Box<Pred>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    String,
+    Boolean,
+}
+
+impl Pred {
+    /// The one kind of value it compares with, `Err` for more.
+    fn kind(&self) -> Result<Kind, String> {
+        match self {
+            Self::Num(..) => Ok(Kind::Number),
+            Self::Str(_) => Ok(Kind::String),
+            Self::Bool(_) => Ok(Kind::Boolean),
+            Self::Not(p) => p.kind(),
+            Self::And(a, b) | Self::Or(a, b) => match (a.kind()?, b.kind()?) {
+                (x, y) if x == y => Ok(x),
+                _ => Err("compares with values of more than one type".into()),
+            },
+        }
+    }
+
+    fn bounds(&self, out: &mut Vec<Decimal>) {
+        match self {
+            Self::Num(lo, hi) => out.extend(lo.iter().chain(hi).map(|(d, _)| *d)),
+            Self::Str(_) | Self::Bool(_) => {}
+            Self::Not(p) => p.bounds(out),
+            Self::And(a, b) | Self::Or(a, b) => {
+                a.bounds(out);
+                b.bounds(out);
+            }
+        }
+    }
+
+    fn strings<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Self::Str(s) => out.push(s),
+            Self::Num(..) | Self::Bool(_) => {}
+            Self::Not(p) => p.strings(out),
+            Self::And(a, b) | Self::Or(a, b) => {
+                a.strings(out);
+                b.strings(out);
+            }
+        }
+    }
+}
+
+fn literal(node: &Node) -> Option<Pred> {
+    match node {
+        Node::Number(d) => Some(Pred::Num(Some((*d, true)), Some((*d, true)))),
+        Node::Unary { node: Node::Number(d), operator: Operator::Arithmetic(ArithmeticOperator::Subtract) } => {
+            Some(Pred::Num(Some((-*d, true)), Some((-*d, true))))
+        }
+        Node::String(s) => Some(Pred::Str(s.to_string())),
+        Node::Bool(b) => Some(Pred::Bool(*b)),
+        Node::Parenthesized(n) => literal(n),
+        _ => None,
+    }
+}
+
+fn number(node: &Node) -> Option<Decimal> {
+    match literal(node)? {
+        Pred::Num(Some((d, _)), _) => Some(d),
+        _ => None,
+    }
+}
+
+/// `$ <op> right`.
+fn comparison(op: ComparisonOperator, right: &Node) -> Result<Pred, String> {
+    use ComparisonOperator::*;
+    let not_literal = || "compares with something other than a literal".to_string();
+    Ok(match op {
+        Equal => literal(right).ok_or_else(not_literal)?,
+        NotEqual => Pred::Not(Box::new(literal(right).ok_or_else(not_literal)?)),
+        LessThan | LessThanOrEqual | GreaterThan | GreaterThanOrEqual => {
+            let d = number(right).ok_or("compares with something other than a number")?;
+            match op {
+                LessThan => Pred::Num(None, Some((d, false))),
+                LessThanOrEqual => Pred::Num(None, Some((d, true))),
+                GreaterThan => Pred::Num(Some((d, false)), None),
+                _ => Pred::Num(Some((d, true)), None),
+            }
+        }
+        In => match right {
+            Node::Interval { left, right, left_bracket, right_bracket } => {
+                let (lo, hi) = number(left).zip(number(right)).ok_or("takes a range between things other than numbers")?;
+                Pred::Num(Some((lo, *left_bracket == "[")), Some((hi, *right_bracket == "]")))
+            }
+            Node::Array(items) => {
+                let mut items = items.iter().map(|i| literal(i).ok_or("takes a list of things other than literals"));
+                let first = items.next().ok_or("takes an empty list")??;
+                items.try_fold(first, |acc, i| Ok::<_, &str>(Pred::Or(Box::new(acc), Box::new(i?))))?
+            }
+            _ => return Err("takes something other than a range or a list".into()),
+        },
+        NotIn => Pred::Not(Box::new(comparison(In, right)?)),
+    })
+}
+
+fn pred(node: &Node) -> Result<Pred, String> {
+    match node {
+        Node::Binary { left, operator: Operator::Logical(op @ (LogicalOperator::And | LogicalOperator::Or)), right } => {
+            let (a, b) = (Box::new(pred(left)?), Box::new(pred(right)?));
+            Ok(if *op == LogicalOperator::And { Pred::And(a, b) } else { Pred::Or(a, b) })
+        }
+        Node::Binary { left: Node::Identifier("$"), operator: Operator::Comparison(op), right } => comparison(*op, right),
+        Node::Unary { node, operator: Operator::Logical(LogicalOperator::Not) } => Ok(Pred::Not(Box::new(pred(node)?))),
+        Node::BuiltIn { kind: BuiltInFunction::Bool, arguments: [arg] } | Node::Parenthesized(arg) => pred(arg),
+        _ => Err("isn't a comparison with a literal".into()),
+    }
+}
+
+/// What `cell` takes: `None` for anything, `Err` with why it can't be read.
+fn read_cell(cell: &str) -> Result<Option<Pred>, String> {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return Ok(None);
+    }
+    let mut lexer = Lexer::new();
+    let tokens = lexer.tokenize(cell).map_err(|_| "doesn't lex".to_string())?;
+    let bump = Bump::new();
+    let parser = Parser::try_new(tokens, &bump).map_err(|_| "doesn't parse".to_string())?;
+    let parsed = parser.unary().parse();
+    if parsed.error().is_err() {
+        return Err("doesn't parse".into());
+    }
+    let p = pred(parsed.root)?;
+    p.kind()?;
+    Ok(Some(p))
+}
+
+// ===== columns and regions ===================================================
+
+/// An input column cut into the pieces its cells tell apart.
+struct Column<'a> {
+    id: &'a str,
+    field: &'a str,
+    /// cells are unary tests; without a field they're expressions
+    unary: bool,
+    kind: Option<Kind>,
+    /// numbers: `(-inf, b0)`, `b0`, `(b0, b1)`, `b1` … `(bk, inf)`
+    bounds: Vec<Decimal>,
+    /// strings: these, then every other one
+    strings: Vec<String>,
+}
+
+impl Column<'_> {
+    fn pieces(&self) -> usize {
+        match self.kind {
+            Some(Kind::Number) => 2 * self.bounds.len() + 1,
+            Some(Kind::String) => self.strings.len() + 1,
+            Some(Kind::Boolean) => 2,
+            None => 1,
+        }
+    }
+
+    /// For piece `i` of a number column: the value, or the open range between
+    /// two bounds.
+    fn number_piece(&self, i: usize) -> (Option<Decimal>, Option<Decimal>, bool) {
+        let b = &self.bounds;
+        if i % 2 == 1 {
+            let d = b[i / 2];
+            (Some(d), Some(d), true)
+        } else {
+            (i.checked_sub(1).map(|j| b[j / 2]), b.get(i / 2).copied(), false)
+        }
+    }
+
+    fn eval(&self, p: &Pred) -> Vec<bool> {
+        let n = self.pieces();
+        match p {
+            Pred::Num(lo, hi) => (0..n)
+                .map(|i| {
+                    let (from, to, point) = self.number_piece(i);
+                    let lo_ok = match (lo, from) {
+                        (None, _) => true,
+                        (Some(_), None) => false,
+                        (Some((l, inc)), Some(f)) => f > *l || (f == *l && (*inc || !point)),
+                    };
+                    let hi_ok = match (hi, to) {
+                        (None, _) => true,
+                        (Some(_), None) => false,
+                        (Some((h, inc)), Some(t)) => t < *h || (t == *h && (*inc || !point)),
+                    };
+                    lo_ok && hi_ok
+                })
+                .collect(),
+            Pred::Str(s) => (0..n).map(|i| self.strings.get(i) == Some(s)).collect(),
+            Pred::Bool(b) => vec![!*b, *b],
+            Pred::Not(p) => self.eval(p).into_iter().map(|x| !x).collect(),
+            Pred::And(a, b) => self.eval(a).into_iter().zip(self.eval(b)).map(|(x, y)| x && y).collect(),
+            Pred::Or(a, b) => self.eval(a).into_iter().zip(self.eval(b)).map(|(x, y)| x || y).collect(),
+        }
+    }
+
+    /// `set` written as a cell would be.
+    fn describe(&self, set: &[bool]) -> String {
+        let text = |d: Decimal| d.normalize().to_string();
+        match self.kind {
+            Some(Kind::Number) => {
+                let mut parts = Vec::new();
+                let mut i = 0;
+                while i < set.len() {
+                    if !set[i] {
+                        i += 1;
+                        continue;
+                    }
+                    let start = i;
+                    while i + 1 < set.len() && set[i + 1] {
+                        i += 1;
+                    }
+                    let (from, _, from_point) = self.number_piece(start);
+                    let (_, to, to_point) = self.number_piece(i);
+                    parts.push(match (from, to) {
+                        (None, None) => String::new(),
+                        (Some(f), Some(t)) if f == t => text(f),
+                        (None, Some(t)) => format!("{} {}", if to_point { "<=" } else { "<" }, text(t)),
+                        (Some(f), None) => format!("{} {}", if from_point { ">=" } else { ">" }, text(f)),
+                        (Some(f), Some(t)) => {
+                            format!("{}{}..{}{}", if from_point { "[" } else { "(" }, text(f), text(t), if to_point { "]" } else { ")" })
+                        }
+                    });
+                    i += 1;
+                }
+                parts.join(", ")
+            }
+            Some(Kind::String) => {
+                let quoted = |s: &String| serde_json::to_string(s).expect("serialize string");
+                if set[self.strings.len()] {
+                    let excluded: Vec<String> = self.strings.iter().zip(set).filter(|(_, &x)| !x).map(|(s, _)| quoted(s)).collect();
+                    format!("not in [{}]", excluded.join(", "))
+                } else {
+                    self.strings.iter().zip(set).filter(|(_, &x)| x).map(|(s, _)| quoted(s)).collect::<Vec<_>>().join(", ")
+                }
+            }
+            Some(Kind::Boolean) => if set[1] { "true" } else { "false" }.to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+/// One set of pieces per column.
+type Region = Vec<Vec<bool>>;
+
+fn intersects(a: &Region, b: &Region) -> bool {
+    a.iter().zip(b).all(|(x, y)| x.iter().zip(y).any(|(p, q)| *p && *q))
+}
+
+/// Whether `a` holds all of `b`.
+fn contains(a: &Region, b: &Region) -> bool {
+    a.iter().zip(b).all(|(x, y)| x.iter().zip(y).all(|(p, q)| *p || !*q))
+}
+
+fn and(a: &[bool], b: &[bool]) -> Vec<bool> {
+    a.iter().zip(b).map(|(p, q)| *p && *q).collect()
+}
+
+/// `regions` without `r`, as disjoint pieces; `None` past `max` pieces.
+fn subtract(regions: Vec<Region>, r: &Region, max: usize) -> Option<Vec<Region>> {
+    let mut out = Vec::new();
+    for b in regions {
+        if !intersects(&b, r) {
+            out.push(b);
+            continue;
+        }
+        // the part of b outside r in column c and inside it before c
+        for c in 0..b.len() {
+            let outside: Vec<bool> = b[c].iter().zip(&r[c]).map(|(p, q)| *p && !*q).collect();
+            if !outside.contains(&true) {
+                continue;
+            }
+            let piece = (0..b.len())
+                .map(|k| match k.cmp(&c) {
+                    std::cmp::Ordering::Less => and(&b[k], &r[k]),
+                    std::cmp::Ordering::Equal => outside.clone(),
+                    std::cmp::Ordering::Greater => b[k].clone(),
+                })
+                .collect();
+            out.push(piece);
+        }
+        if out.len() > max {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+// ===== findings ==============================================================
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum FindingKind {
+    Overlap,
+    Unreachable,
+    Gap,
+    Unanalyzed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Finding {
+    kind: FindingKind,
+    /// 0-based; for `unreachable` the rule itself first, then those covering it
+    rules: Vec<usize>,
+    message: String,
+    /// input column id → the values concerned, for overlaps and gaps;
+    /// columns that take anything are left out
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    inputs: BTreeMap<String, String>,
+    /// the cell that couldn't be read, e.g. `rules/3/in1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TableReport {
+    node_id: String,
+    name: String,
+    hit_policy: String,
+    rules: usize,
+    findings: Vec<Finding>,
+    /// findings, rules or gaps beyond what's analyzed or reported
+    truncated: bool,
+}
+
+fn list(rules: &[usize]) -> String {
+    let text: Vec<String> = rules.iter().map(usize::to_string).collect();
+    match text.as_slice() {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} and {last}", init.join(", ")),
+    }
+}
+
+fn analyze(node: &Value) -> TableReport {
+    let content = &node["content"];
+    let hit_policy = content["hitPolicy"].as_str().unwrap_or("first").to_string();
+    let first_hit = hit_policy != "collect";
+    let all_rules = content["rules"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let rules = &all_rules[..all_rules.len().min(MAX_RULES)];
+    let mut report = TableReport {
+        node_id: node["id"].as_str().unwrap_or_default().to_string(),
+        name: node["name"].as_str().unwrap_or_default().to_string(),
+        hit_policy,
+        rules: all_rules.len(),
+        findings: Vec::new(),
+        truncated: rules.len() < all_rules.len(),
+    };
+
+    let mut columns: Vec<Column> = content["inputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| {
+            let id = c["id"].as_str()?;
+            let field = c["field"].as_str().filter(|f| !f.trim().is_empty());
+            Some(Column { id, field: field.unwrap_or(id), unary: field.is_some(), kind: None, bounds: Vec::new(), strings: Vec::new() })
+        })
+        .collect();
+
+    // every cell read; a rule with one that can't be is out
+    let mut cells: Vec<Option<Vec<Option<Pred>>>> = Vec::with_capacity(rules.len());
+    for (i, rule) in rules.iter().enumerate() {
+        let mut row = Vec::with_capacity(columns.len());
+        let mut unreadable = None;
+        for col in columns.iter_mut() {
+            let text = rule[col.id].as_str().unwrap_or_default();
+            let read = match read_cell(text) {
+                _ if !col.unary && !text.trim().is_empty() => Err("is an expression: the column has no field".to_string()),
+                Ok(Some(p)) => {
+                    let kind = p.kind().expect("checked when read");
+                    match col.kind {
+                        Some(k) if k != kind => Err("compares with another type than the column's other cells".to_string()),
+                        _ => {
+                            col.kind = Some(kind);
+                            Ok(Some(p))
+                        }
+                    }
+                }
+                other => other,
+            };
+            match read {
+                Ok(p) => row.push(p),
+                Err(why) => {
+                    unreadable = Some((col.id, col.field, text, why));
+                    break;
+                }
+            }
+        }
+        match unreadable {
+            None => cells.push(Some(row)),
+            Some((id, field, text, why)) => {
+                report.findings.push(Finding {
+                    kind: FindingKind::Unanalyzed,
+                    rules: vec![i],
+                    message: format!("rule {i} is left out: its {field} cell {text:?} {why}"),
+                    inputs: BTreeMap::new(),
+                    location: Some(format!("rules/{i}/{id}")),
+                });
+                cells.push(None);
+            }
+        }
+    }
+
+    for (c, col) in columns.iter_mut().enumerate() {
+        let (mut bounds, mut strings) = (Vec::new(), Vec::new());
+        for p in cells.iter().flatten().filter_map(|row| row[c].as_ref()) {
+            p.bounds(&mut bounds);
+            p.strings(&mut strings);
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+        col.bounds = bounds;
+        let mut strings: Vec<String> = strings.into_iter().map(str::to_string).collect();
+        strings.sort_unstable();
+        strings.dedup();
+        col.strings = strings;
+    }
+    let regions: Vec<Option<Region>> = cells
+        .iter()
+        .map(|row| {
+            let row = row.as_ref()?;
+            Some(columns.iter().zip(row).map(|(col, p)| p.as_ref().map_or_else(|| vec![true; col.pieces()], |p| col.eval(p))).collect())
+        })
+        .collect();
+    let describe = |r: &Region| -> BTreeMap<String, String> {
+        columns
+            .iter()
+            .zip(r)
+            .filter(|(_, set)| set.contains(&false))
+            .map(|(col, set)| (col.id.to_string(), col.describe(set)))
+            .collect()
+    };
+    let fields = |inputs: &BTreeMap<String, String>| -> String {
+        let parts: Vec<String> = columns.iter().filter_map(|c| Some(format!("{} is {}", c.field, inputs.get(c.id)?))).collect();
+        if parts.is_empty() {
+            "for any input".into()
+        } else {
+            format!("when {}", parts.join(" and "))
+        }
+    };
+    let full = |report: &mut TableReport| {
+        let over = report.findings.len() >= MAX_FINDINGS;
+        report.truncated |= over;
+        over
+    };
+
+    let analyzed: Vec<(usize, &Region)> = regions.iter().enumerate().filter_map(|(i, r)| Some((i, r.as_ref()?))).collect();
+    let mut unreachable = vec![false; rules.len()];
+    if first_hit {
+        for (pos, &(j, rj)) in analyzed.iter().enumerate() {
+            let earlier: Vec<(usize, &Region)> = analyzed[..pos].iter().copied().filter(|(_, ri)| intersects(ri, rj)).collect();
+            let cover = match earlier.iter().find(|(_, ri)| contains(ri, rj)) {
+                Some(&(i, _)) => Some(vec![i]),
+                None if earlier.len() > 1 => {
+                    let left = earlier.iter().try_fold(vec![rj.clone()], |left, (_, ri)| subtract(left, ri, MAX_PIECES));
+                    left.filter(Vec::is_empty).map(|_| earlier.iter().map(|(i, _)| *i).collect())
+                }
+                None => None,
+            };
+            let Some(cover) = cover else { continue };
+            unreachable[j] = true;
+            if full(&mut report) {
+                break;
+            }
+            let message = match cover.as_slice() {
+                [i] => format!("rule {j} never fires: rule {i} comes first and matches everything it does"),
+                _ => format!("rule {j} never fires: rules {} come first and together match everything it does", list(&cover)),
+            };
+            let mut rules = vec![j];
+            rules.extend(cover);
+            report.findings.push(Finding { kind: FindingKind::Unreachable, rules, message, inputs: BTreeMap::new(), location: None });
+        }
+    }
+
+    'pairs: for (pos, &(i, ri)) in analyzed.iter().enumerate() {
+        for &(j, rj) in &analyzed[pos + 1..] {
+            if !intersects(ri, rj) || unreachable[i] || unreachable[j] {
+                continue;
+            }
+            if first_hit && (contains(ri, rj) || contains(rj, ri)) {
+                continue;
+            }
+            if full(&mut report) {
+                break 'pairs;
+            }
+            let shared: Region = ri.iter().zip(rj).map(|(x, y)| and(x, y)).collect();
+            let inputs = describe(&shared);
+            let message = match first_hit {
+                true => format!("rules {i} and {j} both match {}; rule {i} wins there", fields(&inputs)),
+                false => format!("rules {i} and {j} both match {}", fields(&inputs)),
+            };
+            report.findings.push(Finding { kind: FindingKind::Overlap, rules: vec![i, j], message, inputs, location: None });
+        }
+    }
+
+    let universe: Region = columns.iter().map(|c| vec![true; c.pieces()]).collect();
+    let left = analyzed.iter().try_fold(vec![universe], |left, (_, r)| subtract(left, r, MAX_PIECES));
+    match left {
+        Some(gaps) => {
+            report.truncated |= gaps.len() > MAX_GAPS;
+            for gap in gaps.iter().take(MAX_GAPS) {
+                if full(&mut report) {
+                    break;
+                }
+                let inputs = describe(gap);
+                let message = format!("no rule matches {}", fields(&inputs));
+                report.findings.push(Finding { kind: FindingKind::Gap, rules: Vec::new(), message, inputs, location: None });
+            }
+        }
+        None => report.truncated = true,
+    }
+    report
+}
+
+// ===== POST /api/analyze/table ===============================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TableReq {
+    /// a stored decision, e.g. `0/loans/score.json`
+    path: String,
+    /// only this table; every one in the decision otherwise
+    node_id: Option<String>,
+}
+
+pub(crate) async fn table(State(st): State<AppState>, Json(req): Json<TableReq>) -> Response {
+    let full = match read_path(&st.config, &req.path) {
+        Ok(p) => p,
+        Err(status) => return status.into_response(),
+    };
+    let res = tokio::task::spawn_blocking(move || -> Result<Vec<TableReport>, Response> {
+        let raw = fs::read(&full).map_err(|_| StatusCode::NOT_FOUND.into_response())?;
+        let doc: Value = serde_json::from_slice(&raw)
+            .ok()
+            .filter(|d: &Value| d["nodes"].is_array())
+            .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "not a decision graph").into_response())?;
+        let tables: Vec<&Value> = jdm::nodes(&doc)
+            .filter(|n| jdm::node_type(n) == "decisionTableNode")
+            .filter(|n| req.node_id.as_deref().is_none_or(|id| n["id"] == id))
+            .collect();
+        if tables.is_empty() && req.node_id.is_some() {
+            return Err((StatusCode::NOT_FOUND, "no decision table with that node id").into_response());
+        }
+        Ok(tables.into_iter().map(analyze).collect())
+    })
+    .await;
+    match res {
+        Ok(Ok(tables)) => Json(serde_json::json!({ "path": req.path, "tables": tables })).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
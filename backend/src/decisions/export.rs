@@ -0,0 +1,211 @@
+//! `GET /api/decisions/export`: a decision graph as Graphviz DOT or Mermaid
+//! text, for documentation that should follow the decision.
+//!
+//! Each node is labelled with its name and kind, shaped by kind (switches as
+//! diamonds, calls to other decisions as subroutines), and edges leaving a
+//! switch carry their statement's condition. With `durations=true` a node
+//! also shows how long it took the last time `/api/simulate` ran this
+//! decision with a trace; that's remembered in memory, for the most recent
+//! [`MAX_TRACES`] decisions, and gone on restart.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+    sync::Mutex,
+    time::Instant,
+};
+
+use crate::{decisions::split_rev, jdm, read_path, AppState};
+
+const MAX_TRACES: usize = 256;
+
+struct LastTrace {
+    at: Instant,
+    /// node id → the engine's `performance` for it
+    durations: HashMap<String, String>,
+}
+
+/// (revision, key) → its last trace
+static LAST: Mutex<BTreeMap<(u64, String), LastTrace>> = Mutex::new(BTreeMap::new());
+
+/// Remembers the node durations in `response`, when it has a trace.
+pub(crate) fn record(rev: u64, key: &str, response: &Value) {
+    let Some(trace) = response["trace"].as_object() else { return };
+    let durations = trace
+        .iter()
+        .filter_map(|(id, t)| Some((id.clone(), t["performance"].as_str()?.to_string())))
+        .collect();
+    let mut last = LAST.lock().unwrap();
+    let k = (rev, key.to_string());
+    if !last.contains_key(&k) && last.len() >= MAX_TRACES {
+        if let Some(oldest) = last.iter().min_by_key(|(_, t)| t.at).map(|(k, _)| k.clone()) {
+            last.remove(&oldest);
+        }
+    }
+    last.insert(k, LastTrace { at: Instant::now(), durations });
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ExportParams {
+    /// a stored decision, e.g. `0/loans/score.json`
+    path: String,
+    format: Format,
+    /// label nodes with their time in the last traced simulation
+    #[serde(default)]
+    durations: bool,
+}
+
+struct GraphNode<'a> {
+    id: &'a str,
+    name: &'a str,
+    kind: &'a str,
+    duration: Option<&'a str>,
+}
+
+struct GraphEdge<'a> {
+    from: &'a str,
+    to: &'a str,
+    label: Option<String>,
+}
+
+/// The nodes and edges of `doc`, edges to nodes that aren't there dropped.
+fn graph<'a>(doc: &'a Value, durations: Option<&'a HashMap<String, String>>) -> (Vec<GraphNode<'a>>, Vec<GraphEdge<'a>>) {
+    let nodes: Vec<GraphNode> = jdm::nodes(doc)
+        .filter_map(|n| {
+            let id = n["id"].as_str()?;
+            Some(GraphNode {
+                id,
+                name: n["name"].as_str().unwrap_or(id),
+                kind: jdm::node_type(n),
+                duration: durations.and_then(|d| d.get(id)).map(String::as_str),
+            })
+        })
+        .collect();
+    // switch statement id → its condition, or `else` for the empty one
+    let conditions: HashMap<&str, &str> = jdm::nodes(doc)
+        .filter(|n| jdm::node_type(n) == "switchNode")
+        .flat_map(|n| n["content"]["statements"].as_array().into_iter().flatten())
+        .filter_map(|s| Some((s["id"].as_str()?, s["condition"].as_str().map(str::trim).filter(|c| !c.is_empty()).unwrap_or("else"))))
+        .collect();
+    let known = |id: &str| nodes.iter().any(|n| n.id == id);
+    let edges = doc["edges"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| {
+            let (from, to) = (e["sourceId"].as_str()?, e["targetId"].as_str()?);
+            let label = e["sourceHandle"].as_str().and_then(|h| conditions.get(h)).map(|c| c.to_string());
+            (known(from) && known(to)).then_some(GraphEdge { from, to, label })
+        })
+        .collect();
+    (nodes, edges)
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn dot(title: &str, nodes: &[GraphNode], edges: &[GraphEdge]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph \"{}\" {{", dot_escape(title));
+    out.push_str("  rankdir=LR;\n  node [shape=box, style=rounded, fontname=\"Helvetica\"];\n  edge [fontname=\"Helvetica\", fontsize=10];\n");
+    for n in nodes {
+        let shape = match n.kind {
+            "inputNode" | "outputNode" => "ellipse",
+            "switchNode" => "diamond",
+            "decisionNode" => "component",
+            _ => "box",
+        };
+        let mut label = format!("{}\n({})", n.name, n.kind);
+        if let Some(d) = n.duration {
+            label.push('\n');
+            label.push_str(d);
+        }
+        let _ = writeln!(out, "  \"{}\" [label=\"{}\", shape={shape}];", dot_escape(n.id), dot_escape(&label));
+    }
+    for e in edges {
+        let label = e.label.as_deref().map(|l| format!(" [label=\"{}\"]", dot_escape(l))).unwrap_or_default();
+        let _ = writeln!(out, "  \"{}\" -> \"{}\"{label};", dot_escape(e.from), dot_escape(e.to));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid takes entity codes in quoted labels, not backslash escapes.
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;").replace('\n', " ")
+}
+
+fn mermaid(nodes: &[GraphNode], edges: &[GraphEdge]) -> String {
+    // node ids may hold anything; Mermaid ids can't
+    let ids: HashMap<&str, String> = nodes.iter().enumerate().map(|(i, n)| (n.id, format!("n{i}"))).collect();
+    let mut out = String::from("flowchart LR\n");
+    for n in nodes {
+        let mut label = format!("{}<br/><small>{}</small>", mermaid_escape(n.name), n.kind);
+        if let Some(d) = n.duration {
+            let _ = write!(label, "<br/>{}", mermaid_escape(d));
+        }
+        let (open, close) = match n.kind {
+            "inputNode" | "outputNode" => ("([", "])"),
+            "switchNode" => ("{", "}"),
+            "decisionNode" => ("[[", "]]"),
+            _ => ("[", "]"),
+        };
+        let _ = writeln!(out, "  {}{open}\"{label}\"{close}", ids[n.id]);
+    }
+    for e in edges {
+        let label = e.label.as_deref().map(|l| format!("|\"{}\"|", mermaid_escape(l))).unwrap_or_default();
+        let _ = writeln!(out, "  {} -->{label} {}", ids[e.from], ids[e.to]);
+    }
+    out
+}
+
+pub(crate) async fn export(State(st): State<AppState>, Query(q): Query<ExportParams>) -> Response {
+    let full = match read_path(&st.config, &q.path) {
+        Ok(p) => p,
+        Err(status) => return status.into_response(),
+    };
+    let Some((rev, key)) = split_rev(&full) else {
+        return (StatusCode::BAD_REQUEST, "path must name a file inside a revision").into_response();
+    };
+    let (format, with_durations) = (q.format, q.durations);
+    let res = tokio::task::spawn_blocking(move || -> Result<String, Response> {
+        let raw = fs::read(&full).map_err(|_| StatusCode::NOT_FOUND.into_response())?;
+        let doc: Value = serde_json::from_slice(&raw)
+            .ok()
+            .filter(|d: &Value| d["nodes"].is_array())
+            .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "not a decision graph").into_response())?;
+        let last = LAST.lock().unwrap();
+        let durations = with_durations.then(|| last.get(&(rev, key.clone())).map(|t| &t.durations)).flatten();
+        let (nodes, edges) = graph(&doc, durations);
+        Ok(match format {
+            Format::Dot => dot(&key, &nodes, &edges),
+            Format::Mermaid => mermaid(&nodes, &edges),
+        })
+    })
+    .await;
+    let content_type = match format {
+        Format::Dot => "text/vnd.graphviz; charset=utf-8",
+        Format::Mermaid => "text/plain; charset=utf-8",
+    };
+    match res {
+        Ok(Ok(text)) => ([(header::CONTENT_TYPE, content_type)], text).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,240 @@
+//! `GET /api/decisions/metrics`: how big and how tangled decisions are, per
+//! file and added up over a folder, for governance dashboards.
+//!
+//! `path` is a stored decision or a folder (by default the working copy,
+//! `0`); a folder's decisions are every `*.json` under it holding a graph,
+//! other files are skipped. Per decision there are nodes by kind, edges,
+//! table rules and cells, expressions, switch branches, function lines,
+//! calls to other decisions, and the depth of the graph: the most edges on
+//! any path from the input node. Nodes on a cycle don't count towards the
+//! depth.
+//!
+//! The complexity score is meant for ranking, not as a unit of anything:
+//!
+//! ```text
+//! nodes + depth + table rules + switch branches + expressions
+//!       + ⌈function lines / 10⌉ + 5 × decision calls
+//! ```
+//!
+//! Totals add everything up, except depth and the largest table, which are
+//! the largest of any file.
+
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path as StdPath,
+};
+
+use crate::{jdm, read_path, search, AppState, STORAGE_ROOT};
+
+/// Files listed as the most complex.
+const TOP: usize = 10;
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Counts {
+    nodes: usize,
+    /// node type → how many
+    node_kinds: BTreeMap<String, usize>,
+    edges: usize,
+    tables: usize,
+    table_rules: usize,
+    /// rules in the largest table
+    max_table_rules: usize,
+    /// non-empty rule cells
+    table_cells: usize,
+    /// expression node entries
+    expressions: usize,
+    /// switch statements
+    switch_branches: usize,
+    function_lines: usize,
+    decision_calls: usize,
+    max_depth: usize,
+    complexity: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.nodes += other.nodes;
+        for (kind, n) in &other.node_kinds {
+            *self.node_kinds.entry(kind.clone()).or_default() += n;
+        }
+        self.edges += other.edges;
+        self.tables += other.tables;
+        self.table_rules += other.table_rules;
+        self.max_table_rules = self.max_table_rules.max(other.max_table_rules);
+        self.table_cells += other.table_cells;
+        self.expressions += other.expressions;
+        self.switch_branches += other.switch_branches;
+        self.function_lines += other.function_lines;
+        self.decision_calls += other.decision_calls;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.complexity += other.complexity;
+    }
+}
+
+/// The most edges on a path from an input node, over the nodes that aren't
+/// on a cycle (Kahn's order, longest distance first).
+fn depth(doc: &Value) -> usize {
+    let edges: Vec<(&str, &str)> = doc["edges"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| Some((e["sourceId"].as_str()?, e["targetId"].as_str()?)))
+        .collect();
+    let mut next: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut indegree: HashMap<&str, usize> = jdm::nodes(doc).filter_map(|n| Some((n["id"].as_str()?, 0))).collect();
+    for &(from, to) in &edges {
+        if indegree.contains_key(from) && indegree.contains_key(to) {
+            next.entry(from).or_default().push(to);
+            *indegree.get_mut(to).expect("counted above") += 1;
+        }
+    }
+    let inputs: Vec<&str> = jdm::nodes(doc).filter(|n| jdm::node_type(n) == "inputNode").filter_map(|n| n["id"].as_str()).collect();
+    // distance from an input; None for nodes no input reaches
+    let mut dist: HashMap<&str, Option<usize>> = indegree.keys().map(|&n| (n, inputs.contains(&n).then_some(0))).collect();
+    let mut ready: Vec<&str> = indegree.iter().filter(|(_, &d)| d == 0).map(|(&n, _)| n).collect();
+    let mut deepest = 0;
+    while let Some(n) = ready.pop() {
+        let here = dist[n];
+        deepest = deepest.max(here.unwrap_or(0));
+        for &m in next.get(n).into_iter().flatten() {
+            if let Some(d) = here {
+                let slot = dist.get_mut(m).expect("every target is a node");
+                *slot = Some(slot.map_or(d + 1, |s| s.max(d + 1)));
+            }
+            let left = indegree.get_mut(m).expect("every target is a node");
+            *left -= 1;
+            if *left == 0 {
+                ready.push(m);
+            }
+        }
+    }
+    deepest
+}
+
+fn measure(doc: &Value) -> Counts {
+    let mut c = Counts { edges: doc["edges"].as_array().map_or(0, Vec::len), max_depth: depth(doc), ..Counts::default() };
+    for node in jdm::nodes(doc) {
+        let kind = jdm::node_type(node);
+        c.nodes += 1;
+        *c.node_kinds.entry(kind.to_string()).or_default() += 1;
+        let content = &node["content"];
+        let list = |k: &str| content[k].as_array().map(Vec::as_slice).unwrap_or_default();
+        match kind {
+            "decisionTableNode" => {
+                let rules = list("rules");
+                c.tables += 1;
+                c.table_rules += rules.len();
+                c.max_table_rules = c.max_table_rules.max(rules.len());
+            }
+            "expressionNode" => c.expressions += list("expressions").len(),
+            "switchNode" => c.switch_branches += list("statements").len(),
+            "functionNode" => {
+                let source = content.as_str().or_else(|| content["source"].as_str()).unwrap_or_default();
+                c.function_lines += source.lines().filter(|l| !l.trim().is_empty()).count();
+            }
+            "decisionNode" => c.decision_calls += 1,
+            _ => {}
+        }
+    }
+    c.table_cells = jdm::sites(doc).iter().filter(|s| matches!(s.kind, jdm::SiteKind::InputCell | jdm::SiteKind::OutputCell)).count();
+    c.complexity = c.nodes
+        + c.max_depth
+        + c.table_rules
+        + c.switch_branches
+        + c.expressions
+        + c.function_lines.div_ceil(10)
+        + 5 * c.decision_calls;
+    c
+}
+
+fn decision(path: &StdPath) -> Option<Value> {
+    let len = fs::metadata(path).ok()?.len();
+    if len > search::MAX_FILE_BYTES {
+        return None;
+    }
+    let doc: Value = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    doc["nodes"].is_array().then_some(doc)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MetricsParams {
+    /// a stored decision or folder, e.g. `0/loans`; the working copy when
+    /// absent
+    path: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileMetrics {
+    path: String,
+    #[serde(flatten)]
+    counts: Counts,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Ranked {
+    path: String,
+    complexity: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricsResp {
+    path: String,
+    /// by path
+    files: Vec<FileMetrics>,
+    total: Counts,
+    /// `total.complexity` over the files, rounded to a tenth
+    average_complexity: f64,
+    /// the highest scores first
+    most_complex: Vec<Ranked>,
+}
+
+pub(crate) async fn metrics(State(st): State<AppState>, Query(q): Query<MetricsParams>) -> Response {
+    let path = q.path.unwrap_or_else(|| "0".into());
+    let full = match read_path(&st.config, &path) {
+        Ok(p) => p,
+        Err(status) => return status.into_response(),
+    };
+    let follow = st.config.follow_symlinks;
+    let res = tokio::task::spawn_blocking(move || -> Result<MetricsResp, Response> {
+        let rel = |p: &StdPath| p.strip_prefix(STORAGE_ROOT).unwrap_or(p).to_string_lossy().replace('\\', "/");
+        let files: Vec<FileMetrics> = if full.is_file() {
+            let doc = decision(&full).ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "not a decision graph").into_response())?;
+            vec![FileMetrics { path: rel(&full), counts: measure(&doc) }]
+        } else if full.is_dir() {
+            search::files(&full, follow)
+                .filter(|e| e.path().extension().is_some_and(|x| x.eq_ignore_ascii_case("json")))
+                .filter_map(|e| Some(FileMetrics { path: rel(e.path()), counts: measure(&decision(e.path())?) }))
+                .collect()
+        } else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+        let mut total = Counts::default();
+        files.iter().for_each(|f| total.add(&f.counts));
+        let average_complexity = match files.len() {
+            0 => 0.0,
+            n => (total.complexity as f64 * 10.0 / n as f64).round() / 10.0,
+        };
+        let mut most_complex: Vec<Ranked> = files.iter().map(|f| Ranked { path: f.path.clone(), complexity: f.counts.complexity }).collect();
+        most_complex.sort_by(|a, b| b.complexity.cmp(&a.complexity).then_with(|| a.path.cmp(&b.path)));
+        most_complex.truncate(TOP);
+        Ok(MetricsResp { path: path.trim_matches('/').to_string(), files, total, average_complexity, most_complex })
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,602 @@
+//! `GET /api/decisions/schema`: the context a decision expects, guessed from
+//! what it reads.
+//!
+//! Every expression, table cell, switch condition and column field of a node
+//! that gets to see the request context is parsed, and each field path it
+//! reads becomes a property of the schema. Types come from how a path is
+//! used: compared with a number or in arithmetic it's a number, matched
+//! against a string literal a string, an operand of `and` a boolean, the
+//! first argument of `filter` or `sum` an array; a path used in no telling
+//! way gets no type. Decision nodes are followed into the decisions they
+//! call, with whatever scope `inputField` gives them.
+//!
+//! It's a static guess. A node reached only through one that doesn't pass
+//! its input on (`passThrough`) sees that node's output, not the context, so
+//! it adds nothing; paths a node upstream writes are left out too. Function
+//! and custom nodes can read anything, and are listed as `unanalyzed`
+//! instead.
+
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use bumpalo::Bump;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+};
+use zen_expression::{
+    lexer::{ArithmeticOperator, ComparisonOperator, Lexer, LogicalOperator, Operator},
+    parser::{BuiltInFunction, Node, Parser},
+};
+
+use crate::{
+    decisions::{normalize, split_rev},
+    jdm, read_path, AppState, STORAGE_ROOT,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Seg {
+    Key(String),
+    /// any element of an array
+    Item,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Array,
+    Boolean,
+    Number,
+    Object,
+    String,
+}
+
+#[derive(Default)]
+struct Shape {
+    kinds: BTreeSet<Kind>,
+    props: BTreeMap<String, Shape>,
+    items: Option<Box<Shape>>,
+    /// where it's read directly, as (file, node id)
+    read_by: BTreeSet<(String, String)>,
+}
+
+impl Shape {
+    fn add(&mut self, path: &[Seg], kind: Option<Kind>, at: &(String, String)) {
+        let leaf = path.iter().fold(self, |cur, seg| match seg {
+            Seg::Key(k) => cur.props.entry(k.clone()).or_default(),
+            Seg::Item => cur.items.get_or_insert_with(Default::default),
+        });
+        leaf.kinds.extend(kind);
+        leaf.read_by.insert(at.clone());
+    }
+
+    fn kinds(&self) -> Vec<Kind> {
+        let mut kinds = self.kinds.clone();
+        if !self.props.is_empty() {
+            kinds.insert(Kind::Object);
+        }
+        if self.items.is_some() {
+            kinds.insert(Kind::Array);
+        }
+        kinds.into_iter().collect()
+    }
+
+    fn schema(&self) -> Value {
+        let mut out = Map::new();
+        match self.kinds().as_slice() {
+            [] => {}
+            [one] => {
+                out.insert("type".into(), json!(one));
+            }
+            many => {
+                out.insert("type".into(), json!(many));
+            }
+        }
+        if !self.props.is_empty() {
+            let props: Map<String, Value> = self.props.iter().map(|(k, s)| (k.clone(), s.schema())).collect();
+            out.insert("properties".into(), Value::Object(props));
+        }
+        if let Some(items) = &self.items {
+            out.insert("items".into(), items.schema());
+        }
+        Value::Object(out)
+    }
+
+    /// Every path read directly, as `a.b[].c`.
+    fn fields(&self, path: &str, out: &mut Vec<Field>) {
+        if !self.read_by.is_empty() {
+            out.push(Field {
+                path: path.to_string(),
+                types: self.kinds(),
+                read_by: self.read_by.iter().map(|(filepath, node_id)| ReadBy { filepath: filepath.clone(), node_id: node_id.clone() }).collect(),
+            });
+        }
+        for (k, s) in &self.props {
+            s.fields(&if path.is_empty() { k.clone() } else { format!("{path}.{k}") }, out);
+        }
+        if let Some(items) = &self.items {
+            items.fields(&format!("{path}[]"), out);
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadBy {
+    filepath: String,
+    node_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Field {
+    path: String,
+    /// empty when nothing tells
+    types: Vec<Kind>,
+    read_by: Vec<ReadBy>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Unanalyzed {
+    filepath: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    node_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    node_name: String,
+    /// `functionNode` or `customNode` (reads can't be seen), `dynamicScope`
+    /// (an `inputField` that isn't a plain path), `notFound` (a called
+    /// decision that doesn't load), `recursive` (one already being read)
+    reason: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaResp {
+    path: String,
+    /// JSON Schema of the context
+    schema: Value,
+    /// the same, flattened, for autocomplete
+    fields: Vec<Field>,
+    unanalyzed: Vec<Unanalyzed>,
+}
+
+fn parse<'b>(source: &'b str, unary: bool, bump: &'b Bump) -> Option<&'b Node<'b>> {
+    let mut lexer = Lexer::new();
+    let tokens = lexer.tokenize(source).ok()?;
+    let parser = Parser::try_new(tokens, bump).ok()?;
+    let parsed = if unary { parser.unary().parse() } else { parser.standard().parse() };
+    parsed.error().ok()?;
+    Some(parsed.root)
+}
+
+/// What a literal is, looking through parentheses and a sign.
+fn literal(node: &Node) -> Option<Kind> {
+    match node {
+        Node::Number(_) => Some(Kind::Number),
+        Node::String(_) | Node::TemplateString(_) => Some(Kind::String),
+        Node::Bool(_) => Some(Kind::Boolean),
+        Node::Array(_) => Some(Kind::Array),
+        Node::Object(_) => Some(Kind::Object),
+        Node::Parenthesized(n) => literal(n),
+        Node::Unary { node, operator: Operator::Arithmetic(_) } => literal(node).filter(|k| *k == Kind::Number),
+        _ => None,
+    }
+}
+
+/// What the left of `in` is, from the right.
+fn member_of(node: &Node) -> Option<Kind> {
+    match node {
+        Node::Interval { left, right, .. } => literal(left).or_else(|| literal(right)),
+        Node::Array(items) => {
+            let kinds: BTreeSet<Option<Kind>> = items.iter().map(|i| literal(i)).collect();
+            match kinds.into_iter().collect::<Vec<_>>().as_slice() {
+                [one] => *one,
+                _ => None,
+            }
+        }
+        Node::Parenthesized(n) => member_of(n),
+        _ => None,
+    }
+}
+
+/// Where names resolve inside one expression.
+#[derive(Clone)]
+struct Scope {
+    /// what a bare identifier is under
+    prefix: Vec<Seg>,
+    /// `$` in a unary test: the column's field
+    reference: Option<Vec<Seg>>,
+    /// `#` in a closure: an element of the array it iterates
+    pointer: Option<Vec<Seg>>,
+}
+
+/// Reads of one node, with the types they were used as.
+struct Reads<'r> {
+    shape: &'r mut Shape,
+    at: (String, String),
+    /// top-level keys written upstream; reads of them aren't context
+    shadowed: &'r BTreeSet<String>,
+}
+
+impl Reads<'_> {
+    fn path(node: &Node, scope: &Scope) -> Option<Vec<Seg>> {
+        match node {
+            Node::Identifier("$") => scope.reference.clone(),
+            // `$nodes` and the like are the graph's, not the context's
+            Node::Identifier(name) if name.starts_with('$') => None,
+            Node::Identifier(name) => Some(scope.prefix.iter().cloned().chain([Seg::Key(name.to_string())]).collect()),
+            Node::Root => Some(scope.prefix.clone()),
+            Node::Pointer => scope.pointer.clone(),
+            Node::Parenthesized(n) => Self::path(n, scope),
+            Node::Member { node, property } => {
+                let mut p = Self::path(node, scope)?;
+                match property {
+                    Node::String(k) => p.push(Seg::Key(k.to_string())),
+                    Node::Number(_) => p.push(Seg::Item),
+                    _ => return None,
+                }
+                Some(p)
+            }
+            _ => None,
+        }
+    }
+
+    fn add(&mut self, path: &[Seg], kind: Option<Kind>) {
+        if path.is_empty() {
+            return;
+        }
+        if let Some(Seg::Key(first)) = path.first() {
+            if self.shadowed.contains(first) {
+                return;
+            }
+        }
+        self.shape.add(path, kind, &self.at);
+    }
+
+    fn visit(&mut self, node: &Node, scope: &Scope, kind: Option<Kind>) {
+        if let Some(p) = Self::path(node, scope) {
+            self.add(&p, kind);
+            return;
+        }
+        match node {
+            Node::Binary { left, operator, right } => {
+                let (l, r) = match operator {
+                    Operator::Arithmetic(ArithmeticOperator::Add) => {
+                        let either = |k: Option<Kind>| k.filter(|k| matches!(k, Kind::Number | Kind::String));
+                        (either(literal(right)), either(literal(left)))
+                    }
+                    Operator::Arithmetic(_) => (Some(Kind::Number), Some(Kind::Number)),
+                    Operator::Logical(LogicalOperator::And | LogicalOperator::Or) => (Some(Kind::Boolean), Some(Kind::Boolean)),
+                    Operator::Comparison(ComparisonOperator::In) => (member_of(right), None),
+                    Operator::Comparison(ComparisonOperator::Equal | ComparisonOperator::NotEqual) => (literal(right), literal(left)),
+                    Operator::Comparison(_) => {
+                        let ordered = |k: Option<Kind>| k.filter(|k| matches!(k, Kind::Number | Kind::String));
+                        (ordered(literal(right)), ordered(literal(left)))
+                    }
+                    Operator::Logical(LogicalOperator::NullishCoalescing) => (literal(right), None),
+                    _ => (None, None),
+                };
+                let r = match (operator, right) {
+                    // `x in list`: the list is an array unless it's spelled out
+                    (Operator::Comparison(ComparisonOperator::In), n) if member_of(n).is_none() && literal(n).is_none() => Some(Kind::Array),
+                    _ => r,
+                };
+                self.visit(left, scope, l);
+                self.visit(right, scope, r);
+            }
+            Node::Unary { node, operator } => {
+                let kind = match operator {
+                    Operator::Logical(LogicalOperator::Not) => Some(Kind::Boolean),
+                    Operator::Arithmetic(_) => Some(Kind::Number),
+                    _ => None,
+                };
+                self.visit(node, scope, kind);
+            }
+            Node::Conditional { condition, on_true, on_false } => {
+                self.visit(condition, scope, Some(Kind::Boolean));
+                self.visit(on_true, scope, kind);
+                self.visit(on_false, scope, kind);
+            }
+            Node::BuiltIn { kind: f, arguments } => self.builtin(*f, arguments, scope),
+            Node::Parenthesized(n) | Node::Closure(n) => self.visit(n, scope, kind),
+            Node::Member { node, property } => {
+                self.visit(node, scope, None);
+                self.visit(property, scope, None);
+            }
+            Node::Slice { node, from, to } => {
+                self.visit(node, scope, None);
+                for n in from.iter().chain(to) {
+                    self.visit(n, scope, Some(Kind::Number));
+                }
+            }
+            Node::Interval { left, right, .. } => {
+                self.visit(left, scope, None);
+                self.visit(right, scope, None);
+            }
+            Node::Array(items) | Node::TemplateString(items) => items.iter().for_each(|n| self.visit(n, scope, None)),
+            Node::Object(entries) => entries.iter().for_each(|(k, v)| {
+                self.visit(k, scope, None);
+                self.visit(v, scope, None);
+            }),
+            _ => {}
+        }
+    }
+
+    fn builtin(&mut self, f: BuiltInFunction, args: &[&Node], scope: &Scope) {
+        use BuiltInFunction as F;
+        let first = match f {
+            F::Upper | F::Lower | F::Trim | F::StartsWith | F::EndsWith | F::Matches | F::Extract | F::FuzzyMatch | F::Split => {
+                Some(Kind::String)
+            }
+            F::Abs | F::Floor | F::Ceil | F::Round => Some(Kind::Number),
+            F::Sum | F::Avg | F::Min | F::Max | F::Median | F::Mode | F::Flatten => Some(Kind::Array),
+            F::Keys | F::Values => Some(Kind::Object),
+            F::All | F::Some | F::None | F::Filter | F::Map | F::Count | F::One | F::FlatMap => Some(Kind::Array),
+            _ => None,
+        };
+        let Some((head, rest)) = args.split_first() else { return };
+        self.visit(head, scope, first);
+        let closure = matches!(f, F::All | F::Some | F::None | F::Filter | F::Map | F::Count | F::One | F::FlatMap);
+        let inner = match Self::path(head, scope) {
+            Some(p) if closure => {
+                let pointer = p.into_iter().chain([Seg::Item]).collect();
+                Scope { pointer: Some(pointer), ..scope.clone() }
+            }
+            _ => Scope { pointer: None, ..scope.clone() },
+        };
+        let predicate = matches!(f, F::All | F::Some | F::None | F::Filter | F::Count | F::One);
+        for arg in rest {
+            let kind = (closure && predicate).then_some(Kind::Boolean);
+            self.visit(arg, if closure { &inner } else { scope }, kind);
+        }
+    }
+
+    /// `source` as an expression (or a unary test when `reference` is set)
+    /// read under `scope`.
+    fn expression(&mut self, source: &str, scope: &Scope, unary: bool, kind: Option<Kind>) {
+        if source.trim().is_empty() {
+            return;
+        }
+        let bump = Bump::new();
+        if let Some(root) = parse(source, unary, &bump) {
+            self.visit(root, scope, kind);
+        }
+    }
+}
+
+/// Top-level keys a node writes: its `outputPath`, or its expressions' keys
+/// and its table's output fields.
+fn writes(node: &Value) -> BTreeSet<String> {
+    let content = &node["content"];
+    let root = |s: &str| s.trim().split('.').next().unwrap_or_default().to_string();
+    if let Some(out) = content["outputPath"].as_str().filter(|s| !s.trim().is_empty()) {
+        return BTreeSet::from([root(out)]);
+    }
+    let list = |k: &str, field: &str| {
+        content[k].as_array().into_iter().flatten().filter_map(|e| e[field].as_str()).map(root).collect::<Vec<_>>()
+    };
+    match jdm::node_type(node) {
+        "expressionNode" => list("expressions", "key").into_iter().collect(),
+        "decisionTableNode" => list("outputs", "field").into_iter().collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+struct Flow {
+    /// the node's input includes the context
+    sees_context: bool,
+    /// top-level keys written by nodes upstream
+    shadowed: BTreeSet<String>,
+}
+
+/// Which nodes see the context, found by pushing it along edges until
+/// nothing changes: the input node has it, switches pass it on, other
+/// nodes only with `passThrough`.
+fn flow(doc: &Value) -> HashMap<String, Flow> {
+    let nodes: HashMap<&str, &Value> = jdm::nodes(doc).filter_map(|n| Some((n["id"].as_str()?, n))).collect();
+    let edges: Vec<(&str, &str)> = doc["edges"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| Some((e["sourceId"].as_str()?, e["targetId"].as_str()?)))
+        .collect();
+    let mut flow: HashMap<String, Flow> = nodes.keys().map(|id| (id.to_string(), Flow { sees_context: false, shadowed: BTreeSet::new() })).collect();
+    let passes = |n: &Value| matches!(jdm::node_type(n), "switchNode") || n["content"]["passThrough"].as_bool().unwrap_or(false);
+    loop {
+        let mut changed = false;
+        for (from, to) in &edges {
+            let (Some(src), Some(f)) = (nodes.get(from), flow.get(*from)) else { continue };
+            let is_input = jdm::node_type(src) == "inputNode";
+            let carries = is_input || (passes(src) && f.sees_context);
+            let mut written = writes(src);
+            if passes(src) {
+                written.extend(f.shadowed.iter().cloned());
+            }
+            let Some(t) = flow.get_mut(*to) else { continue };
+            if carries && !t.sees_context {
+                t.sees_context = true;
+                changed = true;
+            }
+            for w in written {
+                changed |= t.shadowed.insert(w);
+            }
+        }
+        if !changed {
+            return flow;
+        }
+    }
+}
+
+struct Infer {
+    rev: u64,
+    shape: Shape,
+    unanalyzed: Vec<Unanalyzed>,
+    /// decisions being read, to stop at a loop
+    stack: Vec<String>,
+    max_depth: usize,
+}
+
+impl Infer {
+    fn decision(&mut self, key: &str, prefix: &[Seg]) {
+        let doc = fs::read(format!("{STORAGE_ROOT}/{}/{key}", self.rev))
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+            .filter(|d| d["nodes"].is_array());
+        let Some(doc) = doc else {
+            self.unanalyzed.push(Unanalyzed { filepath: key.to_string(), node_id: String::new(), node_name: String::new(), reason: "notFound" });
+            return;
+        };
+        self.stack.push(key.to_string());
+        let flow = flow(&doc);
+        for node in jdm::nodes(&doc) {
+            let id = node["id"].as_str().unwrap_or_default();
+            let Some(f) = flow.get(id).filter(|f| f.sees_context) else { continue };
+            self.node(key, node, prefix, &f.shadowed);
+        }
+        self.stack.pop();
+    }
+
+    fn node(&mut self, key: &str, node: &Value, prefix: &[Seg], shadowed: &BTreeSet<String>) {
+        let str_of = |v: &Value| v.as_str().unwrap_or_default().to_string();
+        let skip = |reason| Unanalyzed { filepath: key.to_string(), node_id: str_of(&node["id"]), node_name: str_of(&node["name"]), reason };
+        let content = &node["content"];
+        let no_shadow = BTreeSet::new();
+        let mut reads = Reads { shape: &mut self.shape, at: (key.to_string(), str_of(&node["id"])), shadowed };
+        let base = Scope { prefix: prefix.to_vec(), reference: None, pointer: None };
+
+        // `inputField` narrows what the node sees to one field, or to each
+        // item of one in loop mode
+        let mut scope = base.clone();
+        if let Some(field) = content["inputField"].as_str().filter(|s| !s.trim().is_empty()) {
+            let looped = content["executionMode"].as_str() == Some("loop");
+            let bump = Bump::new();
+            let path = parse(field, false, &bump).and_then(|root| Reads::path(root, &base));
+            reads.expression(field, &base, false, Some(if looped { Kind::Array } else { Kind::Object }));
+            let Some(mut path) = path else {
+                self.unanalyzed.push(skip("dynamicScope"));
+                return;
+            };
+            if looped {
+                path.push(Seg::Item);
+            }
+            scope.prefix = path;
+            // what upstream wrote is at the top, not under the field
+            reads.shadowed = &no_shadow;
+        }
+
+        match jdm::node_type(node) {
+            "expressionNode" => {
+                for e in content["expressions"].as_array().into_iter().flatten() {
+                    reads.expression(e["value"].as_str().unwrap_or_default(), &scope, false, None);
+                }
+            }
+            "switchNode" => {
+                for s in content["statements"].as_array().into_iter().flatten() {
+                    reads.expression(s["condition"].as_str().unwrap_or_default(), &scope, false, Some(Kind::Boolean));
+                }
+            }
+            "decisionTableNode" => {
+                let columns = |k: &str| content[k].as_array().map(Vec::as_slice).unwrap_or_default();
+                let rules = content["rules"].as_array().map(Vec::as_slice).unwrap_or_default();
+                for c in columns("inputs") {
+                    let Some(col) = c["id"].as_str() else { continue };
+                    let field = c["field"].as_str().filter(|f| !f.trim().is_empty());
+                    // cells under a column with a field are unary tests of it
+                    let cell_scope = match field {
+                        Some(f) => {
+                            reads.expression(f, &scope, false, None);
+                            let bump = Bump::new();
+                            let reference = parse(f, false, &bump).and_then(|root| Reads::path(root, &scope));
+                            Scope { reference, ..scope.clone() }
+                        }
+                        None => scope.clone(),
+                    };
+                    let kind = field.is_none().then_some(Kind::Boolean);
+                    for rule in rules {
+                        reads.expression(rule[col].as_str().unwrap_or_default(), &cell_scope, field.is_some(), kind);
+                    }
+                }
+                for c in columns("outputs") {
+                    let Some(col) = c["id"].as_str() else { continue };
+                    for rule in rules {
+                        reads.expression(rule[col].as_str().unwrap_or_default(), &scope, false, None);
+                    }
+                }
+            }
+            "decisionNode" => {
+                let Some(called) = content["key"].as_str().and_then(normalize) else {
+                    self.unanalyzed.push(skip("notFound"));
+                    return;
+                };
+                if self.stack.contains(&called) || self.stack.len() >= self.max_depth {
+                    self.unanalyzed.push(skip("recursive"));
+                    return;
+                }
+                self.decision(&called, &scope.prefix);
+            }
+            "functionNode" => self.unanalyzed.push(skip("functionNode")),
+            "customNode" => self.unanalyzed.push(skip("customNode")),
+            _ => {}
+        }
+    }
+}
+
+/// Blocking. What `key` in revision `rev` reads of its context.
+fn analyze(rev: u64, key: &str, max_depth: usize) -> Infer {
+    let mut infer = Infer { rev, shape: Shape::default(), unanalyzed: Vec::new(), stack: Vec::new(), max_depth };
+    infer.decision(key, &[]);
+    infer
+}
+
+/// `shape` as the schema of a whole context: an object, always.
+fn document(shape: &Shape) -> Value {
+    let mut schema = shape.schema();
+    if let Some(obj) = schema.as_object_mut() {
+        obj.insert("$schema".into(), json!("https://json-schema.org/draft/2020-12/schema"));
+        obj.insert("type".into(), json!("object"));
+        obj.entry("properties").or_insert_with(|| json!({}));
+    }
+    schema
+}
+
+/// Blocking. The JSON Schema `GET /api/decisions/schema` answers for `key`
+/// in revision `rev`: `type`, `properties` and `items`, nothing else.
+pub(crate) fn context_schema(rev: u64, key: &str, max_depth: usize) -> Value {
+    document(&analyze(rev, key, max_depth).shape)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SchemaParams {
+    /// a stored decision, e.g. `0/loans/score.json`
+    path: String,
+}
+
+pub(crate) async fn infer(State(st): State<AppState>, Query(q): Query<SchemaParams>) -> Response {
+    let full = match read_path(&st.config, &q.path) {
+        Ok(p) => p,
+        Err(status) => return status.into_response(),
+    };
+    let Some((rev, key)) = split_rev(&full) else {
+        return (StatusCode::BAD_REQUEST, "path must name a file inside a revision").into_response();
+    };
+    if !full.is_file() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let max_depth = usize::from(st.config.simulate_max_depth);
+    let res = tokio::task::spawn_blocking(move || {
+        let infer = analyze(rev, &key, max_depth);
+        let mut fields = Vec::new();
+        infer.shape.fields("", &mut fields);
+        SchemaResp { path: q.path, schema: document(&infer.shape), fields, unanalyzed: infer.unanalyzed }
+    })
+    .await;
+    match res {
+        Ok(resp) => Json(resp).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
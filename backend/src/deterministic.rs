@@ -0,0 +1,138 @@
+//! Deterministic evaluation: a fixed clock and seeded randomness, so a
+//! simulation or a stored test case gives the same answer every time.
+//!
+//! `deterministic: { now, seed }` on `/api/simulate` (and its batch and
+//! stream forms, and on a test case) does two things:
+//!
+//! - the decisions are loaded with every literal `"now"` handed to a date
+//!   function (`date("now")`, `time('now')`, `year("now")`, `startOf("now",
+//!   "day")` …) replaced by `now`, an RFC 3339 timestamp or unix seconds;
+//! - the evaluation gets a thread of its own, its random numbers seeded with
+//!   `seed` (default 0), so `rand()` draws the same sequence each run. Each
+//!   case of a batch starts from the seed, whatever order they run in.
+//!
+//! Only expressions are covered. A `"now"` that arrives in the context or is
+//! built at run time still reads the clock, and so does `Date` in a function
+//! node; custom nodes do what they do.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use zen_expression::lexer::{Bracket, Lexer, TokenKind};
+
+use crate::jdm;
+
+/// The built-ins that read the clock when given `"now"`.
+const DATE_FUNCTIONS: &[&str] = &[
+    "date",
+    "time",
+    "year",
+    "dayOfWeek",
+    "dayOfMonth",
+    "dayOfYear",
+    "weekOfYear",
+    "monthOfYear",
+    "monthString",
+    "dateString",
+    "weekdayString",
+    "startOf",
+    "endOf",
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Deterministic {
+    /// the instant `"now"` stands for
+    #[serde(deserialize_with = "instant", serialize_with = "rfc3339")]
+    pub(crate) now: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) seed: u64,
+}
+
+impl Deterministic {
+    /// `now` as the expression language parses it.
+    pub(crate) fn now_text(&self) -> String {
+        self.now.to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+}
+
+fn instant<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Instant {
+        Seconds(i64),
+        Text(String),
+    }
+    let parsed = match Instant::deserialize(d)? {
+        Instant::Seconds(s) => DateTime::from_timestamp(s, 0),
+        Instant::Text(t) => DateTime::parse_from_rfc3339(t.trim()).ok().map(|t| t.with_timezone(&Utc)),
+    };
+    parsed.ok_or_else(|| serde::de::Error::custom("now must be an RFC 3339 timestamp or unix seconds"))
+}
+
+fn rfc3339<S: Serializer>(now: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&now.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// `source` with `now` in place of each `"now"` a date function is called
+/// with, when there's one; `None` leaves it as it is, including when it
+/// doesn't lex.
+fn freeze_source(source: &str, now: &str) -> Option<String> {
+    if !source.contains("now") {
+        return None;
+    }
+    let mut lexer = Lexer::new();
+    let tokens = lexer.tokenize(source).ok()?;
+    // byte ranges of the `now`s, in order: name ( quote now quote
+    let spans: Vec<(usize, usize)> = tokens
+        .windows(5)
+        .filter(|w| {
+            w[0].kind == TokenKind::Literal
+                && DATE_FUNCTIONS.contains(&w[0].value)
+                && w[1].kind == TokenKind::Bracket(Bracket::LeftParenthesis)
+                && matches!(w[2].kind, TokenKind::QuotationMark(_))
+                && w[3].kind == TokenKind::Literal
+                && w[3].value == "now"
+                && w[4].kind == w[2].kind
+        })
+        .map(|w| (w[3].span.0 as usize, w[3].span.1 as usize))
+        .collect();
+    if spans.is_empty() {
+        return None;
+    }
+    let mut out = String::with_capacity(source.len() + spans.len() * now.len());
+    let mut at = 0;
+    for (start, end) in spans {
+        out.push_str(&source[at..start]);
+        out.push_str(now);
+        at = end;
+    }
+    out.push_str(&source[at..]);
+    Some(out)
+}
+
+fn freeze_value(v: &mut Value, now: &str) {
+    match v {
+        Value::String(s) => {
+            if let Some(frozen) = freeze_source(s, now) {
+                *s = frozen;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|i| freeze_value(i, now)),
+        Value::Object(m) => m.values_mut().for_each(|i| freeze_value(i, now)),
+        _ => {}
+    }
+}
+
+/// Fixes the clock in the expressions of `doc`, a decision graph. Function
+/// and custom nodes are left alone: their content isn't expressions.
+pub(crate) fn freeze(doc: &mut Value, now: &str) {
+    let Some(nodes) = doc["nodes"].as_array_mut() else { return };
+    for node in nodes {
+        if matches!(jdm::node_type(node), "functionNode" | "customNode") {
+            continue;
+        }
+        if let Some(content) = node.get_mut("content") {
+            freeze_value(content, now);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Decision loaders kept warm across simulations.
+//!
+//! Each revision that's been simulated against gets one [`RevisionLoader`],
+//! held in [`EngineCache`] and shared by every engine built for it, so a hot
+//! decision is read and parsed once rather than on every request. A cached
+//! document is reused while its file's modification time and size are what
+//! they were when it was parsed; the handlers that write the working copy also
+//! drop what they replaced ([`EngineCache::invalidate`]), which catches two
+//! writes within the filesystem's timestamp granularity. Revisions don't
+//! change, but get the same check: it's one `stat`.
+//!
+//! Only the last [`MAX_REVISIONS`] revisions used keep their loader.
+//!
+//! Keys come from decision nodes, which anyone editing a graph can set, so a
+//! loader resolves them inside its revision the way
+//! [`decisions::normalize`](crate::decisions::normalize) does (a leading `/`
+//! is the revision root) and won't load from outside it, through `..` or a
+//! symlink, or through any symlink unless `FOLLOW_SYMLINKS` is on.
+//!
+//! A [deterministic](crate::deterministic) evaluation gets a loader of its
+//! own ([`EngineCache::frozen_engine`]) that rewrites `"now"` as it reads,
+//! and so isn't cached past the request.
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path as StdPath, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Instant, SystemTime},
+};
+use zen_engine::{
+    loader::{DecisionLoader, LoaderError, LoaderResponse},
+    model::DecisionContent,
+    DecisionEngine,
+};
+
+use crate::{decisions, deterministic, ensure_contained, plugins::Registry, reject_symlinks, STORAGE_ROOT};
+
+const MAX_REVISIONS: usize = 16;
+
+pub(crate) type Engine = DecisionEngine<RevisionLoader, Registry>;
+
+/// What a cached document was parsed from.
+#[derive(Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(meta: &fs::Metadata) -> Self {
+        Self { modified: meta.modified().ok(), len: meta.len() }
+    }
+}
+
+/// Loads the decisions of one revision directory, keeping what it parsed.
+pub(crate) struct RevisionLoader {
+    root: PathBuf,
+    follow_symlinks: bool,
+    /// what date functions' `"now"` becomes, for a deterministic evaluation
+    now: Option<String>,
+    docs: RwLock<HashMap<String, (Stamp, Arc<DecisionContent>)>>,
+}
+
+impl RevisionLoader {
+    fn new(rev: u64, follow_symlinks: bool, now: Option<String>) -> Self {
+        Self { root: PathBuf::from(format!("{STORAGE_ROOT}/{rev}")), follow_symlinks, now, docs: RwLock::default() }
+    }
+
+    fn read(&self, key: &str) -> LoaderResponse {
+        let internal = |e: anyhow::Error| Box::new(LoaderError::Internal { key: key.to_string(), source: e });
+        let outside = || internal(anyhow::anyhow!("decision key leaves the revision"));
+        let key = decisions::normalize(key).ok_or_else(outside)?;
+        let path = self.root.join(&key);
+        if ensure_contained(&path).is_err() || (!self.follow_symlinks && reject_symlinks(&path).is_err()) {
+            return Err(outside());
+        }
+        let meta = match fs::metadata(&path) {
+            Ok(m) if m.is_file() => m,
+            _ => return Err(Box::new(LoaderError::NotFound(key.to_string()))),
+        };
+        let stamp = Stamp::of(&meta);
+        if let Some((s, doc)) = self.docs.read().unwrap().get(&key) {
+            if *s == stamp {
+                return Ok(doc.clone());
+            }
+        }
+        let raw = fs::read(&path).map_err(|e| internal(e.into()))?;
+        let parsed = match &self.now {
+            None => serde_json::from_slice(&raw),
+            Some(now) => serde_json::from_slice::<Value>(&raw).and_then(|mut v| {
+                deterministic::freeze(&mut v, now);
+                serde_json::from_value(v)
+            }),
+        };
+        let doc: Arc<DecisionContent> = Arc::new(parsed.map_err(|e| internal(e.into()))?);
+        self.docs.write().unwrap().insert(key, (stamp, doc.clone()));
+        Ok(doc)
+    }
+
+    /// Drops `key` and, if it's a folder, everything under it.
+    fn forget(&self, key: &str) {
+        let dir = format!("{key}/");
+        self.docs.write().unwrap().retain(|k, _| k != key && !k.starts_with(&dir));
+    }
+}
+
+impl DecisionLoader for RevisionLoader {
+    async fn load<'a>(&'a self, key: &'a str) -> LoaderResponse {
+        self.read(key)
+    }
+}
+
+struct Slot {
+    loader: Arc<RevisionLoader>,
+    /// when it was last handed out
+    used: Instant,
+}
+
+/// revision → its loader, and the custom node handlers every engine gets
+#[derive(Clone)]
+pub(crate) struct EngineCache {
+    loaders: Arc<Mutex<HashMap<u64, Slot>>>,
+    plugins: Arc<Registry>,
+    follow_symlinks: bool,
+}
+
+impl EngineCache {
+    pub(crate) fn new(plugins: Arc<Registry>, follow_symlinks: bool) -> Self {
+        Self { loaders: Arc::default(), plugins, follow_symlinks }
+    }
+
+    pub(crate) fn loader(&self, rev: u64) -> Arc<RevisionLoader> {
+        let mut all = self.loaders.lock().unwrap();
+        if let Some(slot) = all.get_mut(&rev) {
+            slot.used = Instant::now();
+            return slot.loader.clone();
+        }
+        if all.len() >= MAX_REVISIONS {
+            if let Some(&oldest) = all.iter().min_by_key(|(_, slot)| slot.used).map(|(r, _)| r) {
+                all.remove(&oldest);
+            }
+        }
+        let loader = Arc::new(RevisionLoader::new(rev, self.follow_symlinks, None));
+        all.insert(rev, Slot { loader: loader.clone(), used: Instant::now() });
+        loader
+    }
+
+    pub(crate) fn engine(&self, rev: u64) -> Engine {
+        DecisionEngine::new(self.loader(rev), self.plugins.clone())
+    }
+
+    /// An engine for `rev` whose date functions see `now` for `"now"`, on a
+    /// loader of its own.
+    pub(crate) fn frozen_engine(&self, rev: u64, now: String) -> Engine {
+        DecisionEngine::new(Arc::new(RevisionLoader::new(rev, self.follow_symlinks, Some(now))), self.plugins.clone())
+    }
+
+    /// Forgets what's cached for the user path `path` (`0/a.json`, or `0/dir`
+    /// for everything in a folder, or `4` for a whole revision).
+    pub(crate) fn invalidate(&self, path: &str) {
+        let mut parts = StdPath::new(path).components().filter_map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        });
+        let Some(rev) = parts.next().and_then(|r| r.parse::<u64>().ok()) else { return };
+        let key = parts.collect::<Vec<_>>().join("/");
+        let mut all = self.loaders.lock().unwrap();
+        if key.is_empty() {
+            all.remove(&rev);
+        } else if let Some(slot) = all.get(&rev) {
+            slot.loader.forget(&key);
+        }
+    }
+}
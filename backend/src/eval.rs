@@ -0,0 +1,967 @@
+//! Serving published decisions: `POST /api/eval/:alias`, and the aliases it
+//! takes (`/api/aliases`).
+//!
+//! An alias names a revision, usually a tag so that moving the tag moves
+//! what's served, and a decision in it; they're kept in one sidecar file,
+//! `./decisions/.aliases.json`. Only published revisions are served, so an
+//! alias can't name the working copy, and one whose tag points at a draft
+//! answers 409 until that's published.
+//!
+//! This is the endpoint for callers in production rather than the editor:
+//!
+//! - the body is `{ "context": { … }, "trace": false }` and nothing else;
+//!   the trace is off unless asked for;
+//! - the context must be an object, and each field the decision reads (as
+//!   [`schema`](crate::decisions::schema) infers it) must have a type the
+//!   decision uses it as, or be null or missing; 422 with every mismatch
+//!   otherwise. An alias with `validate: false` skips the type check;
+//! - every alias has a budget of requests per minute, `EVAL_RATE_LIMIT`
+//!   unless it sets its own `rateLimit`; past it, 429 with `Retry-After`.
+//!   Budgets are per alias, shared by every caller, and kept in memory;
+//!   each caller has its own as well (see [`ratelimit`](crate::ratelimit)).
+//!
+//! The response is the engine's (`result`, `performance`, `trace` when
+//! asked for), with the revision that answered in `X-Revision`.
+//!
+//! For a gradual rollout an alias can name a second revision, its `canary`,
+//! to get `percent` of the requests. With a `stickyField` (a dotted path
+//! into the context, say `customer.id`) the field's value picks the arm, so
+//! the same customer gets the same revision every time; without one, or
+//! when the field is null or missing, each request is a coin toss. A canary
+//! that can't be evaluated (its tag now names a draft, say) is skipped and
+//! every request goes to the stable arm, with a warning in the log. The
+//! answering arm is in `X-Arm`, and `GET /api/aliases/:name/metrics` counts
+//! requests, errors and time per arm since the alias was last saved.
+//!
+//! To try a revision on real traffic without serving it, an alias can name
+//! a `shadow`: a `percent` of the contexts that pass validation (all, unless
+//! it says otherwise) are evaluated there too, in the background, after the
+//! response is on its way. What the shadow says never reaches the caller;
+//! whether it agreed with the served output is counted and the latest
+//! disagreements kept, context and diff included, for
+//! `GET /api/aliases/:name/shadow`. Shadow evaluations don't queue up: with
+//! as many running as the pinned pool has threads, a new one is skipped and
+//! counted as such. Like the metrics, the report is kept in memory and
+//! starts over when the alias is saved; `DELETE` on it starts it over too.
+//! `POST /api/eval/:alias/bulk` streams NDJSON in and out for scoring jobs
+//! (see [`bulk`]).
+
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    extract::{Extension, Json, Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio_util::task::LocalPoolHandle;
+
+use crate::{
+    audit, auth,
+    decisions::schema,
+    quota, ratelimit, read_path,
+    revisions::RevRef,
+    simulate::{self, EvalOpts},
+    simulations, storelock::StorageLock, write_atomic, AppState, WriteOpts,
+};
+
+const STORE_FILE: &str = "./decisions/.aliases.json";
+
+/// Inferred context schemas kept; published revisions don't change, so one
+/// is good until it's pushed out.
+const MAX_SCHEMAS: usize = 256;
+
+/// serializes read-modify-write cycles on the store, across instances too
+static STORE: StorageLock = StorageLock::new("aliases");
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Alias {
+    /// taken from the URL; ignored in request bodies
+    #[serde(default)]
+    name: String,
+    /// a tag is resolved on every request
+    rev: RevRef,
+    /// the decision, relative to the revision
+    path: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    /// requests per minute, in place of `EVAL_RATE_LIMIT`; 0 is unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<u32>,
+    /// type-check contexts against the decision's inferred schema
+    #[serde(default = "yes")]
+    validate: bool,
+    /// a second revision getting a share of the requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    canary: Option<Canary>,
+    /// a revision evaluated alongside, never served
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadow: Option<Shadow>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Canary {
+    rev: RevRef,
+    /// of requests, 0-100
+    percent: u8,
+    /// a dotted path into the context whose value picks the arm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sticky_field: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Shadow {
+    rev: RevRef,
+    /// of the requests that evaluate, 0-100
+    #[serde(default = "all")]
+    percent: u8,
+}
+
+fn all() -> u8 {
+    100
+}
+
+fn is_working(rev: &RevRef) -> bool {
+    match rev {
+        RevRef::Id(0) => true,
+        RevRef::Name(s) => matches!(s.trim(), "0" | "working"),
+        RevRef::Id(_) => false,
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl Alias {
+    /// 400 for a name that can't be a path segment, the working copy, a
+    /// path outside the revision or a canary or shadow share over 100; 404
+    /// for a revision that doesn't exist.
+    fn validate(&self, st: &AppState) -> Result<(), Response> {
+        let bad = |msg: &str| Err((StatusCode::BAD_REQUEST, msg.to_string()).into_response());
+        let name_ok = (1..=64).contains(&self.name.len())
+            && self.name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && !self.name.starts_with('.');
+        if !name_ok {
+            return bad("alias names are 1-64 of [A-Za-z0-9._-], not starting with a dot");
+        }
+        let canary_working = self.canary.as_ref().is_some_and(|c| is_working(&c.rev));
+        if is_working(&self.rev) || canary_working || self.shadow.as_ref().is_some_and(|s| is_working(&s.rev)) {
+            return bad("an alias serves a published revision, not the working copy");
+        }
+        let rev = self.rev.resolve()?;
+        if self.path.trim().is_empty() || read_path(&st.config, &format!("{rev}/{}", self.path)).is_err() {
+            return bad("path must name a decision inside the revision");
+        }
+        if let Some(c) = &self.canary {
+            if c.percent > 100 {
+                return bad("canary percent is 0-100");
+            }
+            if c.sticky_field.as_deref().is_some_and(|f| f.trim().is_empty()) {
+                return bad("stickyField can't be empty");
+            }
+            c.rev.resolve()?;
+        }
+        if let Some(sh) = &self.shadow {
+            if sh.percent > 100 {
+                return bad("shadow percent is 0-100");
+            }
+            sh.rev.resolve()?;
+        }
+        Ok(())
+    }
+}
+
+fn load() -> Vec<Alias> {
+    std::fs::read(STORE_FILE)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn store(st: &AppState, all: &[Alias]) -> Result<(), Response> {
+    let target = PathBuf::from(STORE_FILE);
+    let bytes = serde_json::to_vec_pretty(all).expect("serialize aliases");
+    quota::check_write(&st.config, &target, bytes.len() as u64)?;
+    write_atomic(&target, &bytes, WriteOpts::default()).map_err(|e| {
+        tracing::error!("alias store error: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "no such alias").into_response()
+}
+
+/// Drops what's been counted for alias `name`.
+fn forget(name: &str) {
+    METRICS.lock().unwrap().retain(|(alias, _), _| alias != name);
+    SHADOWS.lock().unwrap().remove(name);
+}
+
+// ===== GET /api/aliases ======================================================
+
+pub(crate) async fn list() -> impl IntoResponse {
+    Json(load())
+}
+
+// ===== GET/PUT/DELETE /api/aliases/:name =====================================
+
+pub(crate) async fn get_one(Path(name): Path<String>) -> Response {
+    match load().into_iter().find(|a| a.name == name) {
+        Some(a) => Json(a).into_response(),
+        None => not_found(),
+    }
+}
+
+/// Creates the alias or replaces it; 201 for a new one.
+pub(crate) async fn put(
+    State(st): State<AppState>,
+    Path(name): Path<String>,
+    ident: Option<Extension<auth::Identity>>,
+    Json(mut body): Json<Alias>,
+) -> Response {
+    body.name = name;
+    let res = tokio::task::spawn_blocking(move || -> Result<(bool, Alias), Response> {
+        body.validate(&st)?;
+        let _guard = STORE.lock();
+        let mut all = load();
+        let created = match all.iter_mut().find(|a| a.name == body.name) {
+            Some(slot) => {
+                *slot = body.clone();
+                false
+            }
+            None => {
+                all.push(body.clone());
+                all.sort_by(|a, b| a.name.cmp(&b.name));
+                true
+            }
+        };
+        store(&st, &all)?;
+        forget(&body.name);
+        Ok((created, body))
+    })
+    .await;
+    match res {
+        Ok(Ok((created, alias))) => {
+            audit::record(audit::Event::new("alias-set").path(&alias.name).by(auth::acting(&ident, None))).await;
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            (status, Json(alias)).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn delete(State(st): State<AppState>, Path(name): Path<String>, ident: Option<Extension<auth::Identity>>) -> Response {
+    let alias = name.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        let _guard = STORE.lock();
+        let mut all = load();
+        let before = all.len();
+        all.retain(|a| a.name != name);
+        if all.len() == before {
+            return Err(not_found());
+        }
+        store(&st, &all)?;
+        forget(&name);
+        Ok(())
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => {
+            audit::record(audit::Event::new("alias-delete").path(&alias).by(auth::acting(&ident, None))).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== input validation ======================================================
+
+struct CachedSchema {
+    schema: Arc<Value>,
+    used: Instant,
+}
+
+/// (revision, key) → the context schema inferred for it
+static SCHEMAS: Mutex<BTreeMap<(u64, String), CachedSchema>> = Mutex::new(BTreeMap::new());
+
+/// Blocking. The inferred context schema of `key` in the published `rev`.
+fn schema_of(rev: u64, key: &str, max_depth: usize) -> Arc<Value> {
+    let k = (rev, key.to_string());
+    if let Some(c) = SCHEMAS.lock().unwrap().get_mut(&k) {
+        c.used = Instant::now();
+        return c.schema.clone();
+    }
+    let schema = Arc::new(schema::context_schema(rev, key, max_depth));
+    let mut all = SCHEMAS.lock().unwrap();
+    if !all.contains_key(&k) && all.len() >= MAX_SCHEMAS {
+        if let Some(oldest) = all.iter().min_by_key(|(_, c)| c.used).map(|(k, _)| k.clone()) {
+            all.remove(&oldest);
+        }
+    }
+    all.insert(k, CachedSchema { schema: schema.clone(), used: Instant::now() });
+    schema
+}
+
+#[derive(Serialize)]
+struct Mismatch {
+    /// dotted, array items by position: `applicant.loans.0.amount`
+    path: String,
+    /// the types the decision uses it as
+    expected: Value,
+    actual: &'static str,
+}
+
+fn type_name(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Where `v` doesn't have a type `schema` allows. Null passes anywhere, as
+/// the engine reads a missing field as null; properties the schema doesn't
+/// name pass too.
+fn check(v: &Value, schema: &Value, path: &str, out: &mut Vec<Mismatch>) {
+    if v.is_null() {
+        return;
+    }
+    let actual = type_name(v);
+    let allowed = match &schema["type"] {
+        Value::String(t) => t == actual,
+        Value::Array(ts) => ts.iter().any(|t| t == actual),
+        _ => true,
+    };
+    if !allowed {
+        out.push(Mismatch { path: path.to_string(), expected: schema["type"].clone(), actual });
+        return;
+    }
+    let child = |k: &str| if path.is_empty() { k.to_string() } else { format!("{path}.{k}") };
+    match v {
+        Value::Object(m) => {
+            for (k, sub) in schema["properties"].as_object().into_iter().flatten() {
+                if let Some(field) = m.get(k) {
+                    check(field, sub, &child(k), out);
+                }
+            }
+        }
+        Value::Array(items) if schema["items"].is_object() => {
+            for (i, item) in items.iter().enumerate() {
+                check(item, &schema["items"], &child(&i.to_string()), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn invalid(errors: Vec<Mismatch>) -> Response {
+    let body = json!({ "type": "ValidationError", "message": "context doesn't match what the decision reads", "errors": errors });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
+/// Where `context` isn't what the decision takes: not an object, or (with a
+/// `schema`) fields of the wrong type.
+fn validate(context: &Value, schema: Option<&Value>) -> Result<(), Vec<Mismatch>> {
+    if !context.is_object() {
+        return Err(vec![Mismatch { path: String::new(), expected: json!("object"), actual: type_name(context) }]);
+    }
+    let mut errors = Vec::new();
+    if let Some(schema) = schema {
+        check(context, schema, "", &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+enum ArmName {
+    Stable,
+    Canary,
+}
+
+impl ArmName {
+    fn header(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Self::Stable => "stable",
+            Self::Canary => "canary",
+        })
+    }
+}
+
+/// One revision an alias evaluates.
+struct Arm {
+    name: ArmName,
+    rev: u64,
+    key: String,
+    /// to check contexts against, unless the alias doesn't
+    schema: Option<Arc<Value>>,
+}
+
+/// What a request to an alias evaluates.
+struct Target {
+    alias: String,
+    stable: Arm,
+    /// the canary arm, its share, and the field that picks
+    canary: Option<(Arm, u8, Option<String>)>,
+    shadow: Option<ShadowTarget>,
+}
+
+impl Target {
+    /// The arm `context` goes to.
+    fn pick(&self, context: &Value) -> &Arm {
+        let Some((canary, percent, sticky)) = &self.canary else { return &self.stable };
+        let by_field = sticky.as_deref().and_then(|f| lookup(context, f)).filter(|v| !v.is_null());
+        let roll = match by_field {
+            Some(v) => {
+                let digest = Sha256::new().chain_update(self.alias.as_bytes()).chain_update([0]).chain_update(v.to_string()).finalize();
+                u64::from_le_bytes(digest[..8].try_into().expect("8 bytes")) % 100
+            }
+            None => fastrand::u64(0..100),
+        };
+        if roll < u64::from(*percent) {
+            canary
+        } else {
+            &self.stable
+        }
+    }
+
+    /// The shadow to evaluate a context `arm` answers in too, if this one
+    /// is sampled; none when `arm` is the shadow revision.
+    fn shadowing(&self, arm: &Arm) -> Option<&ShadowTarget> {
+        self.shadow.as_ref().filter(|s| s.rev != arm.rev && fastrand::u8(0..100) < s.percent)
+    }
+}
+
+/// `path` (`a.b.0.c`) inside `v`.
+fn lookup<'a>(v: &'a Value, path: &str) -> Option<&'a Value> {
+    path.trim().split('.').try_fold(v, |cur, part| match cur {
+        Value::Object(m) => m.get(part),
+        Value::Array(a) => a.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Looks up alias `name`, takes a request from its budget and resolves what
+/// it names: 404, 429, or whatever the stable revision says (404, 409).
+async fn target(st: &AppState, name: &str) -> Result<Target, Response> {
+    let Some(alias) = load().into_iter().find(|a| a.name == name) else {
+        return Err(not_found());
+    };
+    if let Err(retry_after) = ratelimit::take(&format!("alias/{}", alias.name), alias.rate_limit.unwrap_or(st.config.eval_rate_limit)) {
+        return Err(ratelimit::too_many(retry_after, "rate limit exceeded for this alias"));
+    }
+    let stable = arm(st, ArmName::Stable, &alias.rev, &alias.path, alias.validate).await?;
+    let canary = match &alias.canary {
+        Some(c) if c.percent > 0 => match arm(st, ArmName::Canary, &c.rev, &alias.path, alias.validate).await {
+            Ok(arm) => Some((arm, c.percent, c.sticky_field.clone())),
+            Err(resp) => {
+                tracing::warn!("alias {}: canary skipped, its revision answers {}", alias.name, resp.status());
+                None
+            }
+        },
+        _ => None,
+    };
+    let shadow = match &alias.shadow {
+        Some(sh) if sh.percent > 0 => match simulate::resolve_root(&st.config, Some(&sh.rev), &alias.path) {
+            Ok((rev, key)) => Some(ShadowTarget { rev, key, percent: sh.percent }),
+            Err(e) => {
+                tracing::warn!("alias {}: shadow skipped, its revision answers {}", alias.name, e.into_response().status());
+                None
+            }
+        },
+        _ => None,
+    };
+    Ok(Target { alias: alias.name, stable, canary, shadow })
+}
+
+async fn arm(st: &AppState, name: ArmName, rev: &RevRef, path: &str, validate: bool) -> Result<Arm, Response> {
+    let (rev, key) = simulate::resolve_root(&st.config, Some(rev), path).map_err(IntoResponse::into_response)?;
+    let schema = match validate {
+        true => {
+            let (k, max_depth) = (key.clone(), usize::from(st.config.simulate_max_depth));
+            let schema = tokio::task::spawn_blocking(move || schema_of(rev, &k, max_depth)).await;
+            Some(schema.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?)
+        }
+        false => None,
+    };
+    Ok(Arm { name, rev, key, schema })
+}
+
+fn with_arm(mut resp: Response, arm: &Arm) -> Response {
+    let headers = resp.headers_mut();
+    headers.insert(header::HeaderName::from_static("x-revision"), HeaderValue::from(arm.rev));
+    headers.insert(header::HeaderName::from_static("x-arm"), arm.name.header());
+    resp
+}
+
+// ===== per-arm metrics =======================================================
+
+#[derive(Default)]
+struct ArmStats {
+    rev: u64,
+    requests: u64,
+    /// evaluation errors and timeouts
+    errors: u64,
+    /// contexts refused before evaluating
+    rejected: u64,
+    total_ms: f64,
+    max_ms: f64,
+}
+
+/// (alias, arm) → since the alias was saved
+static METRICS: Mutex<BTreeMap<(String, ArmName), ArmStats>> = Mutex::new(BTreeMap::new());
+
+/// How a request to one arm went: evaluated (`Some(ok)`) in `elapsed`, or
+/// refused (`None`).
+fn count(alias: &str, arm: &Arm, outcome: Option<bool>, elapsed: Duration) {
+    let mut all = METRICS.lock().unwrap();
+    let s = all.entry((alias.to_string(), arm.name)).or_default();
+    s.rev = arm.rev;
+    s.requests += 1;
+    match outcome {
+        None => s.rejected += 1,
+        Some(ok) => {
+            s.errors += u64::from(!ok);
+            let ms = elapsed.as_secs_f64() * 1000.0;
+            s.total_ms += ms;
+            s.max_ms = s.max_ms.max(ms);
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArmReport {
+    arm: ArmName,
+    /// the revision it last evaluated
+    rev: u64,
+    requests: u64,
+    errors: u64,
+    rejected: u64,
+    /// of the requests that evaluated, in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avg_ms: Option<f64>,
+    max_ms: f64,
+}
+
+/// `GET /api/aliases/:name/metrics`: requests per arm since the alias was
+/// last saved (or the server started). Bulk rows count one by one.
+pub(crate) async fn metrics(Path(name): Path<String>) -> Response {
+    if !load().iter().any(|a| a.name == name) {
+        return not_found();
+    }
+    let round = |x: f64| (x * 10.0).round() / 10.0;
+    let all = METRICS.lock().unwrap();
+    let arms: Vec<ArmReport> = all
+        .range((name.clone(), ArmName::Stable)..=(name.clone(), ArmName::Canary))
+        .map(|((_, arm), s)| {
+            let evaluated = s.requests - s.rejected;
+            ArmReport {
+                arm: *arm,
+                rev: s.rev,
+                requests: s.requests,
+                errors: s.errors,
+                rejected: s.rejected,
+                error_rate: (evaluated > 0).then(|| round(s.errors as f64 * 100.0 / evaluated as f64)),
+                avg_ms: (evaluated > 0).then(|| round(s.total_ms / evaluated as f64)),
+                max_ms: round(s.max_ms),
+            }
+        })
+        .collect();
+    Json(json!({ "alias": name, "arms": arms })).into_response()
+}
+
+// ===== shadow evaluation =====================================================
+
+/// Disagreements kept per alias, the oldest dropped first.
+const MAX_SHADOW_MISMATCHES: usize = 50;
+
+/// shadow evaluations running, across aliases
+static SHADOW_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// alias → how its shadow has done since the alias was saved
+static SHADOWS: Mutex<BTreeMap<String, ShadowReport>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone)]
+struct ShadowTarget {
+    rev: u64,
+    key: String,
+    percent: u8,
+}
+
+/// What a revision made of a context.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    /// the decision's output
+    Result(Value),
+    Error(Value),
+}
+
+impl Outcome {
+    fn of(res: &Result<Value, simulate::SimulateError>) -> Self {
+        match res {
+            Ok(v) => Self::Result(v["result"].clone()),
+            Err(e) => Self::Error(e.to_json()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShadowMismatch {
+    /// unix seconds
+    at: i64,
+    arm: ArmName,
+    served_rev: u64,
+    shadow_rev: u64,
+    context: Value,
+    served: Outcome,
+    shadow: Outcome,
+    /// from the served output (`a`) to the shadow's (`b`); empty unless both
+    /// evaluated
+    diff: Vec<simulate::Change>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct ShadowReport {
+    /// the shadow revision last evaluated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    compared: u64,
+    /// same output, or both failed the same way
+    matched: u64,
+    mismatched: u64,
+    /// sampled, but not evaluated for want of threads
+    skipped: u64,
+    /// newest first
+    mismatches: VecDeque<ShadowMismatch>,
+}
+
+/// Holds a place among [`SHADOW_IN_FLIGHT`].
+struct InFlight;
+
+impl InFlight {
+    fn take(limit: usize) -> Option<Self> {
+        if SHADOW_IN_FLIGHT.fetch_add(1, Ordering::SeqCst) < limit {
+            Some(Self)
+        } else {
+            SHADOW_IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        SHADOW_IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Evaluates `context` in `shadow` in the background and compares with what
+/// `arm` answered, `served`.
+fn spawn_shadow(st: &AppState, pool: &LocalPoolHandle, alias: &str, arm: &Arm, shadow: &ShadowTarget, context: Value, served: Outcome) {
+    let Some(slot) = InFlight::take(pool.num_threads().max(1)) else {
+        SHADOWS.lock().unwrap().entry(alias.to_string()).or_default().skipped += 1;
+        return;
+    };
+    let (st, pool, alias, shadow) = (st.clone(), pool.clone(), alias.to_string(), shadow.clone());
+    let (arm, served_rev) = (arm.name, arm.rev);
+    tokio::spawn(async move {
+        let opts = EvalOpts { trace: Some(false), ..EvalOpts::default() };
+        let (engine_opts, timeout) = (opts.engine_options(&st.config), opts.timeout(&st.config));
+        let engine = st.engines.engine(shadow.rev);
+        let res = simulate::evaluate(&pool, engine, shadow.key, context.clone(), engine_opts, None, timeout).await;
+        drop(slot);
+        let shadowed = Outcome::of(&res);
+        let mut diff = Vec::new();
+        let same = match (&served, &shadowed) {
+            (Outcome::Result(a), Outcome::Result(b)) => {
+                simulate::diff_outputs(Some(a), Some(b), "", &mut diff);
+                diff.is_empty()
+            }
+            (Outcome::Error(a), Outcome::Error(b)) => a["type"] == b["type"],
+            _ => false,
+        };
+        let mut all = SHADOWS.lock().unwrap();
+        let report = all.entry(alias).or_default();
+        report.rev = Some(shadow.rev);
+        report.compared += 1;
+        if same {
+            report.matched += 1;
+            return;
+        }
+        report.mismatched += 1;
+        let at = simulations::now();
+        let m = ShadowMismatch { at, arm, served_rev, shadow_rev: shadow.rev, context, served, shadow: shadowed, diff };
+        report.mismatches.push_front(m);
+        report.mismatches.truncate(MAX_SHADOW_MISMATCHES);
+    });
+}
+
+/// `GET /api/aliases/:name/shadow`: how the shadow's outputs compared with
+/// those served, and the latest disagreements.
+pub(crate) async fn shadow_report(Path(name): Path<String>) -> Response {
+    if !load().iter().any(|a| a.name == name) {
+        return not_found();
+    }
+    let all = SHADOWS.lock().unwrap();
+    let body = match all.get(&name) {
+        Some(report) => serde_json::to_value(report),
+        None => serde_json::to_value(ShadowReport::default()),
+    };
+    Json(body.expect("serialize shadow report")).into_response()
+}
+
+/// `DELETE /api/aliases/:name/shadow`: starts the report over.
+pub(crate) async fn clear_shadow_report(Path(name): Path<String>) -> Response {
+    if !load().iter().any(|a| a.name == name) {
+        return not_found();
+    }
+    SHADOWS.lock().unwrap().remove(&name);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// ===== POST /api/eval/:alias =================================================
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EvalRequest {
+    context: Value,
+    #[serde(default)]
+    trace: bool,
+}
+
+pub(crate) async fn eval(
+    State(st): State<AppState>,
+    Extension(pool): Extension<LocalPoolHandle>,
+    Path(name): Path<String>,
+    Json(req): Json<EvalRequest>,
+) -> Response {
+    let target = match target(&st, &name).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let arm = target.pick(&req.context);
+    let started = Instant::now();
+    if let Err(errors) = validate(&req.context, arm.schema.as_deref()) {
+        count(&target.alias, arm, None, started.elapsed());
+        return with_arm(invalid(errors), arm);
+    }
+    let shadow = target.shadowing(arm).map(|sh| (sh, req.context.clone()));
+    let opts = EvalOpts { trace: Some(req.trace), ..EvalOpts::default() };
+    let (engine_opts, timeout) = (opts.engine_options(&st.config), opts.timeout(&st.config));
+    let res = simulate::evaluate(&pool, st.engines.engine(arm.rev), arm.key.clone(), req.context, engine_opts, None, timeout).await;
+    count(&target.alias, arm, Some(res.is_ok()), started.elapsed());
+    if let Some((sh, context)) = shadow {
+        spawn_shadow(&st, &pool, &target.alias, arm, sh, context, Outcome::of(&res));
+    }
+    match res {
+        Ok(mut v) => {
+            if !req.trace {
+                v.as_object_mut().map(|o| o.remove("trace"));
+            }
+            with_arm(Json(v).into_response(), arm)
+        }
+        Err(e) => with_arm(e.into_response(), arm),
+    }
+}
+
+// ===== POST /api/eval/:alias/bulk ============================================
+
+/// A longer input line ends the job.
+const MAX_LINE_BYTES: usize = 1024 * 1024;
+
+/// One input line: a context to evaluate, or what was wrong with it.
+enum Row {
+    Context(usize, Value),
+    Bad(usize, Value),
+}
+
+fn parse_row(line: usize, raw: &[u8]) -> Option<Row> {
+    let raw = raw.trim_ascii();
+    if raw.is_empty() {
+        return None;
+    }
+    Some(match serde_json::from_slice(raw) {
+        Ok(context) => Row::Context(line, context),
+        Err(e) => Row::Bad(line, json!({ "type": "ParseError", "message": e.to_string() })),
+    })
+}
+
+/// Reading the body line by line, holding no more than one line (and the
+/// chunk it arrived in).
+struct Lines {
+    data: BodyDataStream,
+    buf: Vec<u8>,
+    /// where the next line starts in `buf`
+    start: usize,
+    /// lines read so far
+    line: usize,
+    /// the body is over, or broken
+    done: bool,
+}
+
+impl Lines {
+    async fn next(&mut self) -> Option<Row> {
+        loop {
+            if let Some(pos) = self.buf[self.start..].iter().position(|&b| b == b'\n') {
+                let end = self.start + pos;
+                let raw = &self.buf[self.start..end];
+                self.start = end + 1;
+                self.line += 1;
+                match parse_row(self.line, raw) {
+                    Some(row) => return Some(row),
+                    None => continue,
+                }
+            }
+            if self.done {
+                let raw = &self.buf[self.start..];
+                self.start = self.buf.len();
+                self.line += 1;
+                return parse_row(self.line, raw);
+            }
+            if self.buf.len() - self.start > MAX_LINE_BYTES {
+                self.done = true;
+                self.buf.clear();
+                self.start = 0;
+                let msg = format!("line is over {MAX_LINE_BYTES} bytes; the rest of the input is not read");
+                return Some(Row::Bad(self.line + 1, json!({ "type": "LineTooLong", "message": msg })));
+            }
+            match self.data.next().await {
+                Some(Ok(chunk)) => {
+                    self.buf.drain(..self.start);
+                    self.start = 0;
+                    self.buf.extend_from_slice(&chunk);
+                }
+                Some(Err(e)) => {
+                    self.done = true;
+                    self.buf.clear();
+                    self.start = 0;
+                    return Some(Row::Bad(self.line + 1, json!({ "type": "ReadError", "message": e.to_string() })));
+                }
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RowResult {
+    /// 1-based, counting blank lines
+    line: usize,
+    /// the arm that answered, when the alias has a canary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arm: Option<ArmName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+/// `POST /api/eval/:alias/bulk`: one context per line (NDJSON) in, one
+/// `{line, ok, result | error}` per context out, in input order, as they're
+/// done.
+///
+/// The body is read as results go out: as many rows are in flight as the
+/// pinned pool has threads, and no more are read until the oldest is
+/// written, so a client that reads slowly slows the job down rather than
+/// piling results up in memory. The whole job takes one request of the
+/// alias's budget; a row that fails (bad JSON, the wrong types, an
+/// evaluation error or timeout) fails alone. There's no trace. With a
+/// canary each row picks its arm and says which, and `X-Revision` is left
+/// out.
+pub(crate) async fn bulk(
+    State(st): State<AppState>,
+    Extension(pool): Extension<LocalPoolHandle>,
+    Path(name): Path<String>,
+    body: Body,
+) -> Response {
+    let target = match target(&st, &name).await {
+        Ok(t) => Arc::new(t),
+        Err(resp) => return resp,
+    };
+    let opts = EvalOpts { trace: Some(false), ..EvalOpts::default() };
+    let timeout = opts.timeout(&st.config);
+    let in_flight = pool.num_threads().max(1);
+
+    let lines = Lines { data: body.into_data_stream(), buf: Vec::new(), start: 0, line: 0, done: false };
+    let rows = stream::unfold(lines, |mut lines| async move { lines.next().await.map(|row| (row, lines)) });
+    let single = target.canary.is_none().then_some(target.stable.rev);
+    let results = rows
+        .map(move |row| {
+            let (st, pool, target) = (st.clone(), pool.clone(), target.clone());
+            async move {
+                let (line, context) = match row {
+                    Row::Context(line, context) => (line, context),
+                    Row::Bad(line, error) => return RowResult { line, arm: None, rev: None, ok: false, result: None, error: Some(error) },
+                };
+                let arm = target.pick(&context);
+                let mut out = RowResult { line, arm: None, rev: None, ok: false, result: None, error: None };
+                if target.canary.is_some() {
+                    (out.arm, out.rev) = (Some(arm.name), Some(arm.rev));
+                }
+                let started = Instant::now();
+                if let Err(errors) = validate(&context, arm.schema.as_deref()) {
+                    count(&target.alias, arm, None, started.elapsed());
+                    out.error = Some(json!({ "type": "ValidationError", "errors": errors }));
+                    return out;
+                }
+                let shadow = target.shadowing(arm).map(|sh| (sh, context.clone()));
+                let engine_opts = opts.engine_options(&st.config);
+                let res = simulate::evaluate(&pool, st.engines.engine(arm.rev), arm.key.clone(), context, engine_opts, None, timeout).await;
+                count(&target.alias, arm, Some(res.is_ok()), started.elapsed());
+                if let Some((sh, context)) = shadow {
+                    spawn_shadow(&st, &pool, &target.alias, arm, sh, context, Outcome::of(&res));
+                }
+                match res {
+                    Ok(mut v) => (out.ok, out.result) = (true, v.get_mut("result").map(Value::take)),
+                    Err(e) => out.error = Some(e.to_json()),
+                }
+                out
+            }
+        })
+        .buffered(in_flight)
+        .map(|r| {
+            let mut out = serde_json::to_vec(&r).expect("serialize bulk row");
+            out.push(b'\n');
+            Ok::<_, Infallible>(Bytes::from(out))
+        });
+    let mut resp = ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(results)).into_response();
+    if let Some(rev) = single {
+        resp.headers_mut().insert(header::HeaderName::from_static("x-revision"), HeaderValue::from(rev));
+    }
+    resp
+}
@@ -0,0 +1,243 @@
+//! Server events: filesystem change notifications (`/api/fs/events`, SSE) and the
+//! general event bus exposed over `/api/ws`.
+//!
+//! A single debounced `notify` watcher covers the whole storage root and publishes
+//! into the same broadcast bus that handlers use for snapshot/simulation events;
+//! every SSE or WebSocket client subscribes to it.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+};
+use futures_util::{Stream, StreamExt};
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{
+        event::{ModifyKind, RenameMode},
+        EventKind, RecommendedWatcher, RecursiveMode,
+    },
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    path::{Path as StdPath, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{locks::LockInfo, workflow::RevState, AppState, STORAGE_ROOT};
+
+const DEBOUNCE: Duration = Duration::from_millis(250);
+const BUS_CAPACITY: usize = 1024;
+
+// ===== event bus =============================================================
+
+/// Everything a client can be told about. Serialized as the `type`/`data` part of
+/// the [`Envelope`].
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub(crate) enum ServerEvent {
+    Fs(FsEvent),
+    #[serde(rename_all = "camelCase")]
+    SnapshotCreated { id: u64 },
+    /// the working copy was replaced by revision `id`
+    #[serde(rename_all = "camelCase")]
+    RevisionRestored { id: u64, safety_snapshot: u64 },
+    #[serde(rename_all = "camelCase")]
+    RevisionDeleted { id: u64 },
+    /// moved along the review workflow
+    #[serde(rename_all = "camelCase")]
+    RevisionStateChanged { id: u64, state: RevState },
+    /// the working copy is now on branch `name`, a copy of its head
+    #[serde(rename_all = "camelCase")]
+    BranchCheckedOut { name: String, head: u64 },
+    #[serde(rename_all = "camelCase")]
+    SimulationFinished { filepath: String, ok: bool, duration_ms: u64 },
+    LockAcquired(LockInfo),
+    LockReleased { path: String, owner: String },
+}
+
+/// Wire format on `/api/ws`: `{ "type": ..., "data": ..., "ts": <unix millis> }`.
+#[derive(Clone, Serialize)]
+struct Envelope {
+    #[serde(flatten)]
+    event: ServerEvent,
+    ts: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct EventBus(broadcast::Sender<Envelope>);
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        Self(broadcast::channel(BUS_CAPACITY).0)
+    }
+
+    pub(crate) fn publish(&self, event: ServerEvent) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        // no subscribers is not an error — nobody is listening right now
+        let _ = self.0.send(Envelope { event, ts });
+    }
+
+    fn subscribe(&self) -> BroadcastStream<Envelope> {
+        BroadcastStream::new(self.0.subscribe())
+    }
+}
+
+// ===== fs watcher ============================================================
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FsEventKind {
+    Create,
+    Modify,
+    Delete,
+    Rename,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct FsEvent {
+    pub kind: FsEventKind,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+pub(crate) type FsWatcher = Debouncer<RecommendedWatcher, RecommendedCache>;
+
+/// Starts watching the storage root. The returned debouncer must be kept alive
+/// for as long as events should be delivered.
+pub(crate) fn spawn_watcher(bus: EventBus) -> notify_debouncer_full::notify::Result<FsWatcher> {
+    let root = StdPath::new(STORAGE_ROOT).canonicalize()?;
+    let prefix = root.clone();
+
+    let mut debouncer = new_debouncer(DEBOUNCE, None, move |res: DebounceEventResult| match res {
+        Ok(events) => {
+            for ev in events {
+                if let Some(ev) = translate(&prefix, &ev.event.kind, &ev.event.paths) {
+                    bus.publish(ServerEvent::Fs(ev));
+                }
+            }
+        }
+        Err(errors) => {
+            for e in errors {
+                tracing::warn!("fs watcher error: {e}");
+            }
+        }
+    })?;
+    debouncer.watch(&root, RecursiveMode::Recursive)?;
+    Ok(debouncer)
+}
+
+fn translate(root: &StdPath, kind: &EventKind, paths: &[PathBuf]) -> Option<FsEvent> {
+    let rel = |p: &PathBuf| relative(root, p);
+    let (kind, path, from) = match kind {
+        EventKind::Create(_) => (FsEventKind::Create, rel(paths.first()?)?, None),
+        EventKind::Remove(_) => (FsEventKind::Delete, rel(paths.first()?)?, None),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match rel(paths.first()?) {
+            Some(from) => (FsEventKind::Rename, rel(paths.get(1)?)?, Some(from)),
+            // renamed from a hidden temp file: that's how atomic saves land
+            None => (FsEventKind::Modify, rel(paths.get(1)?)?, None),
+        },
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => (FsEventKind::Delete, rel(paths.first()?)?, None),
+        EventKind::Modify(ModifyKind::Name(_)) => (FsEventKind::Create, rel(paths.first()?)?, None),
+        EventKind::Modify(_) => (FsEventKind::Modify, rel(paths.first()?)?, None),
+        _ => return None,
+    };
+    Some(FsEvent { kind, path, from })
+}
+
+/// Storage-relative, forward-slashed path; `None` for HEAD and dot-prefixed
+/// sidecar entries, which are not part of the user-visible tree.
+fn relative(root: &StdPath, p: &StdPath) -> Option<String> {
+    let rel = p.strip_prefix(root).ok()?.to_string_lossy().replace('\\', "/");
+    if rel.is_empty() || rel == "HEAD" || rel.split('/').any(|c| c.starts_with('.')) {
+        return None;
+    }
+    Some(rel)
+}
+
+// ===== GET /api/fs/events ====================================================
+
+pub(crate) async fn fs_events(
+    State(st): State<AppState>,
+    Query(p): Query<HashMap<String, String>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // optional ?path= prefix filter, e.g. "0" for the working copy only
+    let scope = p.get("path").map(|s| s.trim_matches('/').to_string()).unwrap_or_default();
+
+    let stream = st.events.subscribe().filter_map(move |msg| {
+        let out = match msg {
+            Ok(Envelope { event: ServerEvent::Fs(ev), .. }) if in_scope(&scope, &ev) => Event::default()
+                .event(kind_name(ev.kind))
+                .json_data(&ev)
+                .ok()
+                .map(Ok),
+            Ok(_) => None,
+            Err(e) => {
+                // a slow client fell behind; tell it to re-list instead of guessing
+                tracing::debug!("fs events subscriber lagged: {e}");
+                Some(Ok(Event::default().event("resync").data("lagged")))
+            }
+        };
+        std::future::ready(out)
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn in_scope(scope: &str, ev: &FsEvent) -> bool {
+    let under = |p: &str| scope.is_empty() || p == scope || p.starts_with(&format!("{scope}/"));
+    under(&ev.path) || ev.from.as_deref().is_some_and(under)
+}
+
+fn kind_name(k: FsEventKind) -> &'static str {
+    match k {
+        FsEventKind::Create => "create",
+        FsEventKind::Modify => "modify",
+        FsEventKind::Delete => "delete",
+        FsEventKind::Rename => "rename",
+    }
+}
+
+// ===== GET /api/ws ===========================================================
+
+pub(crate) async fn ws(State(st): State<AppState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| ws_session(socket, st.events))
+}
+
+async fn ws_session(mut socket: WebSocket, bus: EventBus) {
+    let mut events = bus.subscribe();
+    loop {
+        tokio::select! {
+            msg = events.next() => {
+                let text = match msg {
+                    Some(Ok(env)) => serde_json::to_string(&env),
+                    Some(Err(_)) => Ok(r#"{"type":"resync"}"#.to_string()),
+                    None => break,
+                };
+                let Ok(text) = text else { continue };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // the channel is server→client only; anything but close is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
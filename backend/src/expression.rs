@@ -0,0 +1,89 @@
+//! `POST /api/expression/eval`: one ZEN expression against a context, for the
+//! expression editor's live preview.
+//!
+//! Syntax problems are found the way `/api/validate` finds them, so they come
+//! with the same character spans; only an expression that compiles is run.
+//! Running happens on the blocking pool under `EXPRESSION_TIMEOUT_MS`. The
+//! expression language has no loops, but a large enough range or `map` can
+//! still take a while; past the timeout the request is answered with 408 and
+//! the thread finishes the expression on its own, which is why expressions are
+//! also capped in length.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zen_expression::Isolate;
+
+use crate::{lint, AppState};
+
+const MAX_EXPRESSION_CHARS: usize = 10_000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EvalReq {
+    expression: String,
+    #[serde(default)]
+    context: Value,
+    /// evaluate as a decision-table input cell, a unary test against `$`
+    /// (this field, read from the context; e.g. `customer.age`)
+    field: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EvalError {
+    /// `syntax` (it doesn't compile), `runtime` or `timeout`
+    kind: &'static str,
+    message: String,
+    /// character offsets into the expression, end exclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<(u32, u32)>,
+}
+
+impl EvalError {
+    fn respond(self, status: StatusCode) -> Response {
+        (status, Json(serde_json::json!({ "error": self }))).into_response()
+    }
+}
+
+/// Blocking.
+fn run(req: EvalReq) -> Result<Value, EvalError> {
+    let unary = req.field.as_deref().is_some_and(|f| !f.trim().is_empty());
+    if let Some((message, span)) = lint::expression_error(&req.expression, unary) {
+        return Err(EvalError { kind: "syntax", message, span });
+    }
+    let runtime = |e: zen_expression::IsolateError| EvalError { kind: "runtime", message: e.to_string(), span: None };
+    let mut isolate = Isolate::with_environment(req.context.into());
+    match req.field.as_deref().filter(|_| unary) {
+        Some(field) => {
+            isolate.set_reference(field).map_err(runtime)?;
+            isolate.run_unary(&req.expression).map(Value::Bool).map_err(runtime)
+        }
+        None => isolate.run_standard(&req.expression).map(|v| v.to_value()).map_err(runtime),
+    }
+}
+
+pub(crate) async fn eval(State(st): State<AppState>, Json(req): Json<EvalReq>) -> Response {
+    if req.expression.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "expression is required").into_response();
+    }
+    if req.expression.chars().count() > MAX_EXPRESSION_CHARS {
+        return (StatusCode::PAYLOAD_TOO_LARGE, format!("expressions are limited to {MAX_EXPRESSION_CHARS} characters")).into_response();
+    }
+    let limit = st.config.expression_timeout;
+    match tokio::time::timeout(limit, tokio::task::spawn_blocking(move || run(req))).await {
+        Ok(Ok(Ok(value))) => Json(serde_json::json!({ "value": value })).into_response(),
+        Ok(Ok(Err(e))) => e.respond(StatusCode::UNPROCESSABLE_ENTITY),
+        Ok(Err(_)) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Err(_) => EvalError {
+            kind: "timeout",
+            message: format!("expression didn't finish within {} ms", limit.as_millis()),
+            span: None,
+        }
+        .respond(StatusCode::REQUEST_TIMEOUT),
+    }
+}
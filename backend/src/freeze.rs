@@ -0,0 +1,156 @@
+//! Read-only mode: editing frozen for a release or an incident, while reads,
+//! simulations and evaluation go on.
+//!
+//! `READ_ONLY` freezes the server from startup (with `READ_ONLY_REASON` as
+//! the reason) until it's restarted without it. Otherwise
+//! `PUT /api/admin/read-only` (`{reason}`) freezes and
+//! `DELETE /api/admin/read-only` thaws, which every instance sharing
+//! the storage root sees at once: the freeze is the file
+//! `./decisions/.read-only.json`, which operators may also write themselves.
+//! `GET /api/read-only` says whether it's on, for anyone.
+//!
+//! While frozen, every route that changes something (any method but `GET`
+//! that needs at least the editor role, see [`roles`](crate::auth::roles))
+//! answers 423 with the reason, except the toggle itself; scheduled GC and
+//! automatic snapshots skip their runs.
+
+use axum::{
+    extract::{Extension, Json, MatchedPath, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{audit, auth, config::Config, write_atomic, AppState, WriteOpts};
+
+const FREEZE_FILE: &str = "./decisions/.read-only.json";
+
+/// The routes that turn it off and on.
+const TOGGLE: &str = "/api/admin/read-only";
+
+const DEFAULT_REASON: &str = "editing is frozen";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Freeze {
+    #[serde(default)]
+    reason: Option<String>,
+    /// unix seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    by: Option<String>,
+}
+
+/// The freeze in force, if any; `READ_ONLY` wins over the file.
+pub(crate) fn frozen(cfg: &Config) -> Option<Freeze> {
+    if cfg.read_only {
+        return Some(Freeze { reason: cfg.read_only_reason.clone(), since: None, by: None });
+    }
+    let raw = fs::read(FREEZE_FILE).ok()?;
+    // a file that doesn't parse still freezes; someone meant to
+    Some(serde_json::from_slice(&raw).unwrap_or(Freeze { reason: None, since: None, by: None }))
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn locked(f: &Freeze) -> Response {
+    let reason = f.reason.as_deref().unwrap_or(DEFAULT_REASON);
+    (StatusCode::LOCKED, format!("read-only: {reason}")).into_response()
+}
+
+// ===== middleware =============================================================
+
+pub(crate) async fn guard(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map_or(req.uri().path(), MatchedPath::as_str);
+    let mutating = req.method() != Method::GET
+        && req.method() != Method::HEAD
+        && route != TOGGLE
+        && auth::roles::required(req.method(), route) >= auth::Role::Editor;
+    if mutating {
+        if let Some(f) = frozen(&st.config) {
+            return locked(&f);
+        }
+    }
+    next.run(req).await
+}
+
+// ===== GET /api/read-only, PUT/DELETE /api/admin/read-only ===================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    read_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by: Option<String>,
+    /// set by `READ_ONLY`, so the API can't lift it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    from_env: bool,
+}
+
+fn status_of(cfg: &Config) -> Status {
+    match frozen(cfg) {
+        Some(f) => Status { read_only: true, reason: f.reason, since: f.since, by: f.by, from_env: cfg.read_only },
+        None => Status { read_only: false, reason: None, since: None, by: None, from_env: false },
+    }
+}
+
+pub(crate) async fn status(State(st): State<AppState>) -> impl IntoResponse {
+    Json(status_of(&st.config))
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct FreezeReq {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+fn internal(e: io::Error) -> Response {
+    tracing::error!("could not change read-only mode: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// Freezes editing; freezing again only updates the reason.
+pub(crate) async fn freeze(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, body: Option<Json<FreezeReq>>) -> Response {
+    if st.config.read_only {
+        return (StatusCode::CONFLICT, "READ_ONLY is set; restart without it to change the mode").into_response();
+    }
+    let reason = body.and_then(|Json(b)| b.reason).map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    let f = Freeze { reason, since: Some(now_secs()), by: auth::acting(&ident, None) };
+    let bytes = serde_json::to_vec_pretty(&f).expect("serialize freeze");
+    if let Err(e) = tokio::task::spawn_blocking(move || write_atomic(FREEZE_FILE.as_ref(), &bytes, WriteOpts { durable: true, ..Default::default() }))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+    {
+        return internal(e);
+    }
+    tracing::warn!("read-only mode on: {}", f.reason.as_deref().unwrap_or(DEFAULT_REASON));
+    audit::record(audit::Event::new("read-only-on").by(f.by.clone())).await;
+    Json(status_of(&st.config)).into_response()
+}
+
+pub(crate) async fn thaw(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>) -> Response {
+    if st.config.read_only {
+        return (StatusCode::CONFLICT, "READ_ONLY is set; restart without it to change the mode").into_response();
+    }
+    match fs::remove_file(FREEZE_FILE) {
+        Ok(()) => {
+            tracing::warn!("read-only mode off");
+            audit::record(audit::Event::new("read-only-off").by(auth::acting(&ident, None))).await;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return internal(e),
+    }
+    Json(status_of(&st.config)).into_response()
+}
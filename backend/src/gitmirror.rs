@@ -0,0 +1,329 @@
+//! Git mirror of the revision store (`GIT_MIRROR_DIR`, see
+//! [`Config`](crate::config::Config)).
+//!
+//! The numbered directories stay the source of truth; the mirror is a bare
+//! repository kept in step with them, so history can be read with `git log`,
+//! cloned, or pushed to a remote with plain git. Every revision becomes a
+//! commit (its tree built from the revision's path map, its parent the
+//! revision's own), recorded as `refs/revisions/<n>`; branches become
+//! `refs/heads/*`, tags lightweight `refs/tags/*`, and the mirror's `HEAD`
+//! follows the working copy's branch.
+//!
+//! Syncing is incremental: revisions that already have a commit are skipped.
+//! Deleting a revision leaves its commit in the mirror, where later commits
+//! may still have it as a parent. `POST /api/admin/git-mirror` syncs on demand
+//! and reports what was written.
+//!
+//! Reads go the other way too: once a revision has its commit, [`mirrored`]
+//! hands out its tree, and revision diffs and restores ([`crate::revisions`])
+//! take file lists and contents from git instead of the numbered directory.
+//! A revision the mirror hasn't caught up with yet is read from the store as
+//! before.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use gix::{
+    bstr::BString,
+    objs::tree::{Entry, EntryKind},
+    refs::{
+        transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog},
+        FullName, Target,
+    },
+    ObjectId,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path as StdPath, PathBuf},
+};
+
+use crate::{branches, config::Config, objects, revisions, storelock::StorageLock, AppState, STORAGE_ROOT};
+
+/// One sync at a time, in any instance: two would race to create the same
+/// commits and refs.
+static SYNC: StorageLock = StorageLock::new("git-mirror");
+
+fn git_err(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::other(e)
+}
+
+fn open_or_init(dir: &StdPath) -> io::Result<gix::Repository> {
+    if dir.join("HEAD").is_file() {
+        return gix::open(dir).map_err(git_err);
+    }
+    fs::create_dir_all(dir)?;
+    gix::init_bare(dir).map_err(git_err)
+}
+
+fn revision_ref(rev: u64) -> String {
+    format!("refs/revisions/{rev}")
+}
+
+fn commit_of(repo: &gix::Repository, rev: u64) -> io::Result<Option<ObjectId>> {
+    let found = repo.try_find_reference(revision_ref(rev).as_str()).map_err(git_err)?;
+    Ok(found.and_then(|r| r.try_id().map(|id| id.detach())))
+}
+
+fn update(name: &str, target: Target, message: &str) -> io::Result<RefEdit> {
+    Ok(RefEdit {
+        change: Change::Update {
+            log: LogChange { mode: RefLog::AndReference, force_create_reflog: false, message: message.into() },
+            expected: PreviousValue::Any,
+            new: target,
+        },
+        name: FullName::try_from(name).map_err(git_err)?,
+        deref: false,
+    })
+}
+
+fn delete(name: FullName) -> RefEdit {
+    RefEdit { change: Change::Delete { expected: PreviousValue::Any, log: RefLog::AndReference }, name, deref: false }
+}
+
+/// A directory of the commit's tree, before it's written.
+#[derive(Default)]
+struct Dir {
+    files: BTreeMap<String, ObjectId>,
+    dirs: BTreeMap<String, Dir>,
+}
+
+impl Dir {
+    fn insert(&mut self, path: &str, blob: ObjectId) {
+        match path.split_once('/') {
+            Some((dir, rest)) => self.dirs.entry(dir.to_string()).or_default().insert(rest, blob),
+            None => {
+                self.files.insert(path.to_string(), blob);
+            }
+        }
+    }
+
+    fn write(self, repo: &gix::Repository) -> io::Result<ObjectId> {
+        let mut entries = Vec::with_capacity(self.files.len() + self.dirs.len());
+        for (name, dir) in self.dirs {
+            entries.push(Entry { mode: EntryKind::Tree.into(), filename: name.into(), oid: dir.write(repo)? });
+        }
+        for (name, oid) in self.files {
+            entries.push(Entry { mode: EntryKind::Blob.into(), filename: name.into(), oid });
+        }
+        // git's order, in which a directory sorts as if its name ended in '/'
+        entries.sort();
+        let id = repo.write_object(&gix::objs::Tree { entries }).map_err(git_err)?;
+        Ok(id.detach())
+    }
+}
+
+/// The commit for revision `rev`, with `parent` as its parent.
+fn write_commit(
+    repo: &gix::Repository,
+    rev: u64,
+    parent: Option<ObjectId>,
+    blobs: &mut HashMap<String, ObjectId>,
+) -> io::Result<ObjectId> {
+    let files: Vec<(String, PathBuf, Option<String>)> = match objects::file_map(rev) {
+        Some(map) => map
+            .into_iter()
+            .map(|(rel, hash)| {
+                let path = objects::locate(rev, &rel).unwrap_or_else(|| PathBuf::from(format!("{STORAGE_ROOT}/{rev}/{rel}")));
+                (rel, path, Some(hash))
+            })
+            .collect(),
+        None => revisions::rev_files(rev, false).into_iter().map(|(rel, path)| (rel, path, None)).collect(),
+    };
+    let mut root = Dir::default();
+    for (rel, path, hash) in files {
+        let oid = match hash.as_ref().and_then(|h| blobs.get(h)) {
+            Some(&oid) => oid,
+            None => {
+                let oid = repo.write_blob(fs::read(&path)?).map_err(git_err)?.detach();
+                if let Some(h) = hash {
+                    blobs.insert(h, oid);
+                }
+                oid
+            }
+        };
+        root.insert(&rel, oid);
+    }
+    let tree = root.write(repo)?;
+
+    let manifest = revisions::read_manifest(rev);
+    let signature = gix::actor::Signature {
+        name: BString::from(manifest.author.as_deref().unwrap_or("editor")),
+        email: BString::default(),
+        time: gix::date::Time::new(manifest.created_at.unwrap_or(0), 0),
+    };
+    let summary = manifest.message.clone().unwrap_or_else(|| format!("revision {rev}"));
+    let commit = gix::objs::Commit {
+        tree,
+        parents: parent.into_iter().collect(),
+        author: signature.clone(),
+        committer: signature,
+        encoding: None,
+        message: format!("{summary}\n\nRevision: {rev}\n").into(),
+        extra_headers: Vec::new(),
+    };
+    let id = repo.write_object(&commit).map_err(git_err)?.detach();
+    repo.edit_reference(update(&revision_ref(rev), Target::Object(id), &format!("revision {rev}"))?).map_err(git_err)?;
+    Ok(id)
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncReport {
+    /// revisions that got a commit in this sync, ascending
+    committed: Vec<u64>,
+    branches: usize,
+    tags: usize,
+}
+
+/// Brings the mirror at `dir` up to date. Blocking.
+pub(crate) fn sync(dir: &StdPath) -> io::Result<SyncReport> {
+    let _g = SYNC.lock();
+    let repo = open_or_init(dir)?;
+    let mut report = SyncReport::default();
+    let mut commits: BTreeMap<u64, ObjectId> = BTreeMap::new();
+    let mut blobs = HashMap::new();
+    let revs: Vec<u64> = revisions::existing().into_iter().filter(|&r| r != 0).collect();
+    for (i, &rev) in revs.iter().enumerate() {
+        if let Some(id) = commit_of(&repo, rev)? {
+            commits.insert(rev, id);
+            continue;
+        }
+        // revisions older than branches have no parent recorded; they were a line
+        let parent = match revisions::read_manifest(rev).parent {
+            Some(0) => None,
+            Some(p) => match commits.get(&p) {
+                Some(&id) => Some(id),
+                None => commit_of(&repo, p)?,
+            },
+            None => i.checked_sub(1).and_then(|j| commits.get(&revs[j]).copied()),
+        };
+        commits.insert(rev, write_commit(&repo, rev, parent, &mut blobs)?);
+        report.committed.push(rev);
+    }
+
+    // branches and tags: set the live ones, drop the rest
+    let mut wanted: BTreeMap<String, ObjectId> = BTreeMap::new();
+    for (name, head) in branches::heads() {
+        if let Some(id) = commits.get(&head).copied().map_or_else(|| commit_of(&repo, head), |id| Ok(Some(id)))? {
+            wanted.insert(format!("refs/heads/{name}"), id);
+            report.branches += 1;
+        }
+    }
+    for (name, rev) in revisions::tags() {
+        if let Some(id) = commits.get(&rev).copied().map_or_else(|| commit_of(&repo, rev), |id| Ok(Some(id)))? {
+            wanted.insert(format!("refs/tags/{name}"), id);
+            report.tags += 1;
+        }
+    }
+    let mut edits = Vec::new();
+    let platform = repo.references().map_err(git_err)?;
+    for prefix in ["refs/heads/", "refs/tags/"] {
+        for r in platform.prefixed(prefix).map_err(git_err)? {
+            let r = r.map_err(io::Error::other)?;
+            let name = r.name().as_bstr().to_string();
+            let current = r.try_id().map(|id| id.detach());
+            match wanted.get(&name) {
+                Some(&id) if current == Some(id) => {
+                    wanted.remove(&name);
+                }
+                Some(_) => {}
+                None => edits.push(delete(r.inner.name.clone())),
+            }
+        }
+    }
+    for (name, id) in wanted {
+        edits.push(update(&name, Target::Object(id), "sync")?);
+    }
+    let head = format!("refs/heads/{}", branches::current());
+    edits.push(update("HEAD", Target::Symbolic(FullName::try_from(head.as_str()).map_err(git_err)?), "sync")?);
+    repo.edit_references(edits).map_err(git_err)?;
+    Ok(report)
+}
+
+/// Syncs after a change to revisions, branches or tags, if the mirror is on.
+/// Blocking; a failure is logged and left for the next sync to catch up on.
+pub(crate) fn update_mirror(cfg: &Config) {
+    let Some(dir) = &cfg.git_mirror_dir else { return };
+    match sync(dir) {
+        Ok(r) if !r.committed.is_empty() => tracing::debug!("git mirror: committed revisions {:?}", r.committed),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("git mirror sync failed: {e}"),
+    }
+}
+
+/// A revision as the mirror has it, see [`mirrored`].
+pub(crate) struct Mirrored {
+    repo: gix::Repository,
+    /// every file in the revision, by path, with its blob id
+    pub(crate) files: BTreeMap<String, ObjectId>,
+}
+
+impl Mirrored {
+    /// The bytes of `rel`, or `None` if the revision doesn't have it.
+    pub(crate) fn read(&self, rel: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(&id) = self.files.get(rel) else { return Ok(None) };
+        let blob = self.repo.find_object(id).map_err(git_err)?;
+        Ok(Some(blob.detach().data))
+    }
+
+    /// Writes every file to `dst`. Git keeps no empty directories; callers
+    /// that need those create them first.
+    pub(crate) fn check_out(&self, dst: &StdPath) -> io::Result<()> {
+        for (rel, &id) in &self.files {
+            let to = dst.join(rel);
+            if let Some(p) = to.parent() {
+                fs::create_dir_all(p)?;
+            }
+            fs::write(to, self.repo.find_object(id).map_err(git_err)?.data.as_slice())?;
+        }
+        Ok(())
+    }
+}
+
+fn walk(repo: &gix::Repository, tree: ObjectId, prefix: &str, out: &mut BTreeMap<String, ObjectId>) -> io::Result<()> {
+    let tree = repo.find_object(tree).map_err(git_err)?.try_into_tree().map_err(git_err)?;
+    for e in tree.decode().map_err(git_err)?.entries {
+        let path = format!("{prefix}{}", e.filename);
+        if e.mode.is_tree() {
+            walk(repo, e.oid.to_owned(), &format!("{path}/"), out)?;
+        } else if e.mode.is_blob() {
+            out.insert(path, e.oid.to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Revision `rev` from the mirror, or `None` when the mirror is off or has no
+/// commit for it yet. Never creates the mirror. Blocking.
+pub(crate) fn mirrored(cfg: &Config, rev: u64) -> io::Result<Option<Mirrored>> {
+    let Some(dir) = &cfg.git_mirror_dir else { return Ok(None) };
+    if rev == 0 || !dir.join("HEAD").is_file() {
+        return Ok(None);
+    }
+    let repo = gix::open(dir).map_err(git_err)?;
+    let Some(commit) = commit_of(&repo, rev)? else { return Ok(None) };
+    let tree = repo.find_object(commit).map_err(git_err)?.try_into_commit().map_err(git_err)?.tree_id().map_err(git_err)?.detach();
+    let mut files = BTreeMap::new();
+    walk(&repo, tree, "", &mut files)?;
+    Ok(Some(Mirrored { repo, files }))
+}
+
+// ===== POST /api/admin/git-mirror ============================================
+
+pub(crate) async fn sync_now(State(st): State<AppState>) -> Response {
+    let Some(dir) = st.config.git_mirror_dir.clone() else {
+        return (StatusCode::NOT_FOUND, "the git mirror is off (set GIT_MIRROR_DIR)").into_response();
+    };
+    match tokio::task::spawn_blocking(move || sync(&dir)).await {
+        Ok(Ok(report)) => Json(report).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("git mirror sync failed: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
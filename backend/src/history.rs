@@ -0,0 +1,194 @@
+//! Per-file history (`FILE_HISTORY`, see [`Config`](crate::config::Config)):
+//! undo for single files without taking a snapshot.
+//!
+//! Before a save or patch replaces a working-copy file, the old content is
+//! kept as `./decisions/.history/<path>/<millis>`, the newest
+//! `FILE_HISTORY_KEEP` versions per file. Versions are reflinks where the
+//! filesystem has them and copies otherwise, never hardlinks: an in-place edit
+//! of the working copy from outside the server would rewrite them too.
+//! `GET /api/fs/history` lists a file's versions, `GET /api/fs/history/version`
+//! returns one and `POST /api/fs/history/restore` puts one back.
+
+use axum::{
+    extract::{Extension, Json, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Component, Path as StdPath, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{audit, auth, build_node, config::Config, quota, read_path, reflink, write_atomic, write_path, AppState, WriteOpts};
+
+const HISTORY_DIR: &str = "./decisions/.history";
+
+fn versions_dir(rel: &str) -> PathBuf {
+    PathBuf::from(HISTORY_DIR).join(rel.trim_matches('/'))
+}
+
+/// Version ids, oldest first.
+fn versions(rel: &str) -> Vec<u64> {
+    let mut ids: Vec<u64> = fs::read_dir(versions_dir(rel))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Keeps the current content of `full` (working-copy path `rel`) as a version
+/// before it's replaced. A no-op unless `FILE_HISTORY` is on and the file
+/// exists. Blocking; failures are logged, never fatal to the save.
+pub(crate) fn record(cfg: &Config, full: &StdPath, rel: &str) {
+    if !cfg.file_history || !full.is_file() {
+        return;
+    }
+    let dir = versions_dir(rel);
+    let mut id = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+    let res = fs::create_dir_all(&dir).and_then(|()| {
+        // two saves in the same millisecond: bump until free
+        while dir.join(id.to_string()).exists() {
+            id += 1;
+        }
+        let dst = dir.join(id.to_string());
+        reflink(full, &dst).or_else(|_| fs::copy(full, &dst).map(drop))
+    });
+    if let Err(e) = res {
+        tracing::warn!("could not keep a version of {rel}: {e}");
+        return;
+    }
+    let all = versions(rel);
+    for old in &all[..all.len().saturating_sub(cfg.file_history_keep)] {
+        let _ = fs::remove_file(dir.join(old.to_string()));
+    }
+}
+
+// ===== GET /api/fs/history ===================================================
+
+#[derive(Deserialize)]
+pub(crate) struct HistoryParams {
+    path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Version {
+    /// pass to `/api/fs/history/version` and `/restore`
+    id: u64,
+    /// unix seconds
+    saved_at: i64,
+    size: u64,
+}
+
+/// `read_path` for a file that has history: only the working copy (`0/…`)
+/// does. Reading it needs no folder ACL, as reading the file doesn't.
+fn history_path(cfg: &Config, user: &str) -> Result<PathBuf, StatusCode> {
+    let first = StdPath::new(user).components().find(|c| !matches!(c, Component::CurDir));
+    if first != Some(Component::Normal("0".as_ref())) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    read_path(cfg, user)
+}
+
+/// Versions of the working-copy file `path`, newest first. Empty, not 404, for a file that never had
+/// any, including one that doesn't exist anymore.
+pub(crate) async fn list(State(st): State<AppState>, Query(q): Query<HistoryParams>) -> Response {
+    if let Err(e) = history_path(&st.config, &q.path) {
+        return e.into_response();
+    }
+    let dir = versions_dir(&q.path);
+    let out: Vec<Version> = versions(&q.path)
+        .into_iter()
+        .rev()
+        .map(|id| Version {
+            id,
+            saved_at: (id / 1000) as i64,
+            size: fs::metadata(dir.join(id.to_string())).map_or(0, |m| m.len()),
+        })
+        .collect();
+    Json(out).into_response()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct VersionParams {
+    path: String,
+    id: u64,
+}
+
+/// `GET /api/fs/history/version`: the bytes of one version.
+pub(crate) async fn version(State(st): State<AppState>, Query(q): Query<VersionParams>) -> Response {
+    if let Err(e) = history_path(&st.config, &q.path) {
+        return e.into_response();
+    }
+    match tokio::fs::read(versions_dir(&q.path).join(q.id.to_string())).await {
+        Ok(bytes) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, "application/octet-stream".parse().unwrap());
+            (headers, bytes).into_response()
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => StatusCode::NOT_FOUND.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/fs/history/restore ==========================================
+
+#[derive(Deserialize)]
+pub(crate) struct RestoreReq {
+    path: String,
+    id: u64,
+    #[serde(default)]
+    owner: Option<String>,
+}
+
+/// Puts version `id` back at `path` (recreating the file if it was deleted)
+/// and returns its node. The content it replaces becomes a version itself, so
+/// a restore can be undone the same way.
+pub(crate) async fn restore(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<RestoreReq>) -> Response {
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check(&body.path, body.owner.as_deref()) {
+        return resp;
+    }
+    let full = match write_path(&body.path) {
+        Ok(p) if p.is_dir() => return StatusCode::CONFLICT.into_response(),
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+    let rel = body.path.trim_matches('/').to_string();
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<_, Response> {
+        let _g = state.rev_lock.lock();
+        let bytes = match fs::read(versions_dir(&rel).join(body.id.to_string())) {
+            Ok(b) => b,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(StatusCode::NOT_FOUND.into_response()),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        };
+        quota::check_write(&state.config, &full, bytes.len() as u64)?;
+        record(&state.config, &full, &rel);
+        let event = audit::Event::new("history-restore").path(&rel).hashes(audit::hash_file(&full), Some(audit::hash(&bytes)));
+        write_atomic(&full, &bytes, WriteOpts::default()).map_err(|e| {
+            tracing::error!("history restore error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+        Ok((build_node(&full, &rel, state.config.follow_symlinks), event))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((Some(node), event))) => {
+            st.index.touch(&node.path);
+            st.engines.invalidate(&node.path);
+            audit::record(event).await;
+            Json(node).into_response()
+        }
+        Ok(Ok((None, _))) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,324 @@
+//! Persistent full-text index for content search, kept under `./decisions/.index`.
+//!
+//! The index is a candidate filter, not the source of truth: it answers "which
+//! files could contain this substring", and `fs_search` still reads those files
+//! to produce exact hits and snippets. Handlers report changed paths via
+//! [`SearchIndex::touch`]; a single worker thread applies them in order, batched
+//! into one commit. The whole index is rebuilt at startup and on
+//! `POST /api/fs/index/rebuild`. Files edited behind the server's back are never
+//! missed: the mtime each file had when indexed is remembered, and a file whose
+//! mtime has moved on is always read.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::Path as StdPath,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, RwLock,
+    },
+    time::SystemTime,
+};
+use tantivy::{
+    collector::{Count, DocSetCollector},
+    directory::MmapDirectory,
+    query::{AllQuery, BooleanQuery, Occur, Query, RegexQuery, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING},
+    tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer},
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+use walkdir::WalkDir;
+
+use crate::{locks::lock_key, looks_binary, search::has_binary_extension, walk_allowed, AppState, STORAGE_ROOT};
+
+const INDEX_DIR: &str = "./decisions/.index";
+const WRITER_BUDGET: usize = 32 * 1024 * 1024;
+/// same cap as the scan, so the index never promises more than search reads
+const MAX_FILE_BYTES: u64 = 1_000_000;
+/// lowercased alphanumeric runs; unlike tantivy's default there is no length
+/// cap, so a short query can never miss a long token that contains it
+const TOKENIZER: &str = "words";
+
+enum Op {
+    Touch(String),
+    Rebuild,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    path: Field,
+    /// every ancestor folder of `path`, so a subtree can be dropped with one term
+    ancestors: Field,
+    body: Field,
+}
+
+struct Shared {
+    tx: mpsc::Sender<Op>,
+    reader: IndexReader,
+    fields: Fields,
+    ready: AtomicBool,
+    /// path → mtime as of the committed index; only updated after a reload
+    known: RwLock<HashMap<String, SystemTime>>,
+}
+
+/// Answer to [`SearchIndex::candidates`].
+pub(crate) struct Candidates {
+    hits: HashSet<String>,
+    shared: Arc<Shared>,
+}
+
+impl Candidates {
+    /// `false` only if the index vouches that this exact version of the file
+    /// doesn't contain the query.
+    pub(crate) fn may_contain(&self, rel: &str, mtime: Option<SystemTime>) -> bool {
+        if self.hits.contains(rel) {
+            return true;
+        }
+        let known = self.shared.known.read().unwrap();
+        mtime.is_none() || known.get(rel) != mtime.as_ref()
+    }
+}
+
+/// Handle to the index worker; a disabled index (failed to open) accepts every
+/// call and never narrows a search.
+#[derive(Clone, Default)]
+pub(crate) struct SearchIndex(Option<Arc<Shared>>);
+
+impl SearchIndex {
+    /// Opens (or recreates) the on-disk index and starts the worker, which
+    /// immediately rebuilds it.
+    pub(crate) fn open(follow: bool) -> Self {
+        match Self::try_open(follow) {
+            Ok(idx) => idx,
+            Err(e) => {
+                tracing::warn!("search index disabled: {e}");
+                Self(None)
+            }
+        }
+    }
+
+    fn try_open(follow: bool) -> tantivy::Result<Self> {
+        let mut sb = Schema::builder();
+        let body_opts = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::Basic),
+        );
+        let fields = Fields {
+            path: sb.add_text_field("path", STRING | STORED),
+            ancestors: sb.add_text_field("ancestors", STRING),
+            body: sb.add_text_field("body", body_opts),
+        };
+        let schema = sb.build();
+
+        fs::create_dir_all(INDEX_DIR)?;
+        let index = match Index::open_or_create(MmapDirectory::open(INDEX_DIR)?, schema.clone()) {
+            Ok(index) => index,
+            Err(e) => {
+                // stale schema or a corrupt index: it's only a cache, start over
+                tracing::warn!("recreating search index: {e}");
+                fs::remove_dir_all(INDEX_DIR)?;
+                fs::create_dir_all(INDEX_DIR)?;
+                Index::create_in_dir(INDEX_DIR, schema)?
+            }
+        };
+        index
+            .tokenizers()
+            .register(TOKENIZER, TextAnalyzer::builder(SimpleTokenizer::default()).filter(LowerCaser).build());
+        let writer: IndexWriter = index.writer_with_num_threads(1, WRITER_BUDGET)?;
+        let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(Op::Rebuild).ok();
+        let shared = Arc::new(Shared {
+            tx,
+            reader,
+            fields,
+            ready: AtomicBool::new(false),
+            known: RwLock::default(),
+        });
+        let worker = shared.clone();
+        std::thread::Builder::new()
+            .name("search-index".into())
+            .spawn(move || run(&worker, writer, rx, follow))?;
+        Ok(Self(Some(shared)))
+    }
+
+    /// Re-indexes a file or folder (or drops it, if it no longer exists).
+    /// `path` is a user path like `0/a.json`.
+    pub(crate) fn touch(&self, path: &str) {
+        if let (Some(s), Some(key)) = (&self.0, lock_key(path)) {
+            s.tx.send(Op::Touch(key)).ok();
+        }
+    }
+
+    fn rebuild(&self) -> bool {
+        self.0.as_ref().is_some_and(|s| s.tx.send(Op::Rebuild).is_ok())
+    }
+
+    /// Which files may contain `q` (compared case-insensitively), or `None` if
+    /// the index can't narrow this query.
+    pub(crate) fn candidates(&self, q: &str) -> Option<Candidates> {
+        let s = self.0.as_ref().filter(|s| s.ready.load(Ordering::Acquire))?;
+        let query = substring_query(s.fields.body, q)?;
+        let searcher = s.reader.searcher();
+        let docs = searcher.search(&query, &DocSetCollector).ok()?;
+        let mut hits = HashSet::with_capacity(docs.len());
+        for addr in docs {
+            let doc: TantivyDocument = searcher.doc(addr).ok()?;
+            if let Some(p) = doc.get_first(s.fields.path).and_then(|v| v.as_str()) {
+                hits.insert(p.to_string());
+            }
+        }
+        Some(Candidates { hits, shared: s.clone() })
+    }
+}
+
+/// Every alphanumeric run of `q` must occur in the file. Inner runs are whole
+/// tokens; the first may be the tail of a longer token and the last its head
+/// (a single run may sit anywhere inside one).
+fn substring_query(body: Field, q: &str) -> Option<Box<dyn Query>> {
+    let words: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let last = words.len().checked_sub(1)?;
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(words.len());
+    for (i, w) in words.iter().enumerate() {
+        // the runs are alphanumeric, so there is nothing to escape
+        let q: Box<dyn Query> = match (i == 0, i == last) {
+            (true, true) => Box::new(RegexQuery::from_pattern(&format!(".*{w}.*"), body).ok()?),
+            (true, false) => Box::new(RegexQuery::from_pattern(&format!(".*{w}"), body).ok()?),
+            (false, true) => Box::new(RegexQuery::from_pattern(&format!("{w}.*"), body).ok()?),
+            (false, false) => Box::new(TermQuery::new(Term::from_field_text(body, w), IndexRecordOption::Basic)),
+        };
+        clauses.push((Occur::Must, q));
+    }
+    Some(Box::new(BooleanQuery::new(clauses)))
+}
+
+// ===== worker ================================================================
+
+fn run(s: &Shared, mut writer: IndexWriter, rx: mpsc::Receiver<Op>, follow: bool) {
+    while let Ok(first) = rx.recv() {
+        // batch whatever queued up behind the first op into one commit
+        let mut rebuild = false;
+        let mut touched = BTreeSet::new();
+        for op in std::iter::once(first).chain(rx.try_iter()) {
+            match op {
+                Op::Rebuild => rebuild = true,
+                Op::Touch(p) => {
+                    touched.insert(p);
+                }
+            }
+        }
+
+        let mut added = Vec::new();
+        let res = if rebuild {
+            writer
+                .delete_all_documents()
+                .map(|_| index_tree(&writer, &s.fields, STORAGE_ROOT, follow, &mut added))
+        } else {
+            for p in &touched {
+                writer.delete_term(Term::from_field_text(s.fields.path, p));
+                writer.delete_term(Term::from_field_text(s.fields.ancestors, p));
+                index_tree(&writer, &s.fields, &format!("{STORAGE_ROOT}/{p}"), follow, &mut added);
+            }
+            Ok(())
+        };
+        match res.and_then(|_| writer.commit()).and_then(|_| s.reader.reload()) {
+            Ok(()) => {
+                let mut known = s.known.write().unwrap();
+                if rebuild {
+                    known.clear();
+                } else {
+                    known.retain(|k, _| !touched.iter().any(|p| k == p || k.starts_with(&format!("{p}/"))));
+                }
+                known.extend(added);
+                s.ready.store(true, Ordering::Release);
+            }
+            Err(e) => {
+                tracing::error!("search index update failed: {e}");
+                writer.rollback().ok();
+            }
+        }
+    }
+}
+
+/// Adds every indexable file at or under `full`, recording each in `added`.
+fn index_tree(writer: &IndexWriter, f: &Fields, full: &str, follow: bool, added: &mut Vec<(String, SystemTime)>) {
+    let walk = WalkDir::new(full)
+        .follow_links(follow)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+    for entry in walk.flatten().filter(|e| walk_allowed(e, follow)) {
+        if !entry.file_type().is_file()
+            || entry.metadata().map(|m| m.len()).unwrap_or(0) > MAX_FILE_BYTES
+            || has_binary_extension(entry.path())
+        {
+            continue;
+        }
+        let Some(rel) = relative(entry.path()) else { continue };
+        // taken before reading, so an edit racing the read shows up as a newer mtime
+        let Some(mtime) = entry.metadata().ok().and_then(|m| m.modified().ok()) else { continue };
+        let Ok(bytes) = fs::read(entry.path()) else { continue };
+        if looks_binary(&bytes) {
+            continue;
+        }
+        let Ok(text) = String::from_utf8(bytes) else { continue };
+
+        let mut doc = TantivyDocument::default();
+        doc.add_text(f.path, &rel);
+        let mut prefix = String::new();
+        for part in rel.split('/').take(rel.matches('/').count()) {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            doc.add_text(f.ancestors, &prefix);
+        }
+        doc.add_text(f.body, &text);
+        match writer.add_document(doc) {
+            Ok(_) => added.push((rel, mtime)),
+            Err(e) => tracing::warn!("could not index {rel}: {e}"),
+        }
+    }
+}
+
+/// Same rules as the fs watcher: HEAD and dot-prefixed sidecars aren't content.
+fn relative(p: &StdPath) -> Option<String> {
+    let rel = p.strip_prefix(STORAGE_ROOT).ok()?.to_string_lossy().replace('\\', "/");
+    if rel.is_empty() || rel == "HEAD" || rel.split('/').any(|c| c.starts_with('.')) {
+        return None;
+    }
+    Some(rel)
+}
+
+// ===== GET /api/fs/index, POST /api/fs/index/rebuild =========================
+
+#[derive(Serialize)]
+struct IndexStatus {
+    enabled: bool,
+    /// false until the first build has committed
+    ready: bool,
+    documents: u64,
+}
+
+pub(crate) async fn status(State(st): State<AppState>) -> impl IntoResponse {
+    let Some(s) = &st.index.0 else {
+        return Json(IndexStatus { enabled: false, ready: false, documents: 0 });
+    };
+    let documents = s.reader.searcher().search(&AllQuery, &Count).unwrap_or_default() as u64;
+    Json(IndexStatus { enabled: true, ready: s.ready.load(Ordering::Acquire), documents })
+}
+
+pub(crate) async fn rebuild(State(st): State<AppState>) -> impl IntoResponse {
+    if st.index.rebuild() {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
@@ -0,0 +1,114 @@
+//! Read-only helpers over JDM (decision graph) documents, kept as plain
+//! `serde_json::Value` so half-edited or newer-format files can still be
+//! inspected.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// What a string inside a node is for.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SiteKind {
+    /// decision-table input column (`inputs[].field`)
+    InputColumn,
+    /// decision-table output column (`outputs[].field`)
+    OutputColumn,
+    /// rule cell under an input column
+    InputCell,
+    /// rule cell under an output column
+    OutputCell,
+    /// expression node target (`expressions[].key`)
+    ExpressionKey,
+    /// expression node value
+    Expression,
+    /// switch node statement
+    Condition,
+    /// function node source
+    Function,
+    /// transform `inputField`
+    InputField,
+    /// transform `outputPath`
+    OutputPath,
+}
+
+impl SiteKind {
+    /// Sites that name a property the node writes rather than reads.
+    pub(crate) fn writes(self) -> bool {
+        matches!(self, Self::OutputColumn | Self::ExpressionKey | Self::OutputPath)
+    }
+
+    /// Sites that hold a bare field path rather than an expression.
+    pub(crate) fn is_field_path(self) -> bool {
+        matches!(self, Self::InputColumn | Self::OutputColumn | Self::ExpressionKey | Self::InputField | Self::OutputPath)
+    }
+}
+
+/// One string-valued site within a node.
+pub(crate) struct Site<'a> {
+    pub node: &'a Value,
+    /// JSON-pointer-ish location inside the node's `content`, e.g. `rules/3/in1`
+    pub location: String,
+    pub kind: SiteKind,
+    pub text: &'a str,
+}
+
+pub(crate) fn nodes(doc: &Value) -> impl Iterator<Item = &Value> {
+    doc["nodes"].as_array().into_iter().flatten()
+}
+
+pub(crate) fn node_type(node: &Value) -> &str {
+    node["type"].as_str().unwrap_or_default()
+}
+
+/// Every non-empty field path, expression and script in `doc`, in node order.
+pub(crate) fn sites<'a>(doc: &'a Value) -> Vec<Site<'a>> {
+    let mut out = Vec::new();
+    for node in nodes(doc) {
+        let content = &node["content"];
+        let mut add = |location: String, kind: SiteKind, v: &'a Value| {
+            if let Some(text) = v.as_str().filter(|t| !t.trim().is_empty()) {
+                out.push(Site { node, location, kind, text });
+            }
+        };
+        add("inputField".into(), SiteKind::InputField, &content["inputField"]);
+        add("outputPath".into(), SiteKind::OutputPath, &content["outputPath"]);
+
+        match node_type(node) {
+            "decisionTableNode" => {
+                let columns = |k: &str| content[k].as_array().map(Vec::as_slice).unwrap_or_default();
+                let (inputs, outputs) = (columns("inputs"), columns("outputs"));
+                for (i, c) in inputs.iter().enumerate() {
+                    add(format!("inputs/{i}/field"), SiteKind::InputColumn, &c["field"]);
+                }
+                for (i, c) in outputs.iter().enumerate() {
+                    add(format!("outputs/{i}/field"), SiteKind::OutputColumn, &c["field"]);
+                }
+                for (r, rule) in content["rules"].as_array().into_iter().flatten().enumerate() {
+                    for (cols, kind) in [(inputs, SiteKind::InputCell), (outputs, SiteKind::OutputCell)] {
+                        for c in cols {
+                            let Some(id) = c["id"].as_str() else { continue };
+                            add(format!("rules/{r}/{id}"), kind, &rule[id]);
+                        }
+                    }
+                }
+            }
+            "expressionNode" => {
+                for (i, e) in content["expressions"].as_array().into_iter().flatten().enumerate() {
+                    add(format!("expressions/{i}/key"), SiteKind::ExpressionKey, &e["key"]);
+                    add(format!("expressions/{i}/value"), SiteKind::Expression, &e["value"]);
+                }
+            }
+            "switchNode" => {
+                for (i, s) in content["statements"].as_array().into_iter().flatten().enumerate() {
+                    add(format!("statements/{i}/condition"), SiteKind::Condition, &s["condition"]);
+                }
+            }
+            "functionNode" => match content {
+                Value::String(_) => add(String::new(), SiteKind::Function, content),
+                _ => add("source".into(), SiteKind::Function, &content["source"]),
+            },
+            _ => {}
+        }
+    }
+    out
+}
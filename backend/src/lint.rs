@@ -0,0 +1,361 @@
+//! `POST /api/validate`: what's wrong with a decision document, short of
+//! running it.
+//!
+//! [`check`] looks at a JDM graph the way the engine will load it: node kinds
+//! it knows, node contents it can parse, one input node, edges between nodes
+//! that exist, no cycles, and expressions and table cells that lex, parse and
+//! compile (nothing is evaluated). Every problem names the node, edge and
+//! place in the node's `content` it was found at, as `/api/search/jdm` does,
+//! and expression problems carry the character span the expression language
+//! reported, so the UI can underline it.
+//!
+//! Saves can ask for the same check (`validate: true`), and
+//! `VALIDATE_ON_SAVE_DIRS` makes it mandatory for the decisions under given
+//! folders ([`gate`]); only errors refuse a write, warnings never do.
+//! Checks a team configures for itself are [`rules`]'.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use bumpalo::Bump;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::OnceLock,
+};
+use zen_expression::{
+    compiler::Compiler,
+    lexer::{Lexer, LexerError},
+    parser::Parser,
+};
+
+use crate::{config::Config, jdm, read_path, AppState};
+
+pub(crate) mod rules;
+
+const NODE_TYPES: &[&str] = &[
+    "inputNode",
+    "outputNode",
+    "functionNode",
+    "decisionNode",
+    "decisionTableNode",
+    "expressionNode",
+    "switchNode",
+    "customNode",
+];
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Severity {
+    /// the engine will refuse it or fail on it
+    Error,
+    /// loads, but probably not what was meant
+    Warning,
+    /// worth knowing; nothing's wrong with it
+    Info,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Problem {
+    pub severity: Severity,
+    /// stable identifier, e.g. `danglingEdge`
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_id: Option<String>,
+    /// inside the node's `content`, e.g. `rules/3/in1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// character offsets into the expression, end exclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<(u32, u32)>,
+    /// for documents that aren't JSON, 1-based
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl Problem {
+    fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            node_id: None,
+            edge_id: None,
+            location: None,
+            span: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    fn on_node(mut self, node: &Value) -> Self {
+        self.node_id = node["id"].as_str().map(str::to_string);
+        self
+    }
+}
+
+/// `at (3, 7)` or `at 4` in an expression-language error message.
+fn span_in(message: &str) -> Option<(u32, u32)> {
+    static AT: OnceLock<Regex> = OnceLock::new();
+    let caps = AT.get_or_init(|| Regex::new(r"at \((\d+), (\d+)\)|at (\d+)$").unwrap()).captures(message)?;
+    let num = |i| caps.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+    match (num(1), num(2), num(3)) {
+        (Some(a), Some(b), _) => Some((a, b)),
+        (_, _, Some(p)) => Some((p, p + 1)),
+        _ => None,
+    }
+}
+
+/// Why `source` wouldn't compile, and where. `unary` for table input cells
+/// under a column with a field, which the engine runs as unary tests.
+pub(crate) fn expression_error(source: &str, unary: bool) -> Option<(String, Option<(u32, u32)>)> {
+    let mut lexer = Lexer::new();
+    let tokens = match lexer.tokenize(source) {
+        Ok(t) => t,
+        Err(e) => {
+            let span = match &e {
+                LexerError::UnexpectedSymbol { span, .. } => *span,
+                LexerError::UnmatchedSymbol { position, .. } | LexerError::UnexpectedEof { position, .. } => (*position, position + 1),
+            };
+            return Some((e.to_string(), Some(span)));
+        }
+    };
+    let bump = Bump::new();
+    let parser = match Parser::try_new(tokens, &bump) {
+        Ok(p) => p,
+        Err(e) => return Some((e.to_string(), None)),
+    };
+    let parsed = if unary { parser.unary().parse() } else { parser.standard().parse() };
+    if let Err(e) = parsed.error() {
+        let message = e.to_string();
+        // running out of tokens is reported at (0, 0)
+        let end = source.chars().count() as u32;
+        let span = span_in(&message).map(|s| if message.contains("token: None at") { (end, end) } else { s });
+        return Some((message, span));
+    }
+    let mut compiler = Compiler::new();
+    compiler.compile(parsed.root).err().map(|e| (e.to_string(), None))
+}
+
+fn check_expressions(doc: &Value, out: &mut Vec<Problem>) {
+    // table input columns with a field: their cells are unary tests
+    let mut unary_columns: HashMap<(&str, &str), bool> = HashMap::new();
+    for node in jdm::nodes(doc).filter(|n| jdm::node_type(n) == "decisionTableNode") {
+        let node_id = node["id"].as_str().unwrap_or_default();
+        for c in node["content"]["inputs"].as_array().into_iter().flatten() {
+            let Some(id) = c["id"].as_str() else { continue };
+            unary_columns.insert((node_id, id), c["field"].is_string());
+        }
+    }
+
+    for site in jdm::sites(doc) {
+        let unary = match site.kind {
+            jdm::SiteKind::InputCell => {
+                let node_id = site.node["id"].as_str().unwrap_or_default();
+                let column = site.location.rsplit('/').next().unwrap_or_default();
+                unary_columns.get(&(node_id, column)).copied().unwrap_or(false)
+            }
+            jdm::SiteKind::OutputCell | jdm::SiteKind::Expression | jdm::SiteKind::Condition => false,
+            _ => continue,
+        };
+        let Some((message, span)) = expression_error(site.text, unary) else { continue };
+        let (code, what) = match site.kind {
+            jdm::SiteKind::InputCell | jdm::SiteKind::OutputCell => ("invalidCell", "table cell"),
+            _ => ("invalidExpression", "expression"),
+        };
+        let mut p = Problem::new(Severity::Error, code, format!("{what} {:?}: {message}", site.text)).on_node(site.node);
+        p.location = Some(site.location);
+        p.span = span;
+        out.push(p);
+    }
+}
+
+/// Whether following edges from some node leads back to it.
+fn has_cycle(edges: &[(&str, &str)]) -> bool {
+    let mut next: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut indegree: HashMap<&str, usize> = HashMap::new();
+    for &(from, to) in edges {
+        next.entry(from).or_default().push(to);
+        indegree.entry(from).or_default();
+        *indegree.entry(to).or_default() += 1;
+    }
+    // Kahn: whatever can't be peeled off from the sources is on a cycle
+    let mut ready: Vec<&str> = indegree.iter().filter(|(_, &d)| d == 0).map(|(&n, _)| n).collect();
+    let mut seen = 0;
+    while let Some(n) = ready.pop() {
+        seen += 1;
+        for &m in next.get(n).into_iter().flatten() {
+            let d = indegree.get_mut(m).expect("every target was counted");
+            *d -= 1;
+            if *d == 0 {
+                ready.push(m);
+            }
+        }
+    }
+    seen < indegree.len()
+}
+
+/// Everything wrong with the decision graph `doc`, errors first.
+pub(crate) fn check(doc: &Value) -> Vec<Problem> {
+    let mut out = Vec::new();
+    if !doc.is_object() || !doc["nodes"].is_array() {
+        out.push(Problem::new(Severity::Error, "notADecision", "not a decision graph: expected an object with a nodes array"));
+        return out;
+    }
+    if !doc["edges"].is_array() && !doc["edges"].is_null() {
+        out.push(Problem::new(Severity::Error, "notADecision", "edges must be an array"));
+    }
+
+    let mut ids = HashSet::new();
+    let mut inputs = 0;
+    for node in jdm::nodes(doc) {
+        let Some(id) = node["id"].as_str() else {
+            out.push(Problem::new(Severity::Error, "invalidNode", "node without an id"));
+            continue;
+        };
+        if !ids.insert(id) {
+            out.push(Problem::new(Severity::Error, "duplicateNodeId", format!("node id {id:?} is used more than once")).on_node(node));
+        }
+        let kind = jdm::node_type(node);
+        if !NODE_TYPES.contains(&kind) {
+            out.push(Problem::new(Severity::Error, "unknownNodeType", format!("unknown node type {kind:?}")).on_node(node));
+            continue;
+        }
+        if kind == "inputNode" {
+            inputs += 1;
+        }
+        if let Err(e) = serde_json::from_value::<zen_engine::model::DecisionNode>(node.clone()) {
+            out.push(Problem::new(Severity::Error, "invalidNode", format!("{kind} can't be loaded: {e}")).on_node(node));
+        }
+        if kind == "decisionNode" && node["content"]["key"].as_str().is_none_or(|k| k.trim().is_empty()) {
+            let mut p = Problem::new(Severity::Error, "missingDecisionKey", "decision node doesn't name a decision").on_node(node);
+            p.location = Some("key".into());
+            out.push(p);
+        }
+    }
+    if inputs != 1 {
+        out.push(Problem::new(Severity::Error, "inputCount", format!("a decision needs exactly one input node, this one has {inputs}")));
+    }
+
+    let mut edges = Vec::new();
+    let (mut has_in, mut has_out) = (HashSet::new(), HashSet::new());
+    for edge in doc["edges"].as_array().into_iter().flatten() {
+        let edge_id = edge["id"].as_str().map(str::to_string);
+        let (from, to) = (edge["sourceId"].as_str().unwrap_or_default(), edge["targetId"].as_str().unwrap_or_default());
+        let mut dangling = false;
+        for (end, id) in [("source", from), ("target", to)] {
+            if !ids.contains(id) {
+                let mut p = Problem::new(Severity::Error, "danglingEdge", format!("edge {end} {id:?} is not a node"));
+                p.edge_id = edge_id.clone();
+                out.push(p);
+                dangling = true;
+            }
+        }
+        if !dangling {
+            edges.push((from, to));
+            has_out.insert(from);
+            has_in.insert(to);
+        }
+    }
+    if has_cycle(&edges) {
+        out.push(Problem::new(Severity::Error, "cycle", "the edges form a cycle"));
+    }
+    for node in jdm::nodes(doc) {
+        let Some(id) = node["id"].as_str() else { continue };
+        let kind = jdm::node_type(node);
+        let missing = match (kind != "inputNode" && !has_in.contains(id), kind != "outputNode" && !has_out.contains(id)) {
+            (true, true) => "isn't connected to anything",
+            (true, false) => "has no incoming edge and will never run",
+            (false, true) => "has no outgoing edge, so its result goes nowhere",
+            (false, false) => continue,
+        };
+        let name = node["name"].as_str().unwrap_or(id);
+        out.push(Problem::new(Severity::Warning, "disconnected", format!("{name} {missing}")).on_node(node));
+    }
+
+    check_expressions(doc, &mut out);
+    out.sort_by_key(|p| p.severity != Severity::Error);
+    out
+}
+
+/// [`check`] on raw bytes, failing the JSON itself with a line and column.
+pub(crate) fn check_bytes(bytes: &[u8]) -> Vec<Problem> {
+    match serde_json::from_slice::<Value>(bytes) {
+        Ok(doc) => check(&doc),
+        Err(e) => {
+            let mut p = Problem::new(Severity::Error, "invalidJson", format!("not JSON: {e}"));
+            p.line = Some(e.line());
+            p.column = Some(e.column());
+            vec![p]
+        }
+    }
+}
+
+/// Whether `VALIDATE_ON_SAVE_DIRS` makes the user path `path` (`0/...`) a
+/// decision that must validate.
+pub(crate) fn required(cfg: &Config, path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    let Some(rel) = path.strip_prefix("0/") else { return false };
+    rel.to_ascii_lowercase().ends_with(".json")
+        && cfg.validate_dirs.iter().any(|d| rel.strip_prefix(d.as_str()).is_some_and(|r| r.starts_with('/')))
+}
+
+/// For saves: 422 with the problems when `content` has errors.
+pub(crate) fn gate(content: &[u8]) -> Result<(), Response> {
+    let problems: Vec<Problem> = check_bytes(content).into_iter().filter(|p| p.severity == Severity::Error).collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let body = serde_json::json!({ "error": "decision failed validation", "problems": problems });
+    Err((StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response())
+}
+
+// ===== POST /api/validate ====================================================
+
+#[derive(Deserialize)]
+pub(crate) struct ValidateReq {
+    /// a stored file, e.g. `0/loans/score.json`
+    path: Option<String>,
+    /// or a document to check before it's stored: the graph, or its JSON text
+    content: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct ValidateResp {
+    /// no errors; warnings allowed
+    valid: bool,
+    problems: Vec<Problem>,
+}
+
+pub(crate) async fn validate(State(st): State<AppState>, Json(req): Json<ValidateReq>) -> Response {
+    let problems = match (req.path, req.content) {
+        (Some(path), None) => {
+            let full = match read_path(&st.config, &path) {
+                Ok(p) => p,
+                Err(status) => return status.into_response(),
+            };
+            let res = tokio::task::spawn_blocking(move || fs::read(&full).map(|bytes| check_bytes(&bytes))).await;
+            match res {
+                Ok(Ok(problems)) => problems,
+                Ok(Err(_)) => return StatusCode::NOT_FOUND.into_response(),
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        (None, Some(Value::String(text))) => check_bytes(text.as_bytes()),
+        (None, Some(doc)) => check(&doc),
+        _ => return (StatusCode::BAD_REQUEST, "give either path or content").into_response(),
+    };
+    let valid = !problems.iter().any(|p| p.severity == Severity::Error);
+    Json(ValidateResp { valid, problems }).into_response()
+}
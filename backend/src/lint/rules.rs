@@ -0,0 +1,356 @@
+//! `POST /api/lint`: [`check`](super::check) plus the house rules of whoever
+//! owns the decisions, read from `.editorlint.json`.
+//!
+//! The config nearest a decision applies: one in its folder, or else the
+//! folder above, up to the root of its revision, so a team can keep its own
+//! in its own folder and the working copy's root can hold everyone's
+//! default. Configs are files like any other, saved with `/api/fs/save` and
+//! snapshotted with the decisions they're about; a save of one that doesn't
+//! parse is refused. It looks like
+//!
+//! ```json
+//! {
+//!   "enforceOnSave": true,
+//!   "rules": {
+//!     "fileNames":          { "severity": "warning", "pattern": "^[a-z0-9-]+$" },
+//!     "nodeNames":          { "pattern": "^[A-Z]" },
+//!     "fieldNames":         { "severity": "error", "pattern": "^[a-z][A-Za-z0-9]*$" },
+//!     "maxNodes":           { "max": 40 },
+//!     "maxTableRules":      { "severity": "error", "max": 500 },
+//!     "maxTableColumns":    { "max": 12 },
+//!     "forbiddenNodeKinds": { "severity": "error", "kinds": ["functionNode"] }
+//!   }
+//! }
+//! ```
+//!
+//! Every rule is off until it's named, and `warning` unless its `severity`
+//! says `error`, `info` or `off`. `fileNames` is matched against the file's
+//! name without `.json`, `nodeNames` against the names of the nodes between
+//! input and output, `fieldNames` against each segment of the field paths
+//! tables and expressions read and write. With `enforceOnSave`, saves and
+//! patches of `*.json` files the config governs are refused (422) when they
+//! lint with errors, the structural ones included; warnings never refuse.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path as StdPath, PathBuf},
+    sync::Arc,
+};
+
+use super::{check, check_bytes, Problem, Severity};
+use crate::{jdm, read_path, search, AppState, STORAGE_ROOT};
+
+const CONFIG_FILE: &str = ".editorlint.json";
+
+/// Files linted at once when `path` is a folder.
+const MAX_FILES: usize = 5000;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Level {
+    Off,
+    Info,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn severity(self) -> Option<Severity> {
+        match self {
+            Self::Off => None,
+            Self::Info => Some(Severity::Info),
+            Self::Warning => Some(Severity::Warning),
+            Self::Error => Some(Severity::Error),
+        }
+    }
+}
+
+fn regex<'de, D: Deserializer<'de>>(d: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(d)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Naming {
+    #[serde(default)]
+    severity: Level,
+    #[serde(deserialize_with = "regex")]
+    pattern: Regex,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Limit {
+    #[serde(default)]
+    severity: Level,
+    max: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Forbidden {
+    #[serde(default)]
+    severity: Level,
+    kinds: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Rules {
+    file_names: Option<Naming>,
+    node_names: Option<Naming>,
+    field_names: Option<Naming>,
+    max_nodes: Option<Limit>,
+    max_table_rules: Option<Limit>,
+    /// inputs and outputs together
+    max_table_columns: Option<Limit>,
+    forbidden_node_kinds: Option<Forbidden>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct LintConfig {
+    /// refuse saves that lint with errors
+    #[serde(default)]
+    enforce_on_save: bool,
+    #[serde(default)]
+    rules: Rules,
+}
+
+fn parse(bytes: &[u8]) -> Result<LintConfig, String> {
+    serde_json::from_slice(bytes).map_err(|e| e.to_string())
+}
+
+/// The config governing some folder: where it is, as a user path, and what
+/// it says or why that can't be read.
+#[derive(Clone)]
+struct Applied {
+    path: String,
+    config: Result<Arc<LintConfig>, String>,
+}
+
+/// The config nearest `dir`, a folder inside a revision; `cache` holds what
+/// was found for folders already looked at.
+fn nearest(dir: &StdPath, cache: &mut HashMap<PathBuf, Option<Applied>>) -> Option<Applied> {
+    if let Some(found) = cache.get(dir) {
+        return found.clone();
+    }
+    let rel = dir.strip_prefix(STORAGE_ROOT).ok()?;
+    let file = dir.join(CONFIG_FILE);
+    let found = match fs::read(&file) {
+        Ok(bytes) => {
+            let path = rel.join(CONFIG_FILE).to_string_lossy().replace('\\', "/");
+            let config = parse(&bytes).map(Arc::new).map_err(|e| format!("{path} can't be read: {e}"));
+            Some(Applied { path, config })
+        }
+        // the revision's root is as far up as configs go
+        Err(_) if rel.components().count() <= 1 => None,
+        Err(_) => dir.parent().and_then(|up| nearest(up, cache)),
+    };
+    cache.insert(dir.to_path_buf(), found.clone());
+    found
+}
+
+fn problem(level: Level, code: &'static str, message: String) -> Option<Problem> {
+    level.severity().map(|s| Problem::new(s, code, message))
+}
+
+/// What `rules` find in the decision `doc`, stored as `file_name`.
+fn apply(rules: &Rules, doc: &Value, file_name: &str, out: &mut Vec<Problem>) {
+    if let Some(r) = &rules.file_names {
+        let stem = file_name.strip_suffix(".json").unwrap_or(file_name);
+        if !r.pattern.is_match(stem) {
+            out.extend(problem(r.severity, "fileNames", format!("file name {stem:?} doesn't match {}", r.pattern)));
+        }
+    }
+    if let Some(r) = &rules.max_nodes {
+        let count = jdm::nodes(doc).count();
+        if count > r.max {
+            out.extend(problem(r.severity, "maxNodes", format!("{count} nodes, more than the {} allowed", r.max)));
+        }
+    }
+    for node in jdm::nodes(doc) {
+        let kind = jdm::node_type(node);
+        let name = node["name"].as_str().unwrap_or_default();
+        if let Some(r) = rules.forbidden_node_kinds.as_ref().filter(|r| r.kinds.iter().any(|k| k == kind)) {
+            out.extend(problem(r.severity, "forbiddenNodeKinds", format!("{name}: {kind}s aren't allowed here")).map(|p| p.on_node(node)));
+        }
+        if let Some(r) = rules.node_names.as_ref().filter(|_| !matches!(kind, "inputNode" | "outputNode")) {
+            if !r.pattern.is_match(name) {
+                out.extend(problem(r.severity, "nodeNames", format!("node name {name:?} doesn't match {}", r.pattern)).map(|p| p.on_node(node)));
+            }
+        }
+        if kind != "decisionTableNode" {
+            continue;
+        }
+        let count = |k: &str| node["content"][k].as_array().map_or(0, Vec::len);
+        if let Some(r) = rules.max_table_rules.as_ref().filter(|r| count("rules") > r.max) {
+            let message = format!("{name} has {} rules, more than the {} allowed", count("rules"), r.max);
+            out.extend(problem(r.severity, "maxTableRules", message).map(|p| p.on_node(node)));
+        }
+        let columns = count("inputs") + count("outputs");
+        if let Some(r) = rules.max_table_columns.as_ref().filter(|r| columns > r.max) {
+            let message = format!("{name} has {columns} columns, more than the {} allowed", r.max);
+            out.extend(problem(r.severity, "maxTableColumns", message).map(|p| p.on_node(node)));
+        }
+    }
+    if let Some(r) = rules.field_names.as_ref().filter(|r| r.severity != Level::Off) {
+        for site in jdm::sites(doc).into_iter().filter(|s| s.kind.is_field_path()) {
+            let bad = site.text.trim().split('.').find(|seg| seg.parse::<usize>().is_err() && !r.pattern.is_match(seg));
+            let Some(seg) = bad else { continue };
+            let message = format!("field {:?} has a segment, {seg:?}, that doesn't match {}", site.text, r.pattern);
+            let mut p = problem(r.severity, "fieldNames", message).expect("not off").on_node(site.node);
+            p.location = Some(site.location);
+            out.push(p);
+        }
+    }
+}
+
+/// Everything wrong with the stored `bytes` of `file_name` under `applied`,
+/// errors first.
+fn lint_bytes(bytes: &[u8], file_name: &str, applied: Option<&Applied>) -> Vec<Problem> {
+    let mut out = match serde_json::from_slice::<Value>(bytes) {
+        Ok(doc) => {
+            let mut out = check(&doc);
+            match applied.map(|a| &a.config) {
+                Some(Ok(config)) if doc["nodes"].is_array() => apply(&config.rules, &doc, file_name, &mut out),
+                Some(Err(e)) => out.push(Problem::new(Severity::Error, "invalidLintConfig", e.clone())),
+                _ => {}
+            }
+            out
+        }
+        Err(_) => check_bytes(bytes),
+    };
+    out.sort_by_key(|p| match p.severity {
+        Severity::Error => 0,
+        Severity::Warning => 1,
+        Severity::Info => 2,
+    });
+    out
+}
+
+fn is_linted(name: &str) -> bool {
+    !name.starts_with('.') && name.to_ascii_lowercase().ends_with(".json")
+}
+
+fn file_name(full: &StdPath) -> &str {
+    full.file_name().and_then(|n| n.to_str()).unwrap_or_default()
+}
+
+/// For saves and patches into the working copy, at `full`: a lint config
+/// must parse, and a decision its config enforces must lint without errors;
+/// 422 otherwise. A config that can't be read enforces nothing.
+pub(crate) fn gate(full: &StdPath, content: &[u8]) -> Result<(), Response> {
+    let name = file_name(full);
+    if name == CONFIG_FILE {
+        return parse(content).map(|_| ()).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("invalid lint config: {e}")).into_response());
+    }
+    if !is_linted(name) {
+        return Ok(());
+    }
+    let applied = full.parent().and_then(|dir| nearest(dir, &mut HashMap::new()));
+    let Some(applied) = applied.filter(|a| a.config.as_ref().is_ok_and(|c| c.enforce_on_save)) else {
+        return Ok(());
+    };
+    let errors: Vec<Problem> = lint_bytes(content, name, Some(&applied)).into_iter().filter(|p| p.severity == Severity::Error).collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let body = serde_json::json!({ "error": "decision failed lint", "config": applied.path, "problems": errors });
+    Err((StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response())
+}
+
+// ===== POST /api/lint ========================================================
+
+#[derive(Deserialize)]
+pub(crate) struct LintReq {
+    /// a stored decision or a folder of them, e.g. `0/loans`; with
+    /// `content`, where that content would be saved
+    path: String,
+    /// a document to lint before it's stored: the graph, or its JSON text
+    content: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileReport {
+    path: String,
+    /// the config that applied, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<String>,
+    /// no errors
+    valid: bool,
+    problems: Vec<Problem>,
+}
+
+#[derive(Serialize)]
+struct LintResp {
+    files: Vec<FileReport>,
+    /// more files than are linted at once
+    truncated: bool,
+}
+
+fn report(path: String, bytes: &[u8], applied: Option<Applied>) -> FileReport {
+    let name = path.rsplit('/').next().unwrap_or_default().to_string();
+    let problems = lint_bytes(bytes, &name, applied.as_ref());
+    let valid = !problems.iter().any(|p| p.severity == Severity::Error);
+    FileReport { path, config: applied.map(|a| a.path), valid, problems }
+}
+
+pub(crate) async fn lint(State(st): State<AppState>, Json(req): Json<LintReq>) -> Response {
+    let full = match read_path(&st.config, &req.path) {
+        Ok(p) => p,
+        Err(status) => return status.into_response(),
+    };
+    let follow = st.config.follow_symlinks;
+    let res = tokio::task::spawn_blocking(move || -> Result<LintResp, Response> {
+        let mut cache = HashMap::new();
+        let user = req.path.trim_start_matches('/').trim_end_matches('/').to_string();
+        if let Some(content) = req.content {
+            let applied = full.parent().and_then(|dir| nearest(dir, &mut cache));
+            let bytes = match content {
+                Value::String(text) => text.into_bytes(),
+                doc => serde_json::to_vec(&doc).expect("serialize document"),
+            };
+            return Ok(LintResp { files: vec![report(user, &bytes, applied)], truncated: false });
+        }
+        if full.is_file() {
+            let bytes = fs::read(&full).map_err(|_| StatusCode::NOT_FOUND.into_response())?;
+            let applied = full.parent().and_then(|dir| nearest(dir, &mut cache));
+            return Ok(LintResp { files: vec![report(user, &bytes, applied)], truncated: false });
+        }
+        if !full.is_dir() {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        }
+        let mut files = Vec::new();
+        let mut truncated = false;
+        for entry in search::files(&full, follow).filter(|e| is_linted(&e.file_name().to_string_lossy())) {
+            if files.len() == MAX_FILES {
+                truncated = true;
+                break;
+            }
+            let Ok(bytes) = fs::read(entry.path()) else { continue };
+            let Ok(rel) = entry.path().strip_prefix(STORAGE_ROOT) else { continue };
+            let applied = entry.path().parent().and_then(|dir| nearest(dir, &mut cache));
+            files.push(report(rel.to_string_lossy().replace('\\', "/"), &bytes, applied));
+        }
+        Ok(LintResp { files, truncated })
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,197 @@
+//! Advisory, TTL-based file leases (`/api/fs/lock`, `/api/fs/unlock`, `/api/fs/locks`).
+//!
+//! Locks are purely in-memory and only enforced by the writes into the working
+//! copy (save, patch, rename, delete, extract, replace, restores, templates and
+//! table imports); they exist so the UI can tell a user "locked by X" before
+//! two people overwrite each other. Once
+//! requests are authenticated the owner is always the caller, whatever `owner`
+//! says, here and in every write that checks for a lease.
+
+use axum::{
+    extract::{Extension, Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{auth, events::ServerEvent, safe_path, AppState};
+
+const DEFAULT_TTL_SECS: u64 = 60;
+const MAX_TTL_SECS: u64 = 3600;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LockInfo {
+    pub path: String,
+    pub owner: String,
+    /// unix seconds
+    pub expires_at: u64,
+}
+
+struct Lease {
+    owner: String,
+    expires_at: SystemTime,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct LockTable(Arc<Mutex<HashMap<String, Lease>>>);
+
+impl LockTable {
+    /// Takes or renews the lease; `Err` carries the current foreign holder.
+    fn acquire(&self, path: &str, owner: &str, ttl: Duration) -> Result<LockInfo, LockInfo> {
+        let mut map = self.0.lock().unwrap();
+        let now = SystemTime::now();
+        if let Some(l) = map.get(path) {
+            if l.expires_at > now && l.owner != owner {
+                return Err(info(path, l));
+            }
+        }
+        let lease = Lease { owner: owner.to_string(), expires_at: now + ttl };
+        let out = info(path, &lease);
+        map.insert(path.to_string(), lease);
+        Ok(out)
+    }
+
+    fn release(&self, path: &str, owner: &str) -> Result<(), Option<LockInfo>> {
+        let mut map = self.0.lock().unwrap();
+        match map.get(path) {
+            Some(l) if l.expires_at <= SystemTime::now() => {
+                map.remove(path);
+                Err(None)
+            }
+            Some(l) if l.owner != owner => Err(Some(info(path, l))),
+            Some(_) => {
+                map.remove(path);
+                Ok(())
+            }
+            None => Err(None),
+        }
+    }
+
+    /// The live lease on `path`, if any.
+    pub(crate) fn holder(&self, path: &str) -> Option<LockInfo> {
+        let map = self.0.lock().unwrap();
+        map.get(path)
+            .filter(|l| l.expires_at > SystemTime::now())
+            .map(|l| info(path, l))
+    }
+
+    /// `Err(409 response)` if `path` is leased to someone other than `owner`.
+    pub(crate) fn check(&self, path: &str, owner: Option<&str>) -> Result<(), Response> {
+        match lock_key(path).and_then(|k| self.holder(&k)) {
+            Some(holder) if owner != Some(holder.owner.as_str()) => Err(conflict(holder)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like [`check`](Self::check), for every lease on `prefix` or below it.
+    pub(crate) fn check_tree(&self, prefix: &str, owner: Option<&str>) -> Result<(), Response> {
+        let Some(prefix) = lock_key(prefix) else { return Ok(()) };
+        let map = self.0.lock().unwrap();
+        let now = SystemTime::now();
+        let foreign = map.iter().find(|(p, l)| {
+            let under = *p == &prefix || p.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with('/'));
+            under && l.expires_at > now && owner != Some(l.owner.as_str())
+        });
+        match foreign {
+            Some((p, l)) => Err(conflict(info(p, l))),
+            None => Ok(()),
+        }
+    }
+
+    fn list(&self) -> Vec<LockInfo> {
+        let mut map = self.0.lock().unwrap();
+        let now = SystemTime::now();
+        map.retain(|_, l| l.expires_at > now);
+        let mut out: Vec<_> = map.iter().map(|(p, l)| info(p, l)).collect();
+        out.sort_by(|a, b| a.path.cmp(&b.path));
+        out
+    }
+}
+
+fn info(path: &str, l: &Lease) -> LockInfo {
+    LockInfo {
+        path: path.to_string(),
+        owner: l.owner.clone(),
+        expires_at: l.expires_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+    }
+}
+
+/// Canonical lock key for a user path (`./0//a.json` → `0/a.json`), or `None`
+/// if the path would escape the storage root.
+pub(crate) fn lock_key(user: &str) -> Option<String> {
+    safe_path(user).ok()?;
+    let key = user
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+    (!key.is_empty()).then_some(key)
+}
+
+/// 409 body returned when someone else holds the lease.
+pub(crate) fn conflict(holder: LockInfo) -> Response {
+    (StatusCode::CONFLICT, Json(holder)).into_response()
+}
+
+// ===== handlers ==============================================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LockReq {
+    path: String,
+    /// ignored when authenticated
+    #[serde(default)]
+    owner: String,
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UnlockReq {
+    path: String,
+    /// ignored when authenticated
+    #[serde(default)]
+    owner: String,
+}
+
+pub(crate) async fn fs_lock(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<LockReq>) -> impl IntoResponse {
+    let Some(key) = lock_key(&body.path) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    body.owner = auth::acting(&ident, Some(body.owner)).unwrap_or_default();
+    if body.owner.is_empty() {
+        return (StatusCode::BAD_REQUEST, "missing owner").into_response();
+    }
+    let ttl = Duration::from_secs(body.ttl_secs.unwrap_or(DEFAULT_TTL_SECS).clamp(1, MAX_TTL_SECS));
+    match st.locks.acquire(&key, &body.owner, ttl) {
+        Ok(lock) => {
+            st.events.publish(ServerEvent::LockAcquired(lock.clone()));
+            Json(lock).into_response()
+        }
+        Err(holder) => conflict(holder),
+    }
+}
+
+pub(crate) async fn fs_unlock(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<UnlockReq>) -> impl IntoResponse {
+    let Some(key) = lock_key(&body.path) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    body.owner = auth::acting(&ident, Some(body.owner)).unwrap_or_default();
+    match st.locks.release(&key, &body.owner) {
+        Ok(()) => {
+            st.events.publish(ServerEvent::LockReleased { path: key, owner: body.owner });
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(Some(holder)) => conflict(holder),
+        Err(None) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub(crate) async fn fs_locks(State(st): State<AppState>) -> impl IntoResponse {
+    Json(st.locks.list())
+}
@@ -10,6 +10,9 @@
 //! tracing-subscriber = { version = "0.3", features = ["env-filter"] }
 //! (plus your existing crates: zen_engine, serde, serde_json, etc.)
 
+// handlers short-circuit with `Result<_, Response>`; boxing every early return buys nothing
+#![allow(clippy::result_large_err)]
+
 use axum::{
     body::Body,
     extract::{DefaultBodyLimit, Extension, Json, Query, State},
@@ -25,21 +28,68 @@ use std::{
     env,
     fs,
     io::Read,
-    io::Cursor,
     path::{Component, Path as StdPath, PathBuf},
-    sync::{Arc, Mutex},
+    sync::Arc,
     thread::available_parallelism,
 };
 use tokio::fs as tokio_fs;
 use tokio_util::{io::ReaderStream, task::LocalPoolHandle};
 use tower_http::{
-    compression::CompressionLayer, cors::CorsLayer, services::{ServeDir, ServeFile},
+    compression::{
+        predicate::{DefaultPredicate, NotForContentType, Predicate},
+        CompressionLayer,
+    },
+    cors::CorsLayer, services::{ServeDir, ServeFile},
     set_status::SetStatus, trace::TraceLayer,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use walkdir::WalkDir;
-use zip::ZipArchive;
-use zen_engine::{loader::{FilesystemLoader, FilesystemLoaderOptions}, DecisionEngine, EvaluationError, EvaluationOptions};
+
+mod archive;
+mod audit;
+mod auth;
+mod autosnap;
+mod branches;
+mod config;
+mod coverage;
+mod decisions;
+mod deterministic;
+mod engines;
+mod eval;
+mod events;
+mod expression;
+mod freeze;
+mod gitmirror;
+mod history;
+mod index;
+mod jdm;
+mod lint;
+mod locks;
+mod objects;
+mod plugins;
+mod profile;
+mod quota;
+mod ratelimit;
+mod recovery;
+mod remote;
+mod replace;
+mod retention;
+mod revisions;
+mod search;
+mod secrets;
+mod share;
+mod simcache;
+mod simulate;
+mod simulations;
+mod staging;
+mod stats;
+mod storelock;
+mod tables;
+mod templates;
+mod testcases;
+mod workflow;
+
+use events::ServerEvent;
 
 const IS_DEVELOPMENT: bool = cfg!(debug_assertions);
 const STORAGE_ROOT: &str = "./decisions";
@@ -48,10 +98,8 @@ const HEAD_FILE: &str = "./decisions/HEAD";
 // ===== storage bootstrap =====================================================
 
 fn ensure_storage_root() {
+    // HEAD itself is written by `recovery::repair`, which also notices one lost
     fs::create_dir_all(format!("{STORAGE_ROOT}/0")).expect("create storage root");
-    if !StdPath::new(HEAD_FILE).exists() {
-        fs::write(HEAD_FILE, b"0").expect("write HEAD");
-    }
 }
 
 // ===== helpers ===============================================================
@@ -65,13 +113,143 @@ fn safe_path(user: &str) -> Result<PathBuf, StatusCode> {
             _ => return Err(StatusCode::BAD_REQUEST),
         }
     }
+    ensure_contained(&p)?;
     Ok(p)
 }
 
-#[derive(Serialize)]
-struct SearchHit {
-    path: String,
-    matched: &'static str, // "name", "content"
+/// Lexical checks can't see symlinks: resolve the deepest existing ancestor of
+/// `p` and make sure it still lives under the storage root.
+fn ensure_contained(p: &StdPath) -> Result<(), StatusCode> {
+    let root = StdPath::new(STORAGE_ROOT)
+        .canonicalize()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut probe = p;
+    loop {
+        match probe.canonicalize() {
+            Ok(real) if real.starts_with(&root) => return Ok(()),
+            Ok(_) => return Err(StatusCode::BAD_REQUEST),
+            // not created yet — check the parent it would be created in
+            Err(_) => probe = probe.parent().ok_or(StatusCode::BAD_REQUEST)?,
+        }
+    }
+}
+
+/// `safe_path` for anything that creates, modifies or removes: never acts on or
+/// through a symlink, whatever `FOLLOW_SYMLINKS` says, and only inside the
+/// working copy (`0/…`), where the caller's folder ACLs allow. Revisions are
+/// history; the revision API is the only way to make or remove one.
+fn write_path(user: &str) -> Result<PathBuf, StatusCode> {
+    let first = StdPath::new(user).components().find(|c| !matches!(c, Component::CurDir));
+    if first != Some(Component::Normal("0".as_ref())) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let p = safe_path(user)?;
+    reject_symlinks(&p)?;
+    auth::acl::check(&p.strip_prefix(format!("{STORAGE_ROOT}/0")).unwrap_or(&p).to_string_lossy())?;
+    Ok(p)
+}
+
+/// Server state kept at the storage root beside the revisions (`.audit.log`,
+/// `.acl.json`, `.secrets.json`, `.simulations`, …): `name` in `dir` is one
+/// when `dir` is the root and `name` starts with a dot. No read serves them.
+fn is_sidecar(dir: &StdPath, name: &std::ffi::OsStr) -> bool {
+    dir == StdPath::new(STORAGE_ROOT) && name.to_string_lossy().starts_with('.')
+}
+
+/// `safe_path` for reads: symlinks are refused unless following is enabled, in
+/// which case `ensure_contained` has already checked where they lead. Sidecars
+/// (see [`is_sidecar`]) are 404.
+fn read_path(cfg: &config::Config, user: &str) -> Result<PathBuf, StatusCode> {
+    let first = StdPath::new(user).components().find(|c| !matches!(c, Component::CurDir));
+    if let Some(Component::Normal(name)) = first {
+        if is_sidecar(StdPath::new(STORAGE_ROOT), name) {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+    let p = safe_path(user)?;
+    if !cfg.follow_symlinks {
+        reject_symlinks(&p)?;
+    }
+    Ok(p)
+}
+
+fn reject_symlinks(p: &StdPath) -> Result<(), StatusCode> {
+    let rel = p.strip_prefix(STORAGE_ROOT).unwrap_or(p);
+    let mut cur = PathBuf::from(STORAGE_ROOT);
+    for comp in rel.components() {
+        cur.push(comp);
+        match fs::symlink_metadata(&cur) {
+            Ok(m) if m.file_type().is_symlink() => return Err(StatusCode::FORBIDDEN),
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Symlink policy for tree walks: plain entries always pass; symlinks only when
+/// following is enabled and their target stays inside the storage root.
+fn walk_allowed(entry: &walkdir::DirEntry, follow: bool) -> bool {
+    !entry.path_is_symlink() || (follow && ensure_contained(entry.path()).is_ok())
+}
+
+#[derive(Default, Clone, Copy)]
+struct WriteOpts {
+    /// also fsync the parent directory so the rename itself survives a power loss
+    durable: bool,
+    /// fail with `AlreadyExists` instead of replacing an existing file
+    create_new: bool,
+}
+
+/// Replaces `target` without ever exposing a half-written file: the bytes go to a
+/// temp file in the same directory, are fsynced, then renamed over the target.
+/// The rename also matters for snapshots, which may hardlink working-copy files
+/// (`SNAPSHOT_HARDLINKS`): the old inode, still part of history, is never touched.
+fn write_atomic(target: &StdPath, bytes: &[u8], opts: WriteOpts) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let parent = target.parent().unwrap_or(StdPath::new("."));
+    fs::create_dir_all(parent)?;
+
+    let mut tmp = tempfile::Builder::new().prefix(".save-").tempfile_in(parent)?;
+    tmp.write_all(bytes)?;
+    // temp files are created 0600; keep whatever mode the file had before
+    let perms = match fs::metadata(target) {
+        Ok(m) => m.permissions(),
+        Err(_) => default_file_permissions(),
+    };
+    tmp.as_file().set_permissions(perms)?;
+    tmp.as_file().sync_all()?;
+    if opts.create_new {
+        tmp.persist_noclobber(target).map_err(|e| e.error)?;
+    } else {
+        tmp.persist(target).map_err(|e| e.error)?;
+    }
+
+    if opts.durable {
+        // directories can't be opened for syncing on every platform; best effort there
+        if let Ok(dir) = fs::File::open(parent) {
+            dir.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn default_file_permissions() -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    fs::Permissions::from_mode(0o644)
+}
+
+#[cfg(not(unix))]
+fn default_file_permissions() -> fs::Permissions {
+    fs::metadata(".").map(|m| m.permissions()).expect("stat working directory")
+}
+
+/// Strong ETag for a file body (quoted hex SHA-256).
+fn etag(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("\"{:x}\"", Sha256::digest(bytes))
 }
 
 fn read_head() -> u64 {
@@ -81,18 +259,35 @@ fn read_head() -> u64 {
         .unwrap_or(0)
 }
 
+/// By rename, so a crash leaves the old HEAD or the new one, never neither.
 fn write_head(n: u64) {
-    fs::write(HEAD_FILE, n.to_string()).expect("write HEAD");
+    write_atomic(StdPath::new(HEAD_FILE), n.to_string().as_bytes(), WriteOpts { durable: true, ..Default::default() })
+        .expect("write HEAD");
+}
+
+fn bump_rev(lock: &storelock::StorageLock) -> u64 {
+    let _g = lock.lock();
+    next_rev()
 }
 
-fn bump_rev(lock: &Arc<Mutex<()>>) -> u64 {
-    let _g = lock.lock().unwrap();
+/// `bump_rev` for callers already holding the rev lock.
+fn next_rev() -> u64 {
     let n = read_head() + 1;
     fs::create_dir_all(format!("{STORAGE_ROOT}/{n}")).expect("new rev dir");
     write_head(n);
     n
 }
 
+/// Like `bump_rev`, but the new revision is the already filled directory
+/// `dir`, renamed into place: it appears complete or not at all.
+fn adopt_rev(lock: &storelock::StorageLock, dir: &StdPath) -> std::io::Result<u64> {
+    let _g = lock.lock();
+    let n = read_head() + 1;
+    fs::rename(dir, format!("{STORAGE_ROOT}/{n}"))?;
+    write_head(n);
+    Ok(n)
+}
+
 // ===== file-tree DTO =========================================================
 
 #[derive(Serialize)]
@@ -107,284 +302,877 @@ struct Node {
     children: Option<Vec<Node>>,
 }
 
-fn build_tree(root_fs: &StdPath, rel: &str) -> Vec<Node> {
+fn build_tree(root_fs: &StdPath, rel: &str, follow: bool) -> Vec<Node> {
+    build_tree_to(root_fs, rel, follow, None)
+}
+
+/// [`build_tree`], descending at most `depth` more levels: directories at the
+/// limit come without `children`.
+fn build_tree_to(root_fs: &StdPath, rel: &str, follow: bool, depth: Option<usize>) -> Vec<Node> {
     let mut out = Vec::new();
     if let Ok(entries) = fs::read_dir(root_fs) {
-        for e in entries.flatten() {
-            let meta = match e.metadata() {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-            let is_dir = meta.is_dir();
+        for e in entries.flatten().filter(|e| !is_sidecar(root_fs, &e.file_name())) {
             let name = e.file_name().to_string_lossy().into_owned();
             let node_path = if rel.is_empty() { name.clone() } else { format!("{rel}/{name}") };
-            let children = if is_dir { Some(build_tree(&e.path(), &node_path)) } else { None };
-            out.push(Node {
-                name,
-                path: node_path,
-                is_directory: is_dir,
-                modified: meta
-                    .modified()
-                    .ok()
-                    .and_then(|t| t.duration_since(std::time::SystemTime::UNIX_EPOCH).ok())
-                    .map(|d| d.as_secs() as i64)
-                    .unwrap_or_default(),
-                size: (!is_dir).then(|| meta.len()),
-                children,
-            });
+            if let Some(node) = build_node_to(&e.path(), &node_path, follow, depth) {
+                out.push(node);
+            }
         }
         out.sort_by_key(|n| (!n.is_directory, n.name.to_lowercase()));
     }
     out
 }
 
+/// Single node (with its subtree, for directories) for `full`, exposed as `rel`.
+/// Symlinks are hidden unless `follow` is set and they resolve inside the root.
+fn build_node(full: &StdPath, rel: &str, follow: bool) -> Option<Node> {
+    build_node_to(full, rel, follow, None)
+}
+
+fn build_node_to(full: &StdPath, rel: &str, follow: bool, depth: Option<usize>) -> Option<Node> {
+    let mut meta = fs::symlink_metadata(full).ok()?;
+    if meta.file_type().is_symlink() {
+        if !follow || ensure_contained(full).is_err() {
+            return None;
+        }
+        meta = fs::metadata(full).ok()?;
+    }
+    let is_dir = meta.is_dir();
+    let name = full.file_name()?.to_string_lossy().into_owned();
+    let children = (is_dir && depth != Some(0)).then(|| build_tree_to(full, rel, follow, depth.map(|d| d - 1)));
+    Some(Node {
+        name,
+        path: rel.to_string(),
+        is_directory: is_dir,
+        modified: meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+        size: (!is_dir).then_some(meta.len()),
+        children,
+    })
+}
+
 // ===== request/response models ==============================================
 
 #[derive(Deserialize)]
 struct PathContent {
     path: String,
     content: String,
+    /// lock owner making the save; required to write a path someone has locked
+    #[serde(default)]
+    owner: Option<String>,
+    /// also fsync the parent directory after the rename
+    #[serde(default)]
+    durable: bool,
+    #[serde(default)]
+    mode: SaveMode,
+    /// refuse content that isn't a valid decision (422 with the problems)
+    #[serde(default)]
+    validate: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SaveMode {
+    /// only write a new file; 409 if it exists
+    Create,
+    /// only replace an existing file; 404 if it's missing
+    Overwrite,
+    #[default]
+    Upsert,
 }
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Rename {
     from: String,
     to: String,
+    /// replace an existing destination (or conflicting files when merging)
+    #[serde(default)]
+    force: bool,
+    /// folder onto existing folder: move the contents in instead of refusing
+    #[serde(default)]
+    merge_directories: bool,
+    /// lock owner; required to move or replace a path someone has locked
+    #[serde(default)]
+    owner: Option<String>,
 }
 #[derive(Deserialize)]
 struct Mkdir {
     path: String,
+    /// lock owner; required to delete a path someone has locked
+    #[serde(default)]
+    owner: Option<String>,
 }
+/// Deprecated JSON form of `POST /api/revisions`.
 #[derive(Deserialize)]
 struct RevCreateReq {
     zip_b64: String,
+    #[serde(flatten)]
+    meta: revisions::RevMeta,
 }
-#[derive(Serialize)]
-struct NewRevResp {
-    id: u64,
+#[derive(Deserialize)]
+struct RevListParams {
+    /// page size, default 50, at most 500
+    limit: Option<usize>,
+    /// only revisions older than this one; pass the previous page's `nextBefore`
+    before: Option<u64>,
 }
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct RevListResp {
     latest: u64,
+    /// every revision that exists, ascending
     list: Vec<u64>,
+    /// one page of `list`, newest first
+    revisions: Vec<RevEntry>,
+    /// cursor for the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_before: Option<u64>,
+    /// tag → revision
+    tags: std::collections::BTreeMap<String, u64>,
+}
+#[derive(Serialize)]
+struct RevEntry {
+    #[serde(flatten)]
+    manifest: revisions::Manifest,
+    tags: Vec<String>,
 }
 
 // ===== global state ==========================================================
 
 #[derive(Clone)]
 struct AppState {
-    rev_lock: Arc<Mutex<()>>,
+    rev_lock: Arc<storelock::StorageLock>,
+    events: events::EventBus,
+    locks: locks::LockTable,
+    config: Arc<config::Config>,
+    index: index::SearchIndex,
+    gc: retention::Gc,
+    autosnap: autosnap::AutoSnap,
+    startup: Arc<recovery::StartupReport>,
+    engines: engines::EngineCache,
+    plugins: Arc<plugins::Registry>,
+    sim_cache: simcache::SimCache,
+    auth: Arc<auth::Auth>,
+    secrets: Arc<secrets::Secrets>,
+    share_key: Arc<share::ShareKey>,
 }
 
 // ===== /api/fs/* ============================================================
 
-async fn fs_search(Query(p): Query<std::collections::HashMap<String, String>>) -> impl IntoResponse {
-    let needle = match p.get("q") {
-        Some(q) if !q.is_empty() => q.to_lowercase(),
-        _ => return (StatusCode::BAD_REQUEST, "missing ?q=").into_response(),
-    };
+async fn fs_list(
+    State(st): State<AppState>,
+    Query(p): Query<std::collections::HashMap<String, String>>,
+) -> impl IntoResponse {
     let sub = p.get("path").cloned().unwrap_or_default();
+    match read_path(&st.config, &sub) {
+        Ok(root) if root.exists() => Json(build_tree(&root, &sub, st.config.follow_symlinks)).into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
 
-    let root = match safe_path(&sub) {
-        Ok(p) if p.exists() => p,
+const READ_LIMIT: u64 = 8 * 1024 * 1024;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    Base64,
+}
+
+#[derive(Serialize)]
+struct FileContent {
+    encoding: Encoding,
+    content: String,
+    /// full size on disk, even when `content` is truncated
+    size: u64,
+    truncated: bool,
+}
+
+/// Whether the first 8 KiB hold a NUL byte, a reliable enough sign of a
+/// binary file. Files without one may still not be text; [`fs_read`] checks
+/// them for UTF-8 itself.
+fn looks_binary(head: &[u8]) -> bool {
+    head[..head.len().min(8192)].contains(&0)
+}
+
+/// Reads a file as text when it's UTF-8 and as base64 otherwise, returning at
+/// most `?maxBytes=` (default and cap 8 MiB). The ETag is only sent for
+/// complete reads, since it must describe the whole file.
+async fn fs_read(
+    State(st): State<AppState>,
+    Query(p): Query<std::collections::HashMap<String, String>>,
+) -> impl IntoResponse {
+    let path = p.get("path").cloned().unwrap_or_default();
+    let limit = p
+        .get("maxBytes")
+        .and_then(|v| v.parse::<u64>().ok())
+        .map_or(READ_LIMIT, |v| v.min(READ_LIMIT));
+    let full = match read_path(&st.config, &path) {
+        Ok(full) if full.is_file() => full,
         _ => return StatusCode::NOT_FOUND.into_response(),
     };
 
-    // Do the heavy IO in a blocking thread and return the Vec<SearchHit>
-    let hits: Vec<SearchHit> = match tokio::task::spawn_blocking(move || {
-        let mut out = Vec::<SearchHit>::new();
+    let res = tokio::task::spawn_blocking(move || -> std::io::Result<(FileContent, Option<String>)> {
+        let f = fs::File::open(&full)?;
+        let size = f.metadata()?.len();
+        let mut bytes = Vec::with_capacity(size.min(limit) as usize);
+        f.take(limit).read_to_end(&mut bytes)?;
+        let truncated = size > bytes.len() as u64;
+        let tag = (!truncated).then(|| etag(&bytes));
 
-        for entry in WalkDir::new(&root).into_iter().flatten() {
-            if !entry.file_type().is_file() {
-                continue;
-            }
-            let rel = entry
-                .path()
-                .strip_prefix(STORAGE_ROOT)
-                .unwrap()
-                .to_string_lossy()
-                .replace('\\', "/");
-
-            // --- name match --------------------------------------------------
-            if rel.to_lowercase().contains(&needle) {
-                out.push(SearchHit { path: rel, matched: "name" });
-                continue;
+        let text = if looks_binary(&bytes) {
+            Err(bytes)
+        } else {
+            match String::from_utf8(bytes) {
+                Ok(s) => Ok(s),
+                // a truncated read may split the last character; that alone isn't binary
+                Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+                    let valid = e.utf8_error().valid_up_to();
+                    let mut b = e.into_bytes();
+                    b.truncate(valid);
+                    Ok(String::from_utf8(b).expect("validated prefix"))
+                }
+                Err(e) => Err(e.into_bytes()),
             }
+        };
+        let body = match text {
+            Ok(content) => FileContent { encoding: Encoding::Utf8, content, size, truncated },
+            Err(bytes) => FileContent {
+                encoding: Encoding::Base64,
+                content: base64::engine::general_purpose::STANDARD.encode(&bytes),
+                size,
+                truncated,
+            },
+        };
+        Ok((body, tag))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((body, Some(tag)))) => ([(header::ETAG, tag)], Json(body)).into_response(),
+        Ok(Ok((body, None))) => Json(body).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("read error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
 
-            // --- content match ----------------------------------------------
-            if let Ok(mut f) = std::fs::File::open(entry.path()) {
-                let mut buf = String::new();
-                if f.metadata().map(|m| m.len()).unwrap_or(0) <= 1_000_000 {
-                    if f.read_to_string(&mut buf).is_ok()
-                        && buf.to_lowercase().contains(&needle)
-                    {
-                        out.push(SearchHit { path: rel, matched: "content" });
+async fn fs_save(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<PathContent>) -> impl IntoResponse {
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check(&body.path, body.owner.as_deref()) {
+        return resp;
+    }
+    match write_path(&body.path) {
+        Ok(full) => {
+            let (index, engines, path) = (st.index.clone(), st.engines.clone(), body.path.clone());
+            let res = tokio::task::spawn_blocking(move || -> Result<audit::Event, Response> {
+                // not while a snapshot or a restore has the working copy
+                let _g = st.rev_lock.lock();
+                match (body.mode, full.exists()) {
+                    (SaveMode::Create, true) => return Err(StatusCode::CONFLICT.into_response()),
+                    (SaveMode::Overwrite, false) => return Err(StatusCode::NOT_FOUND.into_response()),
+                    _ => {}
+                }
+                if full.is_dir() {
+                    return Err(StatusCode::CONFLICT.into_response());
+                }
+                if body.validate || lint::required(&st.config, &body.path) {
+                    lint::gate(body.content.as_bytes())?;
+                }
+                lint::rules::gate(&full, body.content.as_bytes())?;
+                quota::check_write(&st.config, &full, body.content.len() as u64)?;
+                history::record(&st.config, &full, &body.path);
+                let old = audit::hash_file(&full);
+                let opts = WriteOpts { durable: body.durable, create_new: body.mode == SaveMode::Create };
+                write_atomic(&full, body.content.as_bytes(), opts).map_err(|e| match e.kind() {
+                    // lost a race with another create
+                    std::io::ErrorKind::AlreadyExists => StatusCode::CONFLICT.into_response(),
+                    _ => {
+                        tracing::error!("save error: {e}");
+                        StatusCode::INTERNAL_SERVER_ERROR.into_response()
                     }
+                })?;
+                Ok(audit::Event::new("save").path(&body.path).hashes(old, Some(audit::hash(body.content.as_bytes()))))
+            })
+            .await;
+            match res {
+                Ok(Ok(event)) => {
+                    index.touch(&path);
+                    engines.invalidate(&path);
+                    audit::record(event).await;
+                    StatusCode::CREATED.into_response()
                 }
+                Ok(Err(resp)) => resp,
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
             }
         }
-
-        out // returned from the closure
-    })
-    .await
-    {
-        Ok(h) => h,
-        Err(e) => {
-            tracing::error!("search task panicked: {e}");
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-        }
-    };
-
-    Json(hits).into_response()
+        Err(e) => e.into_response(),
+    }
 }
+async fn fs_write(st: State<AppState>, ident: Option<Extension<auth::Identity>>, Json(body): Json<PathContent>) -> impl IntoResponse { fs_save(st, ident, Json(body)).await }
 
-async fn fs_list(Query(p): Query<std::collections::HashMap<String, String>>) -> impl IntoResponse {
-    let sub = p.get("path").cloned().unwrap_or_default();
-    match safe_path(&sub) {
-        Ok(root) if root.exists() => Json(build_tree(&root, &sub)).into_response(),
-        _ => StatusCode::NOT_FOUND.into_response(),
-    }
+#[derive(Deserialize)]
+struct PatchReq {
+    path: String,
+    /// RFC 6902 operation array, or an RFC 7386 merge-patch object
+    patch: Value,
+    #[serde(default)]
+    owner: Option<String>,
 }
 
-async fn fs_read(Query(p): Query<std::collections::HashMap<String, String>>) -> impl IntoResponse {
-    let path = p.get("path").cloned().unwrap_or_default();
-    match safe_path(&path) {
-        Ok(full) if full.is_file() => match tokio_fs::read_to_string(full).await {
-            Ok(c) => Json(c).into_response(),
-            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-        },
-        _ => StatusCode::NOT_FOUND.into_response(),
+/// Applies a JSON (merge) patch to a stored document. An `If-Match` header makes
+/// the write conditional on the ETag last returned by `fs_read`/`fs_patch`.
+async fn fs_patch(
+    State(st): State<AppState>,
+    ident: Option<Extension<auth::Identity>>,
+    headers: HeaderMap,
+    Json(mut body): Json<PatchReq>,
+) -> impl IntoResponse {
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check(&body.path, body.owner.as_deref()) {
+        return resp;
     }
-}
+    let full = match write_path(&body.path) {
+        Ok(p) if p.is_file() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
 
-async fn fs_save(Json(body): Json<PathContent>) -> impl IntoResponse {
-    match safe_path(&body.path) {
-        Ok(full) => {
-            if let Some(parent) = full.parent() {
-                if let Err(e) = tokio_fs::create_dir_all(parent).await {
-                    tracing::error!("mkdir error: {e}");
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+    let (index, engines, path) = (st.index.clone(), st.engines.clone(), body.path.clone());
+    // hold the rev lock so concurrent patches can't interleave read→write
+    let res = tokio::task::spawn_blocking(move || -> Result<(String, Value, audit::Event), Response> {
+        let _g = st.rev_lock.lock();
+        let current = fs::read(&full).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        let tag = etag(&current);
+        if let Some(expected) = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok()) {
+            if expected.trim() != "*" && expected.trim() != tag {
+                return Err((StatusCode::PRECONDITION_FAILED, [(header::ETAG, tag)]).into_response());
             }
-            match tokio_fs::write(full, body.content).await {
-                Ok(_) => StatusCode::CREATED.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        let mut doc: Value = serde_json::from_slice(&current)
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("stored file is not JSON: {e}")).into_response())?;
+        match body.patch {
+            Value::Array(_) => {
+                let ops: json_patch::Patch = serde_json::from_value(body.patch)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid patch: {e}")).into_response())?;
+                json_patch::patch(&mut doc, &ops)
+                    .map_err(|e| (StatusCode::CONFLICT, format!("patch failed: {e}")).into_response())?;
             }
+            merge => json_patch::merge(&mut doc, &merge),
+        }
+
+        let out = serde_json::to_string_pretty(&doc).expect("serialize patched document");
+        if lint::required(&st.config, &body.path) {
+            lint::gate(out.as_bytes())?;
         }
-        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+        lint::rules::gate(&full, out.as_bytes())?;
+        quota::check_write(&st.config, &full, out.len() as u64)?;
+        history::record(&st.config, &full, &body.path);
+        write_atomic(&full, out.as_bytes(), WriteOpts::default()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        let event = audit::Event::new("patch").path(&body.path).hashes(Some(audit::hash(&current)), Some(audit::hash(out.as_bytes())));
+        Ok((etag(out.as_bytes()), doc, event))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((tag, doc, event))) => {
+            index.touch(&path);
+            engines.invalidate(&path);
+            audit::record(event).await;
+            ([(header::ETAG, tag)], Json(doc)).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
-async fn fs_write(Json(body): Json<PathContent>) -> impl IntoResponse { fs_save(Json(body)).await }
 
-async fn fs_rename(Json(body): Json<Rename>) -> impl IntoResponse {
-    match (safe_path(&body.from), safe_path(&body.to)) {
-        (Ok(src), Ok(dst)) => {
-            if let Some(parent) = dst.parent() {
-                if let Err(e) = tokio_fs::create_dir_all(parent).await {
-                    tracing::error!("mkdir error: {e}");
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+async fn fs_rename(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<Rename>) -> impl IntoResponse {
+    body.owner = auth::acting(&ident, body.owner);
+    let (src, dst) = match (write_path(&body.from), write_path(&body.to)) {
+        (Ok(src), Ok(dst)) => (src, dst),
+        (Err(e), _) | (_, Err(e)) => return e.into_response(),
+    };
+    for p in [&body.from, &body.to] {
+        if let Err(resp) = st.locks.check_tree(p, body.owner.as_deref()) {
+            return resp;
+        }
+    }
+    if fs::symlink_metadata(&src).is_err() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if dst.starts_with(&src) && dst != src {
+        return (StatusCode::BAD_REQUEST, "cannot move a folder into itself").into_response();
+    }
+    let (from, to) = (body.from.clone(), body.to.clone());
+
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        let _g = state.rev_lock.lock();
+        let io_err = |e: std::io::Error| {
+            tracing::error!("rename error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        };
+        // a case-only rename on a case-insensitive fs "exists" as itself
+        let same = matches!((src.canonicalize(), dst.canonicalize()), (Ok(a), Ok(b)) if a == b);
+
+        if dst.exists() && !same {
+            if src.is_dir() && dst.is_dir() && body.merge_directories {
+                let conflicts = merge_conflicts(&src, &dst, &body.to);
+                if !conflicts.is_empty() && !body.force {
+                    return Err((StatusCode::CONFLICT, Json(conflicts)).into_response());
                 }
+                return merge_dirs(&src, &dst).map_err(io_err);
             }
-            match tokio_fs::rename(src, dst).await {
-                Ok(_) => StatusCode::NO_CONTENT.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            if !body.force {
+                return Err(StatusCode::CONFLICT.into_response());
             }
+            remove_any(&dst).map_err(io_err)?;
+        }
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).map_err(io_err)?;
+        }
+        fs::rename(&src, &dst).map_err(io_err)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(())) => {
+            st.index.touch(&from);
+            st.index.touch(&to);
+            st.engines.invalidate(&from);
+            st.engines.invalidate(&to);
+            audit::record(audit::Event::new("rename").path(&from).to(&to)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn remove_any(p: &StdPath) -> std::io::Result<()> {
+    if fs::symlink_metadata(p)?.is_dir() {
+        fs::remove_dir_all(p)
+    } else {
+        fs::remove_file(p)
+    }
+}
+
+/// Destination paths (as `rel_dst/...`) that a merge of `src` into `dst` would replace.
+fn merge_conflicts(src: &StdPath, dst: &StdPath, rel_dst: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for entry in fs::read_dir(src).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        let target = dst.join(&name);
+        let rel = format!("{}/{}", rel_dst.trim_end_matches('/'), name.to_string_lossy());
+        if entry.path().is_dir() && target.is_dir() {
+            out.extend(merge_conflicts(&entry.path(), &target, &rel));
+        } else if fs::symlink_metadata(&target).is_ok() {
+            out.push(rel);
+        }
+    }
+    out
+}
+
+/// Moves the contents of `src` into `dst` (replacing conflicts) and removes `src`.
+fn merge_dirs(src: &StdPath, dst: &StdPath) -> std::io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.path().is_dir() && target.is_dir() {
+            merge_dirs(&entry.path(), &target)?;
+            continue;
+        }
+        if fs::symlink_metadata(&target).is_ok() {
+            remove_any(&target)?;
         }
-        _ => StatusCode::BAD_REQUEST.into_response(),
+        fs::rename(entry.path(), target)?;
     }
+    fs::remove_dir(src)
 }
 
-async fn fs_delete(Json(body): Json<Mkdir>) -> impl IntoResponse {
-    match safe_path(&body.path) {
+async fn fs_delete(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<Mkdir>) -> impl IntoResponse {
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check_tree(&body.path, body.owner.as_deref()) {
+        return resp;
+    }
+    match write_path(&body.path) {
         Ok(target) => {
             if tokio_fs::metadata(&target).await.is_err() {
                 return StatusCode::NOT_FOUND.into_response();
             }
-            let result = if target.is_dir() {
-                tokio_fs::remove_dir_all(target).await
-            } else {
-                tokio_fs::remove_file(target).await
-            };
+            let old = audit::hash_file(&target);
+            let state = st.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let _g = state.rev_lock.lock();
+                if target.is_dir() {
+                    fs::remove_dir_all(target)
+                } else {
+                    fs::remove_file(target)
+                }
+            })
+            .await;
             match result {
-                Ok(_) => StatusCode::NO_CONTENT.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                Ok(Ok(())) => {
+                    st.index.touch(&body.path);
+                    st.engines.invalidate(&body.path);
+                    audit::record(audit::Event::new("delete").path(&body.path).hashes(old, None)).await;
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
             }
         }
-        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+        Err(e) => e.into_response(),
     }
 }
 
-async fn fs_mkdir(Json(body): Json<Mkdir>) -> impl IntoResponse {
-    match safe_path(&body.path) {
-        Ok(target) => match tokio_fs::create_dir_all(target).await {
-            Ok(_) => StatusCode::CREATED.into_response(),
-            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+async fn fs_mkdir(State(st): State<AppState>, Json(body): Json<Mkdir>) -> impl IntoResponse {
+    match write_path(&body.path) {
+        Ok(target) => match tokio::task::spawn_blocking(move || {
+            let _g = st.rev_lock.lock();
+            fs::create_dir_all(target)
+        })
+        .await
+        {
+            Ok(Ok(())) => {
+                audit::record(audit::Event::new("mkdir").path(&body.path)).await;
+                StatusCode::CREATED.into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
         },
-        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+        Err(e) => e.into_response(),
     }
 }
 
-async fn fs_snapshot(State(st): State<AppState>) -> impl IntoResponse {
-    let new_rev = bump_rev(&st.rev_lock);
-    let src = format!("{STORAGE_ROOT}/{}", new_rev - 1);
-    let dst = format!("{STORAGE_ROOT}/{new_rev}");
-    if let Err(e) = copy_dir_all(&src, &dst) {
-        tracing::error!("snapshot copy error: {e}");
-        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+async fn fs_duplicate(State(st): State<AppState>, Json(body): Json<Mkdir>) -> impl IntoResponse {
+    let src = match write_path(&body.path) {
+        Ok(p) if p.exists() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let follow = st.config.follow_symlinks;
+    let rel = body.path.trim_end_matches('/').to_string();
+
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<Option<Node>, Response> {
+        let _g = state.rev_lock.lock();
+        let (bytes, files) = quota::subtree_size(&src, follow);
+        quota::check(&state.config, bytes as i64, files as i64)?;
+        let Some(dst) = copy_name(&src) else { return Ok(None) };
+        let copied = if src.is_dir() {
+            copy_dir_all(&src.to_string_lossy(), &dst.to_string_lossy(), follow)
+        } else {
+            fs::copy(&src, &dst).map(|_| ())
+        };
+        copied.map_err(|e| {
+            tracing::error!("duplicate error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+        let name = dst.file_name().unwrap_or_default().to_string_lossy();
+        let dst_rel = match rel.rsplit_once('/') {
+            Some((parent, _)) => format!("{parent}/{name}"),
+            None => name.into_owned(),
+        };
+        Ok(build_node(&dst, &dst_rel, follow))
+    })
+    .await;
+
+    match res {
+        Ok(Ok(Some(node))) => {
+            st.index.touch(&node.path);
+            audit::record(audit::Event::new("duplicate").path(&body.path).to(&node.path)).await;
+            (StatusCode::CREATED, Json(node)).into_response()
+        }
+        Ok(Ok(None)) => StatusCode::BAD_REQUEST.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// First free sibling name in the `name copy`, `name copy 2`, … sequence,
+/// keeping a file's extension at the end (`a.json` → `a copy.json`).
+fn copy_name(src: &StdPath) -> Option<PathBuf> {
+    let parent = src.parent()?;
+    let file_name = src.file_name()?.to_string_lossy().into_owned();
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if src.is_file() && !stem.is_empty() => (stem.to_string(), format!(".{ext}")),
+        _ => (file_name, String::new()),
+    };
+    (1..)
+        .map(|n| match n {
+            1 => parent.join(format!("{stem} copy{ext}")),
+            n => parent.join(format!("{stem} copy {n}{ext}")),
+        })
+        .find(|p| !p.exists())
+}
+
+/// Moves a freshly filled revision into the object store and records its
+/// manifest; a revision without either is still a revision, so failing here
+/// only costs the dedup and the metadata.
+fn finish_revision(cfg: &config::Config, rev: u64, meta: revisions::RevMeta) -> revisions::Manifest {
+    if let Err(e) = objects::store(rev) {
+        tracing::error!("could not move rev {rev} into the object store: {e}");
+    }
+    let placed = branches::advance(meta.branch.as_deref(), rev)
+        .map_err(|e| tracing::error!("could not move a branch to rev {rev}: {e}"))
+        .ok();
+    let manifest = revisions::write_manifest(rev, meta, placed).unwrap_or_else(|e| {
+        tracing::error!("manifest write error for rev {rev}: {e}");
+        revisions::read_manifest(rev)
+    });
+    if cfg.read_only_revisions {
+        if let Err(e) = revisions::set_read_only(rev, true) {
+            tracing::warn!("could not make rev {rev} read-only: {e}");
+        }
+    }
+    gitmirror::update_mirror(cfg);
+    manifest
+}
+
+/// The body (`{message, author}`) is optional.
+async fn fs_snapshot(
+    State(st): State<AppState>,
+    ident: Option<Extension<auth::Identity>>,
+    body: Option<Json<revisions::RevMeta>>,
+) -> impl IntoResponse {
+    let mut meta = body.map(|Json(b)| b).unwrap_or_default();
+    meta.author = auth::acting(&ident, meta.author);
+    if let Err(resp) = meta.branch.as_deref().map_or(Ok(()), branches::check) {
+        return resp;
+    }
+    // of the working copy, not HEAD: revisions can't be edited, so a copy of
+    // HEAD would only repeat it
+    let state = st.clone();
+    match tokio::task::spawn_blocking(move || revisions::snapshot_working_copy(&state.config, &state.rev_lock, meta))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+    {
+        Ok(manifest) => {
+            st.index.touch(&manifest.id.to_string());
+            st.events.publish(ServerEvent::SnapshotCreated { id: manifest.id });
+            audit::record(audit::Event::new("snapshot").rev(manifest.id).by(manifest.author.clone())).await;
+            Json(manifest).into_response()
+        }
+        Err(e) => {
+            tracing::error!("snapshot copy error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
     }
-    Json(NewRevResp { id: new_rev }).into_response()
 }
 
 // ===== /api/revisions/* ======================================================
 
-async fn rev_list() -> impl IntoResponse {
+const REV_PAGE: usize = 50;
+const MAX_REV_PAGE: usize = 500;
+
+async fn rev_list(Query(q): Query<RevListParams>) -> impl IntoResponse {
     let latest = read_head();
-    Json(RevListResp { latest, list: (0..=latest).collect() })
-}
-
-async fn rev_create(State(st): State<AppState>, Json(body): Json<RevCreateReq>) -> impl IntoResponse {
-    let new_rev = bump_rev(&st.rev_lock);
-    let dest = format!("{STORAGE_ROOT}/{new_rev}");
-    if let Err(e) = tokio_fs::create_dir_all(&dest).await {
-        tracing::error!("mkdir error {e}");
-        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-    }
-
-    // decode & unzip on blocking thread
-    let res = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
-        let bytes = base64::engine::general_purpose::STANDARD.decode(body.zip_b64.as_bytes())?;
-        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
-        for i in 0..archive.len() {
-            let mut f = archive.by_index(i)?;
-            let out_path = StdPath::new(&dest).join(f.name());
-            if f.is_dir() {
-                fs::create_dir_all(&out_path)?;
-            } else {
-                if let Some(p) = out_path.parent() {
-                    fs::create_dir_all(p)?;
+    let list = revisions::existing();
+    let tags = revisions::tags();
+    let limit = q.limit.unwrap_or(REV_PAGE).clamp(1, MAX_REV_PAGE);
+    let mut page: Vec<u64> = list.iter().rev().copied().filter(|&r| q.before.is_none_or(|b| r < b)).take(limit + 1).collect();
+    let next_before = (page.len() > limit).then(|| {
+        page.pop();
+        *page.last().expect("limit is at least 1")
+    });
+    let named = tags.clone();
+    let revisions = tokio::task::spawn_blocking(move || {
+        page.into_iter()
+            .map(|rev| {
+                let mut manifest = revisions::read_manifest(rev);
+                if manifest.size.is_none() || manifest.file_count.is_none() {
+                    let (bytes, files) = quota::subtree_size(StdPath::new(&format!("{STORAGE_ROOT}/{rev}")), false);
+                    manifest.size = Some(bytes);
+                    manifest.file_count = Some(files);
                 }
-                let mut w = fs::File::create(out_path)?;
-                std::io::copy(&mut f, &mut w)?;
+                let tags = named.iter().filter(|(_, &r)| r == rev).map(|(name, _)| name.clone()).collect();
+                RevEntry { manifest, tags }
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default();
+    Json(RevListResp { latest, list, revisions, next_before, tags })
+}
+
+/// Cap on an uploaded revision archive (compressed), whichever way it arrives.
+const MAX_REV_UPLOAD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Copies an upload stream into a temp file, refusing more than
+/// `MAX_REV_UPLOAD_BYTES`.
+async fn spool<S, E>(mut stream: S) -> Result<tempfile::NamedTempFile, Response>
+where
+    S: futures_util::Stream<Item = Result<axum::body::Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let internal = |e: std::io::Error| {
+        tracing::error!("upload spool error: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+    let tmp = tempfile::NamedTempFile::new().map_err(internal)?;
+    let mut out = tokio_fs::File::from_std(tmp.reopen().map_err(internal)?);
+    let mut total = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+        total += chunk.len() as u64;
+        if total > MAX_REV_UPLOAD_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+        }
+        out.write_all(&chunk).await.map_err(internal)?;
+    }
+    out.flush().await.map_err(internal)?;
+    Ok(tmp)
+}
+
+/// The zip and what to record about it, from whichever body format was sent.
+/// The third value is set for the deprecated base64 form.
+async fn rev_upload(
+    st: &AppState,
+    mut meta: revisions::RevMeta,
+    req: axum::extract::Request,
+) -> Result<(tempfile::NamedTempFile, revisions::RevMeta, bool), Response> {
+    use axum::extract::{FromRequest, Multipart};
+
+    let ctype = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let ctype = ctype.to_ascii_lowercase();
+    if ctype.starts_with("multipart/form-data") {
+        let mut form = Multipart::from_request(req, st).await.map_err(IntoResponse::into_response)?;
+        let mut upload = None;
+        while let Some(field) = form.next_field().await.map_err(IntoResponse::into_response)? {
+            match field.name() {
+                Some("file") => upload = Some(spool(field).await?),
+                Some("message") => meta.message = Some(field.text().await.map_err(IntoResponse::into_response)?),
+                Some("author") => meta.author = Some(field.text().await.map_err(IntoResponse::into_response)?),
+                Some("branch") => meta.branch = Some(field.text().await.map_err(IntoResponse::into_response)?),
+                _ => {}
             }
         }
-        Ok(())
-    })
-    .await;
+        let upload = upload.ok_or_else(|| (StatusCode::BAD_REQUEST, "missing file field").into_response())?;
+        Ok((upload, meta, false))
+    } else if ctype.starts_with("application/json") {
+        let Json(body) = Json::<RevCreateReq>::from_request(req, st).await.map_err(IntoResponse::into_response)?;
+        let upload = tokio::task::spawn_blocking(move || -> Result<_, Response> {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(body.zip_b64.as_bytes())
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+            let mut tmp = tempfile::NamedTempFile::new().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+            std::io::Write::write_all(&mut tmp, &bytes).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+            Ok(tmp)
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())??;
+        let meta = revisions::RevMeta {
+            message: body.meta.message.or(meta.message),
+            author: body.meta.author.or(meta.author),
+            branch: body.meta.branch.or(meta.branch),
+            ..Default::default()
+        };
+        Ok((upload, meta, true))
+    } else {
+        Ok((spool(req.into_body().into_data_stream()).await?, meta, false))
+    }
+}
+
+/// Unpacks an uploaded zip into `dest`. A root `.manifest.json` (as written by
+/// the revision archive download) isn't extracted; its message and author
+/// fill in whatever the request didn't say.
+fn unpack_revision(upload: fs::File, dest: &StdPath, meta: &mut revisions::RevMeta) -> Result<(), archive::ExtractError> {
+    archive::extract_zip(upload, dest, &archive::UPLOAD_LIMITS)?;
+    // an archive downloaded from /api/revisions/:id/archive carries its manifest
+    let sidecar = dest.join(revisions::ARCHIVE_MANIFEST);
+    if let Ok(raw) = fs::read(&sidecar) {
+        if let Ok(m) = serde_json::from_slice::<revisions::Manifest>(&raw) {
+            meta.message = meta.message.take().or(m.message);
+            meta.author = meta.author.take().or(m.author);
+        }
+        fs::remove_file(&sidecar)?;
+    }
+    Ok(())
+}
+
+/// Makes the next revision out of the zip in `upload`. Blocking.
+fn revision_from_zip(state: &AppState, upload: &tempfile::NamedTempFile, mut meta: revisions::RevMeta) -> Result<revisions::Manifest, Response> {
+    // quota-check before a revision number is taken
+    let reopen = || upload.reopen().map_err(archive::ExtractError::Io);
+    let (size, files) = archive::inspect(reopen()?, &archive::UPLOAD_LIMITS)?;
+    quota::check(&state.config, size as i64, files as i64)?;
+
+    let new_rev = bump_rev(&state.rev_lock);
+    let dest = PathBuf::from(format!("{STORAGE_ROOT}/{new_rev}"));
+    if let Err(e) = unpack_revision(reopen()?, &dest, &mut meta) {
+        // the archive lied in its central directory; don't leave half of it
+        tracing::warn!("unpacking revision {new_rev} failed: {e:?}");
+        let _ = fs::remove_dir_all(&dest);
+        return Err(e.into_response());
+    }
+    Ok(finish_revision(&state.config, new_rev, meta))
+}
+
+/// `POST /api/revisions` creates a revision from a zip sent as
+/// `multipart/form-data` (`file`, optional `message`/`author` fields), as a raw
+/// `application/zip` body (`?message=&author=`), or, deprecated, base64-encoded
+/// in JSON (`{zip_b64, message, author}`). Uploads are spooled to disk, never
+/// held in memory whole.
+async fn rev_create(
+    State(st): State<AppState>,
+    ident: Option<Extension<auth::Identity>>,
+    Query(query): Query<revisions::RevMeta>,
+    req: axum::extract::Request,
+) -> Response {
+    let (upload, mut meta, deprecated) = match rev_upload(&st, query, req).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    meta.author = auth::acting(&ident, meta.author);
+    if let Err(resp) = meta.branch.as_deref().map_or(Ok(()), branches::check) {
+        return resp;
+    }
+    if deprecated {
+        tracing::warn!("POST /api/revisions with zip_b64 is deprecated; send multipart/form-data or application/zip");
+    }
+
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || revision_from_zip(&state, &upload, meta)).await;
 
     match res {
-        Ok(Ok(())) => Json(NewRevResp { id: new_rev }).into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Ok(manifest)) => {
+            st.index.touch(&manifest.id.to_string());
+            st.events.publish(ServerEvent::SnapshotCreated { id: manifest.id });
+            audit::record(audit::Event::new("revision").rev(manifest.id).by(manifest.author.clone())).await;
+            let mut resp = Json(manifest).into_response();
+            if deprecated {
+                resp.headers_mut().insert("deprecation", header::HeaderValue::from_static("true"));
+            }
+            resp
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
 
 #[derive(Deserialize)]
 struct RevFileParams {
-    rev: u64,
+    rev: revisions::RevRef,
     path: String,
 }
 
-async fn rev_file(Query(q): Query<RevFileParams>) -> impl IntoResponse {
-    match safe_path(&format!("{}/{}", q.rev, q.path)) {
-        Ok(full) if full.is_file() => match tokio_fs::File::open(full).await {
+async fn rev_file(State(st): State<AppState>, Query(q): Query<RevFileParams>) -> impl IntoResponse {
+    let rev = match q.rev.resolve() {
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    match read_path(&st.config, &format!("{rev}/{}", q.path)) {
+        Ok(full) if full.is_file() => match tokio_fs::File::open(objects::locate(rev, &q.path).unwrap_or(full)).await {
             Ok(file) => {
                 let stream  = ReaderStream::new(file);
                 let body    = Body::from_stream(stream);
@@ -405,9 +1193,39 @@ async fn rev_file(Query(q): Query<RevFileParams>) -> impl IntoResponse {
 
 // ===== helper: recursive copy ===============================================
 
-fn copy_dir_all(src: &str, dst: &str) -> std::io::Result<()> {
-    for entry in WalkDir::new(src) {
+/// Copies the tree at `src` to `dst`. Symlinks are skipped unless `follow` is set,
+/// in which case those resolving inside the storage root are copied as what they
+/// point to.
+fn copy_dir_all(src: &str, dst: &str, follow: bool) -> std::io::Result<()> {
+    copy_tree(src, dst, follow, |from, to| fs::copy(from, to).map(drop))
+}
+
+/// [`copy_dir_all`] for snapshots: every file is a copy-on-write clone of the
+/// original where the filesystem supports it, and a byte copy where it doesn't.
+/// With `hardlink`, files are hardlinked first instead, which is faster still
+/// and works everywhere, but is only safe if nothing edits working-copy files
+/// in place: the API never does (see [`write_atomic`]), editors and scripts
+/// working on the directory directly often do.
+fn link_dir_all(src: &str, dst: &str, follow: bool, hardlink: bool) -> std::io::Result<()> {
+    copy_tree(src, dst, follow, |from, to| {
+        if (hardlink && fs::hard_link(from, to).is_ok()) || reflink(from, to).is_ok() {
+            return Ok(());
+        }
+        fs::copy(from, to).map(drop)
+    })
+}
+
+fn copy_tree(
+    src: &str,
+    dst: &str,
+    follow: bool,
+    place: impl Fn(&StdPath, &StdPath) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    for entry in WalkDir::new(src).follow_links(follow) {
         let entry = entry?;
+        if !walk_allowed(&entry, follow) {
+            continue;
+        }
         let rel = entry.path().strip_prefix(src).unwrap();
         let dest_path = StdPath::new(dst).join(rel);
         if entry.file_type().is_dir() {
@@ -416,59 +1234,29 @@ fn copy_dir_all(src: &str, dst: &str) -> std::io::Result<()> {
             if let Some(p) = dest_path.parent() {
                 fs::create_dir_all(p)?;
             }
-            fs::copy(entry.path(), dest_path)?;
+            // linking a followed symlink would link the symlink itself
+            let from = if entry.path_is_symlink() { fs::canonicalize(entry.path())? } else { entry.into_path() };
+            place(&from, &dest_path)?;
         }
     }
     Ok(())
 }
 
-// ===== simulate (unchanged) ==================================================
-
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct SimulateRequest {
-    root_dir: String,
-    filepath: String,
-    context: Value,
-}
-
-async fn simulate(
-    Extension(local_pool): Extension<LocalPoolHandle>,
-    Json(req): Json<SimulateRequest>,
-) -> Result<Json<Value>, SimulateError> {
-    // 1. Filesystem loader --------------------------------------------------
-    let loader = FilesystemLoader::new(FilesystemLoaderOptions {
-        root: req.root_dir.clone(),
-        keep_in_memory: true,
-    });
-    let engine = DecisionEngine::default().with_loader(loader.into());
-
-    // 2. Evaluation options --------------------------------------------------
-    let opts = EvaluationOptions { trace: Some(true), max_depth: Some(50) };
-
-    let result = local_pool
-        .spawn_pinned(move || async move {
-            engine
-                .evaluate_with_opts(&req.filepath, req.context.into(), opts)
-                .await
-                // -------- flatten here: serialization can’t really fail -------
-                .map(|v| serde_json::to_value(v).expect("serialize DecisionGraphResponse"))
-        })
-        .await
-        .expect("thread join failed")?;   // ← only **one** `?` now
-
-    Ok(Json(result))
-}
-
-struct SimulateError(Box<EvaluationError>);
-
-impl IntoResponse for SimulateError {
-    fn into_response(self) -> Response {
-        (StatusCode::BAD_REQUEST, serde_json::to_string(&self.0).unwrap_or_default()).into_response()
+#[cfg(target_os = "linux")]
+fn reflink(from: &StdPath, to: &StdPath) -> std::io::Result<()> {
+    let src = fs::File::open(from)?;
+    let dst = fs::File::create_new(to)?;
+    if let Err(e) = rustix::fs::ioctl_ficlone(&dst, &src) {
+        drop(dst);
+        let _ = fs::remove_file(to);
+        return Err(e.into());
     }
+    dst.set_permissions(src.metadata()?.permissions())
 }
-impl From<Box<EvaluationError>> for SimulateError {
-    fn from(value: Box<EvaluationError>) -> Self { Self(value) }
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_: &StdPath, _: &StdPath) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
 }
 
 // ===== misc small endpoints ==================================================
@@ -486,6 +1274,13 @@ fn serve_dir_service() -> ServeDir<SetStatus<ServeFile>> {
 
 #[tokio::main]
 async fn main() {
+    // `editor hash-password`: a USERS_FILE hash for the password on stdin
+    if env::args().nth(1).as_deref() == Some("hash-password") {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password).expect("read password");
+        println!("{}", auth::users::hash_password(password.trim_end_matches(['\r', '\n'])));
+        return;
+    }
     ensure_storage_root();
 
     let local_pool = LocalPoolHandle::new(available_parallelism().map(Into::into).unwrap_or(1));
@@ -498,34 +1293,180 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let host = IS_DEVELOPMENT.then_some("127.0.0.1").unwrap_or("0.0.0.0");
+    let host = if IS_DEVELOPMENT { "127.0.0.1" } else { "0.0.0.0" };
     let addr = format!("{host}:3000");
 
-    let app_state = AppState { rev_lock: Arc::new(Mutex::new(())) };
+    let bus = events::EventBus::new();
+    // keep the watcher alive for the lifetime of the server
+    let _watcher = match events::spawn_watcher(bus.clone()) {
+        Ok(w) => Some(w),
+        Err(e) => {
+            tracing::warn!("fs change notifications disabled: {e}");
+            None
+        }
+    };
+
+    let config = Arc::new(config::Config::from_env());
+    let rev_lock = Arc::new(storelock::StorageLock::new("rev"));
+    let startup = Arc::new(recovery::repair(&rev_lock));
+    let secrets = Arc::new(secrets::Secrets::from_config(&config).unwrap_or_else(|e| panic!("{e}")));
+    let plugins = Arc::new(plugins::registry(&config, secrets.clone()));
+    let auth = Arc::new(auth::Auth::load(&config).unwrap_or_else(|e| panic!("{e}")));
+    let app_state = AppState {
+        rev_lock,
+        events: bus,
+        locks: locks::LockTable::default(),
+        index: index::SearchIndex::open(config.follow_symlinks),
+        gc: retention::Gc::default(),
+        autosnap: autosnap::AutoSnap::default(),
+        startup,
+        engines: engines::EngineCache::new(plugins.clone(), config.follow_symlinks),
+        plugins,
+        sim_cache: simcache::SimCache::new(config.simulate_cache_entries),
+        auth,
+        secrets,
+        share_key: Arc::new(share::ShareKey::from_config(&config)),
+        config,
+    };
+    retention::spawn(app_state.clone());
+    autosnap::spawn(app_state.clone());
+    // revisions from before the object store; readers cope until this is done
+    // the git mirror catches up after it, so it can read from the store
+    let cfg = app_state.config.clone();
+    tokio::task::spawn_blocking(move || {
+        objects::migrate();
+        gitmirror::update_mirror(&cfg);
+    });
 
     let app = Router::new()
         // original routes
         .route("/api/health", get(health))
-        .route("/api/simulate", post(simulate).layer(DefaultBodyLimit::max(16 * 1024 * 1024)))
+        .route("/api/aliases",       get(eval::list))
+        .route("/api/aliases/:name", get(eval::get_one).put(eval::put).delete(eval::delete))
+        .route("/api/aliases/:name/metrics", get(eval::metrics))
+        .route("/api/aliases/:name/shadow", get(eval::shadow_report).delete(eval::clear_shadow_report))
+        .route("/api/eval/:alias",   post(eval::eval).layer(DefaultBodyLimit::max(1024 * 1024)))
+        .route("/api/eval/:alias/bulk", post(eval::bulk))
+        .route("/api/simulate", post(simulate::simulate).layer(DefaultBodyLimit::max(16 * 1024 * 1024)))
+        .route("/api/simulate/batch", post(simulate::batch).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+        .route("/api/simulate/cache", get(simcache::stats).delete(simcache::clear))
+        .route("/api/simulate/compare", post(simulate::compare).layer(DefaultBodyLimit::max(16 * 1024 * 1024)))
+        .route("/api/simulate/csv", post(simulate::csv).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+        .route("/api/simulate/stream", post(simulate::stream).layer(DefaultBodyLimit::max(16 * 1024 * 1024)))
+        .route("/api/simulations",   get(simulations::list))
+        .route("/api/simulations/:id", get(simulations::get_one))
+        .route("/api/simulations/:id/replay", post(simulations::replay))
         // file service
-        .route("/api/fs/search",    get(fs_search))
+        .route("/api/fs/search",    get(search::fs_search))
+        .route("/api/fs/search/stream", get(search::fs_search_stream))
+        .route("/api/search/jdm",   post(search::jdm_search))
+        .route("/api/validate",     post(lint::validate))
+        .route("/api/lint",         post(lint::rules::lint))
+        .route("/api/expression/eval", post(expression::eval))
+        .route("/api/custom-nodes", get(plugins::list))
+        .route("/api/decisions/graph", get(decisions::graph))
+        .route("/api/decisions/export", get(decisions::export::export))
+        .route("/api/analyze/table", post(decisions::analyze::table))
+        .route("/api/decisions/schema", get(decisions::schema::infer))
+        .route("/api/decisions/metrics", get(decisions::metrics::metrics))
+        .route("/api/searches",     get(search::saved::list).post(search::saved::create))
+        .route("/api/searches/:id", get(search::saved::get_one).put(search::saved::update).delete(search::saved::delete))
+        .route("/api/searches/:id/run", get(search::saved::run).post(search::saved::run))
+        .route("/api/tests",        get(testcases::list).post(testcases::create))
+        .route("/api/tests/run",    post(testcases::run))
+        .route("/api/tests/coverage", get(coverage::report))
+        .route("/api/tests/:id",    get(testcases::get_one).put(testcases::update).delete(testcases::delete))
+        .route("/api/fs/index",     get(index::status))
+        .route("/api/fs/index/rebuild", post(index::rebuild))
         .route("/api/fs/list",      get(fs_list))
         .route("/api/fs/read",      get(fs_read))
         .route("/api/fs/save",      post(fs_save))
         .route("/api/fs/write",     post(fs_write))
+        .route("/api/fs/patch",     post(fs_patch))
+        .route("/api/fs/history",   get(history::list))
+        .route("/api/fs/history/version", get(history::version))
+        .route("/api/fs/history/restore", post(history::restore))
         .route("/api/fs/rename",    post(fs_rename))
         .route("/api/fs/delete",    post(fs_delete))
         .route("/api/fs/mkdir",     post(fs_mkdir))
+        .route("/api/fs/duplicate", post(fs_duplicate))
+        .route("/api/fs/replace",   post(replace::fs_replace))
+        .route("/api/fs/new-from-template", post(templates::new_from_template))
+        .route("/api/fs/archive",   get(archive::fs_archive))
+        .route("/api/fs/usage",     get(quota::fs_usage))
+        .route("/api/fs/du",        get(quota::fs_du))
+        .route("/api/fs/extract",   post(archive::fs_extract).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
         .route("/api/fs/snapshot",  post(fs_snapshot))
+        .route("/api/fs/events",    get(events::fs_events))
+        .route("/api/fs/lock",      post(locks::fs_lock))
+        .route("/api/fs/unlock",    post(locks::fs_unlock))
+        .route("/api/fs/locks",     get(locks::fs_locks))
+        // server events
+        .route("/api/ws",           get(events::ws))
         // revisions
-        .route("/api/revisions",          get(rev_list).post(rev_create))
+        .route("/api/import/table",       post(tables::import_table).layer(DefaultBodyLimit::max(16 * 1024 * 1024)))
+        .route("/api/export/table",       get(tables::export_table))
+        .route("/api/templates",          get(templates::list).post(templates::save))
+        .route("/api/templates/:id",      axum::routing::delete(templates::delete))
+        .route("/api/revisions",          get(rev_list).post(rev_create).layer(DefaultBodyLimit::max(MAX_REV_UPLOAD_BYTES as usize)))
         .route("/api/revisions/file",     get(rev_file))
+        .route("/api/revisions/from-url", post(remote::from_url))
+        .route("/api/revisions/prepare",  post(staging::prepare))
+        .route("/api/revisions/staging/:id", get(staging::get_one))
+        .route("/api/revisions/staging/:id/file", axum::routing::put(staging::put_file).delete(staging::delete_file).layer(DefaultBodyLimit::max(MAX_REV_UPLOAD_BYTES as usize)))
+        .route("/api/revisions/staging/:id/commit", post(staging::commit))
+        .route("/api/revisions/staging/:id/abort", post(staging::abort))
+        .route("/api/revisions/diff",     get(revisions::diff))
+        .route("/api/revisions/diff/file", get(revisions::diff_file))
+        .route("/api/revisions/restore",  post(revisions::restore))
+        .route("/api/revisions/restore-file", post(revisions::restore_file))
+        .route("/api/revisions/file-history", get(revisions::file_history))
+        .route("/api/revisions/stats",    get(stats::revision_stats))
+        .route("/api/revisions/prune",    post(revisions::prune))
+        .route("/api/revisions/:id",      axum::routing::delete(revisions::delete))
+        .route("/api/revisions/:id/tag",  post(revisions::tag))
+        .route("/api/revisions/:id/archive", get(revisions::archive))
+        .route("/api/revisions/:id/tree", get(revisions::tree))
+        .route("/api/revisions/:id/verify", post(revisions::verify))
+        .route("/api/revisions/:id/state", post(workflow::transition))
+        .route("/api/revisions/:id/approve", post(workflow::approve))
+        .route("/api/revisions/tags",     get(revisions::list_tags))
+        .route("/api/branches",           get(branches::list).post(branches::create))
+        .route("/api/branches/:name",     axum::routing::delete(branches::delete))
+        .route("/api/branches/:name/checkout", post(branches::checkout))
+        .route("/api/revisions/tags/:name", axum::routing::delete(revisions::untag))
+        .route("/api/admin/gc",           get(retention::status))
+        .route("/api/admin/gc/run",       post(retention::run_now))
+        .route("/api/admin/auto-snapshot", get(autosnap::status))
+        .route("/api/admin/git-mirror",   post(gitmirror::sync_now))
+        .route("/api/admin/startup",      get(recovery::startup_report))
+        .route("/api/admin/read-only",    axum::routing::put(freeze::freeze).delete(freeze::thaw))
+        .route("/api/read-only",          get(freeze::status))
+        .route("/api/auth/login",         post(auth::session::login))
+        .route("/api/auth/logout",        post(auth::session::logout))
+        .route("/api/auth/me",            get(auth::session::me))
+        .route("/api/secrets",            get(secrets::list).post(secrets::set))
+        .route("/api/secrets/:name",      axum::routing::delete(secrets::delete))
+        .route("/api/share",              post(share::create))
+        .route("/api/shared/:token",      get(share::open))
+        .route("/api/shared/:token/*path", get(share::open_inside))
+        .route("/api/audit",              get(audit::list))
+        .route("/api/audit/verify",       get(audit::verify))
+        .layer(axum::middleware::from_fn_with_state(app_state.clone(), freeze::guard))
+        .layer(axum::middleware::from_fn(auth::roles::require_role))
+        .layer(axum::middleware::from_fn_with_state(app_state.clone(), ratelimit::limit))
+        .layer(axum::middleware::from_fn_with_state(app_state.clone(), auth::require_auth))
         .with_state(app_state)
         .layer(Extension(local_pool))
         .nest_service("/", serve_dir_service());
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    let compression = CompressionLayer::new().gzip(true).br(true);
+    // encoders buffer until they have a full block, which would hold back
+    // streamed search hits
+    let compression = CompressionLayer::new()
+        .gzip(true)
+        .br(true)
+        .compress_when(DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson")));
 
     tracing::info!("🚀 listening on http://{}", listener.local_addr().unwrap());
 
@@ -534,5 +1475,5 @@ async fn main() {
         stacked = stacked.layer(CorsLayer::permissive());
     }
 
-    axum::serve(listener, stacked).await.unwrap();
+    axum::serve(listener, stacked.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap();
 }
@@ -0,0 +1,175 @@
+//! Content-addressed blob store for revisions.
+//!
+//! Every file of a committed revision is stored once under
+//! `./decisions/.objects/<sha256>`, however many revisions contain it, and the
+//! revision records which blob each of its paths is in
+//! (`./decisions/.revisions/<rev>/files.json`). The numbered directory stays
+//! where it was, so search, the index and archives read it as before, but its
+//! files are hardlinks to the blobs rather than copies: an unchanged file
+//! costs one directory entry per snapshot instead of its size.
+//!
+//! Where hardlinks aren't available the revision keeps its own copy and the
+//! path map still records the hash, so diffs stay cheap. The map doubles as
+//! the revision's checksum list for `/api/revisions/:id/verify`.
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path as StdPath, PathBuf},
+};
+use walkdir::WalkDir;
+
+use crate::{quota, revisions, storelock::StorageLock, write_atomic, WriteOpts, STORAGE_ROOT};
+
+pub(crate) const OBJECTS_DIR: &str = "./decisions/.objects";
+
+/// Serializes interning against sweeping, so a blob is never collected
+/// between being linked into a new revision and that revision's map landing,
+/// whichever instance is doing either.
+static STORE: StorageLock = StorageLock::new("objects");
+
+/// path inside the revision → hex SHA-256
+pub(crate) type FileMap = BTreeMap<String, String>;
+
+pub(crate) fn hash_file(path: &StdPath) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub(crate) fn object_path(hash: &str) -> PathBuf {
+    PathBuf::from(OBJECTS_DIR).join(hash)
+}
+
+fn map_path(rev: u64) -> PathBuf {
+    PathBuf::from(revisions::META_DIR).join(rev.to_string()).join("files.json")
+}
+
+/// The path map of `rev`; `None` for the working copy and for revisions not
+/// (yet) in the store.
+pub(crate) fn file_map(rev: u64) -> Option<FileMap> {
+    let raw = fs::read(map_path(rev)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+#[cfg(unix)]
+pub(crate) fn same_inode(a: &StdPath, b: &StdPath) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn same_inode(_: &StdPath, _: &StdPath) -> bool {
+    false
+}
+
+/// Makes `file` share storage with its blob, adding the blob if it's new.
+fn intern(file: &StdPath) -> io::Result<String> {
+    let hash = hash_file(file)?;
+    let obj = object_path(&hash);
+    if !obj.exists() {
+        // new content: the revision's file becomes the blob
+        match fs::hard_link(file, &obj) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => tracing::debug!("not storing {} as a blob: {e}", file.display()),
+        }
+    } else if !same_inode(file, &obj) {
+        // link beside it and rename over, so the path never goes missing
+        let tmp = file.with_file_name(format!(".link-{hash}"));
+        match fs::hard_link(&obj, &tmp) {
+            Ok(()) => fs::rename(&tmp, file)?,
+            Err(e) => tracing::debug!("keeping a copy of {}: {e}", file.display()),
+        }
+    }
+    Ok(hash)
+}
+
+/// Moves the just-filled revision `rev` into the store and records its path
+/// map. Blocking.
+pub(crate) fn store(rev: u64) -> io::Result<FileMap> {
+    let _g = STORE.lock();
+    fs::create_dir_all(OBJECTS_DIR)?;
+    let root = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+    let mut map = FileMap::new();
+    for entry in WalkDir::new(&root) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+        map.insert(rel, intern(entry.path())?);
+    }
+    let bytes = serde_json::to_vec_pretty(&map).expect("serialize file map");
+    write_atomic(&map_path(rev), &bytes, WriteOpts::default())?;
+    Ok(map)
+}
+
+/// The file to read for `rel` in `rev`: its blob if the revision is in the
+/// store and the blob exists, else `None` (read the revision directory).
+pub(crate) fn locate(rev: u64, rel: &str) -> Option<PathBuf> {
+    let hash = file_map(rev)?.remove(rel.trim_matches('/'))?;
+    let obj = object_path(&hash);
+    obj.is_file().then_some(obj)
+}
+
+/// Hashes referenced by every revision except those in `skip`.
+fn referenced(skip: &[u64]) -> BTreeSet<String> {
+    revisions::existing()
+        .into_iter()
+        .filter(|r| *r != 0 && !skip.contains(r))
+        .filter_map(file_map)
+        .flat_map(BTreeMap::into_values)
+        .collect()
+}
+
+/// Bytes freed by deleting `revs`: the blobs no other revision uses, plus the
+/// whole directory of any revision that isn't in the store.
+pub(crate) fn reclaimable(revs: &[u64]) -> u64 {
+    let keep = referenced(revs);
+    let mut freed = BTreeSet::new();
+    let mut bytes = 0;
+    for &rev in revs {
+        match file_map(rev) {
+            Some(map) => freed.extend(map.into_values().filter(|h| !keep.contains(h))),
+            None => bytes += quota::subtree_size(StdPath::new(&format!("{STORAGE_ROOT}/{rev}")), false).0,
+        }
+    }
+    bytes + freed.iter().filter_map(|h| fs::metadata(object_path(h)).ok()).map(|m| m.len()).sum::<u64>()
+}
+
+/// Deletes blobs no remaining revision references. Blocking.
+pub(crate) fn sweep() -> io::Result<usize> {
+    let _g = STORE.lock();
+    let keep = referenced(&[]);
+    let mut removed = 0;
+    for entry in fs::read_dir(OBJECTS_DIR).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        if name.to_str().is_some_and(|h| !keep.contains(h)) {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Puts every revision made before the store existed into it. Blocking; run
+/// once at startup.
+pub(crate) fn migrate() {
+    let pending: Vec<u64> = revisions::existing().into_iter().filter(|&r| r != 0 && !map_path(r).exists()).collect();
+    if pending.is_empty() {
+        return;
+    }
+    let mut done = 0;
+    for rev in pending {
+        match store(rev) {
+            Ok(_) => done += 1,
+            Err(e) => tracing::warn!("could not move revision {rev} into the object store: {e}"),
+        }
+    }
+    tracing::info!("moved {done} revision(s) into the object store");
+}
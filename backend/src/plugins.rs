@@ -0,0 +1,125 @@
+//! Handlers for organization-specific custom nodes.
+//!
+//! A JDM `customNode` carries a `kind` and a free-form `config`; the engine
+//! hands such nodes to its custom node adapter. Here that adapter is a
+//! [`Registry`] of [`CustomNode`] handlers keyed by kind, filled once at
+//! startup by [`registry`] and shared by every engine the
+//! [`EngineCache`](crate::engines::EngineCache) builds. A node whose kind
+//! nothing handles fails its evaluation with a message saying so.
+//!
+//! Strings in the `config` fields a kind lists in
+//! [`CustomNode::secret_fields`] may name secrets as `{{secrets.NAME}}`;
+//! handlers get the config with their values in, and what they output or
+//! fail with has the values blanked out (see [`secrets`](crate::secrets)).
+//!
+//! To add a kind, implement [`CustomNode`] in a module under `plugins/`, gate
+//! it behind a cargo feature if it pulls in anything heavy or talks to the
+//! network, and register it in [`registry`]. `GET /api/custom-nodes` lists
+//! what this build has, for the editor's node palette.
+
+use axum::{
+    extract::{Json, State},
+    response::{IntoResponse, Response},
+};
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc};
+use zen_engine::handler::{
+    custom_node_adapter::{CustomNodeAdapter, CustomNodeRequest},
+    node::{NodeResponse, NodeResult},
+};
+
+use crate::{
+    config::Config,
+    secrets::{self, Secrets},
+    AppState,
+};
+
+#[cfg(feature = "http-node")]
+mod http;
+mod lookup;
+
+/// One evaluation of a custom node.
+pub(crate) struct Call<'a> {
+    pub node_id: &'a str,
+    pub name: &'a str,
+    /// the node's `content.config`, as authored
+    pub config: &'a Value,
+    /// what the previous nodes produced
+    pub input: Value,
+}
+
+/// What the editor shows for a kind.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct KindInfo {
+    pub kind: &'static str,
+    pub description: &'static str,
+    /// JSON Schema of `config`
+    pub config_schema: Value,
+}
+
+pub(crate) trait CustomNode: Send + Sync {
+    fn info(&self) -> KindInfo;
+
+    /// Top-level `config` fields that may name secrets. None by default: a
+    /// kind that can put its config in its output must never list any.
+    fn secret_fields(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The node's output. Runs on the pinned pool, so the future needn't be
+    /// `Send`; it's dropped if the simulation times out.
+    fn handle<'a>(&'a self, call: Call<'a>) -> LocalBoxFuture<'a, anyhow::Result<Value>>;
+}
+
+/// kind → handler
+pub(crate) struct Registry(BTreeMap<&'static str, Arc<dyn CustomNode>>, Arc<Secrets>);
+
+impl Registry {
+    fn register(&mut self, node: impl CustomNode + 'static) {
+        let kind = node.info().kind;
+        if self.0.insert(kind, Arc::new(node)).is_some() {
+            tracing::warn!("custom node kind {kind} registered twice; the last one wins");
+        }
+    }
+
+    pub(crate) fn kinds(&self) -> Vec<KindInfo> {
+        self.0.values().map(|n| n.info()).collect()
+    }
+}
+
+impl CustomNodeAdapter for Registry {
+    async fn handle(&self, request: CustomNodeRequest) -> NodeResult {
+        let node = &request.node;
+        let Some(handler) = self.0.get(node.kind.as_str()) else {
+            anyhow::bail!("no handler for custom node kind {:?} in this server", node.kind);
+        };
+        let (config, used) = self.1.resolve(&node.config, handler.secret_fields()).map_err(|e| anyhow::anyhow!("{}: {e}", node.name))?;
+        let call = Call { node_id: &node.id, name: &node.name, config: &config, input: request.input.to_value() };
+        let output = handler.handle(call).await.map_err(|e| anyhow::anyhow!(secrets::redact(&format!("{e:#}"), &used)))?;
+        // the output is traced and returned; a service echoing a credential
+        // mustn't hand it to whoever ran the simulation
+        Ok(NodeResponse { output: secrets::redact_value(&output, &used).into(), trace_data: None })
+    }
+}
+
+/// Every handler this build has.
+#[cfg_attr(not(feature = "http-node"), allow(unused_variables))]
+pub(crate) fn registry(cfg: &Config, secrets: Arc<Secrets>) -> Registry {
+    let mut reg = Registry(BTreeMap::new(), secrets);
+    reg.register(lookup::LookupNode);
+    #[cfg(feature = "http-node")]
+    reg.register(http::HttpNode::new(cfg));
+    for k in reg.0.keys() {
+        tracing::info!("custom node kind {k} available");
+    }
+    reg
+}
+
+// ===== GET /api/custom-nodes ==================================================
+
+pub(crate) async fn list(State(st): State<AppState>) -> Response {
+    Json(st.plugins.kinds()).into_response()
+}
@@ -0,0 +1,90 @@
+//! `http` (feature `http-node`): posts the node's input as JSON to an internal
+//! service and takes the JSON it answers with as the node's output. Only hosts
+//! in `CUSTOM_NODE_HTTP_HOSTS` can be called, the same way
+//! `IMPORT_ALLOWED_HOSTS` limits imports; with none listed every call fails.
+//! `headers` are sent along, typically credentials from a secret
+//! (`"Authorization": "Bearer {{secrets.PRICING_TOKEN}}"`); `url` and
+//! `headers` are the only fields that may name secrets.
+
+use futures_util::future::LocalBoxFuture;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use super::{Call, CustomNode, KindInfo};
+use crate::{config::Config, remote::host_allowed};
+
+const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+const MAX_RESPONSE_BYTES: usize = 4 * 1024 * 1024;
+
+pub(crate) struct HttpNode {
+    allowed: Vec<String>,
+    client: reqwest::Client,
+}
+
+impl HttpNode {
+    pub(crate) fn new(cfg: &Config) -> Self {
+        let client = reqwest::Client::builder()
+            // a redirect could leave the allowed hosts
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("build http client");
+        Self { allowed: cfg.custom_node_http_hosts.clone(), client }
+    }
+
+    async fn run(&self, call: Call<'_>) -> anyhow::Result<Value> {
+        let raw = call.config["url"].as_str().unwrap_or_default();
+        let url = reqwest::Url::parse(raw).map_err(|e| anyhow::anyhow!("{}: invalid url {raw:?}: {e}", call.name))?;
+        let host = url.host_str().unwrap_or_default();
+        if !matches!(url.scheme(), "http" | "https") || !host_allowed(&self.allowed, host) {
+            anyhow::bail!("{}: {host} is not in CUSTOM_NODE_HTTP_HOSTS", call.name);
+        }
+        let timeout = Duration::from_millis(call.config["timeoutMs"].as_u64().unwrap_or(DEFAULT_TIMEOUT_MS));
+        let mut req = self.client.post(url).timeout(timeout).header(CONTENT_TYPE, "application/json");
+        for (name, value) in call.config["headers"].as_object().into_iter().flatten() {
+            let Some(value) = value.as_str() else {
+                anyhow::bail!("{}: header {name} must be a string", call.name);
+            };
+            req = req.header(name.as_str(), value);
+        }
+        let resp = req
+            .body(serde_json::to_vec(&call.input)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        if resp.content_length().is_some_and(|n| n > MAX_RESPONSE_BYTES as u64) {
+            anyhow::bail!("{}: response larger than {MAX_RESPONSE_BYTES} bytes", call.name);
+        }
+        let body = resp.bytes().await?;
+        if body.len() > MAX_RESPONSE_BYTES {
+            anyhow::bail!("{}: response larger than {MAX_RESPONSE_BYTES} bytes", call.name);
+        }
+        serde_json::from_slice(&body).map_err(|e| anyhow::anyhow!("{} ({}): response is not JSON: {e}", call.name, call.node_id))
+    }
+}
+
+impl CustomNode for HttpNode {
+    fn info(&self) -> KindInfo {
+        KindInfo {
+            kind: "http",
+            description: "POSTs the input as JSON to `url` and outputs the JSON response",
+            config_schema: json!({
+                "type": "object",
+                "required": ["url"],
+                "properties": {
+                    "url": { "type": "string", "format": "uri" },
+                    "timeoutMs": { "type": "integer", "default": DEFAULT_TIMEOUT_MS },
+                    "headers": { "type": "object", "additionalProperties": { "type": "string" } },
+                },
+            }),
+        }
+    }
+
+    fn secret_fields(&self) -> &'static [&'static str] {
+        &["url", "headers"]
+    }
+
+    fn handle<'a>(&'a self, call: Call<'a>) -> LocalBoxFuture<'a, anyhow::Result<Value>> {
+        Box::pin(self.run(call))
+    }
+}
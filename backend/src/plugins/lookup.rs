@@ -0,0 +1,59 @@
+//! `lookup`: picks a value out of a table kept in the node, by a field of the
+//! input. For mappings that are data rather than rules (country → VAT rate),
+//! without a decision table of one-cell rows.
+
+use futures_util::future::{self, LocalBoxFuture};
+use serde_json::{json, Value};
+
+use super::{Call, CustomNode, KindInfo};
+
+pub(crate) struct LookupNode;
+
+impl LookupNode {
+    fn run(call: &Call<'_>) -> anyhow::Result<Value> {
+        let field = call.config["field"].as_str().filter(|f| !f.is_empty());
+        let Some(field) = field else {
+            anyhow::bail!("{}: config.field is required", call.name);
+        };
+        let into = call.config["into"].as_str().filter(|f| !f.is_empty()).unwrap_or("value");
+        let key = field.split('.').try_fold(&call.input, |v, part| v.get(part));
+        let key = match key {
+            Some(Value::String(s)) => s.clone(),
+            Some(v @ (Value::Number(_) | Value::Bool(_))) => v.to_string(),
+            _ => String::new(),
+        };
+        let found = call.config["entries"].get(&key).or_else(|| call.config.get("default"));
+        let Some(found) = found else {
+            anyhow::bail!("{} ({}): no entry for {field} = {key:?} and no default", call.name, call.node_id);
+        };
+        let mut out = match &call.input {
+            Value::Object(m) => m.clone(),
+            _ => Default::default(),
+        };
+        out.insert(into.to_string(), found.clone());
+        Ok(Value::Object(out))
+    }
+}
+
+impl CustomNode for LookupNode {
+    fn info(&self) -> KindInfo {
+        KindInfo {
+            kind: "lookup",
+            description: "Sets `into` to the entry keyed by the input's `field`, or to `default`",
+            config_schema: json!({
+                "type": "object",
+                "required": ["field", "entries"],
+                "properties": {
+                    "field": { "type": "string", "description": "dotted path into the input" },
+                    "entries": { "type": "object" },
+                    "default": {},
+                    "into": { "type": "string", "default": "value" },
+                },
+            }),
+        }
+    }
+
+    fn handle<'a>(&'a self, call: Call<'a>) -> LocalBoxFuture<'a, anyhow::Result<Value>> {
+        Box::pin(future::ready(Self::run(&call)))
+    }
+}
@@ -0,0 +1,92 @@
+//! `profile: true` on `/api/simulate`: where an evaluation's time went.
+//!
+//! Built from the engine's trace, which times every node of the top-level
+//! graph; a decision node's time includes the whole decision it calls. Node
+//! times are the engine's own, rounded the way it reports them (`1.2ms`), so
+//! they're good for finding the slow node, not for benchmarking. A node in
+//! loop mode (`executionMode: "loop"`) counts one call per item it ran on;
+//! others ran once.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::jdm;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeProfile {
+    id: String,
+    name: String,
+    kind: String,
+    duration_ms: f64,
+    calls: usize,
+    /// of `engineMs`, in percent
+    share: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Profile {
+    /// what the engine took for the whole graph
+    engine_ms: f64,
+    /// from the request being read to the response being built: resolving
+    /// the revision, queueing for a worker, the engine, serializing
+    handler_ms: f64,
+    /// slowest first
+    nodes: Vec<NodeProfile>,
+}
+
+/// `1.2ms`, `345.1µs`, `438.0ns`, `1.5s` in milliseconds.
+fn millis(perf: &str) -> Option<f64> {
+    let perf = perf.trim();
+    let split = perf.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (num, unit) = perf.split_at(split);
+    let n: f64 = num.parse().ok()?;
+    let scale = match unit {
+        "s" => 1_000.0,
+        "ms" => 1.0,
+        "µs" | "us" => 0.001,
+        "ns" => 0.000_001,
+        _ => return None,
+    };
+    Some(n * scale)
+}
+
+/// To the microsecond.
+fn round(ms: f64) -> f64 {
+    (ms * 1000.0).round() / 1000.0
+}
+
+/// The profile of `response` (the engine's, with trace), for the graph
+/// `doc` it evaluated and a handler that has run for `handler_ms`.
+pub(crate) fn build(response: &Value, doc: Option<&Value>, handler_ms: f64) -> Profile {
+    let engine_ms = response["performance"].as_str().and_then(millis).unwrap_or_default();
+    let by_id: HashMap<&str, &Value> =
+        doc.map(|d| jdm::nodes(d).filter_map(|n| Some((n["id"].as_str()?, n))).collect()).unwrap_or_default();
+    let mut nodes: Vec<NodeProfile> = response["trace"]
+        .as_object()
+        .into_iter()
+        .flat_map(|t| t.values())
+        .map(|t| {
+            let id = t["id"].as_str().unwrap_or_default();
+            let node = by_id.get(id).copied();
+            let looped = node.is_some_and(|n| n["content"]["executionMode"] == "loop");
+            let calls = match &t["output"] {
+                Value::Array(items) if looped => items.len(),
+                _ => 1,
+            };
+            let duration_ms = t["performance"].as_str().and_then(millis).unwrap_or_default();
+            NodeProfile {
+                id: id.to_string(),
+                name: t["name"].as_str().unwrap_or_default().to_string(),
+                kind: node.map(jdm::node_type).unwrap_or_default().to_string(),
+                duration_ms: round(duration_ms),
+                calls,
+                share: if engine_ms > 0.0 { (duration_ms / engine_ms * 1000.0).round() / 10.0 } else { 0.0 },
+            }
+        })
+        .collect();
+    nodes.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+    Profile { engine_ms: round(engine_ms), handler_ms: round(handler_ms), nodes }
+}
@@ -0,0 +1,161 @@
+//! Disk quota for the storage root (`QUOTA_MAX_BYTES` / `QUOTA_MAX_FILES`), the
+//! `/api/fs/usage` report and per-subtree sizes (`/api/fs/du`).
+//!
+//! Usage is measured by walking the tree on demand rather than tracked
+//! incrementally, so out-of-band edits are always accounted for.
+
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path as StdPath;
+use walkdir::WalkDir;
+
+use crate::{config::Config, objects::OBJECTS_DIR, read_path, walk_allowed, AppState, STORAGE_ROOT};
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Usage {
+    pub bytes: u64,
+    pub files: u64,
+    pub max_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+}
+
+/// Walks the storage root. Blocking.
+pub(crate) fn usage(cfg: &Config) -> Usage {
+    let (mut bytes, mut files) = (0, 0);
+    // revision files are hardlinks to blobs: count each inode's bytes once
+    let mut seen = std::collections::HashSet::new();
+    for entry in WalkDir::new(STORAGE_ROOT).into_iter().flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        if first_link(&meta, &mut seen) {
+            bytes += meta.len();
+        }
+        if !entry.path().starts_with(OBJECTS_DIR) {
+            files += 1;
+        }
+    }
+    Usage { bytes, files, max_bytes: cfg.quota_max_bytes, max_files: cfg.quota_max_files }
+}
+
+#[cfg(unix)]
+fn first_link(meta: &std::fs::Metadata, seen: &mut std::collections::HashSet<(u64, u64)>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink() == 1 || seen.insert((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn first_link(_: &std::fs::Metadata, _: &mut std::collections::HashSet<(u64, u64)>) -> bool {
+    true
+}
+
+/// Verifies that adding `add_bytes`/`add_files` (negative for shrinking writes)
+/// stays within quota. Blocking; a no-op when no quota is configured.
+pub(crate) fn check(cfg: &Config, add_bytes: i64, add_files: i64) -> Result<(), Response> {
+    if cfg.quota_max_bytes.is_none() && cfg.quota_max_files.is_none() {
+        return Ok(());
+    }
+    let u = usage(cfg);
+    let over = |used: u64, add: i64, max: Option<u64>| {
+        add > 0 && max.is_some_and(|m| used.saturating_add_signed(add) > m)
+    };
+    if over(u.bytes, add_bytes, u.max_bytes) || over(u.files, add_files, u.max_files) {
+        return Err((StatusCode::INSUFFICIENT_STORAGE, Json(u)).into_response());
+    }
+    Ok(())
+}
+
+/// Quota check for overwriting (or creating) a single file with `new_len` bytes.
+pub(crate) fn check_write(cfg: &Config, target: &std::path::Path, new_len: u64) -> Result<(), Response> {
+    let (old_len, add_files) = match std::fs::metadata(target) {
+        Ok(m) => (m.len(), 0),
+        Err(_) => (0, 1),
+    };
+    check(cfg, new_len as i64 - old_len as i64, add_files)
+}
+
+// ===== GET /api/fs/usage =====================================================
+
+pub(crate) async fn fs_usage(State(st): State<AppState>) -> impl IntoResponse {
+    match tokio::task::spawn_blocking(move || usage(&st.config)).await {
+        Ok(u) => Json(u).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/fs/du ========================================================
+
+#[derive(Deserialize)]
+pub(crate) struct DuParams {
+    #[serde(default)]
+    path: String,
+    /// include the N largest direct children
+    top: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DuEntry {
+    path: String,
+    bytes: u64,
+    files: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<DuEntry>>,
+}
+
+/// `(bytes, files)` of everything under `p`, honouring the symlink policy.
+pub(crate) fn subtree_size(p: &StdPath, follow: bool) -> (u64, u64) {
+    let (mut bytes, mut files) = (0, 0);
+    let walk = WalkDir::new(p).follow_links(follow).into_iter().flatten();
+    for entry in walk.filter(|e| walk_allowed(e, follow)) {
+        if entry.file_type().is_file() {
+            bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            files += 1;
+        }
+    }
+    (bytes, files)
+}
+
+pub(crate) async fn fs_du(State(st): State<AppState>, Query(q): Query<DuParams>) -> impl IntoResponse {
+    let root = match read_path(&st.config, &q.path) {
+        Ok(p) if p.exists() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let follow = st.config.follow_symlinks;
+    let rel = q.path.trim_matches('/').to_string();
+
+    let res = tokio::task::spawn_blocking(move || {
+        let (bytes, files) = subtree_size(&root, follow);
+        let children = q.top.filter(|_| root.is_dir()).map(|top| {
+            let mut kids: Vec<DuEntry> = std::fs::read_dir(&root)
+                .into_iter()
+                .flatten()
+                .flatten()
+                // walkdir always follows a symlinked root, so apply the policy here
+                .filter(|e| follow || !e.file_type().is_ok_and(|t| t.is_symlink()))
+                .map(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    let (bytes, files) = subtree_size(&e.path(), follow);
+                    let path = if rel.is_empty() { name } else { format!("{rel}/{name}") };
+                    DuEntry { path, bytes, files, children: None }
+                })
+                .collect();
+            kids.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+            kids.truncate(top);
+            kids
+        });
+        DuEntry { path: rel, bytes, files, children }
+    })
+    .await;
+
+    match res {
+        Ok(entry) => Json(entry).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,174 @@
+//! Request budgets: per client across `/api`, and per alias on `/api/eval`
+//! (see [`eval`](crate::eval)).
+//!
+//! Every client gets three budgets of requests per minute, one for each
+//! class of route:
+//!
+//! - `eval`, the published decisions at `/api/eval/*` (`RATE_LIMIT_EVAL`,
+//!   default 600);
+//! - `expensive`, what walks or evaluates a lot: searches, simulations and
+//!   test runs, snapshots, revision uploads and imports, archives, replace,
+//!   index rebuilds and password sign-ins (`RATE_LIMIT_EXPENSIVE`, default 120);
+//! - `cheap`, everything else (`RATE_LIMIT_CHEAP`, default 1200).
+//!
+//! A client is its API key or signed-in user when `/api` needs one, and its
+//! IP address otherwise: the peer's, or with `RATE_LIMIT_TRUST_PROXY` the
+//! first one in `X-Forwarded-For`. Past a budget, 429 with `Retry-After`.
+//! Budgets are token buckets refilled evenly over the minute, so a quiet
+//! client may burst up to the whole minute's worth; they're kept in memory,
+//! per instance. 0 turns a class's limit off; `/api/health` is never limited.
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Mutex, time::Instant};
+
+use crate::{auth::Identity, config::Config, AppState};
+
+/// Buckets kept before the full ones are dropped; a full bucket is the same
+/// as none.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Routes of the `expensive` class, as the router writes them.
+const EXPENSIVE: &[&str] = &[
+    "/api/fs/search",
+    "/api/fs/search/stream",
+    "/api/search/jdm",
+    "/api/searches/:id/run",
+    "/api/simulate",
+    "/api/simulate/batch",
+    "/api/simulate/compare",
+    "/api/simulate/csv",
+    "/api/simulate/stream",
+    "/api/simulations/:id/replay",
+    "/api/tests/run",
+    "/api/fs/snapshot",
+    "/api/fs/replace",
+    "/api/fs/archive",
+    "/api/fs/extract",
+    "/api/fs/index/rebuild",
+    "/api/revisions/from-url",
+    "/api/revisions/:id/archive",
+    "/api/revisions/:id/verify",
+    "/api/auth/login",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Class {
+    Cheap,
+    Expensive,
+    Eval,
+}
+
+impl Class {
+    fn of(method: &axum::http::Method, route: &str) -> Self {
+        if route.starts_with("/api/eval/") {
+            Class::Eval
+        } else if EXPENSIVE.contains(&route) || (route == "/api/revisions" && method == axum::http::Method::POST) {
+            Class::Expensive
+        } else {
+            Class::Cheap
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Class::Cheap => "cheap",
+            Class::Expensive => "expensive",
+            Class::Eval => "eval",
+        }
+    }
+
+    fn per_minute(self, cfg: &Config) -> u32 {
+        match self {
+            Class::Cheap => cfg.rate_limit_cheap,
+            Class::Expensive => cfg.rate_limit_expensive,
+            Class::Eval => cfg.rate_limit_eval,
+        }
+    }
+}
+
+/// A token bucket holding a minute's worth of requests, refilled evenly.
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+    capacity: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        self.tokens = (self.tokens + now.duration_since(self.at).as_secs_f64() * self.capacity / 60.0).min(self.capacity);
+        self.at = now;
+    }
+}
+
+/// `<scope>/<who>` → its bucket
+static BUCKETS: Mutex<BTreeMap<String, Bucket>> = Mutex::new(BTreeMap::new());
+
+/// Takes a request from `key`'s budget of `per_minute`; `Err` with the
+/// seconds until there's one again.
+pub(crate) fn take(key: &str, per_minute: u32) -> Result<(), u64> {
+    if per_minute == 0 {
+        return Ok(());
+    }
+    let capacity = f64::from(per_minute);
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock().unwrap();
+    if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+        buckets.retain(|_, b| {
+            b.refill(now);
+            b.tokens < b.capacity
+        });
+    }
+    let b = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, at: now, capacity });
+    b.capacity = capacity;
+    b.refill(now);
+    if b.tokens >= 1.0 {
+        b.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(((1.0 - b.tokens) * 60.0 / capacity).ceil() as u64)
+    }
+}
+
+/// 429 telling the caller to come back in `retry_after` seconds.
+pub(crate) fn too_many(retry_after: u64, msg: &'static str) -> Response {
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, HeaderValue::from(retry_after))], msg).into_response()
+}
+
+fn client(req: &Request, trust_proxy: bool) -> String {
+    if let Some(ident) = req.extensions().get::<Identity>() {
+        return format!("user:{}", ident.name);
+    }
+    let forwarded = trust_proxy
+        .then(|| req.headers().get("x-forwarded-for")?.to_str().ok()?.split(',').next().map(str::trim).filter(|s| !s.is_empty()))
+        .flatten();
+    match (forwarded, req.extensions().get::<ConnectInfo<SocketAddr>>()) {
+        (Some(ip), _) => format!("ip:{ip}"),
+        (None, Some(ConnectInfo(addr))) => format!("ip:{}", addr.ip()),
+        (None, None) => "ip:unknown".to_string(),
+    }
+}
+
+// ===== middleware =============================================================
+
+/// Sits inside [`require_auth`](crate::auth::require_auth), so a key's
+/// requests count against the key wherever they come from.
+pub(crate) async fn limit(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(route) = req.extensions().get::<MatchedPath>().map(|m| m.as_str().to_string()) else {
+        return next.run(req).await;
+    };
+    if route == "/api/health" {
+        return next.run(req).await;
+    }
+    let class = Class::of(req.method(), &route);
+    let who = client(&req, st.config.rate_limit_trust_proxy);
+    if let Err(retry_after) = take(&format!("{}/{who}", class.name()), class.per_minute(&st.config)) {
+        tracing::warn!(client = who, class = class.name(), route, "rate limited");
+        return too_many(retry_after, "rate limit exceeded, try again later");
+    }
+    next.run(req).await
+}
@@ -0,0 +1,167 @@
+//! Startup check of HEAD against the revision directories.
+//!
+//! A revision is numbered, under the rev lock, by creating its directory and
+//! then moving HEAD onto it; `adopt_rev` renames a filled directory into place
+//! instead. A crash between the two leaves a directory above HEAD that the
+//! next revision would silently reuse, and a HEAD file lost or unreadable
+//! (from before HEAD was written by rename) would restart numbering at 1 on
+//! top of existing revisions. Before serving, [`repair`]:
+//!
+//! * rebuilds a missing or unreadable HEAD from the highest revision present;
+//! * moves HEAD forward over revisions that were finished (they have a
+//!   manifest or a path map) but that HEAD doesn't reach;
+//! * removes empty directories above HEAD and moves non-empty ones to
+//!   `./decisions/.lost/<n>` for someone to look at;
+//! * notes a HEAD whose own directory is gone. That one is left alone: its
+//!   number may be in the git mirror or in clients' hands, so it isn't reused.
+//!
+//! What it did is kept for `GET /api/admin/startup`.
+
+use axum::{
+    extract::{Json, State},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path as StdPath, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{objects, revisions, storelock::StorageLock, write_head, AppState, HEAD_FILE, STORAGE_ROOT};
+
+const LOST_DIR: &str = "./decisions/.lost";
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum RepairKind {
+    HeadRebuilt,
+    HeadAdvanced,
+    OrphanRemoved,
+    OrphanQuarantined,
+    HeadMissing,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Repair {
+    kind: RepairKind,
+    rev: u64,
+    detail: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StartupReport {
+    /// unix seconds
+    checked_at: i64,
+    /// HEAD once the check was done
+    head: u64,
+    /// empty when storage was consistent
+    repairs: Vec<Repair>,
+}
+
+impl StartupReport {
+    fn note(&mut self, kind: RepairKind, rev: u64, detail: String) {
+        tracing::warn!("storage recovery: {detail}");
+        self.repairs.push(Repair { kind, rev, detail });
+    }
+}
+
+/// Revision numbers with a directory, ascending, the working copy left out.
+fn numbered_dirs() -> Vec<u64> {
+    let mut revs: Vec<u64> = fs::read_dir(STORAGE_ROOT)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+        .filter(|&n| n > 0)
+        .collect();
+    revs.sort_unstable();
+    revs
+}
+
+/// Whether `finish_revision` got far enough on `rev` to record anything.
+fn finished(rev: u64) -> bool {
+    revisions::manifest_path(rev).is_file() || objects::file_map(rev).is_some()
+}
+
+fn quarantine(rev: u64) -> io::Result<PathBuf> {
+    fs::create_dir_all(LOST_DIR)?;
+    let mut dest = PathBuf::from(LOST_DIR).join(rev.to_string());
+    if dest.exists() {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        dest = PathBuf::from(LOST_DIR).join(format!("{rev}-{secs}"));
+    }
+    fs::rename(format!("{STORAGE_ROOT}/{rev}"), &dest)?;
+    Ok(dest)
+}
+
+/// Brings HEAD and the revision directories back in line. Blocking; run once
+/// at startup, before anything numbers a revision.
+pub(crate) fn repair(rev_lock: &StorageLock) -> StartupReport {
+    // another instance may be numbering a revision right now
+    let _g = rev_lock.lock();
+    let dirs = numbered_dirs();
+    let mut report = StartupReport {
+        checked_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64),
+        head: 0,
+        repairs: Vec::new(),
+    };
+
+    let recorded = fs::read_to_string(HEAD_FILE).ok().and_then(|s| s.trim().parse::<u64>().ok());
+    let mut head = match recorded {
+        Some(h) => h,
+        None => {
+            // every directory there was numbered under some HEAD; trust them all
+            let h = dirs.last().copied().unwrap_or(0);
+            if h > 0 || StdPath::new(HEAD_FILE).exists() {
+                report.note(RepairKind::HeadRebuilt, h, format!("HEAD was missing or unreadable; rebuilt as {h}"));
+            }
+            h
+        }
+    };
+    if let Some(&last) = dirs.iter().rev().find(|&&n| n > head && finished(n)) {
+        report.note(RepairKind::HeadAdvanced, last, format!("HEAD was {head} but revision {last} was finished; moved HEAD to {last}"));
+        head = last;
+    }
+    if recorded != Some(head) {
+        write_head(head);
+    }
+
+    for &rev in dirs.iter().filter(|&&n| n > head) {
+        let dir = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+        let empty = fs::read_dir(&dir).is_ok_and(|mut d| d.next().is_none());
+        if empty {
+            match fs::remove_dir(&dir) {
+                Ok(()) => report.note(RepairKind::OrphanRemoved, rev, format!("removed empty directory {rev} above HEAD")),
+                Err(e) => tracing::error!("could not remove orphaned revision directory {rev}: {e}"),
+            }
+            continue;
+        }
+        match quarantine(rev) {
+            Ok(dest) => report.note(
+                RepairKind::OrphanQuarantined,
+                rev,
+                format!("moved unfinished directory {rev} above HEAD to {}", dest.display()),
+            ),
+            Err(e) => tracing::error!("could not quarantine orphaned revision directory {rev}: {e}"),
+        }
+    }
+
+    if head > 0 && !dirs.contains(&head) {
+        report.note(RepairKind::HeadMissing, head, format!("HEAD revision {head} has no directory; its number won't be reused"));
+    }
+    report.head = head;
+    if report.repairs.is_empty() {
+        tracing::debug!("storage recovery: HEAD {head} is consistent");
+    }
+    report
+}
+
+// ===== GET /api/admin/startup ================================================
+
+pub(crate) async fn startup_report(State(st): State<AppState>) -> Response {
+    Json(&*st.startup).into_response()
+}
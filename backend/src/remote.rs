@@ -0,0 +1,149 @@
+//! Revisions published from elsewhere: `POST /api/revisions/from-url`.
+//!
+//! CI builds a decision bundle, uploads it somewhere, and tells the server
+//! where. The server downloads the zip over HTTPS, checks it against the
+//! SHA-256 the caller gave, and makes a revision of it exactly as an upload to
+//! `POST /api/revisions` would. Only hosts listed in `IMPORT_ALLOWED_HOSTS`
+//! can be fetched from, redirects included, and downloads are capped at
+//! `IMPORT_MAX_BYTES` (see [`Config`](crate::config::Config)).
+
+use axum::{
+    extract::{Extension, Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{io::Write, time::Duration};
+
+use crate::{audit, auth, branches, events::ServerEvent, revision_from_zip, revisions::RevMeta, AppState};
+
+const MAX_REDIRECTS: usize = 5;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Deserialize)]
+pub(crate) struct FromUrlReq {
+    url: String,
+    /// hex SHA-256 of the zip, optionally prefixed `sha256:`
+    checksum: String,
+    #[serde(flatten)]
+    meta: RevMeta,
+}
+
+/// Whether `host` is allowed by `IMPORT_ALLOWED_HOSTS`: listed exactly, or
+/// under an entry written `.example.com` (or `*.example.com`).
+pub(crate) fn host_allowed(allowed: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed.iter().any(|a| match a.strip_prefix('*').unwrap_or(a).strip_prefix('.') {
+        Some(domain) => host.ends_with(&format!(".{domain}")),
+        None => host == *a,
+    })
+}
+
+fn check_url(allowed: &[String], url: &reqwest::Url) -> Result<(), Response> {
+    if url.scheme() != "https" {
+        return Err((StatusCode::BAD_REQUEST, "only https:// URLs can be imported").into_response());
+    }
+    match url.host_str() {
+        Some(host) if host_allowed(allowed, host) => Ok(()),
+        Some(host) => Err((StatusCode::FORBIDDEN, format!("{host} is not in IMPORT_ALLOWED_HOSTS")).into_response()),
+        None => Err((StatusCode::BAD_REQUEST, "URL has no host").into_response()),
+    }
+}
+
+fn bad_gateway(e: impl std::fmt::Display) -> Response {
+    (StatusCode::BAD_GATEWAY, format!("download failed: {e}")).into_response()
+}
+
+/// Downloads `url` into a temp file, refusing more than `max_bytes`, and
+/// returns it with its SHA-256.
+async fn download(allowed: Vec<String>, url: reqwest::Url, max_bytes: u64) -> Result<(tempfile::NamedTempFile, String), Response> {
+    let policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if check_url(&allowed, attempt.url()).is_err() {
+            let msg = format!("redirect to {} is not allowed", attempt.url());
+            attempt.error(msg)
+        } else {
+            attempt.follow()
+        }
+    });
+    let client = reqwest::Client::builder().redirect(policy).timeout(FETCH_TIMEOUT).build().map_err(bad_gateway)?;
+    let mut resp = client.get(url).send().await.map_err(bad_gateway)?;
+    if !resp.status().is_success() {
+        return Err(bad_gateway(format!("upstream answered {}", resp.status())));
+    }
+    if resp.content_length().is_some_and(|n| n > max_bytes) {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("archive is larger than {max_bytes} bytes")).into_response());
+    }
+
+    let internal = |e: std::io::Error| {
+        tracing::error!("import spool error: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+    let mut tmp = tempfile::NamedTempFile::new().map_err(internal)?;
+    let mut hasher = Sha256::new();
+    let mut total = 0u64;
+    while let Some(chunk) = resp.chunk().await.map_err(bad_gateway)? {
+        total += chunk.len() as u64;
+        if total > max_bytes {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("archive is larger than {max_bytes} bytes")).into_response());
+        }
+        hasher.update(&chunk);
+        // small chunks into a local temp file; not worth a blocking task each
+        tmp.write_all(&chunk).map_err(internal)?;
+    }
+    tmp.flush().map_err(internal)?;
+    Ok((tmp, format!("{:x}", hasher.finalize())))
+}
+
+/// 201 with the new revision's manifest. 403 when the host isn't allowed (or
+/// imports are off), 422 when the download doesn't match `checksum`, 502 when
+/// it fails.
+pub(crate) async fn from_url(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(body): Json<FromUrlReq>) -> Response {
+    let allowed = st.config.import_allowed_hosts.clone();
+    if allowed.is_empty() {
+        return (StatusCode::FORBIDDEN, "imports from URLs are off (set IMPORT_ALLOWED_HOSTS)").into_response();
+    }
+    let url = match reqwest::Url::parse(body.url.trim()) {
+        Ok(u) => u,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("invalid URL: {e}")).into_response(),
+    };
+    if let Err(resp) = check_url(&allowed, &url) {
+        return resp;
+    }
+    let expected = body.checksum.trim().trim_start_matches("sha256:").to_ascii_lowercase();
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return (StatusCode::BAD_REQUEST, "checksum must be a hex SHA-256").into_response();
+    }
+    let mut meta = body.meta;
+    meta.author = auth::acting(&ident, meta.author);
+    if let Err(resp) = meta.branch.as_deref().map_or(Ok(()), branches::check) {
+        return resp;
+    }
+
+    let (upload, actual) = match download(allowed, url.clone(), st.config.import_max_bytes).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    if actual != expected {
+        tracing::warn!("import from {url} rejected: checksum {actual}, expected {expected}");
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("checksum mismatch: downloaded sha256:{actual}")).into_response();
+    }
+    if meta.message.is_none() {
+        meta.message = Some(format!("imported from {url}"));
+    }
+
+    let state = st.clone();
+    match tokio::task::spawn_blocking(move || revision_from_zip(&state, &upload, meta)).await {
+        Ok(Ok(manifest)) => {
+            tracing::info!("imported revision {} from {url}", manifest.id);
+            st.index.touch(&manifest.id.to_string());
+            st.events.publish(ServerEvent::SnapshotCreated { id: manifest.id });
+            audit::record(audit::Event::new("revision").rev(manifest.id).by(manifest.author.clone())).await;
+            (StatusCode::CREATED, Json(manifest)).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
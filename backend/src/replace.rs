@@ -0,0 +1,193 @@
+//! Project-wide find-and-replace (`/api/fs/replace`).
+//!
+//! All new contents are computed up front; files are then written one by one
+//! (each write atomic) and, should any write fail, the ones already written are
+//! restored from their original contents — so the operation lands entirely or
+//! not at all.
+
+use axum::{
+    extract::{Extension, Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::{fs, path::PathBuf};
+use walkdir::WalkDir;
+
+use crate::{audit, auth, looks_binary, quota, walk_allowed, write_atomic, write_path, AppState, WriteOpts, STORAGE_ROOT};
+
+const MAX_FILE_BYTES: u64 = 1_000_000;
+pub(crate) const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReplaceReq {
+    query: String,
+    replacement: String,
+    /// treat `query` as a regex; `replacement` may then use `$1` / `${name}`
+    #[serde(default)]
+    regex: bool,
+    /// files and/or folders to search, relative to the storage root
+    paths: Vec<String>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    owner: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileChange {
+    path: String,
+    replacements: usize,
+    /// unified diff, only for dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplaceResp {
+    dry_run: bool,
+    total_replacements: usize,
+    files: Vec<FileChange>,
+}
+
+struct Planned {
+    rel: String,
+    full: PathBuf,
+    before: String,
+    after: String,
+    count: usize,
+}
+
+pub(crate) async fn fs_replace(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<ReplaceReq>) -> Response {
+    body.owner = auth::acting(&ident, body.owner);
+    if body.query.is_empty() {
+        return (StatusCode::BAD_REQUEST, "empty query").into_response();
+    }
+    let pattern = if body.regex { body.query.clone() } else { regex::escape(&body.query) };
+    let re = match RegexBuilder::new(&pattern).size_limit(REGEX_SIZE_LIMIT).build() {
+        Ok(re) => re,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let mut roots = Vec::with_capacity(body.paths.len());
+    for p in &body.paths {
+        // same path rules as a real write, so a dry run previews exactly what would change
+        match write_path(p) {
+            Ok(full) if full.exists() => roots.push(full),
+            Ok(_) => return (StatusCode::NOT_FOUND, p.clone()).into_response(),
+            Err(e) => return e.into_response(),
+        }
+    }
+
+    let by = auth::caller().map(|c| c.name);
+    let res = tokio::task::spawn_blocking(move || -> Result<ReplaceResp, Response> {
+        // snapshots hold the rev lock for their whole copy, so they see the
+        // replace applied in full or not at all
+        let _g = st.rev_lock.lock();
+        let plan = plan(&roots, &re, &body.replacement, body.regex);
+
+        if !body.dry_run {
+            for p in &plan {
+                st.locks.check(&p.rel, body.owner.as_deref())?;
+            }
+            let delta: i64 = plan.iter().map(|p| p.after.len() as i64 - p.before.len() as i64).sum();
+            quota::check(&st.config, delta, 0)?;
+            apply(&plan).map_err(|e| {
+                tracing::error!("replace failed and was rolled back: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            })?;
+        }
+
+        let files: Vec<FileChange> = plan
+            .iter()
+            .map(|p| FileChange {
+                path: p.rel.clone(),
+                replacements: p.count,
+                diff: body.dry_run.then(|| {
+                    TextDiff::from_lines(&p.before, &p.after)
+                        .unified_diff()
+                        .context_radius(3)
+                        .header(&format!("a/{}", p.rel), &format!("b/{}", p.rel))
+                        .to_string()
+                }),
+            })
+            .collect();
+        if !body.dry_run {
+            for p in &plan {
+                st.index.touch(&p.rel);
+                st.engines.invalidate(&p.rel);
+                let hashes = (Some(audit::hash(p.before.as_bytes())), Some(audit::hash(p.after.as_bytes())));
+                audit::append(audit::Event::new("replace").path(&p.rel).hashes(hashes.0, hashes.1).by(by.clone()));
+            }
+        }
+        Ok(ReplaceResp {
+            dry_run: body.dry_run,
+            total_replacements: files.iter().map(|f| f.replacements).sum(),
+            files,
+        })
+    })
+    .await;
+
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Every text file under `roots` with at least one match, deduplicated and sorted.
+/// Symlinks are never followed: this walk feeds writes.
+fn plan(roots: &[PathBuf], re: &Regex, replacement: &str, expand: bool) -> Vec<Planned> {
+    let mut seen = std::collections::BTreeMap::new();
+    for root in roots {
+        for entry in WalkDir::new(root).into_iter().flatten().filter(|e| walk_allowed(e, false)) {
+            if !entry.file_type().is_file() || entry.metadata().map(|m| m.len()).unwrap_or(0) > MAX_FILE_BYTES {
+                continue;
+            }
+            let rel = entry
+                .path()
+                .strip_prefix(STORAGE_ROOT)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .replace('\\', "/");
+            if seen.contains_key(&rel) {
+                continue;
+            }
+            let Ok(bytes) = fs::read(entry.path()) else { continue };
+            if looks_binary(&bytes) {
+                continue;
+            }
+            let Ok(before) = String::from_utf8(bytes) else { continue };
+            let count = re.find_iter(&before).count();
+            if count == 0 {
+                continue;
+            }
+            let after = if expand {
+                re.replace_all(&before, replacement).into_owned()
+            } else {
+                re.replace_all(&before, NoExpand(replacement)).into_owned()
+            };
+            seen.insert(rel.clone(), Planned { rel, full: entry.path().to_path_buf(), before, after, count });
+        }
+    }
+    seen.into_values().collect()
+}
+
+fn apply(plan: &[Planned]) -> std::io::Result<()> {
+    for (i, p) in plan.iter().enumerate() {
+        if let Err(e) = write_atomic(&p.full, p.after.as_bytes(), WriteOpts::default()) {
+            for done in &plan[..i] {
+                if let Err(re) = write_atomic(&done.full, done.before.as_bytes(), WriteOpts::default()) {
+                    tracing::error!("rollback of {} failed: {re}", done.rel);
+                }
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! Automatic revision retention (`RETENTION_*`, see [`Config`](crate::config::Config)).
+//!
+//! A background task wakes every `RETENTION_INTERVAL_SECS` and deletes the
+//! revisions the policy doesn't keep; `/api/admin/gc` reports the policy, the
+//! schedule and the last run, and `/api/admin/gc/run` runs it right away.
+//! Tagged revisions and branch heads are never collected. Automatic snapshots
+//! can be capped on their own (`RETENTION_KEEP_AUTO`): they pile up much
+//! faster than the ones people take.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{freeze, read_head, revisions, AppState};
+
+const DAY_SECS: i64 = 86_400;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LastRun {
+    /// unix seconds
+    at: i64,
+    removed: Vec<u64>,
+    reclaimed_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct GcState {
+    last_run: Option<LastRun>,
+    next_run_at: Option<i64>,
+}
+
+/// Shared view of the GC task, for the admin endpoint.
+#[derive(Clone, Default)]
+pub(crate) struct Gc(Arc<Mutex<GcState>>);
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn enabled(st: &AppState) -> bool {
+    st.config.retention_keep_last.is_some() || st.config.retention_daily_days.is_some() || st.config.retention_keep_auto.is_some()
+}
+
+/// Revisions the policy lets go, given `(rev, created_at)` for every revision
+/// except the working copy, ascending. HEAD always stays.
+fn doomed(revs: &[(u64, Option<i64>)], head: u64, keep_last: Option<usize>, daily_days: Option<u64>, now: i64) -> Vec<u64> {
+    let mut keep: BTreeSet<u64> = BTreeSet::from([head]);
+    keep.extend(revs.iter().rev().take(keep_last.unwrap_or(0)).map(|&(r, _)| r));
+    if let Some(days) = daily_days {
+        let today = now.div_euclid(DAY_SECS);
+        let mut newest_per_day: HashMap<i64, u64> = HashMap::new();
+        for &(rev, created) in revs {
+            let Some(day) = created.map(|t| t.div_euclid(DAY_SECS)) else { continue };
+            if today - day < days as i64 {
+                // ascending, so later revisions of the same day win
+                newest_per_day.insert(day, rev);
+            }
+        }
+        keep.extend(newest_per_day.into_values());
+    }
+    revs.iter().map(|&(r, _)| r).filter(|r| !keep.contains(r)).collect()
+}
+
+/// One GC pass. Blocking.
+fn run_once(st: &AppState) -> LastRun {
+    let at = now_secs();
+    let _g = st.rev_lock.lock();
+    let revs: Vec<(u64, Option<i64>)> =
+        revisions::existing().into_iter().filter(|&r| r != 0).map(|r| (r, revisions::created_at(r))).collect();
+    let pinned = revisions::pinned();
+    let head = read_head();
+    let mut victims = match (st.config.retention_keep_last, st.config.retention_daily_days) {
+        (None, None) => Vec::new(),
+        (keep_last, daily_days) => doomed(&revs, head, keep_last, daily_days, at),
+    };
+    if let Some(keep) = st.config.retention_keep_auto {
+        let autos: Vec<u64> = revs.iter().map(|&(r, _)| r).filter(|&r| revisions::read_manifest(r).auto).collect();
+        victims.extend(&autos[..autos.len().saturating_sub(keep)]);
+        victims.sort_unstable();
+        victims.dedup();
+    }
+    victims.retain(|&r| r != head && !pinned.contains(&r));
+    let (mut removed, mut reclaimed_bytes, mut error) = (Vec::new(), 0, None);
+    if !victims.is_empty() {
+        match revisions::remove_revisions(st, &victims, false, "gc", None) {
+            Ok(r) => (removed, reclaimed_bytes) = (r.removed, r.reclaimed_bytes),
+            Err(resp) => error = Some(format!("deleting revisions failed ({})", resp.status())),
+        }
+    }
+    if removed.is_empty() {
+        tracing::debug!("revision GC: nothing to remove");
+    } else {
+        tracing::info!("revision GC removed {removed:?} ({reclaimed_bytes} bytes)");
+    }
+    LastRun { at, removed, reclaimed_bytes, error }
+}
+
+async fn run_and_record(st: &AppState) -> Option<LastRun> {
+    let state = st.clone();
+    let run = tokio::task::spawn_blocking(move || run_once(&state)).await.ok()?;
+    st.gc.0.lock().unwrap().last_run = Some(run.clone());
+    Some(run)
+}
+
+/// Starts the GC loop if a policy is configured; the first pass runs one
+/// interval after startup.
+pub(crate) fn spawn(st: AppState) {
+    if !enabled(&st) {
+        return;
+    }
+    let every = st.config.retention_interval;
+    tracing::info!(
+        "revision GC every {}s (keep last {:?}, one per day for {:?} days, {:?} automatic)",
+        every.as_secs(),
+        st.config.retention_keep_last,
+        st.config.retention_daily_days,
+        st.config.retention_keep_auto
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+        loop {
+            st.gc.0.lock().unwrap().next_run_at = Some(now_secs() + ticker.period().as_secs() as i64);
+            ticker.tick().await;
+            if freeze::frozen(&st.config).is_some() {
+                tracing::info!("revision GC skipped: read-only mode");
+                continue;
+            }
+            run_and_record(&st).await;
+        }
+    });
+}
+
+// ===== GET /api/admin/gc, POST /api/admin/gc/run =============================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GcStatus {
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_last: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_days: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_auto: Option<usize>,
+    interval_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_run_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_run: Option<LastRun>,
+}
+
+pub(crate) async fn status(State(st): State<AppState>) -> impl IntoResponse {
+    let gc = st.gc.0.lock().unwrap();
+    Json(GcStatus {
+        enabled: enabled(&st),
+        keep_last: st.config.retention_keep_last,
+        daily_days: st.config.retention_daily_days,
+        keep_auto: st.config.retention_keep_auto,
+        interval_secs: st.config.retention_interval.as_secs(),
+        next_run_at: gc.next_run_at,
+        last_run: gc.last_run.clone(),
+    })
+}
+
+/// Runs a pass now, off schedule.
+pub(crate) async fn run_now(State(st): State<AppState>) -> Response {
+    if !enabled(&st) {
+        return (StatusCode::CONFLICT, "no retention policy configured").into_response();
+    }
+    match run_and_record(&st).await {
+        Some(run) => Json(run).into_response(),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
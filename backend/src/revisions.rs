@@ -0,0 +1,1231 @@
+//! Revision metadata.
+//!
+//! Revisions themselves are bare numbered directories; what a revision is
+//! (message, author, when, how big) is recorded in a manifest under the
+//! `./decisions/.revisions/<rev>/` sidecar, outside the revision so that
+//! copying, exporting or restoring it never drags the manifest along.
+//!
+//! Also compares revisions: which files changed (`/api/revisions/diff`) and
+//! how one file changed (`/api/revisions/diff/file`), and rolls the working
+//! copy back to one (`/api/revisions/restore`) or brings back a single file
+//! (`/api/revisions/restore-file`), lists the revisions that changed a file
+//! (`/api/revisions/file-history`), and deletes old ones (`DELETE
+//! /api/revisions/:id`, `/api/revisions/prune`). Revisions can be named by
+//! tags (`/api/revisions/:id/tag`); see [`RevRef`]. `/api/revisions/:id/archive`
+//! downloads one as a zip, `/api/revisions/:id/tree` lists its files and
+//! `/api/revisions/:id/verify` checks them against the hashes recorded when it
+//! was made.
+//!
+//! New revisions start as drafts of the review workflow ([`workflow`]).
+//!
+//! File contents live in the blob store ([`crate::objects`]); diffs compare
+//! hashes from the revisions' path maps instead of reading both sides. With
+//! the git mirror on ([`gitmirror`]), revisions it has committed are diffed
+//! and restored from their git trees instead.
+
+use axum::{
+    extract::{Extension, Json, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{ChangeTag, TextDiff};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path as StdPath, PathBuf},
+};
+use walkdir::WalkDir;
+
+use crate::{
+    archive, audit, auth, branches, config::Config, gitmirror, build_node, build_tree_to, copy_dir_all, events::ServerEvent, finish_revision, link_dir_all, looks_binary, next_rev, objects, quota, read_head, read_path,
+    storelock::StorageLock, walk_allowed, workflow, write_atomic, write_path, AppState, WriteOpts, STORAGE_ROOT,
+};
+
+pub(crate) const META_DIR: &str = "./decisions/.revisions";
+
+/// What the client may say about a revision it creates.
+#[derive(Deserialize, Default)]
+pub(crate) struct RevMeta {
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// branch to put it on; the working copy's by default
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// made by the scheduler, not a client
+    #[serde(skip)]
+    pub auto: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// seconds since the epoch, like `Node::modified`; absent for revisions
+    /// made before manifests existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<u64>,
+    /// total bytes of its files, before dedup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// the branch's previous head (0 for its first revision)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u64>,
+    /// an automatic snapshot
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto: bool,
+    /// review state; absent for revisions from before the workflow, which
+    /// count as published (see [`workflow`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<workflow::RevState>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<workflow::Approval>,
+}
+
+impl Manifest {
+    /// Placeholder for a revision without a manifest (including the working copy).
+    fn bare(id: u64) -> Self {
+        Self {
+            id,
+            message: None,
+            author: None,
+            created_at: None,
+            file_count: None,
+            size: None,
+            branch: None,
+            parent: None,
+            auto: false,
+            state: None,
+            approvals: Vec::new(),
+        }
+    }
+}
+
+pub(crate) fn manifest_path(rev: u64) -> PathBuf {
+    PathBuf::from(META_DIR).join(rev.to_string()).join("manifest.json")
+}
+
+/// Records `meta` for the just-filled revision `rev`, counting its files.
+pub(crate) fn write_manifest(rev: u64, meta: RevMeta, placed: Option<branches::Placed>) -> io::Result<Manifest> {
+    let non_empty = |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let (size, file_count) = quota::subtree_size(StdPath::new(&format!("{STORAGE_ROOT}/{rev}")), false);
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let manifest = Manifest {
+        id: rev,
+        message: non_empty(meta.message),
+        author: non_empty(meta.author),
+        created_at: Some(created_at),
+        file_count: Some(file_count),
+        size: Some(size),
+        parent: placed.as_ref().map(|p| p.parent),
+        branch: placed.map(|p| p.branch),
+        auto: meta.auto,
+        state: Some(workflow::RevState::Draft),
+        approvals: Vec::new(),
+    };
+    let bytes = serde_json::to_vec_pretty(&manifest).expect("serialize manifest");
+    write_atomic(&manifest_path(rev), &bytes, WriteOpts::default())?;
+    Ok(manifest)
+}
+
+/// Rewrites the manifest of `rev` through `f`. Caller holds the rev lock.
+pub(crate) fn update_manifest(rev: u64, f: impl FnOnce(&mut Manifest)) -> io::Result<Manifest> {
+    let mut manifest = read_manifest(rev);
+    f(&mut manifest);
+    let bytes = serde_json::to_vec_pretty(&manifest).expect("serialize manifest");
+    write_atomic(&manifest_path(rev), &bytes, WriteOpts::default())?;
+    Ok(manifest)
+}
+
+/// Manifest of `rev`, or a bare one if it has none (or it is unreadable).
+pub(crate) fn read_manifest(rev: u64) -> Manifest {
+    fs::read(manifest_path(rev))
+        .ok()
+        .and_then(|raw| serde_json::from_slice::<Manifest>(&raw).ok())
+        .map(|m| Manifest { id: rev, ..m })
+        .unwrap_or_else(|| Manifest::bare(rev))
+}
+
+// ===== GET /api/revisions/diff ===============================================
+
+#[derive(Deserialize)]
+pub(crate) struct DiffParams {
+    from: RevRef,
+    to: RevRef,
+}
+
+/// Paths are relative to the revision root, sorted.
+#[derive(Serialize)]
+struct DiffResp {
+    from: u64,
+    to: u64,
+    added: Vec<String>,
+    removed: Vec<String>,
+    modified: Vec<String>,
+    unchanged: usize,
+}
+
+/// Revisions that still exist, ascending; always starts with the working copy.
+/// Numbers of deleted revisions are never reused, so there may be gaps.
+pub(crate) fn existing() -> Vec<u64> {
+    let head = read_head();
+    let mut revs: Vec<u64> = fs::read_dir(STORAGE_ROOT)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+        .filter(|&n| n > 0 && n <= head)
+        .collect();
+    revs.push(0);
+    revs.sort_unstable();
+    revs
+}
+
+/// 404 unless `rev` exists (the working copy, 0, always does).
+pub(crate) fn check_rev(rev: u64) -> Result<(), Response> {
+    if rev > read_head() || (rev > 0 && !StdPath::new(&format!("{STORAGE_ROOT}/{rev}")).is_dir()) {
+        return Err((StatusCode::NOT_FOUND, format!("no such revision: {rev}")).into_response());
+    }
+    Ok(())
+}
+
+/// Every file in revision `rev`, keyed by its path inside the revision.
+pub(crate) fn rev_files(rev: u64, follow: bool) -> BTreeMap<String, PathBuf> {
+    let root = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+    WalkDir::new(&root)
+        .follow_links(follow)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file() && walk_allowed(e, follow))
+        .map(|e| {
+            let rel = e.path().strip_prefix(&root).unwrap_or(e.path()).to_string_lossy().replace('\\', "/");
+            (rel, e.into_path())
+        })
+        .collect()
+}
+
+/// SHA-256 of every file in `rev`: its path map if it's in the object store,
+/// hashed on the spot otherwise (the working copy).
+pub(crate) fn rev_hashes(rev: u64, follow: bool) -> io::Result<objects::FileMap> {
+    if let Some(map) = objects::file_map(rev) {
+        return Ok(map);
+    }
+    rev_files(rev, follow).into_iter().map(|(rel, path)| Ok((rel, objects::hash_file(&path)?))).collect()
+}
+
+/// Path maps of `from` and `to` to compare: blob ids from the git mirror when
+/// it has both, content hashes otherwise (the two don't mix).
+fn diff_maps(cfg: &Config, from: u64, to: u64) -> io::Result<(objects::FileMap, objects::FileMap)> {
+    if let (Some(a), Some(b)) = (gitmirror::mirrored(cfg, from)?, gitmirror::mirrored(cfg, to)?) {
+        let ids = |m: gitmirror::Mirrored| m.files.into_iter().map(|(rel, id)| (rel, id.to_string())).collect();
+        return Ok((ids(a), ids(b)));
+    }
+    Ok((rev_hashes(from, cfg.follow_symlinks)?, rev_hashes(to, cfg.follow_symlinks)?))
+}
+
+pub(crate) async fn diff(State(st): State<AppState>, Query(q): Query<DiffParams>) -> Response {
+    let (from, to) = match q.from.resolve().and_then(|f| Ok((f, q.to.resolve()?))) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let res = tokio::task::spawn_blocking(move || -> io::Result<DiffResp> {
+        let (old, new) = diff_maps(&st.config, from, to)?;
+        let mut resp = DiffResp {
+            from,
+            to,
+            added: new.keys().filter(|k| !old.contains_key(*k)).cloned().collect(),
+            removed: old.keys().filter(|k| !new.contains_key(*k)).cloned().collect(),
+            modified: Vec::new(),
+            unchanged: 0,
+        };
+        for (rel, a) in &old {
+            let Some(b) = new.get(rel) else { continue };
+            if a == b {
+                resp.unchanged += 1;
+            } else {
+                resp.modified.push(rel.clone());
+            }
+        }
+        Ok(resp)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("revision diff error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/revisions/diff/file ==========================================
+
+const MAX_DIFF_BYTES: u64 = 1_000_000;
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Deserialize)]
+pub(crate) struct FileDiffParams {
+    from: RevRef,
+    to: RevRef,
+    /// relative to the revision root
+    path: String,
+    /// pretty-print JSON on both sides first, so a re-serialized JDM file only
+    /// shows what actually changed
+    #[serde(default)]
+    normalize: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileDiffResp {
+    from: u64,
+    to: u64,
+    path: String,
+    status: &'static str, // "added", "removed", "modified", "unchanged"
+    /// either side isn't text; no diff is produced
+    binary: bool,
+    /// `a/<path>` → `b/<path>`, empty when unchanged or binary
+    unified: String,
+    hunks: Vec<Hunk>,
+}
+
+/// Same ranges as the `@@ -a,b +c,d @@` header of the unified diff.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Hunk {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+    lines: Vec<HunkLine>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HunkLine {
+    kind: &'static str, // "context", "delete", "insert"
+    /// 1-based, absent on inserted lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_line: Option<usize>,
+    /// 1-based, absent on deleted lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_line: Option<usize>,
+    /// without the line break
+    text: String,
+}
+
+/// Bytes of `path` in `rev`, or `None` if it doesn't exist there.
+fn side(st: &AppState, rev: u64, path: &str) -> Result<Option<Vec<u8>>, Response> {
+    let full = read_path(&st.config, &format!("{rev}/{path}")).map_err(IntoResponse::into_response)?;
+    if let Some(m) = gitmirror::mirrored(&st.config, rev).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())? {
+        let bytes = m.read(path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        if bytes.as_ref().is_some_and(|b| b.len() as u64 > MAX_DIFF_BYTES) {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, "file too large to diff").into_response());
+        }
+        return Ok(bytes);
+    }
+    if !full.is_file() {
+        return Ok(None);
+    }
+    let full = objects::locate(rev, path).unwrap_or(full);
+    let meta = fs::metadata(&full).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    if meta.len() > MAX_DIFF_BYTES {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "file too large to diff").into_response());
+    }
+    fs::read(&full).map(Some).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+fn as_text(bytes: Vec<u8>, normalize: bool) -> Option<String> {
+    if looks_binary(&bytes) {
+        return None;
+    }
+    let text = String::from_utf8(bytes).ok()?;
+    if normalize {
+        if let Ok(v) = serde_json::from_str::<Value>(&text) {
+            let mut pretty = serde_json::to_string_pretty(&v).expect("serialize JSON");
+            pretty.push('\n');
+            return Some(pretty);
+        }
+    }
+    Some(text)
+}
+
+fn hunks(diff: &TextDiff<'_, '_, '_, str>) -> Vec<Hunk> {
+    diff.grouped_ops(CONTEXT_LINES)
+        .iter()
+        .filter_map(|group| {
+            let (first, last) = (group.first()?, group.last()?);
+            let (old, new) = (first.old_range().start..last.old_range().end, first.new_range().start..last.new_range().end);
+            let lines = group
+                .iter()
+                .flat_map(|op| diff.iter_changes(op))
+                .map(|c| HunkLine {
+                    kind: match c.tag() {
+                        ChangeTag::Equal => "context",
+                        ChangeTag::Delete => "delete",
+                        ChangeTag::Insert => "insert",
+                    },
+                    old_line: c.old_index().map(|i| i + 1),
+                    new_line: c.new_index().map(|i| i + 1),
+                    text: c.value().trim_end_matches(['\n', '\r']).to_string(),
+                })
+                .collect();
+            // unified-diff convention: an empty range starts at the line before it
+            let start = |r: &std::ops::Range<usize>| if r.is_empty() { r.start } else { r.start + 1 };
+            Some(Hunk { old_start: start(&old), old_lines: old.len(), new_start: start(&new), new_lines: new.len(), lines })
+        })
+        .collect()
+}
+
+/// A file missing on one side diffs against empty text.
+pub(crate) async fn diff_file(State(st): State<AppState>, Query(q): Query<FileDiffParams>) -> Response {
+    let (from, to) = match q.from.resolve().and_then(|f| Ok((f, q.to.resolve()?))) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let res = tokio::task::spawn_blocking(move || -> Result<FileDiffResp, Response> {
+        let path = q.path.trim_matches('/').to_string();
+        let (old, new) = (side(&st, from, &path)?, side(&st, to, &path)?);
+        let status = match (&old, &new) {
+            (None, None) => return Err(StatusCode::NOT_FOUND.into_response()),
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(a), Some(b)) if a == b => "unchanged",
+            _ => "modified",
+        };
+        let mut resp = FileDiffResp { from, to, path, status, binary: false, unified: String::new(), hunks: Vec::new() };
+        let texts = (
+            old.map_or(Some(String::new()), |b| as_text(b, q.normalize)),
+            new.map_or(Some(String::new()), |b| as_text(b, q.normalize)),
+        );
+        let (Some(old), Some(new)) = texts else {
+            resp.binary = true;
+            return Ok(resp);
+        };
+        let diff = TextDiff::from_lines(&old, &new);
+        resp.hunks = hunks(&diff);
+        if !resp.hunks.is_empty() {
+            resp.unified = diff
+                .unified_diff()
+                .context_radius(CONTEXT_LINES)
+                .header(&format!("a/{}", resp.path), &format!("b/{}", resp.path))
+                .to_string();
+        }
+        Ok(resp)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/revisions/restore ===========================================
+
+const WORKING_COPY: &str = "./decisions/0";
+
+#[derive(Deserialize)]
+pub(crate) struct RestoreReq {
+    rev: RevRef,
+    /// lock owner; leases held by anyone else under `0/` block the restore
+    #[serde(default)]
+    owner: Option<String>,
+    /// recorded on the safety snapshot
+    #[serde(default)]
+    author: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RestoreResp {
+    restored: u64,
+    /// the working copy as it was right before the restore
+    safety_snapshot: Manifest,
+}
+
+/// Snapshots the working copy into a new revision. Blocking.
+///
+/// Writers to the working copy take the rev lock too, so the snapshot never
+/// catches one half done.
+pub(crate) fn snapshot_working_copy(cfg: &Config, rev_lock: &StorageLock, meta: RevMeta) -> io::Result<Manifest> {
+    let _g = rev_lock.lock();
+    snapshot_locked(cfg, meta)
+}
+
+/// [`snapshot_working_copy`] for callers already holding the rev lock.
+pub(crate) fn snapshot_locked(cfg: &Config, meta: RevMeta) -> io::Result<Manifest> {
+    let rev = next_rev();
+    link_dir_all(WORKING_COPY, &format!("{STORAGE_ROOT}/{rev}"), cfg.follow_symlinks, cfg.snapshot_hardlinks)?;
+    Ok(finish_revision(cfg, rev, meta))
+}
+
+/// Drops (or restores) write permission on every directory of revision `rev`.
+/// Files keep theirs: they may be hardlinks shared with the working copy.
+pub(crate) fn set_read_only(rev: u64, read_only: bool) -> io::Result<()> {
+    for entry in WalkDir::new(format!("{STORAGE_ROOT}/{rev}")) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            let mut perms = entry.metadata()?.permissions();
+            if read_only {
+                perms.set_readonly(true);
+            } else {
+                owner_writable(&mut perms);
+            }
+            fs::set_permissions(entry.path(), perms)?;
+        }
+    }
+    Ok(())
+}
+
+/// `set_readonly(false)` would make it writable by everyone on unix.
+#[cfg(unix)]
+fn owner_writable(perms: &mut fs::Permissions) {
+    use std::os::unix::fs::PermissionsExt;
+    perms.set_mode(perms.mode() | 0o200);
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)] // there's only the one flag here
+fn owner_writable(perms: &mut fs::Permissions) {
+    perms.set_readonly(false);
+}
+
+/// Writes a private copy of revision `rev` to `dst`, file contents from the
+/// git mirror if it has the revision, the blob store otherwise. The copy never
+/// shares storage with the revision, so editing it can't reach back into
+/// history.
+pub(crate) fn check_out(cfg: &Config, rev: u64, dst: &str) -> io::Result<()> {
+    let src = format!("{STORAGE_ROOT}/{rev}");
+    let mirrored = gitmirror::mirrored(cfg, rev)?;
+    let map = objects::file_map(rev);
+    if mirrored.is_none() && map.is_none() {
+        return copy_dir_all(&src, dst, cfg.follow_symlinks);
+    }
+    // directories first, so empty ones come back too
+    for entry in WalkDir::new(&src) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(StdPath::new(dst).join(entry.path().strip_prefix(&src).unwrap()))?;
+        }
+    }
+    if let Some(m) = mirrored {
+        return m.check_out(StdPath::new(dst));
+    }
+    let Some(map) = map else { return Ok(()) };
+    for (rel, hash) in map {
+        let blob = objects::object_path(&hash);
+        let from = if blob.is_file() { blob } else { StdPath::new(&src).join(&rel) };
+        let to = StdPath::new(dst).join(&rel);
+        if let Some(p) = to.parent() {
+            fs::create_dir_all(p)?;
+        }
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Swaps in a full copy of `rev` as the working copy. The copy is built
+/// beside it first, so a failure part-way leaves the working copy untouched.
+/// Caller holds the rev lock.
+pub(crate) fn replace_working_copy(cfg: &Config, rev: u64) -> io::Result<()> {
+    let nonce = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let staging = format!("{STORAGE_ROOT}/.restore-{rev}-{nonce}");
+    let retired = format!("{STORAGE_ROOT}/.retired-{nonce}");
+    if let Err(e) = check_out(cfg, rev, &staging) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    fs::rename(WORKING_COPY, &retired)?;
+    if let Err(e) = fs::rename(&staging, WORKING_COPY) {
+        let _ = fs::rename(&retired, WORKING_COPY);
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    if let Err(e) = fs::remove_dir_all(&retired) {
+        tracing::warn!("could not remove {retired}: {e}");
+    }
+    Ok(())
+}
+
+/// Replaces the working copy with revision `rev`, after snapshotting it.
+pub(crate) async fn restore(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<RestoreReq>) -> Response {
+    body.author = auth::acting(&ident, body.author);
+    body.owner = auth::acting(&ident, body.owner);
+    let rev = match body.rev.resolve() {
+        Ok(0) => return (StatusCode::BAD_REQUEST, "revision 0 is the working copy").into_response(),
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = st.locks.check_tree("0", body.owner.as_deref()) {
+        return resp;
+    }
+    if let Err(e) = auth::acl::check("") {
+        return e.into_response();
+    }
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<Manifest, Response> {
+        let follow = state.config.follow_symlinks;
+        // net effect: one more copy of `rev` (the safety snapshot replaces nothing)
+        let (bytes, files) = quota::subtree_size(StdPath::new(&format!("{STORAGE_ROOT}/{rev}")), follow);
+        quota::check(&state.config, bytes as i64, files as i64)?;
+        let internal = |e: io::Error| {
+            tracing::error!("restore of rev {rev} failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        };
+        let meta = RevMeta { message: Some(format!("before restoring revision {rev}")), author: body.author, ..Default::default() };
+        // nothing may land in the working copy between the snapshot and the swap
+        let _g = state.rev_lock.lock();
+        let safety = snapshot_locked(&state.config, meta).map_err(internal)?;
+        replace_working_copy(&state.config, rev).map_err(internal)?;
+        Ok(safety)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(safety)) => {
+            st.index.touch(&safety.id.to_string());
+            st.index.touch("0");
+            st.engines.invalidate("0");
+            st.events.publish(ServerEvent::SnapshotCreated { id: safety.id });
+            st.events.publish(ServerEvent::RevisionRestored { id: rev, safety_snapshot: safety.id });
+            audit::record(audit::Event::new("snapshot").rev(safety.id).by(safety.author.clone())).await;
+            audit::record(audit::Event::new("restore").rev(rev).by(safety.author.clone())).await;
+            Json(RestoreResp { restored: rev, safety_snapshot: safety }).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/revisions/restore-file ======================================
+
+#[derive(Deserialize)]
+pub(crate) struct RestoreFileReq {
+    rev: RevRef,
+    /// relative to the revision root
+    path: String,
+    /// working-copy path to restore to instead of `path`; must not exist yet
+    #[serde(default, rename = "as")]
+    as_path: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+}
+
+/// Copies one file of revision `rev` into the working copy and returns its
+/// node: over the current version at the same path, or next to it with `as`.
+pub(crate) async fn restore_file(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<RestoreFileReq>) -> Response {
+    body.owner = auth::acting(&ident, body.owner);
+    let rev = match body.rev.resolve() {
+        Ok(0) => return (StatusCode::BAD_REQUEST, "revision 0 is the working copy").into_response(),
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    let src = match read_path(&st.config, &format!("{rev}/{}", body.path)) {
+        Ok(p) if p.is_file() => objects::locate(rev, &body.path).unwrap_or(p),
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let side_by_side = body.as_path.is_some();
+    let rel = format!("0/{}", body.as_path.as_deref().unwrap_or(&body.path).trim_matches('/'));
+    if let Err(resp) = st.locks.check(&rel, body.owner.as_deref()) {
+        return resp;
+    }
+    let dst = match write_path(&rel) {
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<_, Response> {
+        let _g = state.rev_lock.lock();
+        if dst.is_dir() {
+            return Err(StatusCode::CONFLICT.into_response());
+        }
+        let bytes = fs::read(&src).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        quota::check_write(&state.config, &dst, bytes.len() as u64)?;
+        let event = audit::Event::new("restore-file").path(&rel).hashes(audit::hash_file(&dst), Some(audit::hash(&bytes)));
+        write_atomic(&dst, &bytes, WriteOpts { create_new: side_by_side, ..Default::default() }).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => StatusCode::CONFLICT.into_response(),
+            _ => {
+                tracing::error!("restore-file error: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        })?;
+        Ok((build_node(&dst, &rel, state.config.follow_symlinks), event))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((Some(node), event))) => {
+            st.index.touch(&node.path);
+            audit::record(event.rev(rev)).await;
+            Json(node).into_response()
+        }
+        Ok(Ok((None, _))) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/revisions/file-history ======================================
+
+#[derive(Deserialize)]
+pub(crate) struct FileHistoryParams {
+    /// relative to the revision root
+    path: String,
+    /// only follow this branch's line of revisions; every revision by default
+    #[serde(default)]
+    branch: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileChange {
+    rev: u64,
+    change: &'static str, // "added", "modified", "removed"
+    /// SHA-256 of the file after the change, absent when removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+}
+
+/// Hash of `rel` in `rev`: from its path map, or hashed on the spot for a
+/// revision that isn't in the store.
+fn hash_at(rev: u64, rel: &str, follow: bool) -> io::Result<Option<String>> {
+    if let Some(mut map) = objects::file_map(rev) {
+        return Ok(map.remove(rel));
+    }
+    let full = PathBuf::from(format!("{STORAGE_ROOT}/{rev}/{rel}"));
+    let allowed = follow || !fs::symlink_metadata(&full).is_ok_and(|m| m.file_type().is_symlink());
+    if !allowed || !full.is_file() {
+        return Ok(None);
+    }
+    objects::hash_file(&full).map(Some)
+}
+
+/// Revisions on `branch`, oldest first: its head, then parent after parent.
+/// Revisions older than branches have no parent recorded and follow the one
+/// numbered before them.
+fn lineage(branch: &str, all: &[u64]) -> Vec<u64> {
+    let mut line = Vec::new();
+    let mut cur = branches::heads().get(branch).copied().unwrap_or(0);
+    while cur > 0 {
+        if all.binary_search(&cur).is_ok() {
+            line.push(cur);
+        }
+        cur = match read_manifest(cur).parent {
+            Some(p) if p < cur => p,
+            _ => all.iter().rev().find(|&&r| r < cur).copied().unwrap_or(0),
+        };
+    }
+    line.reverse();
+    line
+}
+
+/// `git log -- <path>` for revisions: every revision that added, changed or
+/// removed `path` compared with its parent, newest first. Compares the hashes
+/// recorded in the revisions' path maps, so no file is read twice.
+pub(crate) async fn file_history(State(st): State<AppState>, Query(q): Query<FileHistoryParams>) -> Response {
+    let rel = q.path.trim_matches('/').to_string();
+    if rel.is_empty() {
+        return (StatusCode::BAD_REQUEST, "give a file path").into_response();
+    }
+    if let Err(e) = read_path(&st.config, &format!("0/{rel}")) {
+        return e.into_response();
+    }
+    if let Some(b) = &q.branch {
+        if let Err(resp) = branches::check(b) {
+            return resp;
+        }
+    }
+    let follow = st.config.follow_symlinks;
+    let res = tokio::task::spawn_blocking(move || -> io::Result<Vec<FileChange>> {
+        let all: Vec<u64> = existing().into_iter().filter(|&r| r != 0).collect();
+        let revs = match &q.branch {
+            Some(b) => lineage(b, &all),
+            None => all.clone(),
+        };
+        let mut hashes: BTreeMap<u64, Option<String>> = BTreeMap::new();
+        let mut changes = Vec::new();
+        for (i, &rev) in revs.iter().enumerate() {
+            let manifest = read_manifest(rev);
+            let previous = i.checked_sub(1).map(|j| revs[j]);
+            // across branches, compare with the revision's own parent
+            let parent = match manifest.parent {
+                _ if q.branch.is_some() => previous,
+                Some(0) => None,
+                Some(p) if all.binary_search(&p).is_ok() => Some(p),
+                _ => previous,
+            };
+            let before = match parent {
+                Some(p) => match hashes.get(&p) {
+                    Some(h) => h.clone(),
+                    None => hash_at(p, &rel, follow)?,
+                },
+                None => None,
+            };
+            let now = hash_at(rev, &rel, follow)?;
+            let change = match (&before, &now) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(a), Some(b)) if a != b => "modified",
+                _ => {
+                    hashes.insert(rev, now);
+                    continue;
+                }
+            };
+            changes.push(FileChange {
+                rev,
+                change,
+                hash: now.clone(),
+                message: manifest.message,
+                author: manifest.author,
+                created_at: manifest.created_at,
+                branch: manifest.branch,
+            });
+            hashes.insert(rev, now);
+        }
+        changes.reverse();
+        Ok(changes)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(changes)) => Json(changes).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("file history error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== DELETE /api/revisions/:id, POST /api/revisions/prune ==================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PruneResp {
+    pub dry_run: bool,
+    /// ascending
+    pub removed: Vec<u64>,
+    pub reclaimed_bytes: u64,
+}
+
+/// Deletes `revs` (or only measures them, for a dry run). The working copy,
+/// HEAD and branch heads are refused here, whatever the caller picked. Each
+/// removal is audited as `action` by `by`. Blocking; the caller holds the rev
+/// lock.
+pub(crate) fn remove_revisions(st: &AppState, revs: &[u64], dry_run: bool, action: &'static str, by: Option<String>) -> Result<PruneResp, Response> {
+    let head = read_head();
+    if let Some(&rev) = revs.iter().find(|&&r| r == 0 || r == head) {
+        let why = if rev == 0 { "revision 0 is the working copy" } else { "can't delete HEAD" };
+        return Err((StatusCode::CONFLICT, why).into_response());
+    }
+    if let Some((branch, rev)) = branches::heads().into_iter().find(|(_, h)| revs.contains(h)) {
+        return Err((StatusCode::CONFLICT, format!("revision {rev} is the head of branch {branch}")).into_response());
+    }
+    // blobs shared with surviving revisions stay, so only count the rest
+    let mut resp = PruneResp { dry_run, removed: Vec::new(), reclaimed_bytes: objects::reclaimable(revs) };
+    for &rev in revs {
+        let dir = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+        if !dry_run {
+            let event = audit::Event::new(action).removed(rev).by(by.clone());
+            // a read-only revision's directories can't be emptied otherwise
+            let _ = set_read_only(rev, false);
+            fs::remove_dir_all(&dir).map_err(|e| {
+                tracing::error!("could not delete revision {rev}: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            })?;
+            let _ = fs::remove_dir_all(PathBuf::from(META_DIR).join(rev.to_string()));
+            tracing::info!("deleted revision {rev}");
+            audit::append(event);
+        }
+        resp.removed.push(rev);
+    }
+    if !dry_run && !revs.is_empty() {
+        if let Err(e) = objects::sweep() {
+            tracing::warn!("could not sweep unused blobs: {e}");
+        }
+        for &rev in revs {
+            st.index.touch(&rev.to_string());
+            st.engines.invalidate(&rev.to_string());
+            st.events.publish(ServerEvent::RevisionDeleted { id: rev });
+        }
+    }
+    Ok(resp)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeleteParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Tagged revisions are refused; untag them first.
+pub(crate) async fn delete(State(st): State<AppState>, Path(id): Path<String>, Query(q): Query<DeleteParams>) -> Response {
+    let by = auth::caller().map(|c| c.name);
+    let res = tokio::task::spawn_blocking(move || {
+        let _g = st.rev_lock.lock();
+        let rev = RevRef::Name(id).resolve()?;
+        if let Some(tag) = tags().into_iter().find_map(|(name, r)| (r == rev).then_some(name)) {
+            return Err((StatusCode::CONFLICT, format!("revision {rev} is tagged {tag:?}")).into_response());
+        }
+        remove_revisions(&st, &[rev], q.dry_run, "revision-delete", by)
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PruneReq {
+    /// keep this many of the newest revisions (HEAD among them)
+    keep_last: Option<usize>,
+    /// only prune revisions created before this (unix seconds)
+    older_than: Option<i64>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// When a revision was made: its manifest, or the directory's mtime for
+/// revisions older than manifests.
+pub(crate) fn created_at(rev: u64) -> Option<i64> {
+    read_manifest(rev).created_at.or_else(|| {
+        let modified = fs::metadata(format!("{STORAGE_ROOT}/{rev}")).ok()?.modified().ok()?;
+        Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64)
+    })
+}
+
+/// Deletes every revision that is neither among the `keepLast` newest nor
+/// younger than `olderThan`; HEAD, the working copy and pinned revisions
+/// always stay.
+pub(crate) async fn prune(State(st): State<AppState>, Json(body): Json<PruneReq>) -> Response {
+    if body.keep_last.is_none() && body.older_than.is_none() {
+        return (StatusCode::BAD_REQUEST, "give keepLast and/or olderThan").into_response();
+    }
+    let by = auth::caller().map(|c| c.name);
+    let res = tokio::task::spawn_blocking(move || {
+        let _g = st.rev_lock.lock();
+        let head = read_head();
+        let mut revs: Vec<u64> = existing().into_iter().filter(|&r| r != 0 && r != head).collect();
+        let pinned = pinned();
+        // HEAD counts towards keepLast
+        let keep = body.keep_last.map_or(0, |k| k.saturating_sub(1));
+        revs.truncate(revs.len().saturating_sub(keep));
+        if let Some(cutoff) = body.older_than {
+            revs.retain(|&r| created_at(r).is_some_and(|t| t < cutoff));
+        }
+        revs.retain(|r| !pinned.contains(r));
+        remove_revisions(&st, &revs, body.dry_run, "prune", by)
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== tags ==================================================================
+
+const TAGS_FILE: &str = "./decisions/.revisions/tags.json";
+
+/// A revision as clients name it: its number, or a tag (`prod`, `v1.2.0`).
+/// Every endpoint that takes a revision accepts either.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub(crate) enum RevRef {
+    Id(u64),
+    Name(String),
+}
+
+impl RevRef {
+    /// The revision number; 404 unless that revision exists.
+    pub(crate) fn resolve(&self) -> Result<u64, Response> {
+        let rev = match self {
+            Self::Id(rev) => *rev,
+            // query strings and path segments always arrive as strings
+            Self::Name(s) => match s.trim().parse() {
+                Ok(rev) => rev,
+                Err(_) => tags()
+                    .get(s.trim())
+                    .copied()
+                    .or_else(|| branches::heads().get(s.trim()).copied())
+                    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no such revision or tag: {s}")).into_response())?,
+            },
+        };
+        check_rev(rev)?;
+        Ok(rev)
+    }
+}
+
+/// tag → revision
+pub(crate) fn tags() -> BTreeMap<String, u64> {
+    fs::read(TAGS_FILE)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Revisions that carry a tag or are a branch's head, which pruning and GC
+/// never pick.
+pub(crate) fn pinned() -> BTreeSet<u64> {
+    tags().into_values().chain(branches::heads().into_values()).collect()
+}
+
+/// Caller holds the rev lock.
+fn store_tags(tags: &BTreeMap<String, u64>) -> Result<(), Response> {
+    let bytes = serde_json::to_vec_pretty(tags).expect("serialize tags");
+    write_atomic(StdPath::new(TAGS_FILE), &bytes, WriteOpts::default()).map_err(|e| {
+        tracing::error!("tags write error: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+/// Letters, digits, `.`, `_`, `-`; not all digits, so it can't shadow a number.
+pub(crate) fn valid_tag(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        && !name.chars().all(|c| c.is_ascii_digit())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TagReq {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TagResp {
+    name: String,
+    rev: u64,
+    /// where the tag pointed before, if it moved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<u64>,
+}
+
+/// `POST /api/revisions/:id/tag`: points `name` at the revision, moving it if
+/// it already names another one.
+pub(crate) async fn tag(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    ident: Option<Extension<auth::Identity>>,
+    Json(body): Json<TagReq>,
+) -> Response {
+    let name = body.name.trim().to_string();
+    if !valid_tag(&name) {
+        return (StatusCode::BAD_REQUEST, "tag must be [A-Za-z0-9._-]+ and not a number").into_response();
+    }
+    let res = tokio::task::spawn_blocking(move || {
+        let _g = st.rev_lock.lock();
+        let rev = RevRef::Name(id).resolve()?;
+        if rev == 0 {
+            return Err((StatusCode::BAD_REQUEST, "the working copy can't be tagged").into_response());
+        }
+        if branches::heads().contains_key(&name) {
+            return Err((StatusCode::CONFLICT, format!("{name:?} is a branch")).into_response());
+        }
+        let mut all = tags();
+        let previous = all.insert(name.clone(), rev).filter(|&p| p != rev);
+        store_tags(&all)?;
+        gitmirror::update_mirror(&st.config);
+        Ok(TagResp { name, rev, previous })
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => {
+            audit::record(audit::Event::new("tag").path(&resp.name).rev(resp.rev).by(auth::acting(&ident, None))).await;
+            Json(resp).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// `GET /api/revisions/tags`
+pub(crate) async fn list_tags() -> impl IntoResponse {
+    Json(tags())
+}
+
+/// `DELETE /api/revisions/tags/:name`
+pub(crate) async fn untag(State(st): State<AppState>, Path(name): Path<String>, ident: Option<Extension<auth::Identity>>) -> Response {
+    let tag = name.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        let _g = st.rev_lock.lock();
+        let mut all = tags();
+        let Some(rev) = all.remove(&name) else {
+            return Err((StatusCode::NOT_FOUND, "no such tag").into_response());
+        };
+        store_tags(&all)?;
+        gitmirror::update_mirror(&st.config);
+        Ok(rev)
+    })
+    .await;
+    match res {
+        Ok(Ok(rev)) => {
+            audit::record(audit::Event::new("tag-delete").path(&tag).rev(rev).by(auth::acting(&ident, None))).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/revisions/:id/archive ========================================
+
+/// Name of the manifest inside a revision archive; dot-prefixed like every
+/// other sidecar, so it can't collide with a user file.
+pub(crate) const ARCHIVE_MANIFEST: &str = ".manifest.json";
+
+/// Streams revision `id` as a zip, its manifest first.
+pub(crate) async fn archive(Path(id): Path<String>) -> Response {
+    let rev = match RevRef::Name(id).resolve() {
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    let root = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+    let body = archive::stream_body(move |w| {
+        let manifest = serde_json::to_vec_pretty(&read_manifest(rev)).expect("serialize manifest");
+        archive::write_zip(&root, w, &[(ARCHIVE_MANIFEST, &manifest)])
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+    if let Ok(v) = HeaderValue::from_str(&format!("attachment; filename=\"revision-{rev}.zip\"")) {
+        headers.insert(header::CONTENT_DISPOSITION, v);
+    }
+    (headers, body).into_response()
+}
+
+// ===== GET /api/revisions/:id/tree ===========================================
+
+#[derive(Deserialize, Default)]
+pub(crate) struct TreeParams {
+    /// directory inside the revision to list; the revision root by default
+    #[serde(default)]
+    path: String,
+    /// levels to descend, at least 1; unlimited by default
+    depth: Option<usize>,
+}
+
+/// The revision's files as `fs_list` nodes. Paths are relative to the revision
+/// root, as `/api/revisions/file` and the diff endpoints take them.
+pub(crate) async fn tree(State(st): State<AppState>, Path(id): Path<String>, Query(q): Query<TreeParams>) -> Response {
+    let rev = match RevRef::Name(id).resolve() {
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    let rel = q.path.trim_matches('/').to_string();
+    let root = match read_path(&st.config, &format!("{rev}/{rel}")) {
+        Ok(p) if p.is_dir() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let follow = st.config.follow_symlinks;
+    let depth = q.depth.map(|d| d.max(1) - 1);
+    match tokio::task::spawn_blocking(move || build_tree_to(&root, &rel, follow, depth)).await {
+        Ok(nodes) => Json(nodes).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/revisions/:id/verify ========================================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyResp {
+    rev: u64,
+    /// nothing missing, corrupted or unexpected
+    ok: bool,
+    checked: usize,
+    /// recorded but gone from the revision
+    missing: Vec<String>,
+    /// the file, or the blob it's read from, no longer hashes to what was recorded
+    corrupted: Vec<String>,
+    /// in the revision but never recorded
+    unexpected: Vec<String>,
+}
+
+/// Re-hashes every file of the revision against its path map (see
+/// [`objects`]). 409 for revisions that have none, which can't be verified.
+pub(crate) async fn verify(Path(id): Path<String>) -> Response {
+    let rev = match RevRef::Name(id).resolve() {
+        Ok(0) => return (StatusCode::BAD_REQUEST, "the working copy has no checksums").into_response(),
+        Ok(rev) => rev,
+        Err(resp) => return resp,
+    };
+    let res = tokio::task::spawn_blocking(move || -> Result<VerifyResp, Response> {
+        let recorded = objects::file_map(rev)
+            .ok_or_else(|| (StatusCode::CONFLICT, format!("revision {rev} has no recorded checksums")).into_response())?;
+        let mut on_disk = rev_files(rev, false);
+        let mut resp = VerifyResp {
+            rev,
+            ok: false,
+            checked: recorded.len(),
+            missing: Vec::new(),
+            corrupted: Vec::new(),
+            unexpected: Vec::new(),
+        };
+        for (rel, hash) in recorded {
+            let Some(file) = on_disk.remove(&rel) else {
+                resp.missing.push(rel);
+                continue;
+            };
+            let blob = objects::object_path(&hash);
+            let intact = |p: &StdPath| objects::hash_file(p).is_ok_and(|h| h == hash);
+            // a hardlinked revision file *is* its blob; hash it once
+            let blob_ok = !blob.is_file() || objects::same_inode(&file, &blob) || intact(&blob);
+            if !intact(&file) || !blob_ok {
+                resp.corrupted.push(rel);
+            }
+        }
+        resp.unexpected = on_disk.into_keys().collect();
+        resp.ok = resp.missing.is_empty() && resp.corrupted.is_empty() && resp.unexpected.is_empty();
+        if !resp.ok {
+            tracing::warn!(
+                "revision {rev} failed verification: {} missing, {} corrupted, {} unexpected",
+                resp.missing.len(),
+                resp.corrupted.len(),
+                resp.unexpected.len()
+            );
+        }
+        Ok(resp)
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,718 @@
+//! Workspace search (`/api/fs/search`, `/api/fs/search/stream`) and JDM field
+//! references (`/api/search/jdm`).
+//!
+//! Every file under the scope is matched by path and by content, and reported
+//! once with all of its content matches. Pages are ranked by `score`; the
+//! stream reports hits in path order as it finds them. Matching is
+//! case-insensitive unless `caseSensitive=true`, in every mode. Substring
+//! queries only open the files the full-text index names as candidates.
+//! `mode=fuzzy` is quick-open: paths only, ranked by subsequence score.
+//! Named queries can be saved and re-run, see [`saved`].
+
+use axum::{
+    extract::{Json, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::{Path as StdPath, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use walkdir::WalkDir;
+
+mod fuzzy;
+pub(crate) mod saved;
+
+use crate::{archive, index::{Candidates, SearchIndex}, jdm, looks_binary, read_path, replace::REGEX_SIZE_LIMIT, revisions::RevRef, walk_allowed, AppState, STORAGE_ROOT};
+
+pub(crate) const MAX_FILE_BYTES: u64 = 1_000_000;
+const DEFAULT_CONTEXT: usize = 40;
+const MAX_CONTEXT: usize = 200;
+const DEFAULT_PER_FILE: usize = 20;
+const MAX_PER_FILE: usize = 100;
+const SNIFF_BYTES: u64 = 8192;
+/// never worth opening for a content search
+const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "avi", "bin", "bmp", "class", "dll", "docx", "dylib", "eot", "exe", "gif", "gz", "ico", "jar", "jpeg",
+    "jpg", "mov", "mp3", "mp4", "ods", "otf", "pdf", "png", "pptx", "rar", "so", "tar", "tgz", "ttf", "wasm",
+    "wav", "webp", "woff", "woff2", "xls", "xlsx", "xz", "zip",
+];
+/// how often a scan waiting on its workers checks the clock and the client
+const POLL: Duration = Duration::from_millis(100);
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SearchMode {
+    #[default]
+    Substring,
+    Regex,
+    /// names only, ranked by [`fuzzy::fuzzy_match`]; not available streamed
+    Fuzzy,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    path: String,
+    /// search inside `decisions/<rev>` (a number or a tag); `path` is then
+    /// relative to it
+    rev: Option<RevRef>,
+    #[serde(default)]
+    mode: SearchMode,
+    #[serde(default)]
+    case_sensitive: bool,
+    /// only match where `q` is not flanked by word characters
+    #[serde(default)]
+    whole_word: bool,
+    /// characters of context on each side of a content match
+    context: Option<usize>,
+    /// content matches reported per file
+    max_per_file: Option<usize>,
+    /// hits per page
+    limit: Option<usize>,
+    /// hits to skip, i.e. the previous page's `nextOffset`
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SearchHit {
+    /// relative to the revision when the search was scoped with `?rev=`
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    matched: &'static str, // "name", "content"
+    /// content matches in the file, including any past `maxPerFile`
+    #[serde(skip_serializing_if = "is_zero")]
+    count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    matches: Vec<ContentMatch>,
+    /// higher is better; see [`relevance`] (or [`fuzzy::fuzzy_match`] in fuzzy mode)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i64>,
+    /// fuzzy mode: matched character indices into `path`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    positions: Vec<usize>,
+}
+
+impl SearchHit {
+    fn new(path: String, rev: Option<u64>, matched: &'static str, matches: Vec<ContentMatch>) -> Self {
+        Self { path, rev, matched, count: matches.len(), matches, score: None, positions: Vec::new() }
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+const NAME_WEIGHT: i64 = 1000;
+const MATCH_WEIGHT: i64 = 10;
+/// counted matches stop adding to the score here, so a name match always wins
+const MAX_SCORED_MATCHES: usize = 50;
+const DEPTH_PENALTY: i64 = 5;
+
+/// A name match outranks any number of content matches; more content matches
+/// rank higher; shallower paths break the tie.
+fn relevance(name: bool, count: usize, path: &str) -> i64 {
+    let depth = path.matches('/').count() as i64;
+    (if name { NAME_WEIGHT } else { 0 }) + MATCH_WEIGHT * count.min(MAX_SCORED_MATCHES) as i64 - DEPTH_PENALTY * depth
+}
+
+#[derive(Serialize)]
+struct ContentMatch {
+    /// 1-based
+    line: usize,
+    /// 1-based, in characters
+    column: usize,
+    snippet: String,
+}
+
+/// Substring mode is an escaped regex, so both modes share match offsets,
+/// flags and the automaton size limit.
+fn matcher(p: &SearchParams) -> Result<Regex, regex::Error> {
+    let pattern = match p.mode {
+        SearchMode::Substring => regex::escape(&p.q),
+        SearchMode::Regex => p.q.clone(),
+        SearchMode::Fuzzy => unreachable!("fuzzy queries don't compile to a regex"),
+    };
+    // half boundaries only look outside the match, so `.json` or `-x` work as
+    // whole words too (a plain `\b` would demand a word character next to `.`)
+    let pattern = if p.whole_word { format!(r"\b{{start-half}}(?:{pattern})\b{{end-half}}") } else { pattern };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!p.case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Line/column/snippet for the first `cap` matches in `text`.
+fn content_matches(re: &Regex, text: &str, context: usize, cap: usize) -> Vec<ContentMatch> {
+    let (mut line, mut line_start, mut scanned) = (1, 0, 0);
+    re.find_iter(text)
+        .take(cap)
+        .map(|m| {
+            for (i, _) in text[scanned..m.start()].match_indices('\n') {
+                line += 1;
+                line_start = scanned + i + 1;
+            }
+            scanned = m.start();
+            let line_end = text[m.start()..].find('\n').map_or(text.len(), |i| m.start() + i);
+            let before = &text[line_start..m.start()];
+            let after = &text[m.end().min(line_end)..line_end];
+            let lead: String = {
+                let skip = before.chars().count().saturating_sub(context);
+                before.chars().skip(skip).collect()
+            };
+            let tail: String = after.chars().take(context).collect();
+            ContentMatch {
+                line,
+                column: before.chars().count() + 1,
+                snippet: format!("{lead}{}{tail}", &text[m.start()..m.end().min(line_end)]).trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Walk-wide settings that don't depend on the pattern.
+struct ScanOpts {
+    follow: bool,
+    context: usize,
+    per_file: usize,
+    rev: Option<u64>,
+    /// workers reading files in parallel
+    threads: usize,
+    index: SearchIndex,
+    /// substring to narrow content reads with, when the index can answer it
+    index_query: Option<String>,
+    /// give up (keeping what was found) at this point
+    deadline: Instant,
+    /// `true` once nobody is waiting for the answer any more
+    cancelled: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+/// Flags a search as cancelled when the handler's future is dropped, which is
+/// what happens to it when the client disconnects.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    fn new(opts: &mut ScanOpts) -> Self {
+        let flag = Arc::new(AtomicBool::new(false));
+        let seen = flag.clone();
+        opts.cancelled = Box::new(move || seen.load(Ordering::Relaxed));
+        Self(flag)
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn has_binary_extension(p: &StdPath) -> bool {
+    p.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| BINARY_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Text content of a file, or `None` if it turns out to be binary: a NUL in
+/// the first 8 KiB is checked before reading the rest, invalid UTF-8 after.
+fn read_text(path: &StdPath) -> std::io::Result<Option<String>> {
+    let mut f = std::fs::File::open(path)?;
+    let mut bytes = Vec::new();
+    (&mut f).take(SNIFF_BYTES).read_to_end(&mut bytes)?;
+    if looks_binary(&bytes) {
+        return Ok(None);
+    }
+    f.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8(bytes).ok())
+}
+
+#[derive(Default)]
+struct ScanStats {
+    /// files whose name didn't match and whose content is binary
+    skipped_binary: usize,
+    /// the deadline cut the walk short
+    timed_out: bool,
+}
+
+/// Outcome of looking at one file.
+enum Examined {
+    Hit(SearchHit),
+    Binary,
+    Nothing,
+}
+
+fn examine(entry: &walkdir::DirEntry, re: &Regex, o: &ScanOpts, candidates: Option<&Candidates>) -> Examined {
+    // names are matched against what the client sees
+    let (rel, shown) = display_paths(entry.path(), o.rev);
+    let name = re.is_match(&shown);
+
+    let (matches, count) = match content_of(entry.path(), &rel, candidates) {
+        Content::Text(text) => {
+            let matches = content_matches(re, &text, o.context, o.per_file);
+            let count = if matches.len() < o.per_file { matches.len() } else { re.find_iter(&text).count() };
+            (matches, count)
+        }
+        // binary files are only worth reporting if nothing else was found in them
+        Content::Binary if !name => return Examined::Binary,
+        Content::Binary | Content::Skipped => (Vec::new(), 0),
+    };
+    if !name && count == 0 {
+        return Examined::Nothing;
+    }
+    let mut hit = SearchHit::new(shown, o.rev, if name { "name" } else { "content" }, matches);
+    hit.count = count;
+    hit.score = Some(relevance(name, count, &hit.path));
+    Examined::Hit(hit)
+}
+
+/// Feeds every hit under `root` to `emit`, in path order, until it returns
+/// `false`, the deadline passes or the client goes away.
+///
+/// One thread walks, `o.threads` workers read and match, and this thread puts
+/// their results back in walk order. Both channels are bounded, so a slow
+/// consumer (a streaming client) holds the whole pipeline back instead of
+/// buffering the tree; once `emit` says stop, the walker and workers wind down.
+fn scan(root: &StdPath, re: &Regex, o: &ScanOpts, mut emit: impl FnMut(SearchHit) -> bool) -> ScanStats {
+    let mut stats = ScanStats::default();
+    let candidates = o.index_query.as_deref().and_then(|q| o.index.candidates(q));
+    let threads = o.threads.max(1);
+    let stop = &AtomicBool::new(false);
+
+    thread::scope(|s| {
+        let (job_tx, job_rx) = mpsc::sync_channel::<(usize, walkdir::DirEntry)>(threads * 4);
+        let (out_tx, out_rx) = mpsc::sync_channel::<(usize, Examined)>(threads * 4);
+        // dropped with the last worker, which unblocks a walker stuck on a full queue
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        s.spawn(move || {
+            for job in files(root, o.follow).enumerate() {
+                if stop.load(Ordering::Relaxed) || job_tx.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        for _ in 0..threads {
+            let (job_rx, out_tx) = (job_rx.clone(), out_tx.clone());
+            let candidates = candidates.as_ref();
+            s.spawn(move || loop {
+                let Ok((seq, entry)) = job_rx.lock().unwrap().recv() else { break };
+                if stop.load(Ordering::Relaxed) || out_tx.send((seq, examine(&entry, re, o, candidates))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop((job_rx, out_tx));
+
+        let (mut pending, mut next) = (BTreeMap::new(), 0);
+        'results: loop {
+            if (o.cancelled)() {
+                break;
+            }
+            let Some(left) = o.deadline.checked_duration_since(Instant::now()) else {
+                stats.timed_out = true;
+                break;
+            };
+            let (seq, examined) = match out_rx.recv_timeout(left.min(POLL)) {
+                Ok(v) => v,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            pending.insert(seq, examined);
+            while let Some(examined) = pending.remove(&next) {
+                next += 1;
+                match examined {
+                    Examined::Hit(hit) => {
+                        if !emit(hit) {
+                            break 'results;
+                        }
+                    }
+                    Examined::Binary => stats.skipped_binary += 1,
+                    Examined::Nothing => {}
+                }
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+    });
+    stats
+}
+
+enum Content {
+    Text(String),
+    Binary,
+    /// too big, unreadable, or ruled out by the index
+    Skipped,
+}
+
+fn content_of(path: &StdPath, rel: &str, candidates: Option<&Candidates>) -> Content {
+    if has_binary_extension(path) {
+        return Content::Binary;
+    }
+    let meta = std::fs::metadata(path).ok();
+    if meta.as_ref().map_or(0, |m| m.len()) > MAX_FILE_BYTES
+        || candidates.is_some_and(|c| !c.may_contain(rel, meta.and_then(|m| m.modified().ok())))
+    {
+        return Content::Skipped;
+    }
+    match read_text(path) {
+        Ok(Some(text)) => Content::Text(text),
+        Ok(None) => Content::Binary,
+        Err(_) => Content::Skipped,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResp {
+    hits: Vec<SearchHit>,
+    /// more hits exist past this page
+    truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+    /// binary files whose content wasn't searched (as far as the walk got)
+    skipped_binary: usize,
+    /// the search ran out of time; `hits` is what it found until then
+    timed_out: bool,
+}
+
+/// `path`, inside revision `rev` if given, and that revision's number; 404
+/// unless both exist.
+fn scope_root(st: &AppState, path: &str, rev: Option<&RevRef>) -> Result<(PathBuf, Option<u64>), Response> {
+    let rev = rev.map(RevRef::resolve).transpose()?;
+    let scoped = match rev {
+        Some(rev) => format!("{rev}/{path}"),
+        None => path.to_string(),
+    };
+    match read_path(&st.config, &scoped) {
+        Ok(p) if p.exists() => Ok((p, rev)),
+        _ => Err(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// Walks `root` like [`scan`] does (sorted, no sidecars), yielding files only.
+pub(crate) fn files(root: &StdPath, follow: bool) -> impl Iterator<Item = walkdir::DirEntry> {
+    WalkDir::new(root)
+        .follow_links(follow)
+        .sort_by_file_name()
+        .into_iter()
+        // dot-prefixed sidecars (the index itself, templates, …) aren't content
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .flatten()
+        .filter(move |e| e.file_type().is_file() && walk_allowed(e, follow))
+}
+
+/// Storage-relative path of `p`, and the same with the `rev/` prefix cut off
+/// when the search is revision-scoped (that's what clients see).
+fn display_paths(p: &StdPath, rev: Option<u64>) -> (String, String) {
+    let rel = p.strip_prefix(STORAGE_ROOT).unwrap_or(p).to_string_lossy().replace('\\', "/");
+    let shown = match rev {
+        Some(rev) => rel.strip_prefix(&format!("{rev}/")).unwrap_or(&rel).to_string(),
+        None => rel.clone(),
+    };
+    (rel, shown)
+}
+
+/// Validates the query and resolves everything a scan needs.
+fn prepare(st: &AppState, p: &SearchParams) -> Result<(PathBuf, Regex, ScanOpts), Response> {
+    if p.q.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "missing ?q=").into_response());
+    }
+    if matches!(p.mode, SearchMode::Fuzzy) {
+        return Err((StatusCode::BAD_REQUEST, "mode=fuzzy is only available on /api/fs/search").into_response());
+    }
+    let re = matcher(p).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+    let (root, rev) = scope_root(st, &p.path, p.rev.as_ref())?;
+    let opts = ScanOpts {
+        follow: st.config.follow_symlinks,
+        context: p.context.unwrap_or(DEFAULT_CONTEXT).min(MAX_CONTEXT),
+        per_file: p.max_per_file.unwrap_or(DEFAULT_PER_FILE).clamp(1, MAX_PER_FILE),
+        rev,
+        threads: st.config.search_threads,
+        index: st.index.clone(),
+        index_query: matches!(p.mode, SearchMode::Substring).then(|| p.q.clone()),
+        deadline: Instant::now() + st.config.search_timeout,
+        cancelled: Box::new(|| false),
+    };
+    Ok((root, re, opts))
+}
+
+/// Returns one page of hits, best `score` first (ties in path order). Ranking
+/// needs every hit, so the whole scope is walked; use the stream for early
+/// results. A search that runs out of time ranks what it found so far.
+pub(crate) async fn fs_search(State(st): State<AppState>, Query(p): Query<SearchParams>) -> Response {
+    if matches!(p.mode, SearchMode::Fuzzy) {
+        return fuzzy_search(st, p).await;
+    }
+    let (root, matcher, mut opts) = match prepare(&st, &p) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let _cancel = CancelOnDrop::new(&mut opts);
+    let offset = p.offset.unwrap_or(0);
+    let limit = p.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    // Do the heavy IO in a blocking thread
+    let res = tokio::task::spawn_blocking(move || {
+        let mut ranked = Vec::new();
+        let stats = scan(&root, &matcher, &opts, |hit| {
+            ranked.push(hit);
+            true
+        });
+        // stable: equal scores keep the walk's path order
+        ranked.sort_by_key(|h| std::cmp::Reverse(h.score));
+        let truncated = ranked.len() > offset.saturating_add(limit);
+        let hits: Vec<SearchHit> = ranked.into_iter().skip(offset).take(limit).collect();
+        SearchResp {
+            next_offset: truncated.then_some(offset + hits.len()),
+            hits,
+            truncated,
+            skipped_binary: stats.skipped_binary,
+            timed_out: stats.timed_out,
+        }
+    })
+    .await;
+
+    match res {
+        Ok(resp) => Json(resp).into_response(),
+        Err(e) => {
+            tracing::error!("search task panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Ranks every path under the scope (the whole walk is needed to know the top
+/// N), best first; ties go to the shorter path, then path order.
+async fn fuzzy_search(st: AppState, p: SearchParams) -> Response {
+    if p.q.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "missing ?q=").into_response();
+    }
+    let (root, rev) = match scope_root(&st, &p.path, p.rev.as_ref()) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let follow = st.config.follow_symlinks;
+    let offset = p.offset.unwrap_or(0);
+    let limit = p.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let deadline = Instant::now() + st.config.search_timeout;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let _cancel = CancelOnDrop(cancelled.clone());
+
+    let res = tokio::task::spawn_blocking(move || {
+        let mut timed_out = false;
+        let mut ranked: Vec<SearchHit> = files(&root, follow)
+            .take_while(|_| {
+                timed_out = Instant::now() >= deadline;
+                !timed_out && !cancelled.load(Ordering::Relaxed)
+            })
+            .filter_map(|entry| {
+                let (_, shown) = display_paths(entry.path(), rev);
+                let m = fuzzy::fuzzy_match(&p.q, &shown, p.case_sensitive)?;
+                let mut hit = SearchHit::new(shown, rev, "name", Vec::new());
+                hit.score = Some(m.score);
+                hit.positions = m.positions;
+                Some(hit)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.score.cmp(&a.score).then(a.path.len().cmp(&b.path.len())).then(a.path.cmp(&b.path)));
+        let truncated = ranked.len() > offset.saturating_add(limit);
+        let hits: Vec<SearchHit> = ranked.into_iter().skip(offset).take(limit).collect();
+        SearchResp { next_offset: truncated.then_some(offset + hits.len()), hits, truncated, skipped_binary: 0, timed_out }
+    })
+    .await;
+
+    match res {
+        Ok(resp) => Json(resp).into_response(),
+        Err(e) => {
+            tracing::error!("fuzzy search task panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// ===== GET /api/fs/search/stream =============================================
+
+/// Same query as `fs_search`, answered as NDJSON: one hit per line as soon as
+/// it is found, then a `{"done":true,...}` trailer. `limit` (uncapped here) and
+/// `offset` work as for pages. The walk stops when the client disconnects, or
+/// at the timeout with `"timedOut":true` in the trailer.
+pub(crate) async fn fs_search_stream(State(st): State<AppState>, Query(p): Query<SearchParams>) -> Response {
+    let (root, matcher, mut opts) = match prepare(&st, &p) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let offset = p.offset.unwrap_or(0);
+    let limit = p.limit.unwrap_or(usize::MAX);
+
+    let body = archive::stream_body(move |w| {
+        opts.cancelled = Box::new(w.disconnected());
+        let (mut seen, mut sent, mut truncated, mut failed) = (0, 0, false, None);
+        let stats = scan(&root, &matcher, &opts, |hit| {
+            seen += 1;
+            if seen <= offset {
+                return true;
+            }
+            if sent == limit {
+                truncated = true;
+                return false;
+            }
+            // flush per hit: the point is to show results while the walk runs
+            match write_line(w, &hit) {
+                Ok(()) => {
+                    sent += 1;
+                    true
+                }
+                Err(e) => {
+                    failed = Some(e);
+                    false
+                }
+            }
+        });
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        write_line(w, &serde_json::json!({
+            "done": true,
+            "count": sent,
+            "truncated": truncated,
+            "skippedBinary": stats.skipped_binary,
+            "timedOut": stats.timed_out,
+        }))
+    });
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
+
+fn write_line(w: &mut impl Write, value: &impl Serialize) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *w, value)?;
+    w.write_all(b"\n")?;
+    w.flush()
+}
+
+// ===== POST /api/search/jdm ==================================================
+
+#[derive(Deserialize)]
+pub(crate) struct JdmSearchReq {
+    /// dotted field path, e.g. `customer.age`
+    field: String,
+    #[serde(default)]
+    path: String,
+    rev: Option<RevRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldRef {
+    node_id: String,
+    node_name: String,
+    node_type: String,
+    /// location inside the node's `content`
+    location: String,
+    kind: jdm::SiteKind,
+    access: &'static str, // "read", "write"
+    text: String,
+}
+
+#[derive(Serialize)]
+struct FileRefs {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    references: Vec<FieldRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JdmSearchResp {
+    field: String,
+    files: Vec<FileRefs>,
+    /// decision files parsed
+    decisions_scanned: usize,
+}
+
+const MAX_REF_TEXT: usize = 200;
+
+/// Where a field path (or a property under it) is read or written in the
+/// decision graphs under `path`: table columns and cells, expression keys and
+/// values, switch conditions, function sources and transform settings.
+pub(crate) async fn jdm_search(State(st): State<AppState>, Json(body): Json<JdmSearchReq>) -> Response {
+    let field = body.field.trim().to_string();
+    let valid = !field.is_empty()
+        && field.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$'));
+    if !valid {
+        return (StatusCode::BAD_REQUEST, "field must be a dotted path like customer.age").into_response();
+    }
+    let (root, rev) = match scope_root(&st, &body.path, body.rev.as_ref()) {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    // inside an expression: not part of a longer path (`x.customer.age`) but
+    // `$root.` / `input.` (function nodes) in front and sub-properties behind are fine
+    let in_expr = Regex::new(&format!(r"(?:^|[^\w$.]|\$root\.|\binput\.){}(?:$|[^\w])", regex::escape(&field)))
+        .expect("escaped field path is a valid regex");
+    let follow = st.config.follow_symlinks;
+
+    let res = tokio::task::spawn_blocking(move || {
+        let mut out = JdmSearchResp { field: field.clone(), files: Vec::new(), decisions_scanned: 0 };
+        for entry in files(&root, follow) {
+            let is_json = entry.path().extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+            if !is_json || entry.metadata().map_or(0, |m| m.len()) > MAX_FILE_BYTES {
+                continue;
+            }
+            let Some(doc) = std::fs::read(entry.path()).ok().and_then(|b| serde_json::from_slice::<Value>(&b).ok())
+            else {
+                continue;
+            };
+            if !doc["nodes"].is_array() {
+                continue;
+            }
+            out.decisions_scanned += 1;
+
+            let references: Vec<FieldRef> = jdm::sites(&doc)
+                .into_iter()
+                .filter(|s| {
+                    if s.kind.is_field_path() {
+                        let t = s.text.trim();
+                        t == field || t.starts_with(&format!("{field}.")) || t.starts_with(&format!("{field}["))
+                    } else {
+                        in_expr.is_match(s.text)
+                    }
+                })
+                .map(|s| FieldRef {
+                    node_id: s.node["id"].as_str().unwrap_or_default().to_string(),
+                    node_name: s.node["name"].as_str().unwrap_or_default().to_string(),
+                    node_type: jdm::node_type(s.node).to_string(),
+                    location: s.location,
+                    kind: s.kind,
+                    access: if s.kind.writes() { "write" } else { "read" },
+                    text: s.text.chars().take(MAX_REF_TEXT).collect(),
+                })
+                .collect();
+            if !references.is_empty() {
+                let (_, path) = display_paths(entry.path(), rev);
+                out.files.push(FileRefs { path, rev, references });
+            }
+        }
+        out
+    })
+    .await;
+
+    match res {
+        Ok(resp) => Json(resp).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,86 @@
+//! fzf-style subsequence scoring for quick-open (`?mode=fuzzy`).
+//!
+//! Every query character must appear in the path, in order. Among all ways
+//! to place them, the best-scoring one wins: matches right after a separator
+//! or camelCase hump, runs of consecutive characters and matches inside the
+//! file name score higher; skipped characters between matches cost a little.
+
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 12;
+const BOUNDARY: i64 = 10;
+const IN_FILE_NAME: i64 = 4;
+const GAP: i64 = 1;
+const NONE: i64 = i64::MIN / 4;
+
+pub(super) struct FuzzyMatch {
+    pub score: i64,
+    /// char indices into the path, ascending
+    pub positions: Vec<usize>,
+}
+
+/// Best placement of `query` (whitespace ignored) in `path`, if it matches.
+pub(super) fn fuzzy_match(query: &str, path: &str, case_sensitive: bool) -> Option<FuzzyMatch> {
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let q: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(fold).collect();
+    let raw: Vec<char> = path.chars().collect();
+    let p: Vec<char> = raw.iter().copied().map(fold).collect();
+    let (m, n) = (q.len(), p.len());
+    if m == 0 || m > n || !is_subsequence(&q, &p) {
+        return None;
+    }
+
+    let name_start = raw.iter().rposition(|&c| c == '/').map_or(0, |i| i + 1);
+    let bonus: Vec<i64> = (0..n)
+        .map(|j| {
+            let boundary = j == 0
+                || matches!(raw[j - 1], '/' | '_' | '-' | '.' | ' ')
+                || (raw[j - 1].is_lowercase() && raw[j].is_uppercase());
+            (if boundary { BOUNDARY } else { 0 }) + (if j >= name_start { IN_FILE_NAME } else { 0 })
+        })
+        .collect();
+
+    // dp[i][j]: best score with q[i] placed at p[j]; from[i][j]: where q[i-1] sat
+    let mut dp = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![usize::MAX; n]; m];
+    for j in 0..n {
+        if p[j] == q[0] {
+            dp[0][j] = MATCH + bonus[j];
+        }
+    }
+    for i in 1..m {
+        // best dp[i-1][k] + k*GAP over k <= j-2, i.e. a gapped predecessor
+        let (mut best, mut best_k) = (NONE, usize::MAX);
+        for j in i..n {
+            if j >= 2 && dp[i - 1][j - 2] > NONE && dp[i - 1][j - 2] + (j as i64 - 2) * GAP > best {
+                best = dp[i - 1][j - 2] + (j as i64 - 2) * GAP;
+                best_k = j - 2;
+            }
+            if p[j] != q[i] {
+                continue;
+            }
+            let consecutive = if dp[i - 1][j - 1] > NONE { dp[i - 1][j - 1] + CONSECUTIVE } else { NONE };
+            let gapped = if best > NONE { best - (j as i64 - 1) * GAP } else { NONE };
+            let (prev, k) = if consecutive >= gapped { (consecutive, j - 1) } else { (gapped, best_k) };
+            if prev > NONE {
+                dp[i][j] = prev + MATCH + bonus[j];
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = (0..n).map(|j| (j, dp[m - 1][j])).max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+    if score <= NONE {
+        return None;
+    }
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+fn is_subsequence(q: &[char], p: &[char]) -> bool {
+    let mut it = p.iter();
+    q.iter().all(|c| it.any(|x| x == c))
+}
@@ -0,0 +1,190 @@
+//! Saved searches (`/api/searches`): named queries kept in one sidecar file,
+//! `./decisions/.searches.json`, and re-run through the regular search.
+
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Mutex};
+
+use super::{fs_search, matcher, SearchMode, SearchParams};
+use crate::{quota, revisions::RevRef, write_atomic, AppState, WriteOpts};
+
+const STORE_FILE: &str = "./decisions/.searches.json";
+
+/// serializes read-modify-write cycles on the store
+static STORE: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SavedSearch {
+    /// assigned from `name` on create; ignored in request bodies
+    #[serde(default)]
+    id: String,
+    name: String,
+    q: String,
+    #[serde(default)]
+    path: String,
+    /// a tag here is resolved on every run, so `prod` follows the tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rev: Option<RevRef>,
+    #[serde(default)]
+    mode: SearchMode,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default)]
+    whole_word: bool,
+}
+
+impl SavedSearch {
+    fn params(&self, page: RunParams) -> SearchParams {
+        SearchParams {
+            q: self.q.clone(),
+            path: self.path.clone(),
+            rev: self.rev.clone(),
+            mode: self.mode,
+            case_sensitive: self.case_sensitive,
+            whole_word: self.whole_word,
+            context: page.context,
+            max_per_file: page.max_per_file,
+            limit: page.limit,
+            offset: page.offset,
+        }
+    }
+
+    /// 400 for queries the search endpoint would reject anyway.
+    fn validate(&self) -> Result<(), Response> {
+        if self.name.trim().is_empty() || self.q.trim().is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "name and q are required").into_response());
+        }
+        if !matches!(self.mode, SearchMode::Fuzzy) {
+            matcher(&self.params(RunParams::default()))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+        }
+        Ok(())
+    }
+}
+
+/// Paging and snippet settings aren't part of a saved search; they come with
+/// each run.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RunParams {
+    context: Option<usize>,
+    max_per_file: Option<usize>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+fn load() -> Vec<SavedSearch> {
+    std::fs::read(STORE_FILE)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn store(st: &AppState, all: &[SavedSearch]) -> Result<(), Response> {
+    let target = PathBuf::from(STORE_FILE);
+    let bytes = serde_json::to_vec_pretty(all).expect("serialize saved searches");
+    quota::check_write(&st.config, &target, bytes.len() as u64)?;
+    write_atomic(&target, &bytes, WriteOpts::default()).map_err(|e| {
+        tracing::error!("saved search store error: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+/// `name` lowercased with runs of anything but `[a-z0-9]` turned into `-`,
+/// suffixed `-2`, `-3`, … until it is unused.
+fn fresh_id(name: &str, all: &[SavedSearch]) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let base = match slug.trim_end_matches('-') {
+        "" => "search".to_string(),
+        s => s.to_string(),
+    };
+    let taken = |id: &str| all.iter().any(|s| s.id == id);
+    (1..).map(|n| if n == 1 { base.clone() } else { format!("{base}-{n}") }).find(|id| !taken(id)).unwrap()
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "no such saved search").into_response()
+}
+
+// ===== GET/POST /api/searches ================================================
+
+pub(crate) async fn list() -> impl IntoResponse {
+    Json(load())
+}
+
+pub(crate) async fn create(State(st): State<AppState>, Json(mut body): Json<SavedSearch>) -> Response {
+    if let Err(resp) = body.validate() {
+        return resp;
+    }
+    let _guard = STORE.lock().unwrap();
+    let mut all = load();
+    body.id = fresh_id(&body.name, &all);
+    all.push(body.clone());
+    match store(&st, &all) {
+        Ok(()) => (StatusCode::CREATED, Json(body)).into_response(),
+        Err(resp) => resp,
+    }
+}
+
+// ===== GET/PUT/DELETE /api/searches/:id ======================================
+
+pub(crate) async fn get_one(Path(id): Path<String>) -> Response {
+    match load().into_iter().find(|s| s.id == id) {
+        Some(s) => Json(s).into_response(),
+        None => not_found(),
+    }
+}
+
+/// Replaces everything but the id.
+pub(crate) async fn update(State(st): State<AppState>, Path(id): Path<String>, Json(mut body): Json<SavedSearch>) -> Response {
+    if let Err(resp) = body.validate() {
+        return resp;
+    }
+    let _guard = STORE.lock().unwrap();
+    let mut all = load();
+    let Some(slot) = all.iter_mut().find(|s| s.id == id) else {
+        return not_found();
+    };
+    body.id = id;
+    *slot = body.clone();
+    match store(&st, &all) {
+        Ok(()) => Json(body).into_response(),
+        Err(resp) => resp,
+    }
+}
+
+pub(crate) async fn delete(State(st): State<AppState>, Path(id): Path<String>) -> Response {
+    let _guard = STORE.lock().unwrap();
+    let mut all = load();
+    let before = all.len();
+    all.retain(|s| s.id != id);
+    if all.len() == before {
+        return not_found();
+    }
+    match store(&st, &all) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(resp) => resp,
+    }
+}
+
+// ===== GET|POST /api/searches/:id/run ========================================
+
+/// Answers exactly like `/api/fs/search` with the saved query.
+pub(crate) async fn run(st: State<AppState>, Path(id): Path<String>, Query(page): Query<RunParams>) -> Response {
+    let Some(saved) = load().into_iter().find(|s| s.id == id) else {
+        return not_found();
+    };
+    fs_search(st, Query(saved.params(page))).await
+}
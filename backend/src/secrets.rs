@@ -0,0 +1,304 @@
+//! Secrets for custom nodes that call other services: credentials kept out
+//! of the decision JSON.
+//!
+//! A node's `config` refers to one as `{{secrets.NAME}}`, anywhere inside a
+//! string (`"Bearer {{secrets.PRICING_TOKEN}}"`), and the value is put in
+//! just before the node runs; the stored decision, simulation traces and
+//! the editor only ever see the reference. Only the config fields a kind
+//! declares (see [`secret_fields`]) may hold references, like `url` and
+//! `headers` of `http`; a reference anywhere else, or in a kind declaring
+//! none (`lookup`, which would output it), fails the node, as does naming a
+//! secret that isn't set. The node's output and errors have the values
+//! blanked out all the same.
+//!
+//! `POST /api/secrets` (`{name, value}`) sets one, `DELETE /api/secrets/:name`
+//! removes it, and `GET /api/secrets` lists names and who set them when,
+//! never values: there's no way to read one back. Names are letters, digits
+//! and `_`, not starting with a digit.
+//!
+//! Setting or removing a secret empties the simulation cache, whose results
+//! may have been worked out with the old value.
+//!
+//! Values are encrypted with AES-256-GCM under `SECRETS_KEY` (64 hex digits)
+//! in `./decisions/.secrets.json`, each bound to its name. Without the key
+//! the API answers 409 and nodes naming secrets fail; with another key
+//! than the one they were stored with, they can't be read and must be set
+//! again.
+//!
+//! [`secret_fields`]: crate::plugins::CustomNode::secret_fields
+
+use axum::{
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::SecureRandom,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path as StdPath,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{audit, auth, config::Config, storelock::StorageLock, write_atomic, AppState, WriteOpts};
+
+const SECRETS_FILE: &str = "./decisions/.secrets.json";
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+const REDACTED: &str = "[secret]";
+
+static STORE: StorageLock = StorageLock::new("secrets");
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn reference() -> &'static Regex {
+    static REF: OnceLock<Regex> = OnceLock::new();
+    REF.get_or_init(|| Regex::new(r"\{\{\s*secrets\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Stored {
+    /// base64
+    nonce: String,
+    /// base64 of the ciphertext and its tag
+    value: String,
+    /// unix seconds
+    updated_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_by: Option<String>,
+}
+
+fn load() -> BTreeMap<String, Stored> {
+    fs::read(SECRETS_FILE).ok().and_then(|raw| serde_json::from_slice(&raw).ok()).unwrap_or_default()
+}
+
+/// Caller holds `STORE`.
+fn save(all: &BTreeMap<String, Stored>) -> io::Result<()> {
+    let bytes = serde_json::to_vec_pretty(all).expect("serialize secrets");
+    write_atomic(StdPath::new(SECRETS_FILE), &bytes, WriteOpts { durable: true, ..Default::default() })
+}
+
+fn seal(key: &LessSafeKey, name: &str, value: &str) -> Stored {
+    let mut nonce = [0u8; NONCE_LEN];
+    ring::rand::SystemRandom::new().fill(&mut nonce).expect("system randomness");
+    let mut sealed = value.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(name.as_bytes()), &mut sealed)
+        .expect("encrypt secret");
+    Stored { nonce: STANDARD.encode(nonce), value: STANDARD.encode(sealed), updated_at: now_secs(), updated_by: None }
+}
+
+pub(crate) struct Secrets {
+    key: Option<LessSafeKey>,
+}
+
+impl Secrets {
+    pub(crate) fn from_config(cfg: &Config) -> Result<Self, String> {
+        let Some(hex) = &cfg.secrets_key else {
+            return Ok(Self { key: None });
+        };
+        let bytes: Option<Vec<u8>> = (hex.len() == 64 && hex.is_ascii())
+            .then(|| (0..64).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect())
+            .flatten();
+        let bytes = bytes.ok_or("SECRETS_KEY: expected 64 hex digits")?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| "SECRETS_KEY: unusable key")?;
+        Ok(Self { key: Some(LessSafeKey::new(key)) })
+    }
+
+    fn open(&self, name: &str, stored: &Stored) -> anyhow::Result<String> {
+        let key = self.key.as_ref().ok_or_else(|| anyhow::anyhow!("secret {name} can't be read: no SECRETS_KEY configured"))?;
+        let unreadable = || anyhow::anyhow!("secret {name} can't be read with this SECRETS_KEY; set it again");
+        let nonce: [u8; NONCE_LEN] = STANDARD.decode(&stored.nonce).ok().and_then(|n| n.try_into().ok()).ok_or_else(unreadable)?;
+        let mut sealed = STANDARD.decode(&stored.value).map_err(|_| unreadable())?;
+        let plain = key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(name.as_bytes()), &mut sealed)
+            .map_err(|_| unreadable())?;
+        String::from_utf8(plain.to_vec()).map_err(|_| unreadable())
+    }
+
+    /// `config` with every `{{secrets.NAME}}` in its top-level `fields`
+    /// replaced by its value, and the values put in, for [`redact`]. A
+    /// reference outside them is an error. Configs naming none come back as
+    /// they are, without touching the store.
+    pub(crate) fn resolve(&self, config: &Value, fields: &[&str]) -> anyhow::Result<(Value, Vec<String>)> {
+        let mut used = Vec::new();
+        if !reference().is_match(&config.to_string()) {
+            return Ok((config.clone(), used));
+        }
+        let Value::Object(obj) = config else {
+            anyhow::bail!("secrets can only be named inside config fields");
+        };
+        if let Some(key) = obj.iter().find(|(k, v)| !fields.contains(&k.as_str()) && reference().is_match(&v.to_string())).map(|(k, _)| k) {
+            match fields {
+                [] => anyhow::bail!("this kind of node can't use secrets (found one in {key})"),
+                _ => anyhow::bail!("secrets can only be used in {}, not in {key}", fields.join(", ")),
+            }
+        }
+        let stored = load();
+        let mut substitute = |s: &str| -> anyhow::Result<String> {
+            let mut out = String::with_capacity(s.len());
+            let mut last = 0;
+            for caps in reference().captures_iter(s) {
+                let (whole, name) = (caps.get(0).unwrap(), &caps[1]);
+                let entry = stored.get(name).ok_or_else(|| anyhow::anyhow!("secret {name} is not set"))?;
+                let value = self.open(name, entry)?;
+                out.push_str(&s[last..whole.start()]);
+                out.push_str(&value);
+                last = whole.end();
+                used.push(value);
+            }
+            out.push_str(&s[last..]);
+            Ok(out)
+        };
+        let mut resolved = obj.clone();
+        for (_, v) in resolved.iter_mut().filter(|(k, _)| fields.contains(&k.as_str())) {
+            *v = map_strings(v, &mut substitute)?;
+        }
+        Ok((Value::Object(resolved), used))
+    }
+}
+
+fn map_strings(v: &Value, f: &mut impl FnMut(&str) -> anyhow::Result<String>) -> anyhow::Result<Value> {
+    Ok(match v {
+        Value::String(s) => Value::String(f(s)?),
+        Value::Array(a) => Value::Array(a.iter().map(|x| map_strings(x, f)).collect::<anyhow::Result<_>>()?),
+        Value::Object(o) => Value::Object(o.iter().map(|(k, x)| Ok((k.clone(), map_strings(x, f)?))).collect::<anyhow::Result<_>>()?),
+        other => other.clone(),
+    })
+}
+
+/// `msg` with each of `values` blanked out.
+pub(crate) fn redact(msg: &str, values: &[String]) -> String {
+    values.iter().filter(|v| !v.is_empty()).fold(msg.to_string(), |m, v| m.replace(v.as_str(), REDACTED))
+}
+
+/// [`redact`] over every string in `v`, keys included.
+pub(crate) fn redact_value(v: &Value, values: &[String]) -> Value {
+    if values.is_empty() {
+        return v.clone();
+    }
+    match v {
+        Value::String(s) => Value::String(redact(s, values)),
+        Value::Array(a) => Value::Array(a.iter().map(|x| redact_value(x, values)).collect()),
+        Value::Object(o) => Value::Object(o.iter().map(|(k, x)| (redact(k, values), redact_value(x, values))).collect()),
+        other => other.clone(),
+    }
+}
+
+// ===== GET/POST /api/secrets, DELETE /api/secrets/:name =======================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Listed {
+    name: String,
+    updated_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated_by: Option<String>,
+}
+
+fn no_key() -> Response {
+    (StatusCode::CONFLICT, "no SECRETS_KEY configured").into_response()
+}
+
+fn internal(e: io::Error) -> Response {
+    tracing::error!("secrets write error: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+pub(crate) async fn list(State(st): State<AppState>) -> Response {
+    if st.secrets.key.is_none() {
+        return no_key();
+    }
+    let all = load();
+    Json(
+        all.into_iter()
+            .map(|(name, s)| Listed { name, updated_at: s.updated_at, updated_by: s.updated_by })
+            .collect::<Vec<_>>(),
+    )
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SetReq {
+    name: String,
+    value: String,
+}
+
+/// Sets a secret, replacing any value it had: 201 when it's new, 200 when
+/// it's replaced.
+pub(crate) async fn set(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(req): Json<SetReq>) -> Response {
+    let Some(key) = &st.secrets.key else { return no_key() };
+    let name = req.name.trim().to_string();
+    if !valid_name(&name) {
+        return (StatusCode::BAD_REQUEST, "secret names are letters, digits and _, not starting with a digit").into_response();
+    }
+    if req.value.len() > MAX_VALUE_BYTES {
+        return (StatusCode::PAYLOAD_TOO_LARGE, format!("secret values are at most {MAX_VALUE_BYTES} bytes")).into_response();
+    }
+    let by = auth::acting(&ident, None);
+    let stored = Stored { updated_by: by.clone(), ..seal(key, &name, &req.value) };
+    let updated_at = stored.updated_at;
+    let res = {
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || {
+            let _g = STORE.lock();
+            let mut all = load();
+            let existed = all.insert(name, stored).is_some();
+            save(&all).map(|()| existed)
+        })
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+    };
+    match res {
+        Ok(existed) => {
+            tracing::info!(secret = name, "secret set");
+            st.sim_cache.invalidate();
+            audit::record(audit::Event::new("secret-set").path(&name).by(by.clone())).await;
+            let status = if existed { StatusCode::OK } else { StatusCode::CREATED };
+            (status, Json(Listed { name, updated_at, updated_by: by })).into_response()
+        }
+        Err(e) => internal(e),
+    }
+}
+
+pub(crate) async fn delete(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Path(name): Path<String>) -> Response {
+    if st.secrets.key.is_none() {
+        return no_key();
+    }
+    let res = {
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || {
+            let _g = STORE.lock();
+            let mut all = load();
+            match all.remove(&name) {
+                Some(_) => save(&all).map(|()| true),
+                None => Ok(false),
+            }
+        })
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+    };
+    match res {
+        Ok(true) => {
+            tracing::info!(secret = name, "secret deleted");
+            st.sim_cache.invalidate();
+            audit::record(audit::Event::new("secret-delete").path(&name).by(auth::acting(&ident, None))).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, format!("no such secret: {name}")).into_response(),
+        Err(e) => internal(e),
+    }
+}
@@ -0,0 +1,184 @@
+//! Share links: read access to one file, folder or revision for people
+//! without an account, until the link expires.
+//!
+//! `POST /api/share` takes `{path, rev, expiresIn}`: a path in the working
+//! copy, a whole revision, or a path inside one, and the link's lifetime in
+//! seconds (default a week, at most `SHARE_MAX_TTL_SECS`, default 30 days).
+//! A tag or branch is resolved when the link is made, so the link keeps
+//! showing that snapshot when they move on; a working-copy path shows what
+//! it holds when the link is opened.
+//!
+//! `GET /api/shared/:token` answers with the file, or for a folder or a
+//! revision the tree of what's in it (as `/api/fs/list` does, paths relative
+//! to the shared folder); `GET /api/shared/:token/<path>` the same for what's
+//! inside. No key or session is needed.
+//!
+//! The token holds what's shared and when it expires, signed with
+//! `SHARE_SECRET`: nothing is stored, and links can only be revoked all at
+//! once, by changing the secret. Without one a random secret is made at
+//! startup, and restarting breaks every link.
+
+use axum::{
+    body::Body,
+    extract::{Extension, Json, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{hmac, rand::SecureRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path as StdPath,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{audit, auth, build_tree_to, config::Config, objects, read_path, revisions::RevRef, AppState};
+
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 3600;
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+/// What a token grants, signed.
+#[derive(Serialize, Deserialize)]
+struct Grant {
+    /// 0 for the working copy
+    rev: u64,
+    /// inside the revision; empty for all of it
+    path: String,
+    /// unix seconds
+    exp: i64,
+}
+
+pub(crate) struct ShareKey(hmac::Key);
+
+impl ShareKey {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        let secret = match &cfg.share_secret {
+            Some(s) => s.as_bytes().to_vec(),
+            None => {
+                let mut random = [0u8; 32];
+                ring::rand::SystemRandom::new().fill(&mut random).expect("system randomness");
+                random.to_vec()
+            }
+        };
+        Self(hmac::Key::new(hmac::HMAC_SHA256, &secret))
+    }
+
+    fn sign(&self, grant: &Grant) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(grant).expect("serialize grant"));
+        let tag = hmac::sign(&self.0, payload.as_bytes());
+        format!("{payload}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    fn verify(&self, token: &str) -> Option<Grant> {
+        let (payload, tag) = token.split_once('.')?;
+        hmac::verify(&self.0, payload.as_bytes(), &URL_SAFE_NO_PAD.decode(tag).ok()?).ok()?;
+        let grant: Grant = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (grant.exp > now_secs()).then_some(grant)
+    }
+}
+
+fn clean(path: &str) -> String {
+    path.split('/').filter(|s| !s.is_empty() && *s != ".").collect::<Vec<_>>().join("/")
+}
+
+// ===== POST /api/share ========================================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ShareReq {
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    rev: Option<RevRef>,
+    /// seconds
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShareResp {
+    token: String,
+    /// where to open it, relative to the server
+    url: String,
+    rev: u64,
+    path: String,
+    expires_at: i64,
+}
+
+pub(crate) async fn create(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(req): Json<ShareReq>) -> Response {
+    if req.path.is_none() && req.rev.is_none() {
+        return (StatusCode::BAD_REQUEST, "expected a path, a rev or both").into_response();
+    }
+    let rev = match &req.rev {
+        Some(r) => match r.resolve() {
+            Ok(rev) => rev,
+            Err(resp) => return resp,
+        },
+        None => 0,
+    };
+    let path = clean(req.path.as_deref().unwrap_or_default());
+    match read_path(&st.config, &format!("{rev}/{path}")) {
+        Ok(p) if p.exists() => {}
+        Ok(_) => return (StatusCode::NOT_FOUND, format!("nothing at {path:?} in revision {rev}")).into_response(),
+        Err(e) => return e.into_response(),
+    }
+    let ttl = req.expires_in.unwrap_or(DEFAULT_TTL_SECS);
+    if ttl == 0 || ttl > st.config.share_max_ttl.as_secs() {
+        return (StatusCode::BAD_REQUEST, format!("expiresIn must be between 1 and {} seconds", st.config.share_max_ttl.as_secs())).into_response();
+    }
+    let grant = Grant { rev, path, exp: now_secs() + ttl as i64 };
+    let token = st.share_key.sign(&grant);
+    tracing::info!(rev, path = grant.path, expires_at = grant.exp, "share link created");
+    let mut event = audit::Event::new("share").path(&grant.path).by(auth::acting(&ident, None));
+    if rev > 0 {
+        event = event.rev(rev);
+    }
+    audit::record(event).await;
+    Json(ShareResp { url: format!("/api/shared/{token}"), token, rev, path: grant.path, expires_at: grant.exp }).into_response()
+}
+
+// ===== GET /api/shared/:token[/*path] ========================================
+
+async fn serve(st: AppState, token: &str, sub: &str) -> Response {
+    let Some(grant) = st.share_key.verify(token) else {
+        return (StatusCode::NOT_FOUND, "this link is invalid or has expired").into_response();
+    };
+    let rel = clean(&format!("{}/{}", grant.path, clean(sub)));
+    let full = match read_path(&st.config, &format!("{}/{rel}", grant.rev)) {
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+    if full.is_dir() {
+        let follow = st.config.follow_symlinks;
+        let inner = clean(sub);
+        return match tokio::task::spawn_blocking(move || build_tree_to(&full, &inner, follow, None)).await {
+            Ok(nodes) => Json(nodes).into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+    }
+    if !full.is_file() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let source = if grant.rev > 0 { objects::locate(grant.rev, &rel).unwrap_or(full) } else { full };
+    let content_type = match StdPath::new(&rel).extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    };
+    match tokio::fs::File::open(source).await {
+        Ok(file) => ([(header::CONTENT_TYPE, content_type)], Body::from_stream(ReaderStream::new(file))).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn open(State(st): State<AppState>, Path(token): Path<String>) -> Response {
+    serve(st, &token, "").await
+}
+
+pub(crate) async fn open_inside(State(st): State<AppState>, Path((token, sub)): Path<(String, String)>) -> Response {
+    serve(st, &token, &sub).await
+}
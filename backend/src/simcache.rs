@@ -0,0 +1,200 @@
+//! Results of `POST /api/simulate`, kept for the next identical request.
+//!
+//! An entry is keyed by what decides the result: the content of the decision
+//! and of every decision it calls (hashed, so an edit anywhere in the chain
+//! misses), the context, and the options that change the output (trace,
+//! depth, a `deterministic` clock and seed). Only successful evaluations are
+//! kept, serialized, and the least recently used one goes when
+//! `SIMULATE_CACHE_ENTRIES` are held.
+//!
+//! A decision that isn't a function of its input (a function node reading
+//! the clock, a custom node calling a service) shouldn't be cached; the
+//! request's `cache` says `bypass` for those, or `refresh` to replace what's
+//! kept. Expressions calling `date("now")` or `rand()` are, once
+//! `deterministic` pins them. Hits and misses are counted for
+//! `GET /api/simulate/cache`.
+
+use axum::{
+    body::Bytes,
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use crate::{decisions, deterministic::Deterministic, jdm, AppState, STORAGE_ROOT};
+
+/// Bigger results are evaluated every time rather than crowd out the rest.
+const MAX_ENTRY_BYTES: usize = 1024 * 1024;
+
+/// What a request wants of the cache.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum CacheMode {
+    /// answer from the cache when it can, keep the result when it can't
+    #[default]
+    Use,
+    /// evaluate, and keep the result in place of what's there
+    Refresh,
+    /// evaluate, and leave the cache alone
+    Bypass,
+}
+
+struct Entry {
+    body: Bytes,
+    /// last use, on the cache's own clock
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct SimCache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+}
+
+impl SimCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { inner: Arc::default(), capacity }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The kept response, counting a hit or a miss.
+    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        match inner.entries.get_mut(key) {
+            Some(e) => {
+                e.used = clock;
+                let body = e.body.clone();
+                inner.hits += 1;
+                Some(body)
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn put(&self, key: String, body: Bytes) {
+        if !self.enabled() || body.len() > MAX_ENTRY_BYTES {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let used = inner.clock;
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= self.capacity {
+            if let Some(oldest) = inner.entries.iter().min_by_key(|(_, e)| e.used).map(|(k, _)| k.clone()) {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.entries.insert(key, Entry { body, used });
+    }
+
+    /// Drops the kept results, for when something they depended on outside
+    /// the decisions changed (a secret); the counters stay.
+    pub(crate) fn invalidate(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+}
+
+/// A hash of `key`'s bytes and of every decision it calls, inside revision
+/// `rev`; `None` when `key` itself can't be read (evaluating will say why).
+/// Blocking.
+pub(crate) fn content_hash(rev: u64, key: &str) -> Option<String> {
+    let mut hasher = Sha256::new();
+    let (mut seen, mut queue) = (BTreeSet::new(), VecDeque::from([key.to_string()]));
+    while let Some(k) = queue.pop_front() {
+        if !seen.insert(k.clone()) {
+            continue;
+        }
+        hasher.update(k.as_bytes());
+        hasher.update([0]);
+        let Ok(raw) = fs::read(format!("{STORAGE_ROOT}/{rev}/{k}")) else {
+            if k == key {
+                return None;
+            }
+            // a call that fails now may not once the file appears
+            hasher.update(b"missing\0");
+            continue;
+        };
+        hasher.update((raw.len() as u64).to_le_bytes());
+        hasher.update(&raw);
+        let Ok(doc) = serde_json::from_slice::<Value>(&raw) else { continue };
+        let called = jdm::nodes(&doc)
+            .filter(|n| jdm::node_type(n) == "decisionNode")
+            .filter_map(|n| n["content"]["key"].as_str().and_then(decisions::normalize));
+        queue.extend(called);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// The cache key for evaluating the decisions hashed as `content` with
+/// `context` and `opts` (trace, depth, deterministic).
+pub(crate) fn key(content: &str, context: &Value, opts: (Option<bool>, Option<u8>, Option<Deterministic>)) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    // object keys serialize sorted, so equal contexts hash equal
+    hasher.update(serde_json::to_vec(context).unwrap_or_default());
+    hasher.update([u8::from(opts.0.unwrap_or(true)), opts.1.unwrap_or(0)]);
+    if let Some(d) = opts.2 {
+        hasher.update(d.now.timestamp().to_le_bytes());
+        hasher.update(d.seed.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// ===== GET/DELETE /api/simulate/cache ========================================
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheStats {
+    enabled: bool,
+    capacity: usize,
+    entries: usize,
+    hits: u64,
+    misses: u64,
+    /// of the lookups so far, in percent; absent before the first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit_rate: Option<f64>,
+}
+
+pub(crate) async fn stats(State(st): State<AppState>) -> Response {
+    let cache = &st.sim_cache;
+    let inner = cache.inner.lock().unwrap();
+    let lookups = inner.hits + inner.misses;
+    Json(CacheStats {
+        enabled: cache.enabled(),
+        capacity: cache.capacity,
+        entries: inner.entries.len(),
+        hits: inner.hits,
+        misses: inner.misses,
+        hit_rate: (lookups > 0).then(|| (inner.hits as f64 * 1000.0 / lookups as f64).round() / 10.0),
+    })
+    .into_response()
+}
+
+/// Drops every entry and zeroes the counters.
+pub(crate) async fn clear(State(st): State<AppState>) -> Response {
+    *st.sim_cache.inner.lock().unwrap() = Inner::default();
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,964 @@
+//! Evaluating decisions: `POST /api/simulate` for one context,
+//! `POST /api/simulate/batch` for many against the same decision,
+//! `POST /api/simulate/csv` for the rows of a spreadsheet,
+//! `POST /api/simulate/compare` for one in two revisions side by side,
+//! `POST /api/simulate/stream` for one as server-sent events.
+//!
+//! What's evaluated is named by `rev`: a revision number, a tag or branch, or
+//! `working` (the default) for the working copy; `filepath` is relative to it.
+//! The server picks the directory, so a request can't point the loader
+//! anywhere else, and the loader keeps the decisions a graph calls inside it
+//! too (see [`engines`](crate::engines)); `rootDir`, which used to name the
+//! directory, is refused with 400.
+//!
+//! Evaluation futures aren't `Send`, so they run on the pinned local pool
+//! handed to the router as an extension. Engines come from the shared
+//! [`EngineCache`](crate::engines::EngineCache), so decisions parsed for one
+//! request are reused by the next; a batch keeps as many cases in flight as
+//! the pool has threads.
+//!
+//! `trace` (default on), `maxDepth` and `timeoutMs` tune an evaluation; depth
+//! is capped by `SIMULATE_MAX_DEPTH` and the timeout by
+//! `SIMULATE_MAX_TIMEOUT_MS`. Every evaluation has a timeout,
+//! `SIMULATE_TIMEOUT_MS` unless it asks for another; one past it is dropped
+//! and answered with 408 and a `Timeout` error. A client that disconnects
+//! takes its evaluations with it.
+//!
+//! `deterministic: { now, seed }` fixes the clock and seeds `rand()` (see
+//! [`deterministic`](crate::deterministic)); such an evaluation runs on a
+//! thread of its own rather than the pool, and is cached under its `now`
+//! and `seed`.
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Json, Multipart, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    convert::Infallible,
+    time::{Duration, Instant},
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_util::task::LocalPoolHandle;
+use zen_engine::{EvaluationError, EvaluationOptions};
+
+use crate::{
+    config::Config,
+    decisions,
+    deterministic::Deterministic,
+    engines::{Engine, EngineCache},
+    events::ServerEvent,
+    profile,
+    read_path,
+    revisions::RevRef,
+    simcache::{self, CacheMode},
+    simulations::{self, Simulation},
+    workflow, AppState, STORAGE_ROOT,
+};
+
+/// Cases in one batch, however they're sent.
+const MAX_BATCH_CASES: usize = 10_000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimulateRequest {
+    #[serde(default)]
+    rev: Option<RevRef>,
+    filepath: String,
+    context: Value,
+    #[serde(flatten)]
+    opts: EvalOpts,
+    /// add a `profile` of where the time went (see [`profile`](crate::profile));
+    /// never answered from the cache
+    #[serde(default)]
+    profile: bool,
+    /// `use` (the default), `refresh` or `bypass`; see [`simcache`](crate::simcache)
+    #[serde(default)]
+    cache: CacheMode,
+    /// replaced by `rev`; only here to turn old clients away loudly
+    #[serde(default)]
+    root_dir: Option<Value>,
+    /// the logged simulation this evaluates again
+    #[serde(skip)]
+    replay_of: Option<u64>,
+}
+
+impl SimulateRequest {
+    /// The request `sim` logged, to evaluate again.
+    pub(crate) fn replay(sim: Simulation) -> Self {
+        Self {
+            rev: sim.rev,
+            filepath: sim.filepath,
+            context: sim.context,
+            opts: sim.opts,
+            profile: false,
+            cache: CacheMode::Use,
+            root_dir: None,
+            replay_of: Some(sim.id),
+        }
+    }
+}
+
+/// How to evaluate, as the client asks; the server's caps
+/// (`SIMULATE_MAX_DEPTH`, `SIMULATE_MAX_TIMEOUT_MS`) win.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EvalOpts {
+    /// record every node's input and output (default on); off is cheaper
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) trace: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_depth: Option<u8>,
+    /// give up on an evaluation after this long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) timeout_ms: Option<u64>,
+    /// a fixed clock and a seed for `rand()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) deterministic: Option<Deterministic>,
+}
+
+impl EvalOpts {
+    pub(crate) fn engine_options(&self, cfg: &Config) -> EvaluationOptions {
+        let depth = self.max_depth.unwrap_or(cfg.simulate_max_depth).clamp(1, cfg.simulate_max_depth);
+        EvaluationOptions { trace: Some(self.trace.unwrap_or(true)), max_depth: Some(depth) }
+    }
+
+    pub(crate) fn timeout(&self, cfg: &Config) -> Duration {
+        self.timeout_ms.map_or(cfg.simulate_timeout, Duration::from_millis).min(cfg.simulate_max_timeout)
+    }
+
+    /// The engine to evaluate `rev` with: the shared one, or one with the
+    /// clock fixed.
+    pub(crate) fn engine(&self, engines: &EngineCache, rev: u64) -> Engine {
+        match &self.deterministic {
+            Some(d) => engines.frozen_engine(rev, d.now_text()),
+            None => engines.engine(rev),
+        }
+    }
+
+    /// What to seed `rand()` with, for [`evaluate`].
+    pub(crate) fn seed(&self) -> Option<u64> {
+        self.deterministic.map(|d| d.seed)
+    }
+}
+
+/// Aborts the evaluation when dropped, which is how a client hanging up
+/// reaches it: axum drops the handler's future, and with it this. A pinned
+/// task is aborted; one on a thread of its own sees `_cancel` go and drops
+/// its evaluation.
+struct AbortOnDrop {
+    task: JoinHandle<Option<Result<Value, Box<EvaluationError>>>>,
+    _cancel: Option<oneshot::Sender<()>>,
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Evaluates `key` on the pinned pool, dropping the evaluation if it outlives
+/// `timeout` or the request goes away. With a `seed` it runs on a blocking
+/// thread instead, with its own runtime: the pool's workers interleave
+/// evaluations, and `rand()` draws from the thread's generator.
+///
+/// Dropping only takes effect where the engine yields, between nodes and in
+/// function nodes; a single expression or table that never finishes keeps its
+/// worker until it does.
+pub(crate) async fn evaluate(
+    pool: &LocalPoolHandle,
+    engine: Engine,
+    key: String,
+    context: Value,
+    opts: EvaluationOptions,
+    seed: Option<u64>,
+    timeout: Duration,
+) -> Result<Value, SimulateError> {
+    let run = move || async move {
+        engine
+            .evaluate_with_opts(&key, context.into(), opts)
+            .await
+            // -------- flatten here: serialization can’t really fail -------
+            .map(|v| serde_json::to_value(v).expect("serialize DecisionGraphResponse"))
+    };
+    let mut task = match seed {
+        None => AbortOnDrop { task: pool.spawn_pinned(move || async move { Some(run().await) }), _cancel: None },
+        Some(seed) => {
+            let (cancel, cancelled) = oneshot::channel::<()>();
+            let task = tokio::task::spawn_blocking(move || {
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("evaluation runtime");
+                rt.block_on(async move {
+                    fastrand::seed(seed);
+                    tokio::select! {
+                        res = run() => Some(res),
+                        _ = cancelled => None,
+                    }
+                })
+            });
+            AbortOnDrop { task, _cancel: Some(cancel) }
+        }
+    };
+    match tokio::time::timeout(timeout, &mut task.task).await {
+        Ok(joined) => Ok(joined.expect("thread join failed").expect("only a dropped evaluation is cancelled")?),
+        // `task` goes out of scope here and takes the evaluation with it
+        Err(_) => Err(SimulateError::Timeout(timeout)),
+    }
+}
+
+/// The revision `rev` names and `filepath` as a key inside it: 404 for an
+/// unknown revision, 409 for one that may not be evaluated, 400 for a path
+/// that leaves it.
+pub(crate) fn resolve_root(cfg: &Config, rev: Option<&RevRef>, filepath: &str) -> Result<(u64, String), SimulateError> {
+    let rev = match rev {
+        None => 0,
+        Some(RevRef::Name(s)) if s.trim() == "working" => 0,
+        Some(r) => r.resolve().map_err(SimulateError::Refused)?,
+    };
+    workflow::evaluable(rev).map_err(SimulateError::Refused)?;
+    let root = format!("{STORAGE_ROOT}/{rev}");
+    let full = read_path(cfg, &format!("{rev}/{filepath}"))
+        .map_err(|status| SimulateError::Refused((status, "filepath must be relative to the revision, inside it").into_response()))?;
+    // the loader joins keys onto its root, and an absolute one would replace it
+    let key = full.strip_prefix(&root).unwrap_or(&full).to_string_lossy().replace('\\', "/");
+    Ok((rev, key))
+}
+
+fn no_root_dir(root_dir: &Option<Value>) -> Result<(), SimulateError> {
+    match root_dir {
+        Some(_) => Err(bad_request("rootDir is gone: name the revision with rev (a number, tag or `working`)")),
+        None => Ok(()),
+    }
+}
+
+/// The response body as JSON, saying how the cache was used and, when it
+/// was logged, as which simulation.
+fn cached_json(body: Bytes, cache: &'static str, logged: Option<u64>) -> Response {
+    let mut resp =
+        ([(header::CONTENT_TYPE, "application/json"), (header::HeaderName::from_static("x-cache"), cache)], body).into_response();
+    if let Some(id) = logged {
+        resp.headers_mut().insert(header::HeaderName::from_static("x-simulation-id"), header::HeaderValue::from(id));
+    }
+    resp
+}
+
+/// Answered with `X-Cache: hit`, `miss` or `bypass` (not looked up: a
+/// profile, `cache: bypass`, or the cache is off), and `X-Simulation-Id`
+/// when [`simulations`] logged it.
+pub(crate) async fn simulate(
+    State(st): State<AppState>,
+    Extension(local_pool): Extension<LocalPoolHandle>,
+    Json(req): Json<SimulateRequest>,
+) -> Result<Response, SimulateError> {
+    let started = Instant::now();
+    let filepath = req.filepath.clone();
+    no_root_dir(&req.root_dir)?;
+    let (rev, key) = resolve_root(&st.config, req.rev.as_ref(), &req.filepath)?;
+
+    // the profile is made from the trace
+    let keep_trace = req.opts.trace.unwrap_or(true);
+    let eval_opts = EvalOpts { trace: Some(keep_trace || req.profile), ..req.opts };
+    let (opts, timeout) = (eval_opts.engine_options(&st.config), eval_opts.timeout(&st.config));
+    let mut logged_context = simulations::enabled(&st).then(|| req.context.clone());
+    let mut log = |ok: bool, cached: bool, outcome: Value| -> Option<u64> {
+        let context = logged_context.take()?;
+        let id = simulations::next_id();
+        let sim = Simulation {
+            id,
+            at: simulations::now(),
+            filepath: key.clone(),
+            rev: req.rev.clone(),
+            evaluated_rev: rev,
+            context,
+            opts: req.opts,
+            ok,
+            duration_ms: started.elapsed().as_millis() as u64,
+            cached,
+            trace_dropped: false,
+            replay_of: req.replay_of,
+        };
+        simulations::record(&st, sim, outcome);
+        Some(id)
+    };
+
+    let cache_key = match st.sim_cache.enabled() && !req.profile && req.cache != CacheMode::Bypass {
+        true => {
+            let (k, context, opts) = (key.clone(), req.context.clone(), (opts.trace, opts.max_depth, eval_opts.deterministic));
+            let hashed = tokio::task::spawn_blocking(move || simcache::content_hash(rev, &k).map(|h| simcache::key(&h, &context, opts)));
+            hashed.await.ok().flatten()
+        }
+        false => None,
+    };
+    if let (Some(k), CacheMode::Use) = (&cache_key, req.cache) {
+        if let Some(body) = st.sim_cache.get(k) {
+            st.events.publish(ServerEvent::SimulationFinished { filepath, ok: true, duration_ms: started.elapsed().as_millis() as u64 });
+            let logged = log(true, true, serde_json::from_slice(&body).unwrap_or_default());
+            return Ok(cached_json(body, "hit", logged));
+        }
+    }
+    let engine = eval_opts.engine(&st.engines, rev);
+    let result = evaluate(&local_pool, engine, key.clone(), req.context, opts, eval_opts.seed(), timeout).await;
+
+    st.events.publish(ServerEvent::SimulationFinished {
+        filepath,
+        ok: result.is_ok(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+
+    let mut value = match result {
+        Ok(v) => v,
+        Err(e) => {
+            log(false, false, e.to_json());
+            return Err(e);
+        }
+    };
+    decisions::export::record(rev, &key, &value);
+    if req.profile {
+        let doc = tokio::fs::read(format!("{STORAGE_ROOT}/{rev}/{key}")).await.ok();
+        let doc = doc.and_then(|raw| serde_json::from_slice::<Value>(&raw).ok());
+        let handler_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let profile = serde_json::to_value(profile::build(&value, doc.as_ref(), handler_ms)).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            if !keep_trace {
+                obj.remove("trace");
+            }
+            obj.insert("profile".into(), profile);
+        }
+    }
+    let body = Bytes::from(serde_json::to_vec(&value).expect("serialize simulation response"));
+    let logged = log(true, false, value);
+    match cache_key {
+        Some(k) => {
+            st.sim_cache.put(k, body.clone());
+            Ok(cached_json(body, "miss", logged))
+        }
+        None => Ok(cached_json(body, "bypass", logged)),
+    }
+}
+
+// ===== POST /api/simulate/batch ==============================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchRequest {
+    #[serde(default)]
+    rev: Option<RevRef>,
+    filepath: String,
+    contexts: Vec<Value>,
+    /// for every case; `timeoutMs` is per case
+    #[serde(flatten)]
+    opts: EvalOpts,
+    #[serde(default)]
+    root_dir: Option<Value>,
+}
+
+/// Where an NDJSON batch says what to evaluate, and how; its lines are the
+/// contexts. (Spelled out rather than flattening `EvalOpts`: flattened query
+/// fields only ever arrive as strings.)
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BatchParams {
+    rev: Option<RevRef>,
+    filepath: Option<String>,
+    trace: Option<bool>,
+    max_depth: Option<u8>,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CaseResult {
+    /// position of the context in the request
+    index: usize,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+    duration_ms: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchResp {
+    filepath: String,
+    failed: usize,
+    duration_ms: u64,
+    /// in request order
+    results: Vec<CaseResult>,
+}
+
+fn bad_request(msg: impl Into<String>) -> SimulateError {
+    SimulateError::Refused((StatusCode::BAD_REQUEST, msg.into()).into_response())
+}
+
+/// The batch in `body`: a JSON `{rev, filepath, contexts}`, or with
+/// `Content-Type: application/x-ndjson` one context per line and the rest in
+/// the query.
+fn parse_batch(headers: &HeaderMap, q: BatchParams, body: &[u8]) -> Result<BatchRequest, SimulateError> {
+    let ndjson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/x-ndjson") || ct.starts_with("application/jsonl"));
+    if !ndjson {
+        return serde_json::from_slice(body).map_err(|e| bad_request(format!("invalid batch: {e}")));
+    }
+    let Some(filepath) = q.filepath else {
+        return Err(bad_request("an NDJSON batch takes filepath (and rev) in the query"));
+    };
+    let text = std::str::from_utf8(body).map_err(|_| bad_request("NDJSON batch is not UTF-8"))?;
+    let contexts = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| bad_request(format!("line {}: {e}", i + 1))))
+        .collect::<Result<_, _>>()?;
+    let opts = EvalOpts { trace: q.trace, max_depth: q.max_depth, timeout_ms: q.timeout_ms, deterministic: None };
+    Ok(BatchRequest { rev: q.rev, filepath, contexts, opts, root_dir: None })
+}
+
+/// Evaluates every context against the one decision; 200 with a result or an
+/// error per case whether or not some failed.
+pub(crate) async fn batch(
+    State(st): State<AppState>,
+    Extension(local_pool): Extension<LocalPoolHandle>,
+    Query(q): Query<BatchParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, SimulateError> {
+    let started = Instant::now();
+    let req = parse_batch(&headers, q, &body)?;
+    if req.contexts.is_empty() {
+        return Err(bad_request("the batch has no contexts"));
+    }
+    if req.contexts.len() > MAX_BATCH_CASES {
+        let msg = format!("at most {MAX_BATCH_CASES} contexts per batch");
+        return Err(SimulateError::Refused((StatusCode::PAYLOAD_TOO_LARGE, msg).into_response()));
+    }
+    no_root_dir(&req.root_dir)?;
+    let (rev, key) = resolve_root(&st.config, req.rev.as_ref(), &req.filepath)?;
+
+    let results = run_cases(&st, &local_pool, rev, &key, req.contexts, req.opts).await;
+    let failed = results.iter().filter(|r| !r.ok).count();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    st.events.publish(ServerEvent::SimulationFinished { filepath: key.clone(), ok: failed == 0, duration_ms });
+    let resp = BatchResp { filepath: key, failed, duration_ms, results };
+    Ok(Json(resp).into_response())
+}
+
+/// Every context against `key`, as many at once as the pool has threads; in
+/// the order given.
+async fn run_cases(st: &AppState, pool: &LocalPoolHandle, rev: u64, key: &str, contexts: Vec<Value>, opts: EvalOpts) -> Vec<CaseResult> {
+    let timeout = opts.timeout(&st.config);
+    let in_flight = pool.num_threads().max(1);
+    let mut results: Vec<CaseResult> = stream::iter(contexts.into_iter().enumerate())
+        .map(|(index, context)| {
+            let (engine, key, seed) = (opts.engine(&st.engines, rev), key.to_string(), opts.seed());
+            let opts = opts.engine_options(&st.config);
+            async move {
+                let case_started = Instant::now();
+                let res = evaluate(pool, engine, key, context, opts, seed, timeout).await;
+                let duration_ms = case_started.elapsed().as_millis() as u64;
+                match res {
+                    Ok(v) => CaseResult { index, ok: true, result: Some(v), error: None, duration_ms },
+                    Err(e) => CaseResult { index, ok: false, result: None, error: Some(e.to_json()), duration_ms },
+                }
+            }
+        })
+        .buffer_unordered(in_flight)
+        .collect()
+        .await;
+    results.sort_unstable_by_key(|r| r.index);
+    results
+}
+
+// ===== POST /api/simulate/csv ================================================
+
+/// Where a CSV upload's own cells end and the decision's outputs begin, when
+/// an output path is already an input header.
+const RESULT_PREFIX: &str = "result.";
+
+#[derive(Default)]
+struct CsvForm {
+    file: Option<Vec<u8>>,
+    filepath: Option<String>,
+    rev: Option<RevRef>,
+    /// `csv` (the default) or `json`
+    format: Option<String>,
+    max_depth: Option<u8>,
+    timeout_ms: Option<u64>,
+}
+
+/// A cell as a context value: anything that reads as JSON (numbers,
+/// `true`, `null`, `[1, 2]`, `"007"`) is that, the rest is text; an empty
+/// cell leaves the field out.
+fn cell_value(cell: &str) -> Option<Value> {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return None;
+    }
+    Some(serde_json::from_str(cell).unwrap_or_else(|_| Value::String(cell.to_string())))
+}
+
+/// `value` at the dotted `path` inside `ctx`, making the objects on the way.
+fn set_path(ctx: &mut Map<String, Value>, path: &str, value: Value) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let last = parts.pop().unwrap_or_default();
+    let obj = parts.into_iter().fold(ctx, |cur, part| {
+        let next = cur.entry(part).or_insert_with(|| Value::Object(Map::new()));
+        if !next.is_object() {
+            *next = Value::Object(Map::new());
+        }
+        next.as_object_mut().expect("made an object above")
+    });
+    obj.insert(last.to_string(), value);
+}
+
+/// `value` as dotted paths to its leaves; arrays and empty objects are
+/// leaves, and a result that isn't an object is the one leaf `result`.
+fn flatten(path: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(m) if !m.is_empty() => {
+            for (k, v) in m {
+                flatten(&if path.is_empty() { k.clone() } else { format!("{path}.{k}") }, v, out);
+            }
+        }
+        v => out.push((if path.is_empty() { "result".to_string() } else { path.to_string() }, v.clone())),
+    }
+}
+
+struct CsvUpload {
+    header: Vec<String>,
+    /// each row as uploaded
+    cells: Vec<Vec<String>>,
+    /// and as the context built from it
+    contexts: Vec<Value>,
+}
+
+fn csv_contexts(bytes: &[u8]) -> Result<CsvUpload, SimulateError> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+    let header: Vec<String> = rdr.headers().map_err(|e| bad_request(format!("csv: {e}")))?.iter().map(|h| h.trim().to_string()).collect();
+    for (i, h) in header.iter().enumerate() {
+        if h.is_empty() || h.split('.').any(str::is_empty) {
+            return Err(bad_request(format!("column {} needs a header naming a field, like `customer.age`", i + 1)));
+        }
+        if header[..i].contains(h) {
+            return Err(bad_request(format!("duplicate column {h:?}")));
+        }
+        let inside = |a: &str, b: &str| b.strip_prefix(a).is_some_and(|rest| rest.starts_with('.'));
+        if let Some(other) = header[..i].iter().find(|o| inside(o, h) || inside(h, o)) {
+            return Err(bad_request(format!("columns {other:?} and {h:?} name the same field")));
+        }
+    }
+    let (mut cells, mut contexts) = (Vec::new(), Vec::new());
+    for (i, record) in rdr.records().enumerate() {
+        let record = record.map_err(|e| bad_request(format!("csv: {e}")))?;
+        if record.len() > header.len() {
+            return Err(bad_request(format!("row {} has more cells than the header", i + 2)));
+        }
+        if contexts.len() == MAX_BATCH_CASES {
+            let msg = format!("at most {MAX_BATCH_CASES} rows per upload");
+            return Err(SimulateError::Refused((StatusCode::PAYLOAD_TOO_LARGE, msg).into_response()));
+        }
+        let mut ctx = Map::new();
+        for (h, cell) in header.iter().zip(record.iter()) {
+            if let Some(v) = cell_value(cell) {
+                set_path(&mut ctx, h, v);
+            }
+        }
+        contexts.push(Value::Object(ctx));
+        cells.push(record.iter().map(str::to_string).collect());
+    }
+    if contexts.is_empty() {
+        return Err(bad_request("the CSV has no rows"));
+    }
+    Ok(CsvUpload { header, cells, contexts })
+}
+
+fn cell_text(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+async fn read_csv_form(mut multipart: Multipart) -> Result<CsvForm, SimulateError> {
+    let mut form = CsvForm::default();
+    let refused = |e: axum::extract::multipart::MultipartError| SimulateError::Refused((StatusCode::BAD_REQUEST, e.body_text()).into_response());
+    while let Some(field) = multipart.next_field().await.map_err(refused)? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            form.file = Some(field.bytes().await.map_err(refused)?.to_vec());
+            continue;
+        }
+        let text = field.text().await.map_err(refused)?;
+        match name.as_str() {
+            "filepath" => form.filepath = Some(text),
+            "rev" => form.rev = Some(RevRef::Name(text)),
+            "format" => form.format = Some(text),
+            "maxDepth" => form.max_depth = Some(text.trim().parse().map_err(|_| bad_request("maxDepth must be a number"))?),
+            "timeoutMs" => form.timeout_ms = Some(text.trim().parse().map_err(|_| bad_request("timeoutMs must be a number"))?),
+            _ => {}
+        }
+    }
+    Ok(form)
+}
+
+/// Multipart fields: `file` (a CSV whose header names context fields as dot
+/// paths, `customer.age`), `filepath`, and optionally `rev`, `format` (`csv`
+/// or `json`), `maxDepth` and `timeoutMs` (per row).
+///
+/// Each row is evaluated like a batch case. The answer is the upload's
+/// columns with the decision's outputs appended, one column per output path
+/// (prefixed `result.` where that path is already an input header) and an
+/// `error` column when some row failed; as JSON, the same columns and one
+/// object per row.
+pub(crate) async fn csv(
+    State(st): State<AppState>,
+    Extension(local_pool): Extension<LocalPoolHandle>,
+    multipart: Multipart,
+) -> Result<Response, SimulateError> {
+    let started = Instant::now();
+    let form = read_csv_form(multipart).await?;
+    let Some(bytes) = form.file else {
+        return Err(bad_request("missing file field"));
+    };
+    let Some(filepath) = form.filepath else {
+        return Err(bad_request("missing filepath field"));
+    };
+    let json = match form.format.as_deref().map(str::trim) {
+        None | Some("" | "csv") => false,
+        Some("json") => true,
+        Some(_) => return Err(bad_request("format must be csv or json")),
+    };
+    let (rev, key) = resolve_root(&st.config, form.rev.as_ref(), &filepath)?;
+    let CsvUpload { header, cells, contexts } = tokio::task::spawn_blocking(move || csv_contexts(&bytes))
+        .await
+        .map_err(|_| SimulateError::Refused(StatusCode::INTERNAL_SERVER_ERROR.into_response()))??;
+
+    let opts = EvalOpts { trace: Some(false), max_depth: form.max_depth, timeout_ms: form.timeout_ms, deterministic: None };
+    let results = run_cases(&st, &local_pool, rev, &key, contexts, opts).await;
+    let failed = results.iter().filter(|r| !r.ok).count();
+    st.events.publish(ServerEvent::SimulationFinished {
+        filepath: key.clone(),
+        ok: failed == 0,
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+
+    // output columns in the order rows first have them
+    let mut columns = header.clone();
+    let mut outputs: Vec<Vec<(String, Value)>> = Vec::with_capacity(results.len());
+    for r in &results {
+        let mut flat = Vec::new();
+        if let Some(result) = r.result.as_ref().map(|v| &v["result"]) {
+            flatten("", result, &mut flat);
+        }
+        for (path, _) in flat.iter_mut() {
+            if header.contains(path) {
+                path.insert_str(0, RESULT_PREFIX);
+            }
+            if !columns.contains(path) {
+                columns.push(path.clone());
+            }
+        }
+        outputs.push(flat);
+    }
+    if failed > 0 {
+        columns.push("error".into());
+    }
+
+    // outputs and errors by column; the inputs are the upload's own cells
+    let rows = results.iter().zip(outputs).map(|(r, out)| {
+        let mut row: Map<String, Value> = out.into_iter().collect();
+        if let Some(e) = &r.error {
+            let message = e["message"].as_str().or_else(|| e["source"].as_str()).map(str::to_string);
+            row.insert("error".into(), Value::String(message.unwrap_or_else(|| e.to_string())));
+        }
+        row
+    });
+    if json {
+        let rows: Vec<Value> = rows
+            .zip(&cells)
+            .map(|(mut row, cells)| {
+                row.extend(header.iter().zip(cells).filter_map(|(h, c)| Some((h.clone(), cell_value(c)?))));
+                Value::Object(row)
+            })
+            .collect();
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let body = serde_json::json!({ "filepath": key, "rev": rev, "failed": failed, "durationMs": duration_ms, "columns": columns, "rows": rows });
+        return Ok(Json(body).into_response());
+    }
+
+    let mut w = csv::Writer::from_writer(Vec::new());
+    let written = w.write_record(&columns).and_then(|()| {
+        rows.zip(&cells).try_for_each(|(row, cells)| {
+            let record = columns.iter().enumerate().map(|(i, c)| match cells.get(i).filter(|_| i < header.len()) {
+                Some(cell) => cell.clone(),
+                None => row.get(c).map(cell_text).unwrap_or_default(),
+            });
+            w.write_record(record)
+        })
+    });
+    let body = written.map_err(|e| e.to_string()).and_then(|()| w.into_inner().map_err(|e| e.to_string()));
+    match body {
+        Ok(bytes) => Ok(([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], bytes).into_response()),
+        Err(e) => {
+            tracing::error!("csv simulate write error: {e}");
+            Err(SimulateError::Refused(StatusCode::INTERNAL_SERVER_ERROR.into_response()))
+        }
+    }
+}
+
+// ===== POST /api/simulate/compare ============================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CompareRequest {
+    /// the decision, relative to both revisions
+    #[serde(alias = "filepath")]
+    path: String,
+    context: Value,
+    rev_a: RevRef,
+    /// the working copy when absent
+    #[serde(default)]
+    rev_b: Option<RevRef>,
+    /// for both sides; the trace is off unless asked for
+    #[serde(flatten)]
+    opts: EvalOpts,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Side {
+    rev: u64,
+    ok: bool,
+    /// the decision's output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+    duration_ms: u64,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ChangeKind {
+    /// only B has it
+    Added,
+    /// only A has it
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Change {
+    /// dotted, array items by position: `offers.0.rate`
+    path: String,
+    kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    a: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    b: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompareResp {
+    path: String,
+    a: Side,
+    b: Side,
+    /// both evaluated, to the same output
+    same: bool,
+    /// from A's output to B's; empty unless both evaluated
+    diff: Vec<Change>,
+}
+
+/// Where `b` departs from `a`, both ways: objects key by key, arrays item by
+/// item, numbers by value (`6` is `6.0`).
+pub(crate) fn diff_outputs(a: Option<&Value>, b: Option<&Value>, path: &str, out: &mut Vec<Change>) {
+    let child = |k: &str| if path.is_empty() { k.to_string() } else { format!("{path}.{k}") };
+    match (a, b) {
+        (Some(Value::Object(x)), Some(Value::Object(y))) => {
+            for (k, v) in x {
+                diff_outputs(Some(v), y.get(k), &child(k), out);
+            }
+            for (k, w) in y.iter().filter(|(k, _)| !x.contains_key(*k)) {
+                diff_outputs(None, Some(w), &child(k), out);
+            }
+        }
+        (Some(Value::Array(x)), Some(Value::Array(y))) => {
+            for i in 0..x.len().max(y.len()) {
+                diff_outputs(x.get(i), y.get(i), &child(&i.to_string()), out);
+            }
+        }
+        (Some(Value::Number(x)), Some(Value::Number(y))) if x.as_f64() == y.as_f64() => {}
+        (Some(x), Some(y)) if x == y => {}
+        (None, None) => {}
+        (a, b) => {
+            let kind = match (a, b) {
+                (None, _) => ChangeKind::Added,
+                (_, None) => ChangeKind::Removed,
+                _ => ChangeKind::Changed,
+            };
+            out.push(Change { path: path.to_string(), kind, a: a.cloned(), b: b.cloned() });
+        }
+    }
+}
+
+/// Evaluates `path` with `context` in `rev_a` and in `rev_b` at once and
+/// says how the outputs differ. A revision that can't be evaluated refuses
+/// the request; a side that fails to evaluate is reported as such, with no
+/// diff.
+pub(crate) async fn compare(
+    State(st): State<AppState>,
+    Extension(local_pool): Extension<LocalPoolHandle>,
+    Json(req): Json<CompareRequest>,
+) -> Result<Response, SimulateError> {
+    let (rev_a, key) = resolve_root(&st.config, Some(&req.rev_a), &req.path)?;
+    let (rev_b, _) = resolve_root(&st.config, req.rev_b.as_ref(), &req.path)?;
+    let opts = EvalOpts { trace: Some(req.opts.trace.unwrap_or(false)), ..req.opts };
+    let timeout = opts.timeout(&st.config);
+
+    let side = |rev: u64| {
+        let (engine, key, context, pool) = (opts.engine(&st.engines, rev), key.clone(), req.context.clone(), &local_pool);
+        let engine_opts = opts.engine_options(&st.config);
+        async move {
+            let started = Instant::now();
+            let res = evaluate(pool, engine, key, context, engine_opts, opts.seed(), timeout).await;
+            let duration_ms = started.elapsed().as_millis() as u64;
+            match res {
+                Ok(mut v) => {
+                    let trace = v.get_mut("trace").map(Value::take).filter(|_| opts.trace == Some(true));
+                    let result = v.get_mut("result").map(Value::take);
+                    Side { rev, ok: true, result, trace, error: None, duration_ms }
+                }
+                Err(e) => Side { rev, ok: false, result: None, trace: None, error: Some(e.to_json()), duration_ms },
+            }
+        }
+    };
+    let (a, b) = futures_util::join!(side(rev_a), side(rev_b));
+
+    let mut diff = Vec::new();
+    if a.ok && b.ok {
+        diff_outputs(a.result.as_ref(), b.result.as_ref(), "", &mut diff);
+    }
+    let same = a.ok && b.ok && diff.is_empty();
+    st.events.publish(ServerEvent::SimulationFinished { filepath: key.clone(), ok: a.ok && b.ok, duration_ms: a.duration_ms.max(b.duration_ms) });
+    Ok(Json(CompareResp { path: key, a, b, same, diff }).into_response())
+}
+
+// ===== POST /api/simulate/stream =============================================
+
+/// The trace entries in `trace` (a node id → entry map) as `node` events, in
+/// the order the engine ran them.
+fn node_events(trace: Option<&Value>) -> Vec<Event> {
+    let mut nodes: Vec<&Value> = trace.and_then(Value::as_object).map(|m| m.values().collect()).unwrap_or_default();
+    nodes.sort_by_key(|n| n["order"].as_u64().unwrap_or(u64::MAX));
+    nodes
+        .into_iter()
+        .filter_map(|n| {
+            let ev = serde_json::json!({
+                "id": n["id"],
+                "name": n["name"],
+                "input": n["input"],
+                "output": n["output"],
+                "duration": n["performance"],
+            });
+            Event::default().event("node").json_data(ev).ok()
+        })
+        .collect()
+}
+
+/// `POST /api/simulate/stream`: the body of `/api/simulate`, answered as
+/// server-sent events: `started`, a `node` per evaluated node, then `result`,
+/// or `error` after the nodes that ran before the failure.
+///
+/// The engine only hands over its trace once evaluation is over, so the
+/// `node` events come together at the end (keep-alives until then); they're
+/// still one per node, in evaluation order, instead of one document to pick
+/// apart. Trace is always on here.
+pub(crate) async fn stream(
+    State(st): State<AppState>,
+    Extension(local_pool): Extension<LocalPoolHandle>,
+    Json(req): Json<SimulateRequest>,
+) -> Result<Response, SimulateError> {
+    no_root_dir(&req.root_dir)?;
+    let (rev, key) = resolve_root(&st.config, req.rev.as_ref(), &req.filepath)?;
+    let eval_opts = EvalOpts { trace: Some(true), ..req.opts };
+    let (opts, timeout) = (eval_opts.engine_options(&st.config), eval_opts.timeout(&st.config));
+    let started = Event::default().event("started").json_data(serde_json::json!({ "filepath": key, "rev": rev })).ok();
+
+    let engine = eval_opts.engine(&st.engines, rev);
+    let finished = async move {
+        let began = Instant::now();
+        let res = evaluate(&local_pool, engine, key.clone(), req.context, opts, eval_opts.seed(), timeout).await;
+        st.events.publish(ServerEvent::SimulationFinished {
+            filepath: key,
+            ok: res.is_ok(),
+            duration_ms: began.elapsed().as_millis() as u64,
+        });
+        let (mut events, last) = match res {
+            Ok(mut v) => {
+                let events = node_events(v.get("trace"));
+                v.as_object_mut().map(|o| o.remove("trace"));
+                (events, Event::default().event("result").json_data(v))
+            }
+            Err(e) => {
+                let mut err = e.to_json();
+                let events = node_events(err.get("trace"));
+                err.as_object_mut().map(|o| o.remove("trace"));
+                (events, Event::default().event("error").json_data(err))
+            }
+        };
+        events.extend(last.ok());
+        stream::iter(events)
+    };
+    let events = stream::iter(started).chain(stream::once(finished).flatten()).map(Ok::<_, Infallible>);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()).into_response())
+}
+
+pub(crate) enum SimulateError {
+    Evaluation(Box<EvaluationError>),
+    /// ran past its `timeoutMs`
+    Timeout(Duration),
+    /// the request can't be evaluated as it stands
+    Refused(Response),
+}
+
+impl IntoResponse for SimulateError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Evaluation(e) => (StatusCode::BAD_REQUEST, serde_json::to_string(&e).unwrap_or_default()).into_response(),
+            Self::Timeout(_) => (StatusCode::REQUEST_TIMEOUT, Json(self.to_json())).into_response(),
+            Self::Refused(resp) => resp,
+        }
+    }
+}
+
+impl SimulateError {
+    /// The error as a batch or test run reports it for one case.
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            Self::Evaluation(e) => serde_json::to_value(e).unwrap_or_default(),
+            Self::Timeout(limit) => serde_json::json!({
+                "type": "Timeout",
+                "timeoutMs": limit.as_millis() as u64,
+                "message": format!("evaluation didn't finish within {} ms", limit.as_millis()),
+            }),
+            Self::Refused(resp) => serde_json::json!({ "type": "Refused", "status": resp.status().as_u16() }),
+        }
+    }
+}
+
+impl From<Box<EvaluationError>> for SimulateError {
+    fn from(value: Box<EvaluationError>) -> Self { Self::Evaluation(value) }
+}
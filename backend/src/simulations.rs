@@ -0,0 +1,235 @@
+//! The last simulations (`SIMULATION_HISTORY_KEEP`, see
+//! [`Config`](crate::config::Config)): `GET /api/simulations`,
+//! `GET /api/simulations/:id` and `POST /api/simulations/:id/replay`.
+//!
+//! Every `POST /api/simulate` that got as far as evaluating (or answering
+//! from the cache) is logged under `./decisions/.simulations/`: `<id>.json`
+//! holds what was asked (decision, revision, context, options) and how it
+//! went, `<id>.out.json` the response, trace included, or the error. The
+//! response says which entry it became in `X-Simulation-Id`. Ids are unix
+//! milliseconds, so they sort by time; only the newest entries are kept.
+//!
+//! Replaying evaluates the logged request again, revision named as it was
+//! (`working` is the working copy as it is now, a tag whatever it points at
+//! now), and answers as `/api/simulate` does; the new entry names the one it
+//! replayed in `replayOf`.
+
+use axum::{
+    extract::{Extension, Json, Path, Query, State},
+    http::{header::HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio_util::task::LocalPoolHandle;
+
+use crate::{
+    quota,
+    revisions::RevRef,
+    simulate::{self, EvalOpts, SimulateRequest},
+    storelock::StorageLock,
+    write_atomic, AppState, WriteOpts,
+};
+
+const SIMULATIONS_DIR: &str = "./decisions/.simulations";
+
+/// A bigger response is kept without its trace.
+const MAX_OUTCOME_BYTES: usize = 4 * 1024 * 1024;
+
+/// Listed at once unless `limit` says otherwise.
+const DEFAULT_LIMIT: usize = 50;
+
+/// the last id handed out
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Serializes writes and trims, across instances too.
+static STORE: StorageLock = StorageLock::new("simulations");
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Simulation {
+    pub(crate) id: u64,
+    /// unix seconds
+    pub(crate) at: i64,
+    /// the decision, relative to the revision
+    pub(crate) filepath: String,
+    /// the revision as asked for, which a replay resolves again; absent for
+    /// the working copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) rev: Option<RevRef>,
+    /// the revision it resolved to
+    pub(crate) evaluated_rev: u64,
+    pub(crate) context: Value,
+    #[serde(default)]
+    pub(crate) opts: EvalOpts,
+    pub(crate) ok: bool,
+    pub(crate) duration_ms: u64,
+    /// answered from the simulation cache
+    #[serde(default)]
+    pub(crate) cached: bool,
+    /// kept without its trace, which was too big
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) trace_dropped: bool,
+    /// the entry this one replayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) replay_of: Option<u64>,
+}
+
+pub(crate) fn enabled(st: &AppState) -> bool {
+    st.config.simulation_history_keep > 0
+}
+
+/// A fresh id: now in unix milliseconds, or one past the last if that's
+/// already taken.
+pub(crate) fn next_id() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+    let prev = LAST_ID.fetch_max(now, Ordering::SeqCst);
+    if prev < now {
+        return now;
+    }
+    LAST_ID.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+pub(crate) fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn entry_path(id: u64) -> PathBuf {
+    PathBuf::from(SIMULATIONS_DIR).join(format!("{id}.json"))
+}
+
+fn outcome_path(id: u64) -> PathBuf {
+    PathBuf::from(SIMULATIONS_DIR).join(format!("{id}.out.json"))
+}
+
+/// Entry ids, oldest first.
+fn ids() -> Vec<u64> {
+    let mut ids: Vec<u64> = fs::read_dir(SIMULATIONS_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn load(id: u64) -> Option<Simulation> {
+    fs::read(entry_path(id)).ok().and_then(|raw| serde_json::from_slice(&raw).ok())
+}
+
+/// Logs `sim` with its `outcome` (the response, or the error) in the
+/// background; failures are logged, never the request's.
+pub(crate) fn record(st: &AppState, mut sim: Simulation, mut outcome: Value) {
+    let st = st.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut out = serde_json::to_vec(&outcome).expect("serialize simulation outcome");
+        if out.len() > MAX_OUTCOME_BYTES {
+            if let Some(obj) = outcome.as_object_mut() {
+                obj.remove("trace");
+            }
+            out = serde_json::to_vec(&outcome).expect("serialize simulation outcome");
+            sim.trace_dropped = true;
+        }
+        let entry = serde_json::to_vec_pretty(&sim).expect("serialize simulation");
+        let (entry_target, out_target) = (entry_path(sim.id), outcome_path(sim.id));
+        if quota::check_write(&st.config, &out_target, (entry.len() + out.len()) as u64).is_err() {
+            tracing::warn!("simulation {} not logged: over quota", sim.id);
+            return;
+        }
+        let _guard = STORE.lock();
+        // the outcome first: an entry is listed once it's there
+        let res = fs::create_dir_all(SIMULATIONS_DIR)
+            .and_then(|()| write_atomic(&out_target, &out, WriteOpts::default()))
+            .and_then(|()| write_atomic(&entry_target, &entry, WriteOpts::default()));
+        if let Err(e) = res {
+            tracing::error!("simulation log error: {e}");
+            return;
+        }
+        let all = ids();
+        for old in &all[..all.len().saturating_sub(st.config.simulation_history_keep)] {
+            let _ = fs::remove_file(entry_path(*old));
+            let _ = fs::remove_file(outcome_path(*old));
+        }
+    });
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "no such simulation").into_response()
+}
+
+// ===== GET /api/simulations ==================================================
+
+#[derive(Deserialize)]
+pub(crate) struct ListParams {
+    /// only simulations of this decision
+    filepath: Option<String>,
+    /// only the ones that evaluated (`true`) or failed (`false`)
+    ok: Option<bool>,
+    /// at most this many (default 50)
+    limit: Option<usize>,
+}
+
+/// Newest first, without their responses.
+pub(crate) async fn list(Query(q): Query<ListParams>) -> Response {
+    let res = tokio::task::spawn_blocking(move || {
+        let filepath = q.filepath.as_deref().map(|f| f.trim_start_matches('/'));
+        ids()
+            .into_iter()
+            .rev()
+            .filter_map(load)
+            .filter(|s| filepath.is_none_or(|f| s.filepath.trim_start_matches('/') == f))
+            .filter(|s| q.ok.is_none_or(|ok| s.ok == ok))
+            .take(q.limit.unwrap_or(DEFAULT_LIMIT))
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match res {
+        Ok(all) => Json(all).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/simulations/:id ==============================================
+
+/// The entry with its `response`, or its `error` when it didn't evaluate.
+pub(crate) async fn get_one(Path(id): Path<u64>) -> Response {
+    let res = tokio::task::spawn_blocking(move || -> Option<Value> {
+        let sim = load(id)?;
+        let outcome: Value = fs::read(outcome_path(id)).ok().and_then(|raw| serde_json::from_slice(&raw).ok())?;
+        let field = if sim.ok { "response" } else { "error" };
+        let mut out = serde_json::to_value(&sim).ok()?;
+        out.as_object_mut()?.insert(field.into(), outcome);
+        Some(out)
+    })
+    .await;
+    match res {
+        Ok(Some(v)) => Json(v).into_response(),
+        Ok(None) => not_found(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/simulations/:id/replay ======================================
+
+/// Evaluates entry `id`'s request again, answered as `/api/simulate` would
+/// with `X-Replay-Of` added.
+pub(crate) async fn replay(
+    State(st): State<AppState>,
+    Extension(pool): Extension<LocalPoolHandle>,
+    Path(id): Path<u64>,
+) -> Response {
+    let Ok(Some(sim)) = tokio::task::spawn_blocking(move || load(id)).await else {
+        return not_found();
+    };
+    let req = SimulateRequest::replay(sim);
+    let mut resp = simulate::simulate(State(st), Extension(pool), Json(req)).await.into_response();
+    resp.headers_mut().insert(HeaderName::from_static("x-replay-of"), HeaderValue::from(id));
+    resp
+}
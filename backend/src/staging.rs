@@ -0,0 +1,287 @@
+//! Two-phase revisions: stage files first, then commit them as one revision.
+//!
+//! Uploading a revision file by file into the working copy races with
+//! everyone else editing it. Instead a client prepares a staging area
+//! (`POST /api/revisions/prepare`), fills it (`PUT
+//! /api/revisions/staging/:id/file?path=`), and commits it: under the rev lock
+//! the whole directory is renamed into place as the next revision, so nobody
+//! ever sees it half-uploaded. `abort` throws it away. Staging areas live
+//! under `./decisions/.staging/`; ones nobody has put or deleted a file in for
+//! `STAGING_TTL_SECS` are swept whenever a new one is prepared.
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Json, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path as StdPath, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use walkdir::WalkDir;
+
+use crate::{
+    adopt_rev, archive, audit, auth, branches, events::ServerEvent, finish_revision, quota,
+    revisions::{self, RevMeta, RevRef},
+    write_atomic, AppState, WriteOpts,
+};
+
+const STAGING_DIR: &str = "./decisions/.staging";
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn internal(e: io::Error) -> Response {
+    tracing::error!("staging error: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// What the revision will say about itself, kept beside the staged files.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Staged {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    /// unix seconds
+    created_at: i64,
+}
+
+fn files_dir(id: &str) -> PathBuf {
+    PathBuf::from(STAGING_DIR).join(id)
+}
+
+fn meta_path(id: &str) -> PathBuf {
+    PathBuf::from(STAGING_DIR).join(format!("{id}.json"))
+}
+
+/// The staging area `id`, 404 if there's none (ids are our own names, so
+/// anything else can't be one).
+fn load(id: &str) -> Result<Staged, Response> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("no such staging area: {id}")).into_response();
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+        return Err(not_found());
+    }
+    let raw = fs::read(meta_path(id)).map_err(|_| not_found())?;
+    serde_json::from_slice(&raw).map_err(|_| not_found())
+}
+
+fn remove(id: &str) {
+    let _ = fs::remove_dir_all(files_dir(id));
+    let _ = fs::remove_file(meta_path(id));
+}
+
+/// Marks staging area `id` as in use: its metadata's mtime is when it was
+/// last filled, for [`sweep`].
+fn touch(id: &str) -> io::Result<()> {
+    fs::File::options().write(true).open(meta_path(id))?.set_modified(SystemTime::now())
+}
+
+/// Drops staging areas last touched more than `ttl` ago, and directories left
+/// without their metadata (a write racing a commit) that old. Blocking.
+fn sweep(ttl: Duration) {
+    let cutoff = SystemTime::now() - ttl;
+    let older = |p: &StdPath| fs::metadata(p).and_then(|m| m.modified()).is_ok_and(|t| t < cutoff);
+    for entry in fs::read_dir(STAGING_DIR).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let id = name.strip_suffix(".json").unwrap_or(&name);
+        let abandoned = match load(id) {
+            Ok(_) => older(&meta_path(id)),
+            Err(_) => older(&entry.path()),
+        };
+        if abandoned {
+            tracing::info!("dropping abandoned staging area {id}");
+            remove(id);
+        }
+    }
+}
+
+/// A path inside the staging area, relative and without `..`.
+fn staged_path(id: &str, rel: &str) -> Result<PathBuf, Response> {
+    archive::sanitize_entry(rel)
+        .map(|p| files_dir(id).join(p))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "path must be relative, without `..`").into_response())
+}
+
+// ===== POST /api/revisions/prepare ===========================================
+
+#[derive(Deserialize, Default)]
+pub(crate) struct PrepareReq {
+    #[serde(flatten)]
+    meta: RevMeta,
+    /// start from a copy of this revision (0: the working copy) instead of empty
+    #[serde(default)]
+    from: Option<RevRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StagedFile {
+    path: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StagingResp {
+    #[serde(flatten)]
+    staged: Staged,
+    files: Vec<StagedFile>,
+}
+
+fn describe(staged: Staged) -> StagingResp {
+    let root = files_dir(&staged.id);
+    let files = WalkDir::new(&root)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .map(|e| StagedFile {
+            path: e.path().strip_prefix(&root).unwrap_or(e.path()).to_string_lossy().replace('\\', "/"),
+            size: e.metadata().map_or(0, |m| m.len()),
+        })
+        .collect();
+    StagingResp { staged, files }
+}
+
+/// 201 with the new staging area; its `id` names it in the other calls.
+pub(crate) async fn prepare(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, body: Option<Json<PrepareReq>>) -> Response {
+    let mut body = body.map(|Json(b)| b).unwrap_or_default();
+    body.meta.author = auth::acting(&ident, body.meta.author);
+    if let Err(resp) = body.meta.branch.as_deref().map_or(Ok(()), branches::check) {
+        return resp;
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<StagingResp, Response> {
+        sweep(st.config.staging_ttl);
+        fs::create_dir_all(STAGING_DIR).map_err(internal)?;
+        let dir = tempfile::Builder::new().prefix("s-").rand_bytes(12).tempdir_in(STAGING_DIR).map_err(internal)?;
+        let id = dir.path().file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if let Some(from) = body.from {
+            let rev = from.resolve()?;
+            revisions::check_out(&st.config, rev, &dir.path().to_string_lossy()).map_err(internal)?;
+        }
+        let staged = Staged {
+            id,
+            message: body.meta.message,
+            author: body.meta.author,
+            branch: body.meta.branch,
+            created_at: now_secs(),
+        };
+        let bytes = serde_json::to_vec_pretty(&staged).expect("serialize staging area");
+        write_atomic(&meta_path(&staged.id), &bytes, WriteOpts::default()).map_err(internal)?;
+        // from here on it's swept or committed, not dropped with the guard
+        let _ = dir.into_path();
+        Ok(describe(staged))
+    })
+    .await;
+    match res {
+        Ok(Ok(resp)) => (StatusCode::CREATED, Json(resp)).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== /api/revisions/staging/:id ============================================
+
+pub(crate) async fn get_one(Path(id): Path<String>) -> Response {
+    match tokio::task::spawn_blocking(move || load(&id).map(describe)).await {
+        Ok(Ok(resp)) => Json(resp).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct FileParams {
+    /// relative to the revision root
+    path: String,
+}
+
+/// `PUT …/file?path=`: the request body becomes the file, replacing any
+/// staged one.
+pub(crate) async fn put_file(State(st): State<AppState>, Path(id): Path<String>, Query(q): Query<FileParams>, body: Bytes) -> Response {
+    if let Err(e) = auth::acl::check(&q.path) {
+        return e.into_response();
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        load(&id)?;
+        let full = staged_path(&id, &q.path)?;
+        if full.is_dir() {
+            return Err(StatusCode::CONFLICT.into_response());
+        }
+        quota::check_write(&st.config, &full, body.len() as u64)?;
+        touch(&id).map_err(internal)?;
+        write_atomic(&full, &body, WriteOpts::default()).map_err(internal)
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn delete_file(Path(id): Path<String>, Query(q): Query<FileParams>) -> Response {
+    if let Err(e) = auth::acl::check(&q.path) {
+        return e.into_response();
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        load(&id)?;
+        touch(&id).map_err(internal)?;
+        match fs::remove_file(staged_path(&id, &q.path)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(StatusCode::NOT_FOUND.into_response()),
+            Err(e) => Err(internal(e)),
+        }
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// `POST …/commit`: the staged files become the next revision, all at once,
+/// and the staging area is gone. 201 with its manifest.
+pub(crate) async fn commit(State(st): State<AppState>, Path(id): Path<String>) -> Response {
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<revisions::Manifest, Response> {
+        let staged = load(&id)?;
+        let rev = adopt_rev(&state.rev_lock, &files_dir(&id)).map_err(|e| match e.kind() {
+            // a concurrent commit of the same area got there first
+            io::ErrorKind::NotFound => (StatusCode::NOT_FOUND, format!("no such staging area: {id}")).into_response(),
+            _ => internal(e),
+        })?;
+        let _ = fs::remove_file(meta_path(&id));
+        let meta = RevMeta { message: staged.message, author: staged.author, branch: staged.branch, ..Default::default() };
+        Ok(finish_revision(&state.config, rev, meta))
+    })
+    .await;
+    match res {
+        Ok(Ok(manifest)) => {
+            st.index.touch(&manifest.id.to_string());
+            st.events.publish(ServerEvent::SnapshotCreated { id: manifest.id });
+            audit::record(audit::Event::new("revision").rev(manifest.id).by(manifest.author.clone())).await;
+            (StatusCode::CREATED, Json(manifest)).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// `POST …/abort`: throws the staging area away.
+pub(crate) async fn abort(Path(id): Path<String>) -> Response {
+    match tokio::task::spawn_blocking(move || load(&id).map(|_| remove(&id))).await {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,157 @@
+//! `GET /api/revisions/stats`: where the disk goes, for tuning retention.
+//!
+//! Reports what the storage root takes in all, what each revision would take
+//! on its own, how much the object store saves by keeping each distinct file
+//! once, and how storage grew day by day. Like `/api/fs/usage` it's measured
+//! by walking the store on every call, so it's exact but not cheap.
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fs,
+    path::{Path as StdPath, PathBuf},
+};
+
+use crate::{objects, quota, revisions, AppState, STORAGE_ROOT};
+
+const DAY_SECS: i64 = 86_400;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionStats {
+    id: u64,
+    /// unix seconds; absent for revisions from before manifests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<i64>,
+    /// its files' sizes added up, as a download of it would be
+    bytes: u64,
+    files: u64,
+    /// on disk because of this revision: files no earlier revision had
+    new_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Dedup {
+    /// what the revisions take on disk: their distinct blobs plus any copies
+    stored_bytes: u64,
+    /// `bytes` of every revision added up, less `stored_bytes`
+    saved_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Growth {
+    /// unix seconds, midnight UTC
+    day: i64,
+    revisions: u64,
+    new_bytes: u64,
+    /// `new_bytes` of this day and every one before it
+    total_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsResp {
+    /// the whole storage root, working copy and sidecars included
+    usage: quota::Usage,
+    working_copy_bytes: u64,
+    working_copy_files: u64,
+    /// ascending
+    revisions: Vec<RevisionStats>,
+    /// absent while no revision is in the object store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dedup: Option<Dedup>,
+    /// ascending; revisions without a creation time aren't in it
+    growth: Vec<Growth>,
+}
+
+fn len(p: &StdPath) -> Option<u64> {
+    fs::metadata(p).ok().map(|m| m.len())
+}
+
+/// Blocking.
+fn collect(st: &AppState) -> StatsResp {
+    let follow = st.config.follow_symlinks;
+    let (working_copy_bytes, working_copy_files) = quota::subtree_size(StdPath::new(&format!("{STORAGE_ROOT}/0")), follow);
+
+    // blob → size, for the blobs some revision has had so far
+    let mut blobs: HashMap<String, u64> = HashMap::new();
+    let (mut logical, mut copies, mut in_store) = (0u64, 0u64, false);
+    let mut revs = Vec::new();
+    for rev in revisions::existing().into_iter().filter(|&r| r != 0) {
+        let root = PathBuf::from(format!("{STORAGE_ROOT}/{rev}"));
+        let mut stats = RevisionStats { id: rev, created_at: revisions::created_at(rev), bytes: 0, files: 0, new_bytes: 0 };
+        match objects::file_map(rev) {
+            Some(map) => {
+                in_store = true;
+                for (rel, hash) in map {
+                    let file = root.join(&rel);
+                    let blob = objects::object_path(&hash);
+                    let blob_size = len(&blob);
+                    let size = blob_size.or_else(|| len(&file)).unwrap_or(0);
+                    stats.bytes += size;
+                    stats.files += 1;
+                    if let Entry::Vacant(e) = blobs.entry(hash) {
+                        e.insert(blob_size.unwrap_or(0));
+                        stats.new_bytes += blob_size.unwrap_or(0);
+                    }
+                    // kept as its own copy where hardlinks weren't possible
+                    if file.is_file() && !objects::same_inode(&file, &blob) {
+                        copies += size;
+                        stats.new_bytes += size;
+                    }
+                }
+            }
+            None => {
+                let (bytes, files) = quota::subtree_size(&root, follow);
+                stats.bytes = bytes;
+                stats.files = files;
+                stats.new_bytes = bytes;
+                copies += bytes;
+            }
+        }
+        logical += stats.bytes;
+        revs.push(stats);
+    }
+
+    let stored = blobs.values().sum::<u64>() + copies;
+    let dedup = in_store.then(|| Dedup { stored_bytes: stored, saved_bytes: logical.saturating_sub(stored) });
+
+    let mut days: BTreeMap<i64, (u64, u64)> = BTreeMap::new();
+    for r in &revs {
+        let Some(day) = r.created_at.map(|t| t.div_euclid(DAY_SECS) * DAY_SECS) else { continue };
+        let d = days.entry(day).or_default();
+        d.0 += 1;
+        d.1 += r.new_bytes;
+    }
+    let mut total = 0;
+    let growth = days
+        .into_iter()
+        .map(|(day, (revisions, new_bytes))| {
+            total += new_bytes;
+            Growth { day, revisions, new_bytes, total_bytes: total }
+        })
+        .collect();
+
+    StatsResp {
+        usage: quota::usage(&st.config),
+        working_copy_bytes,
+        working_copy_files,
+        revisions: revs,
+        dedup,
+        growth,
+    }
+}
+
+pub(crate) async fn revision_stats(State(st): State<AppState>) -> Response {
+    match tokio::task::spawn_blocking(move || collect(&st)).await {
+        Ok(resp) => Json(resp).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,60 @@
+//! Locks on the storage root that hold across server instances.
+//!
+//! Several replicas may serve the same `./decisions` (a shared volume), so
+//! the locks guarding HEAD, the object store and the branch file can't live
+//! only in one process's memory. A [`StorageLock`] is an in-process mutex, so
+//! threads queue cheaply, plus an exclusive `flock` on
+//! `./decisions/.locks/<name>.lock`, so other processes wait too. The kernel
+//! drops the `flock` when its holder exits, crashed or not, so a dead
+//! instance never leaves a stale lock behind; there's nothing to recover.
+//!
+//! On a filesystem without locking the file lock is skipped with a warning and
+//! the lock only covers this process, as before.
+
+use fs4::FileExt;
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+const LOCKS_DIR: &str = "./decisions/.locks";
+
+pub(crate) struct StorageLock {
+    name: &'static str,
+    local: Mutex<()>,
+}
+
+/// Held until dropped; the file lock goes first, then the mutex.
+pub(crate) struct StorageGuard<'a> {
+    _file: Option<File>,
+    _local: MutexGuard<'a, ()>,
+}
+
+impl StorageLock {
+    pub(crate) const fn new(name: &'static str) -> Self {
+        Self { name, local: Mutex::new(()) }
+    }
+
+    fn open(&self) -> io::Result<File> {
+        fs::create_dir_all(LOCKS_DIR)?;
+        let path = PathBuf::from(LOCKS_DIR).join(format!("{}.lock", self.name));
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }
+
+    /// Blocks until this process and every other instance are out of it.
+    pub(crate) fn lock(&self) -> StorageGuard<'_> {
+        let local = self.local.lock().unwrap();
+        let file = match self.open() {
+            Ok(f) => Some(f),
+            Err(e) => {
+                tracing::warn!("{} lock is process-local: {e}", self.name);
+                None
+            }
+        };
+        StorageGuard { _file: file, _local: local }
+    }
+}
@@ -0,0 +1,358 @@
+//! Spreadsheet interchange for decision tables: `POST /api/import/table` and
+//! `GET /api/export/table`.
+//!
+//! On import, the first row of the sheet holds field names and every later row
+//! becomes a rule. Columns listed in `outputs` (by default just the last one)
+//! become table outputs, the rest inputs. The result is a `Request → table →
+//! Response` graph written into the storage tree like any other decision file.
+//! Export is the inverse, one CSV per table node.
+
+use axum::{
+    body::Body,
+    extract::{Extension, Json, Multipart, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use calamine::Reader;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::io::{self, Cursor, Write};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{archive, audit, auth, build_node, quota, read_path, templates, write_atomic, write_path, AppState, WriteOpts};
+
+const MAX_ROWS: usize = 10_000;
+
+#[derive(Default)]
+struct ImportForm {
+    path: Option<String>,
+    file: Option<(String, Vec<u8>)>,
+    outputs: Option<String>,
+    sheet: Option<String>,
+    hit_policy: Option<String>,
+    quote_text: Option<String>,
+    overwrite: bool,
+    owner: Option<String>,
+}
+
+fn bad(msg: impl Into<String>) -> Response {
+    (StatusCode::BAD_REQUEST, msg.into()).into_response()
+}
+
+/// Rows of trimmed cell text, header first.
+fn read_rows(file_name: &str, bytes: Vec<u8>, sheet: Option<&str>) -> Result<Vec<Vec<String>>, Response> {
+    let lower = file_name.to_ascii_lowercase();
+    if lower.ends_with(".csv") || lower.ends_with(".txt") {
+        let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(bytes.as_slice());
+        return rdr
+            .records()
+            .take(MAX_ROWS + 1)
+            .map(|r| {
+                r.map(|rec| rec.iter().map(|c| c.trim().to_string()).collect())
+                    .map_err(|e| bad(format!("csv: {e}")))
+            })
+            .collect();
+    }
+
+    let mut book = calamine::open_workbook_auto_from_rs(Cursor::new(bytes)).map_err(|e| bad(format!("spreadsheet: {e}")))?;
+    let name = match sheet {
+        Some(s) => s.to_string(),
+        None => book.sheet_names().first().cloned().ok_or_else(|| bad("workbook has no sheets"))?,
+    };
+    let range = book.worksheet_range(&name).map_err(|e| bad(format!("sheet {name:?}: {e}")))?;
+    Ok(range
+        .rows()
+        .take(MAX_ROWS + 1)
+        .map(|row| row.iter().map(|c| c.to_string().trim().to_string()).collect())
+        .collect())
+}
+
+/// Bare words (`gold`, `new york`) become string literals; numbers, booleans,
+/// `null` and anything that already looks like an expression are left alone.
+fn cell_expr(cell: &str, quote_text: bool) -> String {
+    let bare = cell.starts_with(|c: char| c.is_alphabetic())
+        && cell.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-'))
+        && !matches!(cell, "true" | "false" | "null");
+    if quote_text && bare {
+        serde_json::to_string(cell).unwrap_or_default()
+    } else {
+        cell.to_string()
+    }
+}
+
+fn build_table(rows: &[Vec<String>], outputs: &[String], hit_policy: &str, quote_text: bool) -> Result<Value, Response> {
+    let Some((header, body)) = rows.split_first() else {
+        return Err(bad("sheet is empty"));
+    };
+    if body.len() > MAX_ROWS {
+        return Err(bad(format!("more than {MAX_ROWS} rules")));
+    }
+    let width = header.iter().rposition(|h| !h.is_empty()).map_or(0, |i| i + 1);
+    let header = &header[..width];
+    if width < 2 {
+        return Err(bad("need at least one input and one output column"));
+    }
+    for (i, h) in header.iter().enumerate() {
+        if h.is_empty() {
+            return Err(bad(format!("column {} has no header", i + 1)));
+        }
+        if header[..i].contains(h) {
+            return Err(bad(format!("duplicate column {h:?}")));
+        }
+    }
+    for o in outputs {
+        if !header.contains(o) {
+            return Err(bad(format!("unknown output column {o:?}")));
+        }
+    }
+    let is_output = |i: usize| if outputs.is_empty() { i == width - 1 } else { outputs.contains(&header[i]) };
+    if (0..width).all(is_output) {
+        return Err(bad("every column is an output"));
+    }
+
+    let (mut inputs, mut outs, mut ids) = (Vec::new(), Vec::new(), Vec::with_capacity(width));
+    for (i, field) in header.iter().enumerate() {
+        let (list, prefix) = if is_output(i) { (&mut outs, "out") } else { (&mut inputs, "in") };
+        let id = format!("{prefix}{}", list.len() + 1);
+        list.push(json!({ "id": id, "name": field, "type": "expression", "field": field }));
+        ids.push(id);
+    }
+
+    let rules: Vec<Value> = body
+        .iter()
+        .filter(|row| row.iter().any(|c| !c.is_empty()))
+        .enumerate()
+        .map(|(n, row)| {
+            let mut rule = Map::new();
+            rule.insert("_id".into(), Value::String(format!("rule{}", n + 1)));
+            for (i, id) in ids.iter().enumerate() {
+                let cell = row.get(i).map(String::as_str).unwrap_or_default();
+                rule.insert(id.clone(), Value::String(cell_expr(cell, quote_text)));
+            }
+            Value::Object(rule)
+        })
+        .collect();
+
+    let mut doc = templates::io_graph(json!({
+        "id": "main",
+        "name": "Table",
+        "type": "decisionTableNode",
+        "position": { "x": 430, "y": 240 },
+        "content": { "hitPolicy": hit_policy, "inputs": inputs, "outputs": outs, "rules": rules }
+    }));
+    doc["contentType"] = Value::String(templates::CONTENT_TYPE.into());
+
+    // whatever we produce must load in the engine
+    if let Err(e) = serde_json::from_value::<zen_engine::model::DecisionContent>(doc.clone()) {
+        return Err(bad(format!("generated decision is invalid: {e}")));
+    }
+    Ok(doc)
+}
+
+// ===== POST /api/import/table ================================================
+
+/// Multipart fields: `path` (target `.json`), `file` (`.csv` / `.xlsx` / `.xls`
+/// / `.ods`), and optionally `outputs` (comma-separated header names), `sheet`,
+/// `hitPolicy` (`first` | `collect`), `quoteText` (default `true`),
+/// `overwrite` and `owner`.
+pub(crate) async fn import_table(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, mut multipart: Multipart) -> Response {
+    let mut form = ImportForm::default();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(f)) => f,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            let file_name = field.file_name().unwrap_or_default().to_string();
+            match field.bytes().await {
+                Ok(b) => form.file = Some((file_name, b.to_vec())),
+                Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+            }
+            continue;
+        }
+        let text = match field.text().await {
+            Ok(t) => t,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.body_text()).into_response(),
+        };
+        match name.as_str() {
+            "path" => form.path = Some(text),
+            "outputs" => form.outputs = Some(text),
+            "sheet" => form.sheet = Some(text),
+            "hitPolicy" => form.hit_policy = Some(text),
+            "quoteText" => form.quote_text = Some(text),
+            "overwrite" => form.overwrite = text == "true",
+            "owner" => form.owner = Some(text),
+            _ => {}
+        }
+    }
+
+    let Some((file_name, bytes)) = form.file else {
+        return bad("missing file field");
+    };
+    let Some(path) = form.path.filter(|p| p.ends_with(".json")) else {
+        return bad("path must name a .json file");
+    };
+    let hit_policy = form.hit_policy.unwrap_or_else(|| "first".into());
+    if !matches!(hit_policy.as_str(), "first" | "collect") {
+        return bad("hitPolicy must be first or collect");
+    }
+    let quote_text = form.quote_text.as_deref() != Some("false");
+    let outputs: Vec<String> = form
+        .outputs
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let full = match write_path(&path) {
+        Ok(p) if p.is_dir() => return (StatusCode::CONFLICT, "target is a directory").into_response(),
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(resp) = st.locks.check(&path, auth::acting(&ident, form.owner).as_deref()) {
+        return resp;
+    }
+    let rel = path.trim_matches('/').to_string();
+    let index = st.index.clone();
+
+    let res = tokio::task::spawn_blocking(move || -> Result<_, Response> {
+        let rows = read_rows(&file_name, bytes, form.sheet.as_deref())?;
+        let doc = build_table(&rows, &outputs, &hit_policy, quote_text)?;
+        let out = serde_json::to_vec_pretty(&doc).expect("serialize decision");
+        let _g = st.rev_lock.lock();
+        quota::check_write(&st.config, &full, out.len() as u64)?;
+        let event = audit::Event::new("import-table").path(&rel).hashes(audit::hash_file(&full), Some(audit::hash(&out)));
+        let opts = WriteOpts { create_new: !form.overwrite, ..Default::default() };
+        write_atomic(&full, &out, opts).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => StatusCode::CONFLICT.into_response(),
+            _ => {
+                tracing::error!("table import error: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        })?;
+        Ok((build_node(&full, &rel, st.config.follow_symlinks), event))
+    })
+    .await;
+
+    match res {
+        Ok(Ok((Some(node), event))) => {
+            index.touch(&node.path);
+            audit::record(event).await;
+            (StatusCode::CREATED, Json(node)).into_response()
+        }
+        Ok(Ok((None, _))) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET /api/export/table =================================================
+
+#[derive(Deserialize)]
+pub(crate) struct ExportParams {
+    path: String,
+    /// keep cells exactly as stored instead of unquoting string literals
+    #[serde(default)]
+    raw: bool,
+}
+
+/// Inverse of [`cell_expr`]: `"gold"` becomes `gold` whenever importing that
+/// cell again would quote it back.
+fn cell_text(expr: &str, raw: bool) -> String {
+    if !raw {
+        if let Ok(s) = serde_json::from_str::<String>(expr) {
+            if cell_expr(&s, true) == expr {
+                return s;
+            }
+        }
+    }
+    expr.to_string()
+}
+
+fn table_csv(content: &Value, raw: bool) -> io::Result<Vec<u8>> {
+    let list = |k: &str| content[k].as_array().cloned().unwrap_or_default();
+    let cols: Vec<Value> = list("inputs").into_iter().chain(list("outputs")).collect();
+    let mut w = csv::Writer::from_writer(Vec::new());
+    w.write_record(cols.iter().map(|c| match c["field"].as_str() {
+        Some(f) if !f.is_empty() => f,
+        _ => c["name"].as_str().unwrap_or_default(),
+    }))?;
+    for rule in list("rules") {
+        w.write_record(cols.iter().map(|c| {
+            let id = c["id"].as_str().unwrap_or_default();
+            cell_text(rule[id].as_str().unwrap_or_default(), raw)
+        }))?;
+    }
+    w.into_inner().map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// `name.csv`, made safe as a zip entry and unique among `taken`.
+fn entry_name(name: &str, taken: &mut Vec<String>) -> String {
+    let base: String = name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    let base = if base.trim().is_empty() { "table".to_string() } else { base };
+    let out = (1..)
+        .map(|n| if n == 1 { format!("{base}.csv") } else { format!("{base} {n}.csv") })
+        .find(|n| !taken.contains(n))
+        .expect("unbounded range");
+    taken.push(out.clone());
+    out
+}
+
+/// One CSV for a single decision-table node, a zip of CSVs for several.
+pub(crate) async fn export_table(State(st): State<AppState>, Query(q): Query<ExportParams>) -> Response {
+    let full = match read_path(&st.config, &q.path) {
+        Ok(p) if p.is_file() => p,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let Some(doc) = tokio::fs::read(&full).await.ok().and_then(|b| serde_json::from_slice::<Value>(&b).ok()) else {
+        return bad("not a JSON decision");
+    };
+
+    let mut taken = Vec::new();
+    let mut tables = Vec::new();
+    for node in doc["nodes"].as_array().into_iter().flatten() {
+        if node["type"] != "decisionTableNode" {
+            continue;
+        }
+        let name = node["name"].as_str().or(node["id"].as_str()).unwrap_or_default();
+        match table_csv(&node["content"], q.raw) {
+            Ok(csv) => tables.push((entry_name(name, &mut taken), csv)),
+            Err(e) => return bad(format!("table {name:?}: {e}")),
+        }
+    }
+
+    let stem = full.file_stem().map(|s| s.to_string_lossy().replace('"', "")).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    let (file_name, body) = match tables.len() {
+        0 => return (StatusCode::NOT_FOUND, "no decision tables in file").into_response(),
+        1 => {
+            let (_, csv) = tables.pop().expect("one table");
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv; charset=utf-8"));
+            (format!("{stem}.csv"), Body::from(csv))
+        }
+        _ => {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+            let body = archive::stream_body(move |w| {
+                let mut zip = ZipWriter::new_stream(w);
+                let opts = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+                for (name, csv) in tables {
+                    zip.start_file(name, opts).map_err(io::Error::other)?;
+                    zip.write_all(&csv)?;
+                }
+                zip.finish().map_err(io::Error::other)?;
+                Ok(())
+            });
+            (format!("{stem}-tables.zip"), body)
+        }
+    };
+    if let Ok(v) = HeaderValue::from_str(&format!("attachment; filename=\"{file_name}\"")) {
+        headers.insert(header::CONTENT_DISPOSITION, v);
+    }
+    (headers, body).into_response()
+}
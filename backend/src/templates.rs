@@ -0,0 +1,244 @@
+//! Decision templates (`/api/templates`) and scaffolding (`/api/fs/new-from-template`).
+//!
+//! Built-in JDM skeletons live in code; user-defined templates are plain JDM
+//! files under the reserved `./decisions/.templates` folder, keyed by file stem.
+
+use axum::{
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf};
+
+use crate::{audit, auth, build_node, quota, write_atomic, write_path, AppState, WriteOpts};
+
+const TEMPLATES_DIR: &str = "./decisions/.templates";
+pub(crate) const CONTENT_TYPE: &str = "application/vnd.gorules.decision";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TemplateInfo {
+    id: String,
+    name: String,
+    description: String,
+    builtin: bool,
+}
+
+struct Builtin {
+    id: &'static str,
+    name: &'static str,
+    description: &'static str,
+    content: fn() -> Value,
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        id: "blank",
+        name: "Blank decision",
+        description: "Empty graph",
+        content: || json!({ "nodes": [], "edges": [] }),
+    },
+    Builtin {
+        id: "decision-table",
+        name: "Decision table",
+        description: "Request → decision table (first hit) → response",
+        content: decision_table,
+    },
+    Builtin {
+        id: "expression",
+        name: "Expression graph",
+        description: "Request → expression node → response",
+        content: expression_graph,
+    },
+];
+
+/// `Request → middle → Response`; `middle` must have id `"main"`.
+pub(crate) fn io_graph(middle: Value) -> Value {
+    json!({
+        "nodes": [
+            { "id": "request", "name": "Request", "type": "inputNode", "position": { "x": 180, "y": 240 } },
+            middle,
+            { "id": "response", "name": "Response", "type": "outputNode", "position": { "x": 680, "y": 240 } }
+        ],
+        "edges": [
+            { "id": "request-main", "type": "edge", "sourceId": "request", "targetId": "main" },
+            { "id": "main-response", "type": "edge", "sourceId": "main", "targetId": "response" }
+        ]
+    })
+}
+
+fn decision_table() -> Value {
+    io_graph(json!({
+        "id": "main",
+        "name": "Table",
+        "type": "decisionTableNode",
+        "position": { "x": 430, "y": 240 },
+        "content": {
+            "hitPolicy": "first",
+            "inputs": [{ "id": "in1", "name": "Input", "type": "expression", "field": "input" }],
+            "outputs": [{ "id": "out1", "name": "Output", "type": "expression", "field": "output" }],
+            "rules": [{ "_id": "rule1", "in1": "", "out1": "" }]
+        }
+    }))
+}
+
+fn expression_graph() -> Value {
+    io_graph(json!({
+        "id": "main",
+        "name": "Expression",
+        "type": "expressionNode",
+        "position": { "x": 430, "y": 240 },
+        "content": {
+            "expressions": [{ "id": "expr1", "key": "output", "value": "input" }]
+        }
+    }))
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn user_template_path(id: &str) -> PathBuf {
+    PathBuf::from(TEMPLATES_DIR).join(format!("{id}.json"))
+}
+
+/// Template body by id; user templates shadow built-ins of the same name.
+fn load(id: &str) -> Option<Value> {
+    if !valid_id(id) {
+        return None;
+    }
+    if let Ok(raw) = fs::read(user_template_path(id)) {
+        return serde_json::from_slice(&raw).ok();
+    }
+    BUILTINS.iter().find(|b| b.id == id).map(|b| (b.content)())
+}
+
+// ===== GET/POST /api/templates, DELETE /api/templates/:id ====================
+
+pub(crate) async fn list() -> impl IntoResponse {
+    let mut out: Vec<TemplateInfo> = Vec::new();
+    let mut user: Vec<TemplateInfo> = fs::read_dir(TEMPLATES_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let id = name.strip_suffix(".json")?.to_string();
+            valid_id(&id).then(|| TemplateInfo { name: id.clone(), id, description: String::new(), builtin: false })
+        })
+        .collect();
+    user.sort_by(|a, b| a.id.cmp(&b.id));
+
+    for b in BUILTINS {
+        if !user.iter().any(|u| u.id == b.id) {
+            out.push(TemplateInfo {
+                id: b.id.into(),
+                name: b.name.into(),
+                description: b.description.into(),
+                builtin: true,
+            });
+        }
+    }
+    out.append(&mut user);
+    Json(out)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SaveTemplateReq {
+    id: String,
+    content: Value,
+}
+
+pub(crate) async fn save(State(st): State<AppState>, Json(body): Json<SaveTemplateReq>) -> Response {
+    if !valid_id(&body.id) {
+        return (StatusCode::BAD_REQUEST, "template id must be [A-Za-z0-9_-]+").into_response();
+    }
+    if !body.content.get("nodes").is_some_and(Value::is_array) {
+        return (StatusCode::BAD_REQUEST, "template must be a JDM document").into_response();
+    }
+    let target = user_template_path(&body.id);
+    let bytes = serde_json::to_vec_pretty(&body.content).expect("serialize template");
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        quota::check_write(&st.config, &target, bytes.len() as u64)?;
+        write_atomic(&target, &bytes, WriteOpts::default()).map_err(|e| {
+            tracing::error!("template save error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => StatusCode::CREATED.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn delete(Path(id): Path<String>) -> impl IntoResponse {
+    if !valid_id(&id) {
+        return StatusCode::BAD_REQUEST;
+    }
+    match tokio::fs::remove_file(user_template_path(&id)).await {
+        Ok(_) => StatusCode::NO_CONTENT,
+        Err(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+// ===== POST /api/fs/new-from-template ========================================
+
+#[derive(Deserialize)]
+pub(crate) struct NewFromTemplateReq {
+    template: String,
+    path: String,
+    #[serde(default)]
+    owner: Option<String>,
+}
+
+/// Instantiates a template at `path` (create-only) and returns the new node.
+pub(crate) async fn new_from_template(State(st): State<AppState>, ident: Option<Extension<auth::Identity>>, Json(mut body): Json<NewFromTemplateReq>) -> Response {
+    body.owner = auth::acting(&ident, body.owner);
+    if let Err(resp) = st.locks.check(&body.path, body.owner.as_deref()) {
+        return resp;
+    }
+    let Some(mut doc) = load(&body.template) else {
+        return (StatusCode::NOT_FOUND, "unknown template").into_response();
+    };
+    let full = match write_path(&body.path) {
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+    if let Some(obj) = doc.as_object_mut() {
+        obj.insert("contentType".into(), Value::String(CONTENT_TYPE.into()));
+    }
+    let bytes = serde_json::to_vec_pretty(&doc).expect("serialize template");
+    let rel = body.path.trim_matches('/').to_string();
+    let event = audit::Event::new("new-from-template").path(&rel).hashes(None, Some(audit::hash(&bytes)));
+    let follow = st.config.follow_symlinks;
+    let index = st.index.clone();
+
+    let res = tokio::task::spawn_blocking(move || -> Result<_, Response> {
+        let _g = st.rev_lock.lock();
+        quota::check_write(&st.config, &full, bytes.len() as u64)?;
+        write_atomic(&full, &bytes, WriteOpts { create_new: true, ..Default::default() }).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => StatusCode::CONFLICT.into_response(),
+            _ => {
+                tracing::error!("new-from-template error: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        })?;
+        Ok(build_node(&full, &rel, follow))
+    })
+    .await;
+
+    match res {
+        Ok(Ok(Some(node))) => {
+            index.touch(&node.path);
+            audit::record(event).await;
+            (StatusCode::CREATED, Json(node)).into_response()
+        }
+        Ok(Ok(None)) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,552 @@
+//! Stored test cases (`/api/tests`): a decision, a context to evaluate it
+//! with, and what the result should be.
+//!
+//! Each case is one file, `./decisions/.tests/<id>.json`, so cases can be
+//! reviewed and copied around one by one and concurrent edits of different
+//! cases never touch the same file. Expectations are either `expected`, a
+//! JSON value the result must contain (objects are compared by the keys the
+//! expectation has, so extra output fields don't fail a case), or a list of
+//! `assertions` on dotted paths into the result, or both. A case whose
+//! decision reads the clock or calls `rand()` can carry `deterministic: {
+//! now, seed }`, evaluated as `/api/simulate` would with it.
+//!
+//! `POST /api/tests/run` evaluates the cases in a scope and reports each one
+//! as passed, failed (with where the result differs) or error (it didn't
+//! evaluate), plus an `ok` for CI to gate on. Cases run with the trace on,
+//! which is what [`coverage`](crate::coverage) counts rules hit from.
+
+use axum::{
+    extract::{Extension, Json, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio_util::task::LocalPoolHandle;
+
+use crate::{
+    coverage::{self, Evaluated},
+    deterministic::Deterministic,
+    quota, read_path,
+    revisions::RevRef,
+    simulate::{self, EvalOpts},
+    storelock::StorageLock,
+    write_atomic, AppState, WriteOpts,
+};
+
+const TESTS_DIR: &str = "./decisions/.tests";
+
+/// serializes id assignment and read-modify-write cycles, across instances too
+static STORE: StorageLock = StorageLock::new("tests");
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// the path is present (and not null)
+    Exists,
+    /// the path is absent or null
+    Missing,
+    /// a string containing `value`, or an array with an element equal to it
+    Contains,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Assertion {
+    /// dotted path into the result, e.g. `offer.rate` or `items.0.sku`
+    path: String,
+    op: Op,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TestCase {
+    /// assigned from `name` on create; ignored in request bodies
+    #[serde(default)]
+    id: String,
+    name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    /// the decision, relative to the revision as in `/api/simulate`
+    filepath: String,
+    /// the working copy when absent; a tag is resolved on every run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rev: Option<RevRef>,
+    context: Value,
+    /// a fixed clock and seed to evaluate with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deterministic: Option<Deterministic>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    assertions: Vec<Assertion>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// unix seconds; set by the server
+    #[serde(default)]
+    created_at: i64,
+    #[serde(default)]
+    updated_at: i64,
+}
+
+impl TestCase {
+    fn validate(&self, st: &AppState) -> Result<(), Response> {
+        let bad = |msg: &str| Err((StatusCode::BAD_REQUEST, msg.to_string()).into_response());
+        if self.name.trim().is_empty() || self.filepath.trim().is_empty() {
+            return bad("name and filepath are required");
+        }
+        if read_path(&st.config, &format!("0/{}", self.filepath)).is_err() {
+            return bad("filepath must be relative to the revision, inside it");
+        }
+        if self.expected.is_none() && self.assertions.is_empty() {
+            return bad("a test needs expected, assertions, or both");
+        }
+        for a in &self.assertions {
+            if a.path.trim().is_empty() {
+                return bad("assertion path is required");
+            }
+            let needs_value = !matches!(a.op, Op::Exists | Op::Missing);
+            if needs_value && a.value.is_none() {
+                return bad("assertion needs a value for this op");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn case_path(id: &str) -> PathBuf {
+    PathBuf::from(TESTS_DIR).join(format!("{id}.json"))
+}
+
+/// Every stored case, by id; unreadable files are skipped.
+fn load_all() -> Vec<TestCase> {
+    let mut all: Vec<TestCase> = fs::read_dir(TESTS_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+        .filter_map(|e| fs::read(e.path()).ok())
+        .filter_map(|raw| serde_json::from_slice(&raw).ok())
+        .collect();
+    all.sort_by(|a, b| a.id.cmp(&b.id));
+    all
+}
+
+fn load(id: &str) -> Option<TestCase> {
+    // ids are slugs; anything else can't name a case
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    fs::read(case_path(id)).ok().and_then(|raw| serde_json::from_slice(&raw).ok())
+}
+
+fn store(st: &AppState, case: &TestCase) -> Result<(), Response> {
+    let target = case_path(&case.id);
+    let bytes = serde_json::to_vec_pretty(case).expect("serialize test case");
+    quota::check_write(&st.config, &target, bytes.len() as u64)?;
+    fs::create_dir_all(TESTS_DIR)
+        .and_then(|()| write_atomic(&target, &bytes, WriteOpts::default()))
+        .map_err(|e| {
+            tracing::error!("test case store error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+}
+
+/// `name` lowercased with runs of anything but `[a-z0-9]` turned into `-`,
+/// suffixed `-2`, `-3`, … until no case has it.
+fn fresh_id(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let base = match slug.trim_end_matches('-') {
+        "" => "test".to_string(),
+        s => s.to_string(),
+    };
+    (1..).map(|n| if n == 1 { base.clone() } else { format!("{base}-{n}") }).find(|id| !case_path(id).exists()).unwrap()
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "no such test case").into_response()
+}
+
+// ===== GET/POST /api/tests ===================================================
+
+#[derive(Deserialize)]
+pub(crate) struct ListParams {
+    /// only cases of this decision
+    filepath: Option<String>,
+    /// only cases carrying this tag
+    tag: Option<String>,
+}
+
+pub(crate) async fn list(Query(q): Query<ListParams>) -> Response {
+    let res = tokio::task::spawn_blocking(move || {
+        load_all()
+            .into_iter()
+            .filter(|c| q.filepath.as_deref().is_none_or(|f| c.filepath.trim_start_matches('/') == f.trim_start_matches('/')))
+            .filter(|c| q.tag.as_deref().is_none_or(|t| c.tags.iter().any(|x| x == t)))
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match res {
+        Ok(all) => Json(all).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn create(State(st): State<AppState>, Json(mut body): Json<TestCase>) -> Response {
+    if let Err(resp) = body.validate(&st) {
+        return resp;
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<TestCase, Response> {
+        let _guard = STORE.lock();
+        body.id = fresh_id(&body.name);
+        body.created_at = now();
+        body.updated_at = body.created_at;
+        store(&st, &body)?;
+        Ok(body)
+    })
+    .await;
+    match res {
+        Ok(Ok(case)) => (StatusCode::CREATED, Json(case)).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== GET/PUT/DELETE /api/tests/:id =========================================
+
+pub(crate) async fn get_one(Path(id): Path<String>) -> Response {
+    match load(&id) {
+        Some(c) => Json(c).into_response(),
+        None => not_found(),
+    }
+}
+
+/// Replaces everything but the id and creation time.
+pub(crate) async fn update(State(st): State<AppState>, Path(id): Path<String>, Json(mut body): Json<TestCase>) -> Response {
+    if let Err(resp) = body.validate(&st) {
+        return resp;
+    }
+    let res = tokio::task::spawn_blocking(move || -> Result<TestCase, Response> {
+        let _guard = STORE.lock();
+        let old = load(&id).ok_or_else(not_found)?;
+        body.id = old.id;
+        body.created_at = old.created_at;
+        body.updated_at = now();
+        store(&st, &body)?;
+        Ok(body)
+    })
+    .await;
+    match res {
+        Ok(Ok(case)) => Json(case).into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub(crate) async fn delete(Path(id): Path<String>) -> Response {
+    let res = tokio::task::spawn_blocking(move || -> Result<(), Response> {
+        let _guard = STORE.lock();
+        if load(&id).is_none() {
+            return Err(not_found());
+        }
+        fs::remove_file(case_path(&id)).map_err(|e| {
+            tracing::error!("test case delete error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+    })
+    .await;
+    match res {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/tests/run ===================================================
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RunScope {
+    /// just these cases
+    #[serde(default)]
+    ids: Vec<String>,
+    /// cases of this decision, or of every decision under this folder
+    filepath: Option<String>,
+    tag: Option<String>,
+    /// evaluate every case against this instead of its own `rev`
+    rev: Option<RevRef>,
+}
+
+impl RunScope {
+    fn includes(&self, case: &TestCase) -> bool {
+        let file = case.filepath.trim_start_matches('/');
+        let in_path = self.filepath.as_deref().map(|f| f.trim_matches('/')).is_none_or(|f| {
+            f.is_empty() || file == f || file.strip_prefix(f).is_some_and(|rest| rest.starts_with('/'))
+        });
+        (self.ids.is_empty() || self.ids.contains(&case.id))
+            && in_path
+            && self.tag.as_deref().is_none_or(|t| case.tags.iter().any(|x| x == t))
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct RunReq {
+    /// every case when absent
+    #[serde(default)]
+    scope: RunScope,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Failure {
+    /// dotted path into the result
+    path: String,
+    /// `eq`, `gt`, … for assertions; `expected` for the expected value
+    check: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<Value>,
+    /// absent when the result doesn't have the path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<Value>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum Outcome {
+    Passed,
+    Failed,
+    /// didn't evaluate: bad decision, timeout, unknown revision
+    Error,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CaseReport {
+    id: String,
+    name: String,
+    filepath: String,
+    /// the revision it ran against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<u64>,
+    outcome: Outcome,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<Failure>,
+    /// the whole result, for cases that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunResp {
+    /// every case passed; what a CI job gates on
+    ok: bool,
+    total: usize,
+    passed: usize,
+    failed: usize,
+    errors: usize,
+    duration_ms: u64,
+    /// by id
+    cases: Vec<CaseReport>,
+}
+
+/// Numbers compare by value, so `6` matches `6.0`.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        (Value::Array(x), Value::Array(y)) => x.len() == y.len() && x.iter().zip(y).all(|(a, b)| same(a, b)),
+        (Value::Object(x), Value::Object(y)) => x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| same(v, w))),
+        _ => a == b,
+    }
+}
+
+/// Where `actual` departs from `expected`: objects by the keys `expected`
+/// has, arrays element by element, anything else by [`same`].
+fn diff(expected: &Value, actual: Option<&Value>, path: &str, out: &mut Vec<Failure>) {
+    let child = |k: &str| if path.is_empty() { k.to_string() } else { format!("{path}.{k}") };
+    match (expected, actual) {
+        (Value::Object(e), Some(Value::Object(a))) => {
+            for (k, v) in e {
+                diff(v, a.get(k), &child(k), out);
+            }
+        }
+        (Value::Array(e), Some(Value::Array(a))) if e.len() == a.len() => {
+            for (i, (v, w)) in e.iter().zip(a).enumerate() {
+                diff(v, Some(w), &child(&i.to_string()), out);
+            }
+        }
+        (e, Some(a)) if same(e, a) => {}
+        (e, a) => out.push(Failure { path: path.to_string(), check: "expected".into(), expected: Some(e.clone()), actual: a.cloned() }),
+    }
+}
+
+/// `path` (`a.b.0.c`) inside `v`.
+fn lookup<'a>(v: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(v, |cur, part| match cur {
+        Value::Object(m) => m.get(part),
+        Value::Array(a) => a.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn holds(a: &Assertion, actual: Option<&Value>) -> bool {
+    let present = actual.filter(|v| !v.is_null());
+    let order = || match (actual.and_then(Value::as_f64), a.value.as_ref().and_then(Value::as_f64)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y),
+        _ => match (actual.and_then(Value::as_str), a.value.as_ref().and_then(Value::as_str)) {
+            (Some(x), Some(y)) => Some(x.cmp(y)),
+            _ => None,
+        },
+    };
+    let want = a.value.as_ref().unwrap_or(&Value::Null);
+    match a.op {
+        Op::Exists => present.is_some(),
+        Op::Missing => present.is_none(),
+        Op::Eq => actual.is_some_and(|v| same(v, want)),
+        Op::Ne => !actual.is_some_and(|v| same(v, want)),
+        Op::Gt => order().is_some_and(|o| o.is_gt()),
+        Op::Gte => order().is_some_and(|o| o.is_ge()),
+        Op::Lt => order().is_some_and(|o| o.is_lt()),
+        Op::Lte => order().is_some_and(|o| o.is_le()),
+        Op::Contains => match (actual, want) {
+            (Some(Value::String(s)), Value::String(w)) => s.contains(w.as_str()),
+            (Some(Value::Array(items)), w) => items.iter().any(|i| same(i, w)),
+            _ => false,
+        },
+    }
+}
+
+/// What `result` (the engine's output) gets wrong about `case`.
+fn judge(case: &TestCase, result: &Value) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    if let Some(expected) = &case.expected {
+        diff(expected, Some(result), "", &mut failures);
+    }
+    for a in &case.assertions {
+        let actual = lookup(result, a.path.trim());
+        if !holds(a, actual) {
+            let check = serde_json::to_value(a.op).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+            failures.push(Failure { path: a.path.clone(), check, expected: a.value.clone(), actual: actual.cloned() });
+        }
+    }
+    failures
+}
+
+/// The report for `case`, and what coverage needs of it when it evaluated.
+async fn run_case(st: &AppState, pool: &LocalPoolHandle, case: TestCase, rev: Option<&RevRef>) -> (CaseReport, Option<Evaluated>) {
+    let started = Instant::now();
+    let mut report = CaseReport {
+        id: case.id.clone(),
+        name: case.name.clone(),
+        filepath: case.filepath.clone(),
+        rev: None,
+        outcome: Outcome::Error,
+        duration_ms: 0,
+        failures: Vec::new(),
+        actual: None,
+        error: None,
+    };
+    let opts = EvalOpts { trace: Some(true), deterministic: case.deterministic, ..EvalOpts::default() };
+    let (res, key) = match simulate::resolve_root(&st.config, rev.or(case.rev.as_ref()), &case.filepath) {
+        Ok((rev, key)) => {
+            report.rev = Some(rev);
+            let (engine_opts, timeout) = (opts.engine_options(&st.config), opts.timeout(&st.config));
+            let engine = opts.engine(&st.engines, rev);
+            let res = simulate::evaluate(pool, engine, key.clone(), case.context.clone(), engine_opts, opts.seed(), timeout).await;
+            (res, key)
+        }
+        Err(e) => (Err(e), String::new()),
+    };
+    report.duration_ms = started.elapsed().as_millis() as u64;
+    let mut evaluated = None;
+    match res {
+        Ok(mut v) => {
+            let result = v.get("result").cloned().unwrap_or_default();
+            report.failures = judge(&case, &result);
+            report.outcome = if report.failures.is_empty() { Outcome::Passed } else { Outcome::Failed };
+            if report.outcome == Outcome::Failed {
+                report.actual = Some(result);
+            }
+            let trace = v.get_mut("trace").map(Value::take).unwrap_or_default();
+            evaluated = report.rev.map(|rev| Evaluated { rev, key, trace });
+        }
+        Err(e) => report.error = Some(e.to_json()),
+    }
+    (report, evaluated)
+}
+
+/// Runs the cases in `scope`, as many at once as the pinned pool has threads.
+/// 404 when `ids` names a case that doesn't exist; a scope matching nothing
+/// is a run of nothing, which is `ok`. The run's coverage replaces the last.
+pub(crate) async fn run(
+    State(st): State<AppState>,
+    Extension(pool): Extension<LocalPoolHandle>,
+    Json(req): Json<RunReq>,
+) -> Response {
+    let started = Instant::now();
+    let Ok(all) = tokio::task::spawn_blocking(load_all).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let scope = req.scope;
+    if let Some(unknown) = scope.ids.iter().find(|id| !all.iter().any(|c| &c.id == *id)) {
+        return (StatusCode::NOT_FOUND, format!("no such test case: {unknown}")).into_response();
+    }
+    let cases: Vec<TestCase> = all.into_iter().filter(|c| scope.includes(c)).collect();
+    let in_flight = pool.num_threads().max(1);
+    let (mut reports, evaluated): (Vec<CaseReport>, Vec<Option<Evaluated>>) = stream::iter(cases)
+        .map(|case| run_case(&st, &pool, case, scope.rev.as_ref()))
+        .buffer_unordered(in_flight)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .unzip();
+    reports.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let cfg = st.config.clone();
+    let evaluated: Vec<Evaluated> = evaluated.into_iter().flatten().collect();
+    let tallied = tokio::task::spawn_blocking(move || coverage::store(&cfg, &coverage::tally(&evaluated, now()))).await;
+    if tallied.is_err() {
+        tracing::error!("coverage tally panicked");
+    }
+
+    let count = |o: Outcome| reports.iter().filter(|r| r.outcome == o).count();
+    let (passed, failed, errors) = (count(Outcome::Passed), count(Outcome::Failed), count(Outcome::Error));
+    Json(RunResp {
+        ok: failed == 0 && errors == 0,
+        total: reports.len(),
+        passed,
+        failed,
+        errors,
+        duration_ms: started.elapsed().as_millis() as u64,
+        cases: reports,
+    })
+    .into_response()
+}
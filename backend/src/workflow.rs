@@ -0,0 +1,210 @@
+//! Review workflow for revisions: `draft` → `in_review` → `published` →
+//! `archived`.
+//!
+//! Every new revision starts as a draft. Submitting it puts it in review,
+//! where it collects approvals (`POST /api/revisions/:id/approve`) from anyone
+//! but its author; with `REQUIRED_APPROVALS` of them (see
+//! [`Config`](crate::config::Config)) it can be published. State and approvals
+//! live in the revision's manifest. Revisions from before the workflow have no
+//! state and count as published: they were what was live.
+//!
+//! Only published revisions can be evaluated; the working copy, which isn't a
+//! revision, always can. See [`evaluable`].
+
+use axum::{
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    audit,
+    auth::{self, Identity},
+    events::ServerEvent,
+    revisions::{self, Manifest, RevRef},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RevState {
+    Draft,
+    InReview,
+    Published,
+    Archived,
+}
+
+impl RevState {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::InReview => "in_review",
+            Self::Published => "published",
+            Self::Archived => "archived",
+        }
+    }
+
+    fn can_become(self, to: Self) -> bool {
+        use RevState::*;
+        matches!(
+            (self, to),
+            (Draft, InReview) | (InReview, Draft) | (InReview, Published) | (Published, Archived) | (Archived, Published)
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Approval {
+    pub by: String,
+    /// unix seconds
+    pub at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// State of a revision, with the pre-workflow default.
+fn state_of(m: &Manifest) -> RevState {
+    m.state.unwrap_or(RevState::Published)
+}
+
+/// 409 unless revision `rev` may be evaluated: published, or the working copy.
+pub(crate) fn evaluable(rev: u64) -> Result<(), Response> {
+    if rev == 0 {
+        return Ok(());
+    }
+    match state_of(&revisions::read_manifest(rev)) {
+        RevState::Published => Ok(()),
+        s => Err((StatusCode::CONFLICT, format!("revision {rev} is {}; only published revisions can be evaluated", s.name())).into_response()),
+    }
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+// ===== POST /api/revisions/:id/state =========================================
+
+#[derive(Deserialize)]
+pub(crate) struct TransitionReq {
+    to: RevState,
+    /// who is moving it, for the log; the caller when authenticated
+    #[serde(default)]
+    by: Option<String>,
+}
+
+/// Moves a revision along the workflow; 409 for a transition that isn't
+/// allowed from its current state, or publishing without enough approvals.
+/// Sending a revision back to draft clears its approvals.
+pub(crate) async fn transition(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    ident: Option<Extension<Identity>>,
+    Json(body): Json<TransitionReq>,
+) -> Response {
+    let by = auth::acting(&ident, body.by);
+    let actor = by.clone();
+    let required = st.config.required_approvals;
+    let state = st.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<Manifest, Response> {
+        let _g = state.rev_lock.lock();
+        let rev = match RevRef::Name(id).resolve()? {
+            0 => return Err((StatusCode::BAD_REQUEST, "the working copy has no review state").into_response()),
+            rev => rev,
+        };
+        let current = revisions::read_manifest(rev);
+        let from = state_of(&current);
+        if !from.can_become(body.to) {
+            return Err((StatusCode::CONFLICT, format!("revision {rev} is {}; it can't become {}", from.name(), body.to.name())).into_response());
+        }
+        if body.to == RevState::Published && from == RevState::InReview && current.approvals.len() < required {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("revision {rev} has {} of {required} required approvals", current.approvals.len()),
+            )
+                .into_response());
+        }
+        let updated = revisions::update_manifest(rev, |m| {
+            m.state = Some(body.to);
+            if body.to == RevState::Draft {
+                m.approvals.clear();
+            }
+        })
+        .map_err(|e| {
+            tracing::error!("could not update the manifest of revision {rev}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+        let by = non_empty(by).unwrap_or_else(|| "unknown".to_string());
+        tracing::info!("revision {rev}: {} → {} by {by}", from.name(), body.to.name());
+        Ok(updated)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(manifest)) => {
+            if let Some(s) = manifest.state {
+                st.events.publish(ServerEvent::RevisionStateChanged { id: manifest.id, state: s });
+                audit::record(audit::Event::new("transition").rev(manifest.id).to(s.name()).by(actor)).await;
+            }
+            Json(manifest).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// ===== POST /api/revisions/:id/approve =======================================
+
+#[derive(Deserialize)]
+pub(crate) struct ApproveReq {
+    /// ignored when authenticated: the approval is the caller's
+    #[serde(default)]
+    by: String,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// Records an approval on a revision in review. 403 for its own author, 409
+/// when it isn't in review or `by` already approved it.
+pub(crate) async fn approve(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    ident: Option<Extension<Identity>>,
+    Json(body): Json<ApproveReq>,
+) -> Response {
+    let Some(by) = non_empty(auth::acting(&ident, Some(body.by))) else {
+        return (StatusCode::BAD_REQUEST, "give who approves (by)").into_response();
+    };
+    let actor = by.clone();
+    let res = tokio::task::spawn_blocking(move || -> Result<Manifest, Response> {
+        let _g = st.rev_lock.lock();
+        let rev = RevRef::Name(id).resolve()?;
+        let current = revisions::read_manifest(rev);
+        if rev == 0 || current.state != Some(RevState::InReview) {
+            return Err((StatusCode::CONFLICT, format!("revision {rev} is not in review")).into_response());
+        }
+        if current.author.as_deref() == Some(by.as_str()) {
+            return Err((StatusCode::FORBIDDEN, "authors can't approve their own revisions").into_response());
+        }
+        if current.approvals.iter().any(|a| a.by == by) {
+            return Err((StatusCode::CONFLICT, format!("{by} already approved revision {rev}")).into_response());
+        }
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        revisions::update_manifest(rev, |m| m.approvals.push(Approval { by, at, comment: non_empty(body.comment) })).map_err(|e| {
+            tracing::error!("could not update the manifest of revision {rev}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+    })
+    .await;
+
+    match res {
+        Ok(Ok(manifest)) => {
+            audit::record(audit::Event::new("approve").rev(manifest.id).by(Some(actor))).await;
+            Json(manifest).into_response()
+        }
+        Ok(Err(resp)) => resp,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
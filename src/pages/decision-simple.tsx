@@ -59,8 +59,11 @@ export const DecisionSimplePage: React.FC = () => {
     if (existing) { setActive(existing.key); return; }
 
     try {
-      const { data } = await axios.get(`/api/fs/read?path=${encodeURIComponent(path)}`);
-      const parsed = JSON.parse(data);
+      const { data } = await axios.get<{ encoding: 'utf-8' | 'base64'; content: string; truncated: boolean }>(
+        `/api/fs/read?path=${encodeURIComponent(path)}`,
+      );
+      if (data.encoding !== 'utf-8' || data.truncated) throw new Error('File cannot be opened in the editor');
+      const parsed = JSON.parse(data.content);
       if (parsed.contentType !== ContentType.Decision) throw new Error('Invalid type');
 
       addTab({
@@ -186,13 +189,12 @@ export const DecisionSimplePage: React.FC = () => {
                             <GraphSimulator
                               onClear={() => updateActive({ trace: undefined })}
                               onRun={async ({ context }) => {
-                                   /* derive root_dir and filepath from the tab’s filePath (e.g. "4/a/b.json") */
+                                   /* derive rev and filepath from the tab’s filePath (e.g. "4/a/b.json") */
                                    const full = t.filePath ?? '';             // "" if the tab is untitled
                                    const [rev, ...rest] = full.split('/');    // rev = "4", rest = ["a","b.json"]
-                                   const rootDir = `decisions/${rev}`;       // => "decisions/4"
                                    const filepath = rest.join('/');           // => "a/b.json"
                                 try {
-                                  const { data } = await axios.post('/api/simulate', { rootDir, filepath, context });
+                                  const { data } = await axios.post('/api/simulate', { rev, filepath, context });
                                   updateActive({ trace: { result: data } });
                                 } catch (e) { displayError(e); }
                               }}
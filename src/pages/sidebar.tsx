@@ -123,7 +123,7 @@ export const DirectorySidebar:React.FC<{width?:number; onSelect?:(p:string)=>voi
   const fetchRevs=useCallback(async()=>{
     const {data}=await axios.get<{latest:number;list:number[]}>('/api/revisions');
     setRevisions(data.list); setLatest(data.latest);
-    setRevId(v=>v??data.latest);
+    setRevId(v=>v??0);  // revisions are read-only; edit the working copy
   },[]);
 
   const fetchTree=useCallback(async(id:number)=>{
@@ -154,7 +154,7 @@ export const DirectorySidebar:React.FC<{width?:number; onSelect?:(p:string)=>voi
     if(kind==='folder')
       await axios.post('/api/fs/mkdir',{path:`${dir}/${name}`});
     else
-      await axios.post('/api/fs/write',{path:`${dir}/${name}`,content:DEFAULT_DECISION_JSON});
+      await axios.post('/api/fs/write',{path:`${dir}/${name}`,content:DEFAULT_DECISION_JSON,mode:'create'});
     await finish(kind==='folder'?'Folder created':'File created');
   };
 
@@ -179,7 +179,7 @@ export const DirectorySidebar:React.FC<{width?:number; onSelect?:(p:string)=>voi
     try{
       const {data}=await axios.post<{id:number}>('/api/fs/snapshot');
       message.success(`Snapshot rev ${data.id}`);
-      await fetchRevs(); setRevId(data.id);
+      await fetchRevs();
     }finally{setPending(false);}
   };
 
@@ -213,10 +213,11 @@ export const DirectorySidebar:React.FC<{width?:number; onSelect?:(p:string)=>voi
     searchTimeoutRef.current = window.setTimeout(async ()=>{
       if(revId===undefined){ setSearching(false); return; }
       try{
-        const {data} = await axios.get<SearchHit[]>(
+        const {data} = await axios.get<{hits:SearchHit[]; truncated:boolean}>(
           `/api/fs/search?path=${revId}&q=${encodeURIComponent(searchTerm)}`,
         );
-        setTreeData(buildFilteredTree(data));
+        setTreeData(buildFilteredTree(data.hits));
+        if(data.truncated) message.info('Showing the first matches only — refine the search');
       }catch(err){ displayError(err); }
       finally   { setSearching(false); }
     },300);
@@ -253,7 +254,7 @@ export const DirectorySidebar:React.FC<{width?:number; onSelect?:(p:string)=>voi
       <Space style={{marginBottom:8,width:'100%'}}>
         <Select size="small" style={{flex:1}} value={revId} onChange={setRevId}>
           {[...revisions].reverse().map(id=>(
-            <Option key={id} value={id}>{id===latest?`HEAD (${id})`:`rev ${id}`}</Option>
+            <Option key={id} value={id}>{id===0?'working copy':id===latest?`HEAD (${id})`:`rev ${id}`}</Option>
           ))}
         </Select>
         <Button